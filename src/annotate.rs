@@ -0,0 +1,93 @@
+// debug markings over a rendered frame: widget boxes, text baselines and a
+// grid. drawn in colors the panel can't show, so they only ever go into
+// the simulator and preview images.
+
+use crate::{layout::Rect, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+const BOX: bmp::Pixel = bmp::Pixel {
+    r: 255,
+    g: 0,
+    b: 255,
+};
+const BASELINE: bmp::Pixel = bmp::Pixel {
+    r: 0,
+    g: 200,
+    b: 255,
+};
+const GRID: bmp::Pixel = bmp::Pixel {
+    r: 160,
+    g: 160,
+    b: 160,
+};
+
+#[derive(Default)]
+pub struct Annotations {
+    // grid line spacing in pixels, 0 for no grid
+    pub grid: u32,
+    pub boxes: Vec<Rect>,
+    // (y, x from, x to)
+    pub baselines: Vec<(u32, u32, u32)>,
+}
+
+impl Annotations {
+    // mirrors everything the same way the frame was flipped
+    pub fn flip(&mut self, horizontal: bool, vertical: bool) {
+        let (w, h) = (SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+        for r in &mut self.boxes {
+            if horizontal {
+                r.x = w.saturating_sub(r.x + r.w);
+            }
+            if vertical {
+                r.y = h.saturating_sub(r.y + r.h);
+            }
+        }
+        for (y, x0, x1) in &mut self.baselines {
+            if horizontal {
+                (*x0, *x1) = (w.saturating_sub(*x1), w.saturating_sub(*x0));
+            }
+            if vertical {
+                *y = h.saturating_sub(*y + 1);
+            }
+        }
+    }
+
+    pub fn draw(&self, img: &mut bmp::Image) {
+        let (w, h) = (img.get_width(), img.get_height());
+        let mut set = |x: u32, y: u32, px| {
+            if x < w && y < h {
+                img.set_pixel(x, y, px);
+            }
+        };
+        // dotted, so the frame still shows through
+        if self.grid > 0 {
+            for y in (0..h).step_by(self.grid as usize) {
+                for x in (0..w).step_by(2) {
+                    set(x, y, GRID);
+                }
+            }
+            for x in (0..w).step_by(self.grid as usize) {
+                for y in (0..h).step_by(2) {
+                    set(x, y, GRID);
+                }
+            }
+        }
+        for &(y, x0, x1) in &self.baselines {
+            for x in x0..x1 {
+                set(x, y, BASELINE);
+            }
+        }
+        for r in &self.boxes {
+            if r.w == 0 || r.h == 0 {
+                continue;
+            }
+            for x in r.x..r.x + r.w {
+                set(x, r.y, BOX);
+                set(x, r.y + r.h - 1, BOX);
+            }
+            for y in r.y..r.y + r.h {
+                set(r.x, y, BOX);
+                set(r.x + r.w - 1, y, BOX);
+            }
+        }
+    }
+}
@@ -0,0 +1,866 @@
+// what a run is set up with and the modes it can be asked for, and the
+// refresh every mode draws through. the skip for a frame already shown, the
+// --clear-every count and the thumbnail all happen here.
+
+use std::{
+    cell::Cell,
+    error::Error,
+    io,
+    path::PathBuf,
+    process,
+    time::{Duration, Instant},
+};
+
+use clap::{Subcommand, ValueEnum};
+use serde_json::json;
+use tracing::{debug, info, warn};
+
+#[cfg(feature = "battery")]
+use crate::battery;
+#[cfg(feature = "climate")]
+use crate::climate;
+#[cfg(feature = "light")]
+use crate::light;
+#[cfg(feature = "mock")]
+use crate::mock;
+#[cfg(feature = "qr")]
+use crate::roi;
+#[cfg(feature = "ttf")]
+use crate::text;
+use crate::{
+    calibrate, cmd,
+    cmd::{Command, Query},
+    daemon, dither,
+    draw::{self, Color, Corner, Drawable, PaperImage},
+    events,
+    lastframe::{self, LastFrame},
+    layout, localtime, mqtt, pipeline, preview, profile,
+    refreshcount::{self, RefreshCount},
+    render, report, rtc, sim, splash, store, write_atomic, EPaper, SpiDevice,
+};
+#[cfg(feature = "ttf")]
+use crate::{render::TEXT_MARGIN, SCREEN_WIDTH};
+
+// palette domain touch-ups applied to the dithered frame, in flag order
+#[derive(Clone)]
+pub enum Touchup {
+    Remap(Color, Color),
+    Despeckle,
+    Fill(layout::Rect, Color),
+    Flood(u16, u16, Color),
+}
+
+// how the reporting commands print their results
+#[derive(Clone, Copy, PartialEq)]
+pub enum Output {
+    Text,
+    Json,
+}
+
+impl std::str::FromStr for Output {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Output::Text),
+            "json" => Ok(Output::Json),
+            _ => Err(format!("unknown output '{s}' (expected text or json)")),
+        }
+    }
+}
+
+// everything a run is set up with, from the flags over the config file
+pub struct Options {
+    pub mode: Option<Mode>,
+    // the mode and its words as given, for the event log
+    pub words: Vec<String>,
+    pub flip_h: bool,
+    pub flip_v: bool,
+    pub timestamp: Option<Corner>,
+    pub margin: u32,
+    pub border: Option<Color>,
+    pub border_width: u32,
+    pub panes: Vec<(layout::Region, String)>,
+    pub pip: Option<String>,
+    pub pip_corner: Corner,
+    pub pip_scale: f32,
+    pub text_scale: u16,
+    pub interval: Duration,
+    pub button: Option<u8>,
+    pub tmux: Option<String>,
+    pub browser: String,
+    pub wake_every: Option<Duration>,
+    pub power_pin: Option<u8>,
+    pub shutdown: bool,
+    pub preview: Option<String>,
+    pub simulate: Option<preview::ColorBlindness>,
+    pub roi: Vec<layout::Rect>,
+    pub roi_mask: Option<String>,
+    pub ascii_color: bool,
+    pub seed: u64,
+    pub cycles: u32,
+    // how often a wedged panel is reset and the command sent again
+    pub retries: u32,
+    pub check_temperature: bool,
+    pub palette: Option<PathBuf>,
+    pub photo: Option<String>,
+    pub palette_overrides: Vec<String>,
+    pub save_frame: Option<String>,
+    pub event_log: Option<PathBuf>,
+    // mark layout boxes, baselines and a grid in the sim and preview output
+    pub debug_layout: bool,
+    // how images that aren't panel sized are scaled
+    pub scale: layout::Scale,
+    pub filter: image::imageops::FilterType,
+    pub letterbox: Color,
+    // preprocessing before the image is fitted and dithered
+    pub pipeline: pipeline::Pipeline,
+    // where --look names are looked up
+    pub looks: PathBuf,
+    // for a panel mounted on its side, kept out of the pipeline so it isn't
+    // saved with a look
+    pub rotate: Option<draw::Rotation>,
+    // where `@name` images are looked up
+    pub images: PathBuf,
+    pub output: Output,
+    pub cooldown: Duration,
+    pub no_power_off: bool,
+    pub deep_sleep: bool,
+    // power the panel off but skip deep sleep once a mode is done
+    pub stay_awake: bool,
+    pub transfer: cmd::Transfer,
+    pub save_indexed: Option<String>,
+    pub sim: Option<String>,
+    pub thumbnail: Option<PathBuf>,
+    // broker and topic the thumbnail is published to after each refresh
+    pub thumbnail_topic: Option<(String, String)>,
+    // where the last frame drawn is remembered, unless --force
+    pub last_frame: Option<LastFrame>,
+    // where refreshes are counted toward the next clear
+    pub refresh_count: Option<RefreshCount>,
+    pub clear_every: Option<u32>,
+    pub clock: localtime::Clock,
+    pub colors: Option<usize>,
+    pub dither: Box<dyn dither::Ditherer>,
+    // what error diffusion does at the image edges
+    pub edges: dither::Boundary,
+    // how the closest palette color is picked
+    pub metric: draw::Metric,
+    // through a lut::Lut rather than measuring every color
+    pub lut: bool,
+    pub splash: bool,
+    pub panel: crate::Config,
+    // the toml file the panel settings came from, if any
+    pub config: Option<PathBuf>,
+    pub profiles: Option<PathBuf>,
+    pub touchups: Vec<Touchup>,
+    pub temperature: Option<f32>,
+    pub temperature_file: Option<PathBuf>,
+    pub offline_screen: bool,
+    pub sim_realtime: bool,
+    pub sim_video: Option<PathBuf>,
+    pub sim_temperature: f32,
+    // where the looping modes take POST /notify
+    pub notify: Option<String>,
+    // print what would be sent to the panel instead of sending it
+    #[cfg(feature = "mock")]
+    pub mock: bool,
+    pub min_refresh: Duration,
+    #[cfg(feature = "battery")]
+    pub battery: Option<battery::Gauge>,
+    #[cfg(feature = "battery")]
+    pub battery_critical: f32,
+    #[cfg(feature = "light")]
+    pub light: Option<light::Sensor>,
+    // skip refreshes below this many lux
+    #[cfg(feature = "light")]
+    pub dark_below: Option<f32>,
+    // dither with the high contrast colors above this many lux
+    #[cfg(feature = "light")]
+    pub bright_above: Option<f32>,
+    // a .ttf or .otf for the text mode
+    #[cfg(feature = "ttf")]
+    pub font: Option<PathBuf>,
+    #[cfg(feature = "ttf")]
+    pub text_style: text::Style,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            mode: None,
+            words: Vec::new(),
+            flip_h: false,
+            flip_v: false,
+            timestamp: None,
+            margin: 0,
+            border: None,
+            border_width: 2,
+            panes: Vec::new(),
+            pip: None,
+            pip_corner: Corner::BottomRight,
+            pip_scale: 0.3,
+            text_scale: 2,
+            interval: Duration::from_secs(60),
+            button: None,
+            tmux: None,
+            browser: "chromium".to_string(),
+            wake_every: None,
+            power_pin: None,
+            shutdown: false,
+            preview: None,
+            simulate: None,
+            roi: Vec::new(),
+            roi_mask: None,
+            ascii_color: false,
+            seed: 0,
+            cycles: 1,
+            retries: 1,
+            check_temperature: false,
+            palette: None,
+            photo: None,
+            palette_overrides: Vec::new(),
+            save_frame: None,
+            event_log: None,
+            images: store::default_dir(),
+            debug_layout: false,
+            scale: layout::Scale::Fit,
+            filter: image::imageops::FilterType::CatmullRom,
+            letterbox: Color::White,
+            pipeline: Default::default(),
+            looks: pipeline::default_path(),
+            rotate: None,
+            output: Output::Text,
+            cooldown: cmd::DrawOptions::default().cooldown,
+            no_power_off: false,
+            deep_sleep: false,
+            stay_awake: false,
+            transfer: Default::default(),
+            save_indexed: None,
+            sim: None,
+            thumbnail: None,
+            thumbnail_topic: None,
+            last_frame: Some(LastFrame::new(lastframe::default_path())),
+            refresh_count: Some(RefreshCount::new(refreshcount::default_path())),
+            clear_every: None,
+            clock: Default::default(),
+            colors: None,
+            dither: Box::new(dither::FloydSteinberg(Default::default())),
+            edges: dither::Boundary::Drop,
+            metric: draw::Metric::Rgb,
+            lut: false,
+            splash: false,
+            panel: Default::default(),
+            config: None,
+            profiles: None,
+            touchups: Vec::new(),
+            temperature: None,
+            temperature_file: None,
+            offline_screen: false,
+            sim_realtime: false,
+            sim_video: None,
+            sim_temperature: 25.0,
+            notify: None,
+            #[cfg(feature = "mock")]
+            mock: false,
+            min_refresh: Duration::from_secs(180),
+            #[cfg(feature = "battery")]
+            battery: None,
+            #[cfg(feature = "battery")]
+            battery_critical: 3.3,
+            #[cfg(feature = "light")]
+            light: None,
+            #[cfg(feature = "light")]
+            dark_below: None,
+            #[cfg(feature = "light")]
+            bright_above: None,
+            #[cfg(feature = "ttf")]
+            font: None,
+            #[cfg(feature = "ttf")]
+            text_style: text::Style {
+                width: Some(SCREEN_WIDTH as u32 - 2 * TEXT_MARGIN),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+// what to draw, or what to report instead. without one the built-in image is
+// drawn.
+#[derive(Subcommand)]
+pub enum Mode {
+    /// Draw an image, fitted to the panel with --fit
+    Show {
+        /// A path, or @name for one in --images
+        image: String,
+    },
+    /// Clear the panel
+    Clean,
+    /// Draw a pattern for checking a newly assembled panel
+    TestPattern {
+        #[arg(value_enum, default_value = "stripes")]
+        pattern: Pattern,
+    },
+    /// Put the panel into deep sleep without drawing anything
+    Sleep,
+    /// Read the panel's own temperature sensor
+    Temperature,
+    /// Read the controller's status flags
+    Status,
+    /// Print the panel setup this invocation would use
+    Info,
+    /// Summarize the log given with --event-log
+    History,
+    /// Time dithering and packing a picture --cycles times, without the panel
+    Bench {
+        /// A path, or @name for one in --images [default: the built-in image]
+        image: Option<String>,
+    },
+    /// List the images in --images
+    List,
+    /// List the saved and built-in looks
+    Looks,
+    /// Save the stages given with --pipeline or --look as a look
+    SaveLook { name: String },
+    /// Draw the images given with --left, --right, --top, --bottom, --tl, --tr,
+    /// --bl and --br side by side
+    Split,
+    /// Lay out a scene file, cycling through its pages if it has several
+    Scene { file: String },
+    /// Page through a text file every --interval or on a --button press
+    Pages { file: String },
+    /// Draw the output of a command, or of a pane with --tmux
+    Term {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// Set text in --font, wrapped to the screen. a literal \n starts a new line
+    #[cfg(feature = "ttf")]
+    Text {
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        words: Vec<String>,
+    },
+    /// Draw a qr code of the text, as big as fits and centered unless
+    /// --module or --at say otherwise
+    #[cfg(feature = "qr")]
+    Qr {
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        words: Vec<String>,
+        /// Pixels to a module
+        #[arg(long, value_name = "PX")]
+        module: Option<u16>,
+        /// Top-left corner of the code, its quiet zone included
+        #[arg(long, value_name = "X,Y", value_parser = roi::parse_point)]
+        at: Option<(u16, u16)>,
+        /// Color of the dark modules
+        #[arg(long, value_name = "COLOR", default_value = "black")]
+        color: Color,
+    },
+    /// Screenshot a web page every --interval
+    Web { url: String },
+    /// Cycle through the images in a directory
+    Dir { directory: String },
+    /// Cycle through the frames of an animated gif, or the images in a
+    /// directory, one every --interval
+    Slideshow {
+        /// A .gif or a directory
+        path: PathBuf,
+    },
+    /// Draw the pictures and draw commands published on an mqtt topic,
+    /// reconnecting when the broker goes away
+    Mqtt {
+        /// host or host:port
+        broker: String,
+        topic: String,
+    },
+    /// Chart a day of temperature and humidity readings
+    #[cfg(feature = "climate")]
+    Climate {
+        /// sht31 or bme280
+        sensor: climate::Sensor,
+        /// Where the readings are kept between runs
+        #[arg(default_value = "climate.txt")]
+        history: String,
+    },
+    /// Compose independently scheduled regions, refreshing at most every
+    /// --min-refresh
+    Compose { bindings: String },
+    /// Take pictures, clears and notifications over http, drawing them one
+    /// at a time
+    Serve {
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+    },
+    /// Play back a sequence file
+    Run { script: String },
+    /// Refresh through the stress patterns --cycles times and log each refresh
+    Endurance {
+        #[arg(default_value = "endurance.csv")]
+        log: String,
+    },
+    /// Fill the panel with every color --cycles times to clear ghosting
+    Deghost,
+    /// Draw the palette chart, or calibrate the palette with --photo or --set
+    Calibrate,
+    /// Draw the splash screen
+    Splash,
+    /// Draw a png of palette indices, as written by --save-indexed
+    Indexed { image: String },
+    /// Draw a packed frame, as written by --save-frame
+    Frame {
+        /// - reads it from stdin
+        #[arg(default_value = "-")]
+        path: String,
+    },
+    /// Draw generated art, seeded with --seed
+    Art { kind: Art },
+    /// Draw pixel art scaled up by a whole factor
+    Pixel { image: String },
+    /// Draw an image, or the built-in one, as colored characters
+    Ascii { image: Option<String> },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Pattern {
+    /// Diagonal stripes of every color
+    Stripes,
+    /// A bar of each color the panel shows
+    Bars,
+    /// A block of each color, clean included
+    Swatches,
+    /// Dithered ramps from black through each color to white
+    Ramps,
+    /// A grid with a cross through the middle, for alignment
+    Crosshatch,
+    /// A frame around the edge only
+    Border,
+    /// Every pixel a random color
+    Random,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Art {
+    Life,
+    Noise,
+    Truchet,
+}
+
+impl Mode {
+    // the modes that keep redrawing until they are stopped
+    pub fn is_looping(&self) -> bool {
+        match self {
+            Mode::Pages { .. }
+            | Mode::Web { .. }
+            | Mode::Dir { .. }
+            | Mode::Slideshow { .. }
+            | Mode::Mqtt { .. }
+            | Mode::Compose { .. }
+            | Mode::Serve { .. } => true,
+            #[cfg(feature = "climate")]
+            Mode::Climate { .. } => true,
+            _ => false,
+        }
+    }
+
+    // the modes that only read the config and log
+    pub fn is_reporting(&self) -> bool {
+        matches!(
+            self,
+            Mode::Info
+                | Mode::History
+                | Mode::Bench { .. }
+                | Mode::List
+                | Mode::Looks
+                | Mode::SaveLook { .. }
+        )
+    }
+}
+
+// runs the selected mode against a panel
+fn drive(
+    display: &mut impl SpiDevice,
+    mode: Option<&Mode>,
+    opts: &Options,
+) -> Result<(), Box<dyn Error>> {
+    wake(display, opts)?;
+    if let Some(Mode::Sleep) = mode {
+        info!("Putting display to sleep");
+        cmd::PowerOff.send_retrying(display, opts.retries)?;
+        cmd::DeepSleep.send_retrying(display, opts.retries)?;
+        return Ok(());
+    }
+    if let Some(Mode::Temperature) = mode {
+        return report::report_temperature(display, opts);
+    }
+    if let Some(Mode::Status) = mode {
+        return report::report_status(display, opts);
+    }
+    let now = Instant::now();
+    if opts.splash && mode.is_some_and(Mode::is_looping) {
+        draw_dithered(display, &splash::splash(&opts.clock), opts)?;
+        if opts.deep_sleep {
+            wake(display, opts)?;
+        }
+    }
+    info!("Printing image");
+    match mode {
+        Some(Mode::Pages { file }) => daemon::show_pages(display, file, opts)?,
+        Some(
+            source @ (Mode::Web { .. }
+            | Mode::Dir { .. }
+            | Mode::Slideshow { .. }
+            | Mode::Mqtt { .. }),
+        ) => daemon::show_source(display, &mut *daemon::image_source(source, opts)?, opts)?,
+        #[cfg(feature = "climate")]
+        Some(source @ Mode::Climate { .. }) => {
+            daemon::show_source(display, &mut *daemon::image_source(source, opts)?, opts)?
+        }
+        Some(Mode::Compose { bindings }) => daemon::show_composed(display, bindings, opts)?,
+        Some(Mode::Serve { port }) => daemon::serve(display, *port, opts)?,
+        Some(Mode::Run { script }) => daemon::run_script(display, script, opts)?,
+        Some(Mode::Scene { file }) => daemon::show_scene(display, file, opts)?,
+        Some(Mode::Endurance { log }) => {
+            forget_last_frame(opts);
+            daemon::run_endurance(display, log, opts)?
+        }
+        Some(Mode::Deghost) => {
+            forget_last_frame(opts);
+            cmd::Deghost {
+                cycles: opts.cycles,
+                progress: &|step, total, color| {
+                    info!("Deghost {step}/{total}: {}", color.name());
+                },
+                options: draw_options(opts),
+            }
+            .send_retrying(display, opts.retries)?;
+            count_clear(opts);
+        }
+        _ => {
+            let frame = render::single_frame(mode, opts)?;
+            refresh(display, &*frame, opts)?;
+        }
+    }
+    info!("Took {:?}", now.elapsed());
+    Ok(())
+}
+
+// for the panel being drawn around refresh, so the next frame isn't
+// skipped for matching what was there before
+fn forget_last_frame(opts: &Options) {
+    if let Some(last) = &opts.last_frame {
+        last.forget();
+    }
+}
+
+// deghosting clears the panel too, so the next --clear-every clear can wait
+pub fn count_clear(opts: &Options) {
+    if let Some(Err(e)) = opts.refresh_count.as_ref().map(RefreshCount::cleared) {
+        warn!("{e}");
+    }
+}
+
+// a clear before the frame once --clear-every refreshes have gone by
+// since the last one
+fn clear_if_due(display: &mut impl SpiDevice, opts: &Options) -> Result<(), Box<dyn Error>> {
+    let (Some(count), Some(every)) = (&opts.refresh_count, opts.clear_every) else {
+        return Ok(());
+    };
+    let since = match count.read() {
+        Ok(count) => count.since_clear,
+        Err(e) => {
+            warn!("{e}");
+            return Ok(());
+        }
+    };
+    if since < every as u64 {
+        return Ok(());
+    }
+    info!("Clearing the panel after {since} refreshes");
+    cmd::Clear {
+        cycles: 1,
+        // the frame follows
+        options: draw_options(opts).awake(),
+    }
+    .send_retrying(display, opts.retries)?;
+    events::log("cleared", json!({ "refreshes": since }));
+    count_clear(opts);
+    Ok(())
+}
+
+// the panel's temperature and whether it is in the range it refreshes at,
+// warning if not
+pub fn panel_temperature(display: &mut impl SpiDevice) -> Result<(f32, bool), Box<dyn Error>> {
+    let c = cmd::ReadTemperature.read(display)?;
+    let range = display.panel().refresh_range();
+    let ok = range.contains(&c);
+    if !ok {
+        warn!(
+            "The panel is at {c:.1} C, outside the {}..{} C it refreshes well at",
+            range.start(),
+            range.end()
+        );
+    }
+    events::log("temperature", json!({ "celsius": c, "ok": ok }));
+    Ok((c, ok))
+}
+
+// hardware reset and init, also needed to leave deep sleep
+pub fn wake(display: &mut impl SpiDevice, opts: &Options) -> Result<(), Box<dyn Error>> {
+    info!("Reset display");
+    display.reset();
+    display.wait_busy_high()?;
+    info!("Init display");
+    cmd::Init.send_retrying(display, opts.retries)?;
+    Ok(())
+}
+
+pub fn draw_dithered(
+    display: &mut impl SpiDevice,
+    img: &bmp::Image,
+    opts: &Options,
+) -> Result<(), Box<dyn Error>> {
+    refresh(display, &render::dither(img, opts)?, opts)
+}
+
+// --cooldown, --no-power-off, --deep-sleep and the spi transfer flags
+pub fn draw_options(opts: &Options) -> cmd::DrawOptions {
+    cmd::DrawOptions {
+        cooldown: opts.cooldown,
+        power_off: !opts.no_power_off,
+        deep_sleep: opts.deep_sleep,
+        transfer: opts.transfer,
+    }
+}
+
+// sends a frame to the panel with the flip options applied
+pub fn refresh(
+    display: &mut impl SpiDevice,
+    frame: &dyn Drawable,
+    opts: &Options,
+) -> Result<(), Box<dyn Error>> {
+    let lux = render::ambient_lux(opts);
+    #[cfg(feature = "light")]
+    if let (Some(lux), Some(dark)) = (lux, opts.dark_below) {
+        if lux < dark {
+            info!("Skipping refresh, {lux:.1} lux is below {dark}");
+            events::log("refresh_skipped", json!({ "lux": lux }));
+            return Ok(());
+        }
+    }
+    let shown = draw::Flipped {
+        horizontal: opts.flip_h,
+        vertical: opts.flip_v,
+        geometry: display.geometry(),
+        rest: frame,
+    };
+    let last = (opts.last_frame.as_ref()).map(|last| {
+        (
+            last,
+            lastframe::fingerprint(&shown, display.geometry(), display.panel()),
+        )
+    });
+    if let Some((last, fingerprint)) = &last {
+        if last.shows(fingerprint) {
+            info!("Skipping refresh, the panel already shows this frame");
+            events::log("refresh_skipped", json!({ "unchanged": true }));
+            return Ok(());
+        }
+        last.forget();
+    }
+    if opts.check_temperature {
+        // a panel that can't be read still gets its frame
+        match panel_temperature(display) {
+            Ok((c, _)) => debug!("Panel at {c:.1} C"),
+            Err(e) => warn!("Could not read the panel's temperature: {e}"),
+        }
+    }
+    clear_if_due(display, opts)?;
+    events::log("refresh_started", json!({ "lux": lux }));
+    let now = Instant::now();
+    // the transfer a quarter at a time, and each stage
+    let quarter = Cell::new(None);
+    let progress = |progress| match progress {
+        cmd::Progress::Transfer { sent, total } => {
+            let q = sent * 4 / total.max(1);
+            if quarter.replace(Some(q)) != Some(q) {
+                debug!("Sent {sent}/{total} bytes");
+            }
+        }
+        stage => debug!("{stage:?}"),
+    };
+    cmd::Draw {
+        frame: &shown,
+        options: draw_options(opts),
+        progress: Some(&progress),
+        cancel: None,
+    }
+    .send_retrying(display, opts.retries)?;
+    events::log(
+        "refresh_finished",
+        json!({ "duration_ms": now.elapsed().as_millis() as u64 }),
+    );
+    if let Some(Err(e)) = opts.refresh_count.as_ref().map(RefreshCount::bump) {
+        warn!("{e}");
+    }
+    if let Some((last, fingerprint)) = &last {
+        if let Err(e) = last.save(fingerprint) {
+            warn!("{e}");
+        }
+    }
+    // the panel shows the frame either way, a missing thumbnail only warns
+    if opts.thumbnail.is_some() || opts.thumbnail_topic.is_some() {
+        if let Err(e) = write_thumbnail(&shown, opts) {
+            warn!("{e}");
+        }
+    }
+    Ok(())
+}
+
+// 150x112 png of the panel contents for dashboards, written to --thumbnail
+// and published to --thumbnail-topic
+fn write_thumbnail(frame: &dyn Drawable, opts: &Options) -> Result<(), String> {
+    let mut png = Vec::new();
+    preview::thumbnail(frame, 4)
+        .write_to(&mut io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .map_err(|e| format!("could not encode the thumbnail: {e}"))?;
+    if let Some(path) = &opts.thumbnail {
+        write_atomic(path, &png)?;
+        events::log("thumbnail", json!({ "path": path }));
+    }
+    if let Some((broker, topic)) = &opts.thumbnail_topic {
+        // a refresh is minutes apart at the least, not worth a connection
+        // kept open between them
+        let id = format!("rpi-epaper-status-{}", process::id());
+        mqtt::Client::connect(broker, &id)
+            .and_then(|mut c| c.publish(topic, &png, true).and_then(|_| c.disconnect()))
+            .map_err(|e| format!("could not publish the thumbnail to {topic} on {broker}: {e}"))?;
+        events::log("thumbnail", json!({ "topic": topic, "broker": broker }));
+    }
+    Ok(())
+}
+
+// picks the palette and cooldown for the current ambient temperature
+pub fn apply_profile(opts: &mut Options) -> Result<(), Box<dyn Error>> {
+    let Some(path) = &opts.profiles else {
+        return Ok(());
+    };
+    let profiles = profile::load(path)?;
+    let temp = match (&opts.temperature_file, opts.temperature) {
+        (Some(file), _) => profile::read_temperature(file)?,
+        (None, Some(t)) => t,
+        (None, None) => return Err("--profiles needs --temperature or --temperature-file".into()),
+    };
+    let chosen = profile::select(&profiles, temp);
+    info!("Using the {} profile at {temp:.1}C", chosen.name);
+    events::log(
+        "profile",
+        json!({ "name": chosen.name, "temperature": temp }),
+    );
+    // an explicit --palette still wins
+    if opts.palette.is_none() {
+        opts.palette = chosen.palette.clone();
+    }
+    if let Some(cooldown) = chosen.cooldown {
+        opts.cooldown = cooldown;
+    }
+    Ok(())
+}
+
+// a palette from --photo or --set, saved over the calibrated one
+pub fn calibrate(opts: &Options, palette_path: &PathBuf) -> Result<(), Box<dyn Error>> {
+    let palette = match &opts.photo {
+        Some(path) => calibrate::from_photo(&render::load_bmp(path)?),
+        None => calibrate::with_overrides(&opts.palette_overrides)?,
+    };
+    calibrate::save(&palette, palette_path)?;
+    for c in Color::all() {
+        let [r, g, b] = palette[*c as usize];
+        println!("{:>6}: {r:.0} {g:.0} {b:.0}", c.name());
+    }
+    info!("Saved palette to {}", palette_path.display());
+    Ok(())
+}
+
+// prints what would be sent to the panel instead of sending it
+#[cfg(feature = "mock")]
+pub fn print_mock(mode: Option<&Mode>, opts: &Options) -> Result<(), Box<dyn Error>> {
+    let mut device = mock::MockDevice::new();
+    drive(&mut device, mode, opts)?;
+    for t in device.log() {
+        println!("{t}");
+    }
+    Ok(())
+}
+
+// runs the mode against a simulated panel and saves what it ends up
+// showing to `path`
+pub fn simulate(path: &str, mode: Option<&Mode>, opts: &Options) -> Result<(), Box<dyn Error>> {
+    let clock = if opts.sim_realtime {
+        sim::Clock::Real
+    } else {
+        sim::Clock::Virtual(Default::default())
+    };
+    let model = sim::BusyModel {
+        temperature: opts.sim_temperature,
+        ..Default::default()
+    };
+    let mut panel = sim::SimPanel::new(model, clock);
+    panel.recorder = opts.sim_video.clone().map(sim::Recorder::new);
+    drive(&mut panel, mode, opts)?;
+    if let Some(rec) = &panel.recorder {
+        info!("Recorded refreshes to {}", rec.path().display());
+    }
+    for (i, d) in panel.refreshes.iter().enumerate() {
+        info!("Simulated refresh {} took {d:?}", i + 1);
+    }
+    info!("Simulated time {:?}", panel.elapsed());
+    // nothing was drawn to write out
+    if let Some(Mode::Temperature | Mode::Status) = mode {
+        return Ok(());
+    }
+    let frame = sim::shown_frame(&panel).ok_or("the simulated panel never refreshed")?;
+    let mut img = preview::render(frame);
+    if opts.debug_layout {
+        render::annotations(mode, opts)?.draw(&mut img);
+    }
+    preview::save(&img, path)?;
+    info!("Wrote simulated panel to {path}");
+    Ok(())
+}
+
+// runs the mode against the panel in --config, then sleeps it and sets the
+// next --wake-every alarm
+pub fn draw_on_panel(mode: Option<&Mode>, opts: &Options) -> Result<(), Box<dyn Error>> {
+    let mut display = EPaper::open(&opts.panel)?;
+    display.install_panic_hook();
+    display.set_sleep_on_drop(!opts.stay_awake);
+    if opts.offline_screen {
+        let frame = render::dither(&splash::offline(&opts.clock), opts)?;
+        let flipped = PaperImage::from_drawable(&draw::Flipped {
+            horizontal: opts.flip_h,
+            vertical: opts.flip_v,
+            geometry: opts.panel.geometry,
+            rest: &frame,
+        });
+        display.install_shutdown_screen(Box::new(flipped), draw_options(opts));
+        // the panel may be left showing it
+        forget_last_frame(opts);
+    } else if mode.is_some_and(Mode::is_looping) {
+        // the looping modes only end with ctrl-c
+        display.install_shutdown_handler(!opts.stay_awake);
+    }
+    drive(&mut display, mode, opts)?;
+    if !opts.stay_awake && !display.is_asleep() {
+        info!("Putting display to sleep");
+        display.sleep()?;
+    }
+
+    if let Some(every) = opts.wake_every {
+        let (h, m, s) = rtc::set_wake_alarm(every)?;
+        info!("Next wake at {h:02}:{m:02}:{s:02}");
+        if let Some(pin) = opts.power_pin {
+            rtc::signal_power_off(pin, opts.shutdown)?;
+        }
+    }
+
+    Ok(())
+}
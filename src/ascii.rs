@@ -0,0 +1,85 @@
+use crate::{
+    draw::{Color, Drawable},
+    font::{self, ADVANCE, GLYPH_HEIGHT},
+    Rgb, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+// sparse to dense, picked for dark areas on the white background
+const RAMP: &[char] = &[' ', '.', ':', '-', '=', '+', '*', '#', '%', '@'];
+
+struct Cell {
+    c: char,
+    color: Color,
+}
+
+// an image redrawn as a grid of font glyphs chosen by brightness
+pub struct AsciiArt {
+    cells: Vec<Cell>,
+    cols: u16,
+    scale: u16,
+}
+
+impl AsciiArt {
+    fn cell_size(scale: u16) -> (u16, u16) {
+        (ADVANCE * scale, (GLYPH_HEIGHT + 1) * scale)
+    }
+
+    // with `colored` each glyph takes the palette color closest to the
+    // average of its cell, otherwise everything is black
+    pub fn from_image(img: &bmp::Image, scale: u16, colored: bool) -> Self {
+        let (cw, ch) = Self::cell_size(scale);
+        let cols = SCREEN_WIDTH / cw;
+        let rows = SCREEN_HEIGHT / ch;
+        let mut cells = Vec::with_capacity(cols as usize * rows as usize);
+        for row in 0..rows {
+            for col in 0..cols {
+                let mut sum = Rgb {
+                    r: 0.0,
+                    g: 0.0,
+                    b: 0.0,
+                };
+                for x in col * cw..(col + 1) * cw {
+                    for y in row * ch..(row + 1) * ch {
+                        sum += img.get_pixel(x as u32, y as u32).into();
+                    }
+                }
+                let n = (cw * ch) as f32;
+                let avg = Rgb {
+                    r: sum.r / n,
+                    g: sum.g / n,
+                    b: sum.b / n,
+                };
+                let luma = (0.299 * avg.r + 0.587 * avg.g + 0.114 * avg.b) / 255.0;
+                let i = ((1.0 - luma) * (RAMP.len() - 1) as f32).round() as usize;
+                let color = match Color::closest(avg) {
+                    Color::White | Color::Clean => Color::Black,
+                    c if colored => c,
+                    _ => Color::Black,
+                };
+                cells.push(Cell {
+                    c: RAMP[i.min(RAMP.len() - 1)],
+                    color,
+                });
+            }
+        }
+        Self { cells, cols, scale }
+    }
+}
+
+impl Drawable for AsciiArt {
+    fn get_pixel(&self, x: u16, y: u16) -> Color {
+        let (cw, ch) = Self::cell_size(self.scale);
+        let (col, row) = (x / cw, y / ch);
+        if col >= self.cols {
+            return Color::White;
+        }
+        match self.cells.get((row * self.cols + col) as usize) {
+            Some(cell)
+                if font::glyph_pixel(cell.c, (x % cw) / self.scale, (y % ch) / self.scale) =>
+            {
+                cell.color
+            }
+            _ => Color::White,
+        }
+    }
+}
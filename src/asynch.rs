@@ -0,0 +1,318 @@
+// the driver for an async daemon. busy waits poll with tokio's sleep and
+// spi writes and resets run on the blocking pool, so a 30s refresh doesn't
+// stall the runtime:
+//
+//     let mut epd = AsyncEPaper::open(Config::default()).await?;
+//     epd.send(&Init).await?;
+//     epd.send(&Draw {
+//         frame: &image,
+//         options: Default::default(),
+//         progress: None,
+//         cancel: None,
+//     })
+//     .await?;
+//
+// every Command works here too. it is first run against a recorder, then
+// what it sent is played back to the panel, so its own side effects, like
+// Deghost's progress, come before any of it reaches the panel. that goes
+// for a Draw's progress and cancel as well, so neither means much here.
+
+use std::{
+    cell::RefCell,
+    future::Future,
+    panic,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tracing::warn;
+
+use crate::{
+    cmd::{Clear, Command, DeepSleep, DrawOptions, Init, PowerOff, Query},
+    error::{self, EpaperError},
+    panel::Panel,
+    Config, EPaper, Geometry, Hardware, SpiDevice,
+};
+
+// between looks at the busy line
+const POLL: Duration = Duration::from_millis(10);
+
+pub trait AsyncSpiDevice: Send {
+    fn send_cmd(&mut self, cmd: u8) -> impl Future<Output = error::Result<()>> + Send;
+    // owned so it can be handed to another thread
+    fn send_data(&mut self, data: Vec<u8>) -> impl Future<Output = error::Result<()>> + Send;
+    fn wait_busy_high(&self) -> impl Future<Output = error::Result<()>> + Send;
+    fn wait_busy_low(&self) -> impl Future<Output = error::Result<()>> + Send;
+    // hardware reset line
+    fn reset(&mut self) -> impl Future<Output = ()> + Send;
+    fn geometry(&self) -> Geometry;
+    fn panel(&self) -> &'static dyn Panel;
+    fn pause(&self, time: Duration) -> impl Future<Output = ()> + Send {
+        tokio::time::sleep(time)
+    }
+}
+
+pub trait AsyncCommand {
+    fn send_async<D: AsyncSpiDevice>(
+        &self,
+        to: &mut D,
+    ) -> impl Future<Output = error::Result<()>> + Send;
+
+    // like send_async, but a panel that wedges is reset, re-initialized and
+    // sent the command again, up to `retries` more times
+    fn send_retrying_async<D: AsyncSpiDevice>(
+        &self,
+        to: &mut D,
+        retries: u32,
+    ) -> impl Future<Output = error::Result<()>> + Send;
+}
+
+impl<C: Command + ?Sized> AsyncCommand for C {
+    fn send_async<D: AsyncSpiDevice>(
+        &self,
+        to: &mut D,
+    ) -> impl Future<Output = error::Result<()>> + Send {
+        let steps = record(self, to);
+        async move { play(steps?, to).await }
+    }
+
+    fn send_retrying_async<D: AsyncSpiDevice>(
+        &self,
+        to: &mut D,
+        retries: u32,
+    ) -> impl Future<Output = error::Result<()>> + Send {
+        let steps = record(self, to);
+        async move {
+            let steps = steps?;
+            let mut attempt = 0;
+            loop {
+                match play(steps.clone(), to).await {
+                    Err(e) if e.is_recoverable() && attempt < retries => {
+                        attempt += 1;
+                        warn!("{e}, resetting panel (retry {attempt}/{retries})");
+                        to.reset().await;
+                        to.wait_busy_high().await?;
+                        Init.send_async(to).await?;
+                    }
+                    r => return r,
+                }
+            }
+        }
+    }
+}
+
+// one call a command made, in order
+#[derive(Clone)]
+enum Step {
+    Cmd(u8),
+    Data(Vec<u8>),
+    WaitBusyHigh,
+    WaitBusyLow,
+    Reset,
+    Pause(Duration),
+}
+
+// waits and pauses take &self, so the steps are kept like MockDevice's log
+struct Recorder {
+    steps: RefCell<Vec<Step>>,
+    geometry: Geometry,
+    panel: &'static dyn Panel,
+}
+
+impl SpiDevice for Recorder {
+    fn send_cmd(&mut self, cmd: u8) -> error::Result<()> {
+        self.steps.get_mut().push(Step::Cmd(cmd));
+        Ok(())
+    }
+
+    fn send_data(&mut self, data: &[u8]) -> error::Result<()> {
+        self.steps.get_mut().push(Step::Data(data.to_vec()));
+        Ok(())
+    }
+
+    fn wait_busy_high(&self) -> error::Result<()> {
+        self.steps.borrow_mut().push(Step::WaitBusyHigh);
+        Ok(())
+    }
+
+    fn wait_busy_low(&self) -> error::Result<()> {
+        self.steps.borrow_mut().push(Step::WaitBusyLow);
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.steps.get_mut().push(Step::Reset);
+    }
+
+    fn geometry(&self) -> Geometry {
+        self.geometry
+    }
+
+    fn panel(&self) -> &'static dyn Panel {
+        self.panel
+    }
+
+    fn pause(&self, time: Duration) {
+        self.steps.borrow_mut().push(Step::Pause(time));
+    }
+}
+
+fn record<C: Command + ?Sized>(command: &C, to: &impl AsyncSpiDevice) -> error::Result<Vec<Step>> {
+    let mut recorder = Recorder {
+        steps: RefCell::new(Vec::new()),
+        geometry: to.geometry(),
+        panel: to.panel(),
+    };
+    command.send(&mut recorder)?;
+    Ok(recorder.steps.into_inner())
+}
+
+async fn play(steps: Vec<Step>, to: &mut impl AsyncSpiDevice) -> error::Result<()> {
+    for step in steps {
+        match step {
+            Step::Cmd(cmd) => to.send_cmd(cmd).await?,
+            Step::Data(data) => to.send_data(data).await?,
+            Step::WaitBusyHigh => to.wait_busy_high().await?,
+            Step::WaitBusyLow => to.wait_busy_low().await?,
+            Step::Reset => to.reset().await,
+            Step::Pause(time) => to.pause(time).await,
+        }
+    }
+    Ok(())
+}
+
+type Shared = Arc<Mutex<Option<Hardware>>>;
+
+// runs `f` on the blocking pool with the pins locked
+async fn blocking<R: Send + 'static>(
+    hw: &Shared,
+    f: impl FnOnce(&mut Hardware) -> R + Send + 'static,
+) -> R {
+    let hw = Arc::clone(hw);
+    let task = tokio::task::spawn_blocking(move || {
+        let mut hw = hw.lock().unwrap_or_else(|e| e.into_inner());
+        f(hw.as_mut().expect("display was parked after a panic"))
+    });
+    match task.await {
+        Ok(r) => r,
+        Err(e) => panic::resume_unwind(e.into_panic()),
+    }
+}
+
+// EPaper with its waits and writes off the runtime's threads. dropping it
+// still parks the panel in place, blocking for up to 2s.
+pub struct AsyncEPaper {
+    inner: EPaper,
+}
+
+impl AsyncEPaper {
+    pub fn new(inner: EPaper) -> Self {
+        Self { inner }
+    }
+
+    // opens and resets the panel described by `config`
+    pub async fn open(config: Config) -> error::Result<Self> {
+        let task = tokio::task::spawn_blocking(move || EPaper::open(&config));
+        match task.await {
+            Ok(epd) => epd.map(Self::new),
+            Err(e) => panic::resume_unwind(e.into_panic()),
+        }
+    }
+
+    pub fn into_inner(self) -> EPaper {
+        self.inner
+    }
+
+    // the command is recorded right away, so the future doesn't hold on
+    // to it
+    pub fn send<C: Command + ?Sized>(
+        &mut self,
+        command: &C,
+    ) -> impl Future<Output = error::Result<()>> + Send + '_ {
+        let steps = record(command, self);
+        async move { play(steps?, self).await }
+    }
+
+    pub fn is_asleep(&self) -> bool {
+        self.inner.is_asleep()
+    }
+
+    // as EPaper::sleep
+    pub async fn sleep(&mut self) -> error::Result<()> {
+        if self.is_asleep() {
+            return Ok(());
+        }
+        self.send(&PowerOff).await?;
+        self.send(&DeepSleep).await
+    }
+
+    // as EPaper::wake
+    pub async fn wake(&mut self) -> error::Result<()> {
+        AsyncSpiDevice::reset(self).await;
+        AsyncSpiDevice::wait_busy_high(self).await?;
+        self.send(&Init).await
+    }
+
+    // a query can't be recorded ahead like a command, as what it sends can
+    // hang on what it reads, so it runs on the blocking pool as it is
+    pub async fn query<Q>(&mut self, query: Q) -> error::Result<Q::Answer>
+    where
+        Q: Query + Send + 'static,
+        Q::Answer: Send + 'static,
+    {
+        blocking(&self.inner.hw, move |hw| query.read(hw)).await
+    }
+
+    // as EPaper::clear_cycles
+    pub async fn clear_cycles(&mut self, n: u32) -> error::Result<()> {
+        self.send(&Clear {
+            cycles: n,
+            options: DrawOptions::default(),
+        })
+        .await
+    }
+
+    // polls until the busy line reads `high`, or fails once the busy
+    // timeout has passed
+    async fn wait_until(&self, high: bool) -> error::Result<()> {
+        let timeout = self.inner.with_hw(|hw| hw.dev.busy_timeout);
+        let start = Instant::now();
+        while self.inner.with_hw(|hw| hw.dev.busy_is_high())? != high {
+            if start.elapsed() >= timeout {
+                return Err(EpaperError::BusyTimeout(timeout));
+            }
+            tokio::time::sleep(POLL).await;
+        }
+        Ok(())
+    }
+}
+
+impl AsyncSpiDevice for AsyncEPaper {
+    async fn send_cmd(&mut self, cmd: u8) -> error::Result<()> {
+        blocking(&self.inner.hw, move |hw| hw.send_cmd(cmd)).await
+    }
+
+    async fn send_data(&mut self, data: Vec<u8>) -> error::Result<()> {
+        blocking(&self.inner.hw, move |hw| hw.send_data(&data)).await
+    }
+
+    async fn wait_busy_high(&self) -> error::Result<()> {
+        self.wait_until(true).await
+    }
+
+    async fn wait_busy_low(&self) -> error::Result<()> {
+        self.wait_until(false).await
+    }
+
+    async fn reset(&mut self) {
+        blocking(&self.inner.hw, |hw| hw.reset()).await
+    }
+
+    fn geometry(&self) -> Geometry {
+        SpiDevice::geometry(&self.inner)
+    }
+
+    fn panel(&self) -> &'static dyn Panel {
+        SpiDevice::panel(&self.inner)
+    }
+}
@@ -0,0 +1,115 @@
+use std::str::FromStr;
+
+use rppal::i2c::{self, I2c};
+
+use crate::{draw::Color, Rgb};
+
+// 1s li-ion cell, used to estimate a charge level from voltage alone
+const EMPTY_VOLTS: f32 = 3.0;
+const FULL_VOLTS: f32 = 4.2;
+
+#[derive(Clone, Copy)]
+pub enum Gauge {
+    // ina219 current/voltage monitor on the battery rail
+    Ina219,
+    // max17048 fuel gauge, which also reports state of charge
+    Max17048,
+}
+
+impl FromStr for Gauge {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ina219" => Ok(Gauge::Ina219),
+            "max17048" => Ok(Gauge::Max17048),
+            _ => Err(format!(
+                "unknown battery gauge '{s}' (expected ina219 or max17048)"
+            )),
+        }
+    }
+}
+
+pub struct Reading {
+    pub volts: f32,
+    // 0.0-1.0
+    pub charge: f32,
+}
+
+fn read_reg(i2c: &I2c, reg: u8) -> i2c::Result<u16> {
+    let mut buf = [0u8; 2];
+    i2c.write_read(&[reg], &mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+fn estimate_charge(volts: f32) -> f32 {
+    ((volts - EMPTY_VOLTS) / (FULL_VOLTS - EMPTY_VOLTS)).clamp(0.0, 1.0)
+}
+
+impl Gauge {
+    fn address(&self) -> u16 {
+        match self {
+            Gauge::Ina219 => 0x40,
+            Gauge::Max17048 => 0x36,
+        }
+    }
+
+    pub fn read(&self) -> i2c::Result<Reading> {
+        let mut i2c = I2c::new()?;
+        i2c.set_slave_address(self.address())?;
+        Ok(match self {
+            Gauge::Ina219 => {
+                // bus voltage register, bits 15..3 in 4mV steps
+                let volts = (read_reg(&i2c, 0x02)? >> 3) as f32 * 0.004;
+                Reading {
+                    volts,
+                    charge: estimate_charge(volts),
+                }
+            }
+            Gauge::Max17048 => {
+                // VCELL in 78.125uV steps, SOC in 1/256 %
+                let volts = read_reg(&i2c, 0x02)? as f32 * 78.125e-6;
+                let soc = read_reg(&i2c, 0x04)? as f32 / 256.0;
+                Reading {
+                    volts,
+                    charge: (soc / 100.0).clamp(0.0, 1.0),
+                }
+            }
+        })
+    }
+}
+
+// draws a small battery glyph with its fill level in the top right corner
+pub fn draw_glyph(img: &mut bmp::Image, reading: &Reading) {
+    const W: u32 = 28;
+    const H: u32 = 14;
+    const MARGIN: u32 = 6;
+    let x0 = img.get_width().saturating_sub(W + MARGIN + 3);
+    let y0 = MARGIN;
+    let outline: bmp::Pixel = Rgb::from(Color::Black).into();
+    let bg: bmp::Pixel = Rgb::from(Color::White).into();
+    let fill: bmp::Pixel = Rgb::from(if reading.charge < 0.2 {
+        Color::Red
+    } else {
+        Color::Green
+    })
+    .into();
+    let level = 2 + ((W - 4) as f32 * reading.charge) as u32;
+    for x in 0..W {
+        for y in 0..H {
+            let px = if x == 0 || y == 0 || x == W - 1 || y == H - 1 {
+                outline
+            } else if x >= 2 && y >= 2 && x < level && y < H - 2 {
+                fill
+            } else {
+                bg
+            };
+            img.set_pixel(x0 + x, y0 + y, px);
+        }
+    }
+    // terminal nub
+    for x in W..W + 3 {
+        for y in H / 3..H - H / 3 {
+            img.set_pixel(x0 + x, y0 + y, outline);
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::{env, fs, path::PathBuf};
+
+use crate::{
+    draw::{Calibration, Color, PaperImage},
+    floyd_steinberg_dither,
+    layout::{self, Rect},
+    Rgb, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+const COLS: u32 = 4;
+const ROWS: u32 = 2;
+// swatches take the top part of the chart, gradients the rest
+const SWATCH_HEIGHT: u32 = SCREEN_HEIGHT as u32 * 3 / 4;
+
+// default location of the saved palette
+pub fn default_path() -> PathBuf {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+        .unwrap_or_else(|| PathBuf::from("."));
+    base.join("rpi-epaper").join("palette")
+}
+
+// the swatch for the color with device code `i`
+fn swatch(i: u32) -> Rect {
+    let (w, h) = (SCREEN_WIDTH as u32 / COLS, SWATCH_HEIGHT / ROWS);
+    Rect {
+        x: (i % COLS) * w,
+        y: (i / COLS) * h,
+        w,
+        h,
+    }
+}
+
+// swatches of every palette entry over a grey and a hue gradient
+pub fn chart() -> PaperImage {
+    let mut img = layout::blank(Color::White);
+    let (w, h) = (SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+    let ramp_h = (h - SWATCH_HEIGHT) / 2;
+    for x in 0..w {
+        let t = x as f32 / (w - 1) as f32;
+        let grey = (t * 255.0) as u8;
+        // red -> yellow -> green -> blue
+        let (r, g, b) = match t * 3.0 {
+            t if t < 1.0 => (255.0, 255.0 * t, 0.0),
+            t if t < 2.0 => (255.0 * (2.0 - t), 255.0, 0.0),
+            t => (0.0, 255.0 * (3.0 - t), 255.0 * (t - 2.0)),
+        };
+        for y in 0..ramp_h {
+            img.set_pixel(x, SWATCH_HEIGHT + y, bmp::Pixel::new(grey, grey, grey));
+            img.set_pixel(
+                x,
+                SWATCH_HEIGHT + ramp_h + y,
+                bmp::Pixel::new(r as u8, g as u8, b as u8),
+            );
+        }
+    }
+    let mut frame = floyd_steinberg_dither(&img);
+    for c in Color::all() {
+        let rect = swatch(*c as u32);
+        for y in rect.y..rect.y + rect.h {
+            for x in rect.x..rect.x + rect.w {
+                frame.data[x as usize + y as usize * SCREEN_WIDTH as usize] = *c;
+            }
+        }
+    }
+    frame
+}
+
+// averages the middle of each swatch in a photo of the chart. the photo
+// should be cropped to the panel's active area; any size works.
+pub fn from_photo(photo: &bmp::Image) -> Calibration {
+    let sx = photo.get_width() as f32 / SCREEN_WIDTH as f32;
+    let sy = photo.get_height() as f32 / SCREEN_HEIGHT as f32;
+    let mut palette = [[0.0; 3]; 8];
+    for (i, entry) in palette.iter_mut().enumerate() {
+        let r = swatch(i as u32);
+        // skip the outer quarter to avoid edge bleed and crop error
+        let x0 = ((r.x + r.w / 4) as f32 * sx) as u32;
+        let x1 = ((r.x + r.w * 3 / 4) as f32 * sx) as u32;
+        let y0 = ((r.y + r.h / 4) as f32 * sy) as u32;
+        let y1 = ((r.y + r.h * 3 / 4) as f32 * sy) as u32;
+        let mut sum = Rgb {
+            r: 0.0,
+            g: 0.0,
+            b: 0.0,
+        };
+        let mut n = 0.0;
+        for x in x0..x1.max(x0 + 1).min(photo.get_width()) {
+            for y in y0..y1.max(y0 + 1).min(photo.get_height()) {
+                sum += photo.get_pixel(x, y).into();
+                n += 1.0;
+            }
+        }
+        if n > 0.0 {
+            *entry = [sum.r / n, sum.g / n, sum.b / n];
+        }
+    }
+    palette
+}
+
+// current palette, with "name=r,g,b" overrides applied
+pub fn with_overrides(entries: &[String]) -> Result<Calibration, String> {
+    let mut palette = current();
+    for entry in entries {
+        let (name, rgb) = entry.split_once('=').ok_or(format!(
+            "invalid palette entry '{entry}', expected name=r,g,b"
+        ))?;
+        let color: Color = name.parse()?;
+        let parts = rgb
+            .split(',')
+            .map(|p| p.trim().parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("invalid rgb in '{entry}': {e}"))?;
+        match parts[..] {
+            [r, g, b] => palette[color as usize] = [r, g, b],
+            _ => return Err(format!("invalid rgb in '{entry}', expected r,g,b")),
+        }
+    }
+    Ok(palette)
+}
+
+fn current() -> Calibration {
+    let mut palette = [[0.0; 3]; 8];
+    for c in Color::all() {
+        palette[*c as usize] = c.as_rgb();
+    }
+    palette
+}
+
+// one "name r g b" line per color
+pub fn save(palette: &Calibration, path: &PathBuf) -> Result<(), String> {
+    let mut out = String::new();
+    for c in Color::all() {
+        let [r, g, b] = palette[*c as usize];
+        out += &format!("{} {} {} {}\n", c.name(), r.round(), g.round(), b.round());
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("could not create {}: {e}", dir.display()))?;
+    }
+    fs::write(path, out).map_err(|e| format!("could not write {}: {e}", path.display()))
+}
+
+// colors missing from the file keep their nominal values
+pub fn load(path: &PathBuf) -> Result<Calibration, String> {
+    let text =
+        fs::read_to_string(path).map_err(|e| format!("could not read {}: {e}", path.display()))?;
+    let mut palette = [[0.0; 3]; 8];
+    for c in Color::all() {
+        palette[*c as usize] = c.nominal_rgb();
+    }
+    for line in text.lines().filter(|l| !l.trim().is_empty()) {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let [name, r, g, b] = parts[..] else {
+            return Err(format!("invalid palette line '{line}'"));
+        };
+        let color: Color = name.parse()?;
+        let parse = |v: &str| {
+            v.parse::<f32>()
+                .map_err(|e| format!("invalid palette line '{line}': {e}"))
+        };
+        palette[color as usize] = [parse(r)?, parse(g)?, parse(b)?];
+    }
+    Ok(palette)
+}
@@ -0,0 +1,469 @@
+// a room climate display: temperature and humidity from a local i2c sensor,
+// with the last day plotted under the current reading. the history lives in
+// a small file so a restart doesn't wipe the chart.
+
+use std::{
+    collections::VecDeque,
+    fs, io,
+    path::PathBuf,
+    str::FromStr,
+    thread::sleep,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use rppal::i2c::{self, I2c};
+use tracing::warn;
+
+use crate::{
+    draw::Color, font, layout, localtime::Clock, source::ImageSource, write_atomic, Rgb,
+    SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+// one sample every 5 minutes, a day of them kept
+pub const SAMPLE: Duration = Duration::from_secs(5 * 60);
+const WINDOW: u64 = 24 * 60 * 60;
+const CAPACITY: usize = (WINDOW / SAMPLE.as_secs()) as usize;
+// the panel is redrawn less often than it is sampled. a 7 color refresh
+// every 5 minutes would be all the panel ever does.
+const REDRAW: Duration = Duration::from_secs(30 * 60);
+
+#[derive(Clone, Copy)]
+pub enum Sensor {
+    // sht31, temperature and humidity with a crc on each
+    Sht31,
+    // bme280, needs its factory calibration read back to make sense of the
+    // raw values. the pressure reading is ignored.
+    Bme280,
+}
+
+impl FromStr for Sensor {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sht31" => Ok(Sensor::Sht31),
+            "bme280" => Ok(Sensor::Bme280),
+            _ => Err(format!(
+                "unknown climate sensor '{s}' (expected sht31 or bme280)"
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Reading {
+    // degrees celsius
+    pub temperature: f32,
+    // relative, 0-100%
+    pub humidity: f32,
+}
+
+// crc-8 over each 16 bit word, polynomial 0x31 starting from 0xff
+fn sht31_crc(data: &[u8]) -> u8 {
+    let mut crc = 0xFFu8;
+    for b in data {
+        crc ^= b;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x31
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+fn corrupt(what: &str) -> i2c::Error {
+    i2c::Error::Io(io::Error::new(io::ErrorKind::InvalidData, what.to_string()))
+}
+
+impl Sensor {
+    fn address(&self) -> u16 {
+        match self {
+            Sensor::Sht31 => 0x44,
+            Sensor::Bme280 => 0x76,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Sensor::Sht31 => "sht31",
+            Sensor::Bme280 => "bme280",
+        }
+    }
+
+    // takes a single measurement
+    pub fn read(&self) -> i2c::Result<Reading> {
+        let mut i2c = I2c::new()?;
+        i2c.set_slave_address(self.address())?;
+        match self {
+            Sensor::Sht31 => {
+                // single shot, high repeatability, no clock stretching
+                i2c.write(&[0x24, 0x00])?;
+                sleep(Duration::from_millis(20));
+                let mut buf = [0u8; 6];
+                i2c.read(&mut buf)?;
+                if sht31_crc(&buf[0..2]) != buf[2] || sht31_crc(&buf[3..5]) != buf[5] {
+                    return Err(corrupt("sht31 reading failed its crc"));
+                }
+                let t = u16::from_be_bytes([buf[0], buf[1]]) as f32;
+                let rh = u16::from_be_bytes([buf[3], buf[4]]) as f32;
+                Ok(Reading {
+                    temperature: -45.0 + 175.0 * t / 65535.0,
+                    humidity: 100.0 * rh / 65535.0,
+                })
+            }
+            Sensor::Bme280 => read_bme280(&mut i2c),
+        }
+    }
+}
+
+// the float compensation from section 8.1 of the datasheet
+fn read_bme280(i2c: &mut I2c) -> i2c::Result<Reading> {
+    let mut id = [0u8];
+    i2c.write_read(&[0xD0], &mut id)?;
+    if id[0] != 0x60 {
+        return Err(corrupt(&format!("chip id {:#04x} is not a bme280", id[0])));
+    }
+    let mut tp = [0u8; 26];
+    let mut h = [0u8; 7];
+    i2c.write_read(&[0x88], &mut tp)?;
+    i2c.write_read(&[0xE1], &mut h)?;
+    let t1 = u16::from_le_bytes([tp[0], tp[1]]) as f64;
+    let t2 = i16::from_le_bytes([tp[2], tp[3]]) as f64;
+    let t3 = i16::from_le_bytes([tp[4], tp[5]]) as f64;
+    let h1 = tp[25] as f64;
+    let h2 = i16::from_le_bytes([h[0], h[1]]) as f64;
+    let h3 = h[2] as f64;
+    // two 12 bit values sharing the nibbles of 0xe5
+    let h4 = ((h[3] as i8 as i16) << 4 | (h[4] & 0x0F) as i16) as f64;
+    let h5 = ((h[5] as i8 as i16) << 4 | (h[4] >> 4) as i16) as f64;
+    let h6 = h[6] as i8 as f64;
+
+    // humidity and temperature oversampling x1, then a forced measurement.
+    // ctrl_hum only takes effect on the write to ctrl_meas after it.
+    i2c.write(&[0xF2, 0x01])?;
+    i2c.write(&[0xF4, 0x25])?;
+    sleep(Duration::from_millis(20));
+    let mut raw = [0u8; 8];
+    i2c.write_read(&[0xF7], &mut raw)?;
+    let adc_t = ((raw[3] as u32) << 12 | (raw[4] as u32) << 4 | (raw[5] as u32) >> 4) as f64;
+    let adc_h = u16::from_be_bytes([raw[6], raw[7]]) as f64;
+
+    let var1 = (adc_t / 16384.0 - t1 / 1024.0) * t2;
+    let var2 = (adc_t / 131072.0 - t1 / 8192.0).powi(2) * t3;
+    let t_fine = var1 + var2;
+
+    let x = t_fine - 76800.0;
+    let mut rh = (adc_h - (h4 * 64.0 + h5 / 16384.0 * x))
+        * (h2 / 65536.0 * (1.0 + h6 / 67108864.0 * x * (1.0 + h3 / 67108864.0 * x)));
+    rh *= 1.0 - h1 * rh / 524288.0;
+    Ok(Reading {
+        temperature: (t_fine / 5120.0) as f32,
+        humidity: rh.clamp(0.0, 100.0) as f32,
+    })
+}
+
+#[derive(Clone, Copy)]
+pub struct Sample {
+    // unix seconds
+    pub time: u64,
+    pub reading: Reading,
+}
+
+// the last day of samples, oldest first. saved as one `time temperature
+// humidity` line per sample.
+pub struct History {
+    path: PathBuf,
+    samples: VecDeque<Sample>,
+}
+
+impl History {
+    // a missing file is an empty history
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, String> {
+        let path = path.into();
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("could not read {}: {e}", path.display())),
+        };
+        let mut history = Self {
+            path,
+            samples: VecDeque::with_capacity(CAPACITY),
+        };
+        for (n, line) in text.lines().enumerate() {
+            let parse = || -> Option<Sample> {
+                let mut fields = line.split_whitespace();
+                let sample = Sample {
+                    time: fields.next()?.parse().ok()?,
+                    reading: Reading {
+                        temperature: fields.next()?.parse().ok()?,
+                        humidity: fields.next()?.parse().ok()?,
+                    },
+                };
+                fields.next().is_none().then_some(sample)
+            };
+            let sample = parse().ok_or_else(|| {
+                format!(
+                    "{} line {}: malformed sample",
+                    history.path.display(),
+                    n + 1
+                )
+            })?;
+            history.push(sample);
+        }
+        Ok(history)
+    }
+
+    // samples more than a day older than the newest fall off the front
+    pub fn push(&mut self, sample: Sample) {
+        self.samples.push_back(sample);
+        while self.samples.len() > CAPACITY
+            || self
+                .samples
+                .front()
+                .is_some_and(|s| s.time + WINDOW < sample.time)
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn samples(&self) -> &VecDeque<Sample> {
+        &self.samples
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let text: String = self
+            .samples
+            .iter()
+            .map(|s| {
+                format!(
+                    "{} {:.2} {:.2}\n",
+                    s.time, s.reading.temperature, s.reading.humidity
+                )
+            })
+            .collect();
+        write_atomic(&self.path, text.as_bytes())
+    }
+}
+
+// the plot area, with room for the axis labels around it
+const PLOT: layout::Rect = layout::Rect {
+    x: 70,
+    y: 150,
+    w: 460,
+    h: 250,
+};
+const TEMPERATURE: Color = Color::Red;
+const HUMIDITY: Color = Color::Blue;
+
+fn pixel(color: Color) -> bmp::Pixel {
+    Rgb::from(color).into()
+}
+
+// a 2px wide line, clipped to the screen
+fn line(img: &mut bmp::Image, (x0, y0): (i32, i32), (x1, y1): (i32, i32), color: Color) {
+    let px = pixel(color);
+    let steps = (x1 - x0).abs().max((y1 - y0).abs()).max(1);
+    for i in 0..=steps {
+        let x = x0 + (x1 - x0) * i / steps;
+        let y = y0 + (y1 - y0) * i / steps;
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let (x, y) = (x + dx, y + dy);
+            if (0..SCREEN_WIDTH as i32).contains(&x) && (0..SCREEN_HEIGHT as i32).contains(&y) {
+                img.set_pixel(x as u32, y as u32, px);
+            }
+        }
+    }
+}
+
+// the range a series is plotted over, padded and never narrower than
+// `min_span` so a steady reading doesn't turn noise into cliffs
+fn range(values: impl Iterator<Item = f32>, min_span: f32) -> (f32, f32) {
+    let (lo, hi) = values.fold((f32::MAX, f32::MIN), |(lo, hi), v| (lo.min(v), hi.max(v)));
+    if lo > hi {
+        return (0.0, min_span);
+    }
+    let pad = ((min_span - (hi - lo)) / 2.0).max(0.0);
+    ((lo - pad).floor(), (hi + pad).ceil())
+}
+
+fn right_aligned(img: &mut bmp::Image, text: &str, right: u16, y: u16, scale: u16, color: Color) {
+    let (w, _) = font::text_size(text, scale);
+    font::draw_text(img, text, right.saturating_sub(w), y, scale, pixel(color));
+}
+
+// the newest reading large over the last day, temperature on the left axis
+// and humidity on the right. samples further apart than two intervals are
+// left unjoined, so downtime shows as a gap.
+pub fn chart(history: &History, clock: &Clock) -> bmp::Image {
+    let mut img = layout::blank(Color::White);
+    let samples = history.samples();
+    let Some(newest) = samples.back() else {
+        let (w, _) = font::text_size("no readings yet", 4);
+        let x = SCREEN_WIDTH.saturating_sub(w) / 2;
+        font::draw_text(&mut img, "no readings yet", x, 200, 4, pixel(Color::Black));
+        return img;
+    };
+    let now = newest.reading;
+    font::draw_text(
+        &mut img,
+        &format!("{:.1}C", now.temperature),
+        30,
+        30,
+        8,
+        pixel(TEMPERATURE),
+    );
+    right_aligned(
+        &mut img,
+        &format!("{:.0}%", now.humidity),
+        SCREEN_WIDTH - 30,
+        30,
+        8,
+        HUMIDITY,
+    );
+    right_aligned(
+        &mut img,
+        &format!("at {}", clock.format_at(newest.time as i64)),
+        SCREEN_WIDTH - 30,
+        100,
+        2,
+        Color::Black,
+    );
+
+    let (t_lo, t_hi) = range(samples.iter().map(|s| s.reading.temperature), 4.0);
+    let (h_lo, h_hi) = range(samples.iter().map(|s| s.reading.humidity), 20.0);
+    let (left, top) = (PLOT.x as i32, PLOT.y as i32);
+    let (right, bottom) = (left + PLOT.w as i32, top + PLOT.h as i32);
+    let start = newest.time.saturating_sub(WINDOW);
+    let x_at =
+        |t: u64| left + ((t.saturating_sub(start)) as f32 / WINDOW as f32 * PLOT.w as f32) as i32;
+    let y_at = |v: f32, lo: f32, hi: f32| bottom - ((v - lo) / (hi - lo) * PLOT.h as f32) as i32;
+
+    let black = pixel(Color::Black);
+    for x in left..=right {
+        img.set_pixel(x as u32, bottom as u32, black);
+    }
+    for y in top..=bottom {
+        img.set_pixel(left as u32, y as u32, black);
+        img.set_pixel(right as u32, y as u32, black);
+    }
+    // the ends of each axis
+    font::draw_text(
+        &mut img,
+        &format!("{t_hi:.0}"),
+        8,
+        top as u16,
+        2,
+        pixel(TEMPERATURE),
+    );
+    font::draw_text(
+        &mut img,
+        &format!("{t_lo:.0}"),
+        8,
+        bottom as u16 - 14,
+        2,
+        pixel(TEMPERATURE),
+    );
+    right_aligned(
+        &mut img,
+        &format!("{h_hi:.0}%"),
+        SCREEN_WIDTH - 8,
+        top as u16,
+        2,
+        HUMIDITY,
+    );
+    right_aligned(
+        &mut img,
+        &format!("{h_lo:.0}%"),
+        SCREEN_WIDTH - 8,
+        bottom as u16 - 14,
+        2,
+        HUMIDITY,
+    );
+    // a tick and the local time every 6 hours
+    for k in 0..=4 {
+        let t = start + k * WINDOW / 4;
+        let x = x_at(t);
+        for y in bottom..bottom + 6 {
+            img.set_pixel(x as u32, y as u32, black);
+        }
+        let label = clock.format_at(t as i64);
+        let (w, _) = font::text_size(&label, 2);
+        let lx = (x as u16).saturating_sub(w / 2).min(SCREEN_WIDTH - w);
+        font::draw_text(&mut img, &label, lx, bottom as u16 + 12, 2, black);
+    }
+
+    let gap = 2 * SAMPLE.as_secs();
+    for (a, b) in samples.iter().zip(samples.iter().skip(1)) {
+        if b.time.saturating_sub(a.time) > gap {
+            continue;
+        }
+        let (xa, xb) = (x_at(a.time), x_at(b.time));
+        line(
+            &mut img,
+            (xa, y_at(a.reading.humidity, h_lo, h_hi)),
+            (xb, y_at(b.reading.humidity, h_lo, h_hi)),
+            HUMIDITY,
+        );
+        line(
+            &mut img,
+            (xa, y_at(a.reading.temperature, t_lo, t_hi)),
+            (xb, y_at(b.reading.temperature, t_lo, t_hi)),
+            TEMPERATURE,
+        );
+    }
+    img
+}
+
+// samples the sensor on every interval and redraws the chart every so often
+pub struct Station {
+    sensor: Sensor,
+    history: History,
+    clock: Clock,
+    drawn: Option<Instant>,
+}
+
+impl Station {
+    pub fn new(sensor: Sensor, history: History, clock: Clock) -> Self {
+        Self {
+            sensor,
+            history,
+            clock,
+            drawn: None,
+        }
+    }
+}
+
+impl ImageSource for Station {
+    fn name(&self) -> String {
+        format!("{} climate", self.sensor.name())
+    }
+
+    fn next_frame(&mut self) -> Result<Option<bmp::Image>, String> {
+        let reading = match self.sensor.read() {
+            Ok(r) => r,
+            // a glitch on the bus shouldn't take a running display down
+            Err(e) if self.drawn.is_some() => {
+                warn!("could not read {}: {e}", self.sensor.name());
+                return Ok(None);
+            }
+            Err(e) => return Err(format!("could not read {}: {e}", self.sensor.name())),
+        };
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.history.push(Sample { time, reading });
+        self.history.save()?;
+        if self.drawn.is_some_and(|t| t.elapsed() < REDRAW) {
+            return Ok(None);
+        }
+        self.drawn = Some(Instant::now());
+        Ok(Some(chart(&self.history, &self.clock)))
+    }
+
+    fn interval(&self) -> Option<Duration> {
+        Some(SAMPLE)
+    }
+}
@@ -1,10 +1,16 @@
-use std::{thread::sleep, time::Duration};
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+};
 
-use rppal::spi;
+use tracing::{info_span, warn};
 
 use crate::{
-    draw::{Color, Drawable},
-    SpiDevice, SCREEN_HEIGHT, SCREEN_WIDTH,
+    draw::{Color, Drawable, PaletteIndex, SolidColor},
+    error::{EpaperError, Result},
+    panel::Plane,
+    Geometry, SpiDevice,
 };
 
 fn to_bit(f: bool, bit: u8) -> u8 {
@@ -16,7 +22,32 @@ fn to_bit(f: bool, bit: u8) -> u8 {
 }
 
 pub trait Command {
-    fn send(&self, to: &mut impl SpiDevice) -> spi::Result<()>;
+    fn send(&self, to: &mut impl SpiDevice) -> Result<()>;
+
+    // like send, but a panel that wedges is reset, re-initialized and sent
+    // the command again, up to `retries` more times
+    fn send_retrying(&self, to: &mut impl SpiDevice, retries: u32) -> Result<()> {
+        let mut attempt = 0;
+        loop {
+            match self.send(to) {
+                Err(e) if e.is_recoverable() && attempt < retries => {
+                    attempt += 1;
+                    warn!("{e}, resetting panel (retry {attempt}/{retries})");
+                    to.reset();
+                    to.wait_busy_high()?;
+                    Init.send(to)?;
+                }
+                r => return r,
+            }
+        }
+    }
+}
+
+// a command the controller answers
+pub trait Query {
+    type Answer;
+
+    fn read(&self, to: &mut impl SpiDevice) -> Result<Self::Answer>;
 }
 
 pub struct PanelSetting {
@@ -29,128 +60,475 @@ pub struct PanelSetting {
     // reset (yes / no)
     pub rst_n: bool,
 }
+// PanelSetting for the black/white/red controllers, in their tri-color
+// mode with the waveforms from otp
+pub struct KwrPanelSetting(pub PanelSetting);
 pub struct InternalPower;
 pub struct PowerOffSequence;
 pub struct BoosterSoftStart;
 pub struct PLLControl;
 pub struct TempSensor;
+// the panel's own sensor, in C. it is read through the data line, which
+// the waveshare hat only wires one way.
+pub struct ReadTemperature;
+// the controller's flags, read the same way
+pub struct GetStatus;
+// the flag byte GetStatus answers with
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Status(pub u8);
 pub struct VCOMDataInterval {
     pub border_output: Color,
 }
 pub struct Unknown6022;
 pub struct SetResolution;
 pub struct UnknownE3AA;
-pub struct Draw<'a, T: Drawable>(pub &'a T);
+pub struct Draw<'a, T: Drawable + ?Sized> {
+    pub frame: &'a T,
+    pub options: DrawOptions,
+    // told how far the draw has got
+    pub progress: Option<&'a dyn Fn(Progress)>,
+    pub cancel: Option<&'a CancelToken>,
+}
+// the stages of a Draw, in order
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Progress {
+    // bytes of the frame sent so far, before each chunk and once more at
+    // the end
+    Transfer { sent: usize, total: usize },
+    // about 30s until Refreshed
+    Refreshing,
+    Refreshed,
+    Done,
+}
+// set from any thread to stop a Draw between chunks or before it
+// refreshes. the panel is powered off and the draw fails with Cancelled. a
+// refresh that has started runs to the end, as cutting one short can leave
+// the panel streaked.
+#[derive(Default)]
+pub struct CancelToken(AtomicBool);
+// loads a frame into the panel's ram without showing it
+pub struct Upload<'a, T: Drawable + ?Sized> {
+    pub frame: &'a T,
+    pub transfer: Transfer,
+}
+// a Draw that only sends the pixels inside its window, see Draw::partial
+pub struct PartialDraw<'a, T: Drawable + ?Sized> {
+    pub draw: Draw<'a, T>,
+    pub window: PartialWindow,
+}
+// what happens after the refresh completes
+#[derive(Clone, Copy)]
+pub struct DrawOptions {
+    // wait after the panel is done, before the next command
+    pub cooldown: Duration,
+    // turn the dc-dc converters off
+    pub power_off: bool,
+    // enter deep sleep afterwards. the panel needs a hardware reset and a
+    // fresh Init before it accepts another frame.
+    pub deep_sleep: bool,
+    pub transfer: Transfer,
+}
+// how the frame is pushed over spi. the bus is free for other devices
+// between chunks, and the display lock is released with it.
+#[derive(Clone, Copy)]
+pub struct Transfer {
+    // bytes per write. the frame is packed up front and sent a chunk at a
+    // time rather than a byte at a time, and a chunk over spidev's bufsiz
+    // (4096 by default) is split again when it is written.
+    pub chunk: usize,
+    // wait between chunks, zero only yields the thread
+    pub pause: Duration,
+}
+// full panel fills of every color to clear ghosting after long static
+// display. `progress` is called before each fill with (step, total, color).
+pub struct Deghost<'a> {
+    pub cycles: u32,
+    pub progress: &'a dyn Fn(u32, u32, Color),
+    // for every fill, though only the last one deep sleeps
+    pub options: DrawOptions,
+}
+// the quick clear between regular refreshes: `cycles` full panel fills of
+// Clean, or of black then white on a panel without it
+pub struct Clear {
+    pub cycles: u32,
+    // as for Deghost
+    pub options: DrawOptions,
+}
+
+// data written between PartialIn and PartialOut only lands inside the
+// window. the controller works in whole bytes of 8 px across, so x and w are
+// widened to multiples of 8.
+#[derive(Clone, Copy)]
+pub struct PartialWindow {
+    pub x: u16,
+    pub y: u16,
+    pub w: u16,
+    pub h: u16,
+}
+pub struct PartialIn;
+pub struct PartialOut;
 
 pub struct PowerOn;
 pub struct DisplayRefresh;
+// kicks off a refresh without waiting for it, to start several panels at once
+pub struct StartRefresh;
 pub struct PowerOff;
+pub struct DeepSleep;
 
 pub struct Init;
 
+// whatever the attached panel needs to come up
 impl Command for Init {
-    fn send(&self, to: &mut impl SpiDevice) -> spi::Result<()> {
-        // init
-        PanelSetting::default().send(to)?;
-        InternalPower.send(to)?;
-        PowerOffSequence.send(to)?;
-        BoosterSoftStart.send(to)?;
-        PLLControl.send(to)?;
-        TempSensor.send(to)?;
-        VCOMDataInterval {
-            border_output: Color::Clean,
-        }
-        .send(to)?;
-        Unknown6022.send(to)?;
-        SetResolution.send(to)?;
-        UnknownE3AA.send(to)?;
-        sleep(Duration::from_millis(100));
-        VCOMDataInterval {
-            border_output: Color::Clean,
-        }
-        .send(to)?;
-        Ok(())
+    fn send(&self, to: &mut impl SpiDevice) -> Result<()> {
+        let _span = info_span!("init", panel = to.panel().name()).entered();
+        to.panel().init(to)
     }
 }
 
 impl Command for PowerOff {
-    fn send(&self, to: &mut impl SpiDevice) -> spi::Result<()> {
+    fn send(&self, to: &mut impl SpiDevice) -> Result<()> {
         to.send_cmd(0x02)?;
-        to.wait_busy_low();
+        to.wait_busy_low()?;
         Ok(())
     }
 }
 
 impl Command for DisplayRefresh {
-    fn send(&self, to: &mut impl SpiDevice) -> spi::Result<()> {
-        to.send_cmd(0x12)?;
-        to.wait_busy_high();
+    fn send(&self, to: &mut impl SpiDevice) -> Result<()> {
+        let _span = info_span!("refresh").entered();
+        StartRefresh.send(to)?;
+        to.wait_busy_high()?;
         Ok(())
     }
 }
 
+impl Command for StartRefresh {
+    fn send(&self, to: &mut impl SpiDevice) -> Result<()> {
+        to.send_cmd(0x12)
+    }
+}
+
+impl Command for DeepSleep {
+    fn send(&self, to: &mut impl SpiDevice) -> Result<()> {
+        to.send_cmd(0x07)?;
+        // check code
+        to.send_data(&[0xA5])
+    }
+}
+
 impl Command for PowerOn {
-    fn send(&self, to: &mut impl SpiDevice) -> spi::Result<()> {
+    fn send(&self, to: &mut impl SpiDevice) -> Result<()> {
         to.send_cmd(0x04)?;
-        to.wait_busy_high();
+        to.wait_busy_high()?;
         Ok(())
     }
 }
 
-impl<D: Drawable> Command for Draw<'_, D> {
-    fn send(&self, to: &mut impl SpiDevice) -> spi::Result<()> {
-        SetResolution.send(to)?;
-        // each byte fits 2 px
-        to.send_cmd(0x10)?;
-        for y in 0..SCREEN_HEIGHT {
-            for x in 0..SCREEN_WIDTH / 2 {
-                let c1 = self.0.get_pixel(x * 2, y) as u8;
-                let c2 = self.0.get_pixel(x * 2 + 1, y) as u8;
-                let d = (c1 << 4) | c2;
-                to.send_data(&[d])?;
-            }
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// a Draw's progress and cancel, handed down to the transfer
+#[derive(Clone, Copy, Default)]
+struct Watch<'a> {
+    progress: Option<&'a dyn Fn(Progress)>,
+    cancel: Option<&'a CancelToken>,
+}
+
+impl Watch<'_> {
+    fn report(&self, progress: Progress) {
+        if let Some(f) = self.progress {
+            f(progress);
+        }
+    }
+
+    fn check(&self) -> Result<()> {
+        match self.cancel.is_some_and(CancelToken::is_cancelled) {
+            true => Err(EpaperError::Cancelled),
+            false => Ok(()),
         }
+    }
+
+    // powers up and refreshes unless cancelled first
+    fn refresh(&self, to: &mut impl SpiDevice) -> Result<()> {
+        self.check()?;
         PowerOn.send(to)?;
+        self.check()?;
+        self.report(Progress::Refreshing);
         DisplayRefresh.send(to)?;
-        PowerOff.send(to)?;
-        sleep(Duration::from_millis(200));
+        self.report(Progress::Refreshed);
         Ok(())
     }
 }
 
+impl<D: Drawable + ?Sized> Command for Draw<'_, D> {
+    fn send(&self, to: &mut impl SpiDevice) -> Result<()> {
+        let watch = self.watch();
+        let shown =
+            upload(to, self.frame, self.options.transfer, watch).and_then(|_| watch.refresh(to));
+        if let Err(EpaperError::Cancelled) = shown {
+            PowerOff.send(to)?;
+        }
+        shown?;
+        if self.options.power_off {
+            PowerOff.send(to)?;
+        }
+        to.pause(self.options.cooldown);
+        if self.options.deep_sleep {
+            DeepSleep.send(to)?;
+        }
+        watch.report(Progress::Done);
+        Ok(())
+    }
+}
+
+impl<'a, T: Drawable + ?Sized> Draw<'a, T> {
+    fn watch(&self) -> Watch<'a> {
+        Watch {
+            progress: self.progress,
+            cancel: self.cancel,
+        }
+    }
+
+    // only streams the pixels of `frame` inside the window, for updating a
+    // small widget without sending the other 99% of the panel again. the
+    // rest of the panel keeps what it showed. fails on a panel without
+    // partial windows, see Panel::supports_partial.
+    pub fn partial(self, x: u16, y: u16, w: u16, h: u16) -> PartialDraw<'a, T> {
+        PartialDraw {
+            draw: self,
+            window: PartialWindow { x, y, w, h },
+        }
+    }
+}
+
+impl<D: Drawable + ?Sized> Command for PartialDraw<'_, D> {
+    fn send(&self, to: &mut impl SpiDevice) -> Result<()> {
+        if !to.panel().supports_partial() {
+            return Err(EpaperError::Io(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!("the {} can't refresh part of the panel", to.panel().name()),
+            )));
+        }
+        let (x, y, w, h) = self.window.aligned(to.geometry());
+        if w == 0 || h == 0 {
+            return Ok(());
+        }
+        let options = &self.draw.options;
+        let watch = self.draw.watch();
+        PartialIn.send(to)?;
+        let shown = self.window.send(to).and_then(|_| {
+            let planes = to.panel().pack(&self.draw.frame, x, y, w, h);
+            send_planes(to, &planes, options.transfer, watch).and_then(|_| watch.refresh(to))
+        });
+        // left in partial mode, the next full draw would only reach the window
+        let out = PartialOut.send(to);
+        if let Err(EpaperError::Cancelled) = shown {
+            PowerOff.send(to)?;
+        }
+        shown.and(out)?;
+        if options.power_off {
+            PowerOff.send(to)?;
+        }
+        to.pause(options.cooldown);
+        if options.deep_sleep {
+            DeepSleep.send(to)?;
+        }
+        watch.report(Progress::Done);
+        Ok(())
+    }
+}
+
+fn send_planes(
+    to: &mut impl SpiDevice,
+    planes: &[Plane],
+    transfer: Transfer,
+    watch: Watch,
+) -> Result<()> {
+    let total: usize = planes.iter().map(|p| p.data.len()).sum();
+    let _span = info_span!("transfer", bytes = total).entered();
+    let mut sent = 0;
+    for plane in planes {
+        to.send_cmd(plane.cmd)?;
+        for (i, chunk) in plane.data.chunks(transfer.chunk.max(1)).enumerate() {
+            if i > 0 {
+                if transfer.pause.is_zero() {
+                    thread::yield_now();
+                } else {
+                    to.pause(transfer.pause);
+                }
+            }
+            watch.report(Progress::Transfer { sent, total });
+            watch.check()?;
+            to.send_data(chunk)?;
+            sent += chunk.len();
+        }
+    }
+    watch.report(Progress::Transfer { sent, total });
+    Ok(())
+}
+
+// the whole frame into the panel's ram
+fn upload(
+    to: &mut impl SpiDevice,
+    frame: &(impl Drawable + ?Sized),
+    transfer: Transfer,
+    watch: Watch,
+) -> Result<()> {
+    SetResolution.send(to)?;
+    let geometry = to.geometry();
+    let planes = to
+        .panel()
+        .pack(&frame, 0, 0, geometry.width, geometry.height);
+    send_planes(to, &planes, transfer, watch)
+}
+
+impl<D: Drawable + ?Sized> Command for Upload<'_, D> {
+    fn send(&self, to: &mut impl SpiDevice) -> Result<()> {
+        upload(to, self.frame, self.transfer, Watch::default())
+    }
+}
+
+impl PartialWindow {
+    // (x, y, w, h) widened to whole bytes and clipped to the panel
+    pub fn aligned(&self, geometry: Geometry) -> (u16, u16, u16, u16) {
+        let x0 = (self.x / 8 * 8).min(geometry.width);
+        let x1 = self.x.saturating_add(self.w).div_ceil(8).saturating_mul(8);
+        let x1 = x1.min(geometry.width);
+        let y0 = self.y.min(geometry.height);
+        let y1 = self.y.saturating_add(self.h).min(geometry.height);
+        (x0, y0, x1.saturating_sub(x0), y1 - y0)
+    }
+}
+
+impl Command for PartialWindow {
+    fn send(&self, to: &mut impl SpiDevice) -> Result<()> {
+        let (x, y, w, h) = self.aligned(to.geometry());
+        // a window clipped away to nothing has no first or last column
+        if w == 0 || h == 0 {
+            return Ok(());
+        }
+        // first and last column and row, the columns' low 3 bits implied
+        let [hs1, hs0] = x.to_be_bytes();
+        let [he1, he0] = (x + w - 1).to_be_bytes();
+        let [vs1, vs0] = y.to_be_bytes();
+        let [ve1, ve0] = (y + h - 1).to_be_bytes();
+        to.send_cmd(0x90)?;
+        // the last byte keeps scanning the gates outside the window too
+        to.send_data(&[hs1, hs0 & 0xF8, he1, he0 | 0x07, vs1, vs0, ve1, ve0, 0x01])
+    }
+}
+
+impl Command for PartialIn {
+    fn send(&self, to: &mut impl SpiDevice) -> Result<()> {
+        to.send_cmd(0x91)
+    }
+}
+
+impl Command for PartialOut {
+    fn send(&self, to: &mut impl SpiDevice) -> Result<()> {
+        to.send_cmd(0x92)
+    }
+}
+
+impl Command for Deghost<'_> {
+    fn send(&self, to: &mut impl SpiDevice) -> Result<()> {
+        // every color the panel shows
+        let mut sequence = to.panel().palette().colors();
+        sequence.retain(|&c| c != Color::Clean);
+        // plus a final white fill to leave the panel blank
+        let total = self.cycles * sequence.len() as u32 + 1;
+        let mut step = 0;
+        for _ in 0..self.cycles {
+            for color in &sequence {
+                step += 1;
+                (self.progress)(step, total, *color);
+                fill(to, *color, self.options.awake())?;
+            }
+        }
+        (self.progress)(total, total, Color::White);
+        fill(to, Color::White, self.options)
+    }
+}
+
+impl Command for Clear {
+    fn send(&self, to: &mut impl SpiDevice) -> Result<()> {
+        let sequence = if to.panel().palette().colors().contains(&Color::Clean) {
+            &[Color::Clean][..]
+        } else {
+            &[Color::Black, Color::White][..]
+        };
+        let fills = (0..self.cycles).flat_map(|_| sequence);
+        let last = self.cycles as usize * sequence.len();
+        for (i, color) in fills.enumerate() {
+            let options = if i + 1 == last {
+                self.options
+            } else {
+                self.options.awake()
+            };
+            fill(to, *color, options)?;
+        }
+        Ok(())
+    }
+}
+
+// the whole panel in `color`
+fn fill(to: &mut impl SpiDevice, color: Color, options: DrawOptions) -> Result<()> {
+    Draw {
+        frame: &SolidColor(color),
+        options,
+        progress: None,
+        cancel: None,
+    }
+    .send(to)
+}
+
 impl Command for UnknownE3AA {
-    fn send(&self, to: &mut impl SpiDevice) -> spi::Result<()> {
+    fn send(&self, to: &mut impl SpiDevice) -> Result<()> {
         to.send_cmd(0xE3)?;
         to.send_data(&[0xAA])
     }
 }
 
 impl Command for SetResolution {
-    fn send(&self, to: &mut impl SpiDevice) -> spi::Result<()> {
+    fn send(&self, to: &mut impl SpiDevice) -> Result<()> {
         to.send_cmd(0x61)?;
-        to.send_data(&[0x02, 0x58, 0x01, 0xC0])?;
+        let geometry = to.geometry();
+        let [w1, w0] = geometry.width.to_be_bytes();
+        let [h1, h0] = geometry.height.to_be_bytes();
+        to.send_data(&[w1, w0, h1, h0])?;
         Ok(())
     }
 }
 
 impl Command for Unknown6022 {
-    fn send(&self, to: &mut impl SpiDevice) -> spi::Result<()> {
+    fn send(&self, to: &mut impl SpiDevice) -> Result<()> {
         to.send_cmd(0x60)?;
         to.send_data(&[0x22])
     }
 }
 
 impl Command for VCOMDataInterval {
-    fn send(&self, to: &mut impl SpiDevice) -> spi::Result<()> {
+    fn send(&self, to: &mut impl SpiDevice) -> Result<()> {
         to.send_cmd(0x50)?;
-        let d = (self.border_output as u8) << 5 | (1 << 4) | 0b0111;
+        let d = PaletteIndex::from(self.border_output).get() << 5 | (1 << 4) | 0b0111;
         to.send_data(&[d])?;
         Ok(())
     }
 }
 
 impl Command for TempSensor {
-    fn send(&self, to: &mut impl SpiDevice) -> spi::Result<()> {
+    fn send(&self, to: &mut impl SpiDevice) -> Result<()> {
         to.send_cmd(0x41)?;
         // use internal temp sensor
         to.send_data(&[0x00])?;
@@ -158,8 +536,72 @@ impl Command for TempSensor {
     }
 }
 
+impl Query for ReadTemperature {
+    type Answer = f32;
+
+    fn read(&self, to: &mut impl SpiDevice) -> Result<f32> {
+        to.send_cmd(0x40)?;
+        // busy while it measures
+        to.wait_busy_high()?;
+        let mut answer = [0; 2];
+        to.read_data(&mut answer)?;
+        Ok(temperature(answer))
+    }
+}
+
+impl Query for GetStatus {
+    type Answer = Status;
+
+    fn read(&self, to: &mut impl SpiDevice) -> Result<Status> {
+        to.send_cmd(0x71)?;
+        let mut answer = [0];
+        to.read_data(&mut answer)?;
+        Ok(Status(answer[0]))
+    }
+}
+
+impl Status {
+    // the busy line is released
+    pub fn idle(&self) -> bool {
+        self.0 & 1 != 0
+    }
+
+    pub fn powered_off(&self) -> bool {
+        self.0 & 1 << 1 != 0
+    }
+
+    pub fn powered_on(&self) -> bool {
+        self.0 & 1 << 2 != 0
+    }
+
+    // a whole frame has come in since the last refresh
+    pub fn data_received(&self) -> bool {
+        self.0 & 1 << 3 != 0
+    }
+
+    // talking to the external temperature sensor
+    pub fn i2c_busy(&self) -> bool {
+        self.0 & 1 << 4 == 0
+    }
+
+    // the external temperature sensor didn't answer
+    pub fn i2c_error(&self) -> bool {
+        self.0 & 1 << 5 != 0
+    }
+}
+
+// 11 bits of two's complement in eighths of a degree, from the top
+pub(crate) fn temperature([hi, lo]: [u8; 2]) -> f32 {
+    (i16::from_be_bytes([hi, lo]) >> 5) as f32 / 8.0
+}
+
+// how the controller gives back `c`
+pub(crate) fn temperature_bytes(c: f32) -> [u8; 2] {
+    (((c * 8.0).round() as i16) << 5).to_be_bytes()
+}
+
 impl Command for PLLControl {
-    fn send(&self, to: &mut impl SpiDevice) -> spi::Result<()> {
+    fn send(&self, to: &mut impl SpiDevice) -> Result<()> {
         to.send_cmd(0x30)?;
         to.send_data(&[0x3C])?;
         Ok(())
@@ -167,7 +609,7 @@ impl Command for PLLControl {
 }
 
 impl Command for BoosterSoftStart {
-    fn send(&self, to: &mut impl SpiDevice) -> spi::Result<()> {
+    fn send(&self, to: &mut impl SpiDevice) -> Result<()> {
         to.send_cmd(0x06)?;
         to.send_data(&[0xC7, 0xC7, 0x1D])?;
         Ok(())
@@ -175,7 +617,7 @@ impl Command for BoosterSoftStart {
 }
 
 impl Command for PowerOffSequence {
-    fn send(&self, to: &mut impl SpiDevice) -> spi::Result<()> {
+    fn send(&self, to: &mut impl SpiDevice) -> Result<()> {
         to.send_cmd(0x03)?;
         to.send_data(&[0x00])?;
         Ok(())
@@ -183,7 +625,7 @@ impl Command for PowerOffSequence {
 }
 
 impl Command for InternalPower {
-    fn send(&self, to: &mut impl SpiDevice) -> spi::Result<()> {
+    fn send(&self, to: &mut impl SpiDevice) -> Result<()> {
         to.send_cmd(0x01)?;
         to.send_data(&[0x37, 0x00, 0x23, 0x23])?;
         Ok(())
@@ -191,7 +633,7 @@ impl Command for InternalPower {
 }
 
 impl Command for PanelSetting {
-    fn send(&self, to: &mut impl SpiDevice) -> spi::Result<()> {
+    fn send(&self, to: &mut impl SpiDevice) -> Result<()> {
         to.send_cmd(0x00)?;
         let d = 0b11100000
             | to_bit(self.ud, 3)
@@ -203,6 +645,47 @@ impl Command for PanelSetting {
     }
 }
 
+impl DrawOptions {
+    // the same, without the deep sleep, for a draw with more to follow
+    pub fn awake(self) -> Self {
+        Self {
+            deep_sleep: false,
+            ..self
+        }
+    }
+}
+
+impl Default for DrawOptions {
+    fn default() -> Self {
+        Self {
+            cooldown: Duration::from_millis(200),
+            power_off: true,
+            deep_sleep: false,
+            transfer: Transfer::default(),
+        }
+    }
+}
+
+impl Default for Transfer {
+    fn default() -> Self {
+        Self {
+            chunk: 4096,
+            pause: Duration::ZERO,
+        }
+    }
+}
+
+impl Command for KwrPanelSetting {
+    fn send(&self, to: &mut impl SpiDevice) -> Result<()> {
+        to.send_cmd(0x00)?;
+        // the top bits clear: 400x300, tri-color and the otp waveforms
+        let s = &self.0;
+        let d = to_bit(s.ud, 3) | to_bit(s.shl, 2) | to_bit(s.shd_n, 1) | to_bit(s.rst_n, 0);
+        to.send_data(&[d])?;
+        Ok(())
+    }
+}
+
 impl Default for PanelSetting {
     fn default() -> Self {
         Self {
@@ -213,3 +696,208 @@ impl Default for PanelSetting {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        frame::PACKED_LEN,
+        mock::{MockDevice, Transaction},
+    };
+
+    // no cooldown, so the tests don't sleep
+    fn quick() -> DrawOptions {
+        DrawOptions {
+            cooldown: Duration::ZERO,
+            ..DrawOptions::default()
+        }
+    }
+
+    fn draw<T: Drawable>(frame: &T, options: DrawOptions) -> Draw<'_, T> {
+        Draw {
+            frame,
+            options,
+            progress: None,
+            cancel: None,
+        }
+    }
+
+    // the bytes sent as data, one entry per write
+    fn data(mock: &MockDevice) -> Vec<Vec<u8>> {
+        mock.log()
+            .into_iter()
+            .filter_map(|t| match t {
+                Transaction::Data(d) => Some(d),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn draw_uploads_the_frame_then_refreshes_and_powers_off() {
+        let mut mock = MockDevice::new();
+        draw(&SolidColor(Color::Red), quick())
+            .send(&mut mock)
+            .unwrap();
+        let log = mock.log();
+        assert_eq!(
+            log[..3],
+            [
+                Transaction::Cmd(0x61),
+                Transaction::Data(vec![0x02, 0x58, 0x01, 0xC0]),
+                Transaction::Cmd(0x10),
+            ]
+        );
+        assert_eq!(
+            log[log.len() - 6..],
+            [
+                Transaction::Cmd(0x04),
+                Transaction::WaitBusyHigh,
+                Transaction::Cmd(0x12),
+                Transaction::WaitBusyHigh,
+                Transaction::Cmd(0x02),
+                Transaction::WaitBusyLow,
+            ]
+        );
+        // the frame in 4096 byte chunks, two red pixels to a byte
+        let chunks = &data(&mock)[1..];
+        assert_eq!(chunks.len(), PACKED_LEN.div_ceil(4096));
+        assert!(chunks.iter().all(|c| c.len() <= 4096));
+        assert!(mock.frame().unwrap().data.iter().all(|&b| b == 0x44));
+    }
+
+    #[test]
+    fn draw_deep_sleeps_last_when_asked() {
+        let mut mock = MockDevice::new();
+        let options = DrawOptions {
+            power_off: false,
+            deep_sleep: true,
+            ..quick()
+        };
+        draw(&SolidColor(Color::White), options)
+            .send(&mut mock)
+            .unwrap();
+        assert_eq!(mock.commands(), [0x61, 0x10, 0x04, 0x12, 0x07]);
+        assert_eq!(mock.log().last(), Some(&Transaction::Data(vec![0xA5])));
+    }
+
+    #[test]
+    fn cancelled_draw_powers_off_without_refreshing() {
+        let mut mock = MockDevice::new();
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let d = Draw {
+            cancel: Some(&cancel),
+            ..draw(&SolidColor(Color::White), quick())
+        };
+        assert!(matches!(d.send(&mut mock), Err(EpaperError::Cancelled)));
+        assert_eq!(mock.commands(), [0x61, 0x10, 0x02]);
+    }
+
+    #[test]
+    fn partial_window_widens_x_to_whole_bytes() {
+        let geometry = Geometry::default();
+        let window = |x, y, w, h| PartialWindow { x, y, w, h }.aligned(geometry);
+        assert_eq!(window(5, 3, 10, 2), (0, 3, 16, 2));
+        assert_eq!(window(8, 0, 8, 1), (8, 0, 8, 1));
+        // clipped at the right and bottom edges
+        assert_eq!(window(590, 440, 20, 20), (584, 440, 16, 8));
+        // and to nothing past them
+        assert_eq!(window(700, 500, 10, 10), (600, 448, 0, 0));
+        assert_eq!(window(u16::MAX, 0, u16::MAX, 1).2, 0);
+    }
+
+    #[test]
+    fn partial_draw_sends_only_the_window() {
+        let mut mock = MockDevice::new();
+        draw(&SolidColor(Color::Black), quick())
+            .partial(5, 3, 10, 2)
+            .send(&mut mock)
+            .unwrap();
+        assert_eq!(mock.commands(), [0x91, 0x90, 0x10, 0x04, 0x12, 0x92, 0x02]);
+        let data = data(&mock);
+        // columns 0..=15 and rows 3..=4
+        assert_eq!(
+            data[0],
+            [0x00, 0x00, 0x00, 0x0F, 0x00, 0x03, 0x00, 0x04, 0x01]
+        );
+        // 16 x 2 black pixels
+        assert_eq!(data[1], [0x00; 16]);
+    }
+
+    #[test]
+    fn partial_draw_leaves_partial_mode_when_the_refresh_times_out() {
+        let mut mock = MockDevice::new();
+        mock.busy_failures.set(1);
+        let sent = draw(&SolidColor(Color::Black), quick())
+            .partial(0, 0, 8, 8)
+            .send(&mut mock);
+        assert!(matches!(sent, Err(EpaperError::BusyTimeout(_))));
+        assert_eq!(mock.commands(), [0x91, 0x90, 0x10, 0x04, 0x92]);
+    }
+
+    #[test]
+    fn partial_draw_outside_the_panel_sends_nothing() {
+        let mut mock = MockDevice::new();
+        draw(&SolidColor(Color::Black), quick())
+            .partial(600, 0, 8, 8)
+            .send(&mut mock)
+            .unwrap();
+        assert!(mock.log().is_empty());
+    }
+
+    #[test]
+    fn partial_draw_fails_on_a_panel_without_windows() {
+        let mut mock = MockDevice::with_panel(&crate::panel::Bwr420);
+        let sent = draw(&SolidColor(Color::Black), quick())
+            .partial(0, 0, 8, 8)
+            .send(&mut mock);
+        assert!(sent.is_err());
+        assert!(mock.log().is_empty());
+    }
+
+    #[test]
+    fn deghost_fills_each_color_then_white_and_sleeps_once() {
+        let mut mock = MockDevice::new();
+        let steps = std::cell::RefCell::new(Vec::new());
+        let progress = |step, total, color: Color| {
+            steps
+                .borrow_mut()
+                .push((step, total, PaletteIndex::from(color).get()));
+        };
+        Deghost {
+            cycles: 2,
+            progress: &progress,
+            options: DrawOptions {
+                deep_sleep: true,
+                ..quick()
+            },
+        }
+        .send(&mut mock)
+        .unwrap();
+        // 7 colors twice, then the white
+        let steps = steps.into_inner();
+        assert_eq!(steps.len(), 15);
+        assert!(steps.iter().all(|&(_, total, _)| total == 15));
+        assert_eq!(steps[14], (15, 15, Color::White as u8));
+        let commands = mock.commands();
+        assert_eq!(commands.iter().filter(|&&c| c == 0x12).count(), 15);
+        assert_eq!(commands.iter().filter(|&&c| c == 0x07).count(), 1);
+        assert_eq!(commands.last(), Some(&0x07));
+        // the panel is left white
+        assert!(mock.frame().unwrap().data.iter().all(|&b| b == 0x11));
+    }
+
+    #[test]
+    fn clear_fills_clean_on_the_acep() {
+        let mut mock = MockDevice::new();
+        Clear {
+            cycles: 2,
+            options: quick(),
+        }
+        .send(&mut mock)
+        .unwrap();
+        assert_eq!(mock.commands().iter().filter(|&&c| c == 0x12).count(), 2);
+        assert!(mock.frame().unwrap().data.iter().all(|&b| b == 0x77));
+    }
+}
@@ -0,0 +1,250 @@
+use std::{
+    fs,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use tracing::warn;
+
+use crate::{
+    draw::Color,
+    font,
+    layout::{self, Rect},
+    lease::Leases,
+    overlay, roi, web, Rgb,
+};
+
+// what a region shows while its source is failing
+#[derive(Clone, Copy, PartialEq)]
+pub enum Fallback {
+    // the last good render with a stale badge, or the error placeholder if
+    // there never was one
+    Stale,
+    // always the error placeholder
+    Error,
+    // whatever was there before, untouched
+    Keep,
+}
+
+impl FromStr for Fallback {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stale" => Ok(Fallback::Stale),
+            "error" => Ok(Fallback::Error),
+            "keep" => Ok(Fallback::Keep),
+            _ => Err(format!(
+                "unknown fallback '{s}' (expected stale, error or keep)"
+            )),
+        }
+    }
+}
+
+// a screen region fed by its own source on its own schedule
+pub struct Binding {
+    pub name: String,
+    pub rect: Rect,
+    pub interval: Duration,
+    pub url: String,
+    pub fallback: Fallback,
+    next_due: Instant,
+    last_good: Option<bmp::Image>,
+    failing: bool,
+}
+
+// one binding per line: `name x,y,w,h interval_secs url [stale|error|keep]`.
+// the fallback defaults to stale. `lease client name x,y,w,h ttl_secs` lines
+// reserve a region for another client up front. blank lines and lines
+// starting with # are ignored.
+pub fn load(path: &str) -> Result<(Vec<Binding>, Leases), String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("could not read {path}: {e}"))?;
+    let now = Instant::now();
+    let mut bindings = Vec::new();
+    let mut leases = Leases::default();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts[0] == "lease" {
+            let [_, client, name, rect, ttl] = parts[..] else {
+                return Err(format!(
+                    "invalid lease '{line}', expected: lease client name x,y,w,h ttl_secs"
+                ));
+            };
+            let ttl = ttl
+                .parse()
+                .map_err(|e| format!("invalid ttl in '{line}': {e}"))?;
+            leases.acquire(
+                client,
+                name,
+                roi::parse_rect(rect)?,
+                Duration::from_secs(ttl),
+            )?;
+            continue;
+        }
+        let (name, rect, interval, url, fallback) = match parts[..] {
+            [name, rect, interval, url] => (name, rect, interval, url, Fallback::Stale),
+            [name, rect, interval, url, fallback] => (name, rect, interval, url, fallback.parse()?),
+            _ => {
+                return Err(format!(
+                    "invalid binding '{line}', expected: name x,y,w,h interval_secs url [fallback]"
+                ))
+            }
+        };
+        let interval = interval
+            .parse()
+            .map_err(|e| format!("invalid interval in '{line}': {e}"))?;
+        bindings.push(Binding {
+            name: name.to_string(),
+            rect: roi::parse_rect(rect)?,
+            interval: Duration::from_secs(interval),
+            url: url.to_string(),
+            fallback,
+            next_due: now,
+            last_good: None,
+            failing: false,
+        });
+    }
+    if bindings.is_empty() {
+        return Err(format!("{path} has no region bindings"));
+    }
+    Ok((bindings, leases))
+}
+
+// keeps the current composite and updates regions as they fall due
+pub struct Compositor {
+    pub bindings: Vec<Binding>,
+    pub frame: bmp::Image,
+    pub leases: Leases,
+    browser: String,
+}
+
+impl Compositor {
+    pub fn new(bindings: Vec<Binding>, leases: Leases, browser: &str) -> Self {
+        Self {
+            bindings,
+            frame: layout::blank(Color::White),
+            leases,
+            browser: browser.to_string(),
+        }
+    }
+
+    // draws a client's image into `rect`, if its leases allow it
+    pub fn write(&mut self, client: &str, rect: Rect, img: &bmp::Image) -> Result<(), String> {
+        self.leases.check_write(client, rect)?;
+        layout::fit_into(&mut self.frame, img, rect);
+        Ok(())
+    }
+
+    // when the next region is due
+    pub fn next_due(&self) -> Instant {
+        self.bindings
+            .iter()
+            .map(|b| b.next_due)
+            .min()
+            .unwrap_or_else(Instant::now)
+    }
+
+    // re-renders every due region into the frame, returning how many
+    // changed. a failing source gets its fallback instead of failing the
+    // whole frame, and only counts as a change the first time it fails.
+    pub fn update(&mut self) -> usize {
+        let now = Instant::now();
+        let mut updated = 0;
+        for b in self.bindings.iter_mut().filter(|b| b.next_due <= now) {
+            b.next_due = now + b.interval;
+            // each binding draws as a client named after itself
+            if let Err(e) = self.leases.check_write(&b.name, b.rect) {
+                warn!("region {}: {e}", b.name);
+                continue;
+            }
+            match web::screenshot(&self.browser, &b.url, b.rect.w, b.rect.h) {
+                Ok(img) => {
+                    layout::fit_into(&mut self.frame, &img, b.rect);
+                    b.last_good = Some(img);
+                    b.failing = false;
+                    updated += 1;
+                }
+                Err(e) => {
+                    warn!("region {}: {e}", b.name);
+                    if b.failing || b.fallback == Fallback::Keep {
+                        b.failing = true;
+                        continue;
+                    }
+                    b.failing = true;
+                    match (&b.last_good, b.fallback) {
+                        (Some(img), Fallback::Stale) => {
+                            layout::fit_into(&mut self.frame, img, b.rect);
+                            stale_badge(&mut self.frame, b.rect);
+                        }
+                        _ => error_placeholder(&mut self.frame, b.rect, &b.name),
+                    }
+                    updated += 1;
+                }
+            }
+        }
+        updated
+    }
+}
+
+// largest text scale (up to 2) that fits `text` in `w`
+fn fit_scale(text: &str, w: u32) -> u16 {
+    if font::text_size(text, 2).0 as u32 + 8 <= w {
+        2
+    } else {
+        1
+    }
+}
+
+// small "stale" tag in the top right corner of the region
+fn stale_badge(img: &mut bmp::Image, rect: Rect) {
+    let text = "stale";
+    let scale = fit_scale(text, rect.w);
+    let (tw, th) = font::text_size(text, scale);
+    let (w, h) = (tw as u32 + 4, th as u32 + 4);
+    let x = (rect.x + rect.w).saturating_sub(w + 2).max(rect.x);
+    let y = rect.y + 2;
+    overlay::fill_rect(img, x, y, w, h, Rgb::from(Color::Orange).into());
+    font::draw_text(
+        img,
+        text,
+        x as u16 + 2,
+        y as u16 + 2,
+        scale,
+        Rgb::from(Color::White).into(),
+    );
+}
+
+// blanks the region and marks it with a red cross and its name
+fn error_placeholder(img: &mut bmp::Image, rect: Rect, name: &str) {
+    overlay::fill_rect(
+        img,
+        rect.x,
+        rect.y,
+        rect.w,
+        rect.h,
+        Rgb::from(Color::White).into(),
+    );
+    let red: bmp::Pixel = Rgb::from(Color::Red).into();
+    let side = rect.w.min(rect.h) / 3;
+    let (cx, cy) = (rect.x + rect.w / 2, rect.y + rect.h / 2);
+    for i in 0..side {
+        for t in 0..3 {
+            let x = cx - side / 2 + i + t;
+            let (y1, y2) = (cy - side / 2 + i, cy + side / 2 - i);
+            if x < img.get_width() && y2 < img.get_height() {
+                img.set_pixel(x, y1, red);
+                img.set_pixel(x, y2, red);
+            }
+        }
+    }
+    let text = format!("{name} unavailable");
+    let scale = fit_scale(&text, rect.w);
+    let (tw, _) = font::text_size(&text, scale);
+    let x = rect.x + rect.w.saturating_sub(tw as u32) / 2;
+    let y = cy + side / 2 + 6;
+    if y < rect.y + rect.h {
+        font::draw_text(img, &text, x as u16, y as u16, scale, red);
+    }
+}
@@ -0,0 +1,170 @@
+// the wiring of the panel, read from a toml file so a hat that isn't wired
+// like the waveshare one doesn't need flags on every run:
+//
+//     [pins]
+//     dc = 25
+//     busy = 24
+//     reset = 17
+//
+//     [gpio]
+//     backend = "cdev"
+//     chip = "/dev/gpiochip0"
+//
+//     [spi]
+//     bus = 0
+//     select = 0
+//     clock = 4000000
+//     # in place of bus and select, with the spidev feature
+//     device = "/dev/spidev1.0"
+//
+//     [panel]
+//     model = "acep565"
+//
+// every key is optional and falls back to the default config.
+
+use std::{fs, path::Path};
+
+use rppal::spi::{Bus, SlaveSelect};
+use toml::{Table, Value};
+
+use crate::{panel, Config};
+
+// read at startup when it exists
+pub const DEFAULT_PATH: &str = "/etc/rpi-epaper.toml";
+
+// the table called `name`, checking it has only the given keys so a typo
+// doesn't quietly leave the default in place
+fn section<'a>(root: &'a Table, name: &str, keys: &[&str]) -> Result<Option<&'a Table>, String> {
+    let Some(value) = root.get(name) else {
+        return Ok(None);
+    };
+    let table = value
+        .as_table()
+        .ok_or_else(|| format!("[{name}] should be a table"))?;
+    if let Some(key) = table.keys().find(|k| !keys.contains(&k.as_str())) {
+        return Err(format!(
+            "unknown key '{key}' in [{name}] (expected {})",
+            keys.join(", ")
+        ));
+    }
+    Ok(Some(table))
+}
+
+fn integer(table: &Table, section: &str, key: &str, max: u32) -> Result<Option<u32>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::Integer(n)) if (0..=max as i64).contains(n) => Ok(Some(*n as u32)),
+        Some(v) => Err(format!(
+            "{section}.{key} should be a number from 0 to {max}, got {v}"
+        )),
+    }
+}
+
+fn string<'a>(table: &'a Table, section: &str, key: &str) -> Result<Option<&'a str>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s)),
+        Some(v) => Err(format!("{section}.{key} should be a string, got {v}")),
+    }
+}
+
+fn bus(n: u32) -> Bus {
+    match n {
+        0 => Bus::Spi0,
+        1 => Bus::Spi1,
+        2 => Bus::Spi2,
+        3 => Bus::Spi3,
+        4 => Bus::Spi4,
+        5 => Bus::Spi5,
+        _ => Bus::Spi6,
+    }
+}
+
+fn slave_select(n: u32) -> SlaveSelect {
+    [
+        SlaveSelect::Ss0,
+        SlaveSelect::Ss1,
+        SlaveSelect::Ss2,
+        SlaveSelect::Ss3,
+        SlaveSelect::Ss4,
+        SlaveSelect::Ss5,
+        SlaveSelect::Ss6,
+        SlaveSelect::Ss7,
+        SlaveSelect::Ss8,
+        SlaveSelect::Ss9,
+        SlaveSelect::Ss10,
+        SlaveSelect::Ss11,
+        SlaveSelect::Ss12,
+        SlaveSelect::Ss13,
+        SlaveSelect::Ss14,
+        SlaveSelect::Ss15,
+    ][n as usize]
+}
+
+impl Config {
+    // these settings with the ones in `text` laid over them
+    pub fn with_toml(mut self, text: &str) -> Result<Self, String> {
+        let root: Table = text
+            .parse()
+            .map_err(|e: toml::de::Error| e.message().to_string())?;
+        if let Some(key) = root
+            .keys()
+            .find(|k| !["pins", "gpio", "spi", "panel"].contains(&k.as_str()))
+        {
+            return Err(format!(
+                "unknown section [{key}] (expected pins, gpio, spi or panel)"
+            ));
+        }
+        if let Some(pins) = section(&root, "pins", &["dc", "busy", "reset"])? {
+            // bcm numbering, as rppal and the gpio chip use
+            for (key, pin) in [
+                ("dc", &mut self.dc),
+                ("busy", &mut self.busy),
+                ("reset", &mut self.reset),
+            ] {
+                if let Some(n) = integer(pins, "pins", key, 53)? {
+                    *pin = n as u8;
+                }
+            }
+        }
+        if let Some(gpio) = section(&root, "gpio", &["backend", "chip"])? {
+            if let Some(backend) = string(gpio, "gpio", "backend")? {
+                self.gpio = backend.parse()?;
+            }
+            if let Some(chip) = string(gpio, "gpio", "chip")? {
+                self.gpiochip = chip.to_string();
+            }
+        }
+        if let Some(spi) = section(&root, "spi", &["bus", "select", "clock", "device"])? {
+            if let Some(n) = integer(spi, "spi", "bus", 6)? {
+                self.spi_bus = bus(n);
+            }
+            if let Some(n) = integer(spi, "spi", "select", 15)? {
+                self.spi_select = slave_select(n);
+            }
+            match integer(spi, "spi", "clock", 125_000_000)? {
+                Some(0) => return Err("spi.clock can't be 0".into()),
+                Some(hz) => self.spi_clock = hz,
+                None => {}
+            }
+            if let Some(device) = string(spi, "spi", "device")? {
+                self.spidev = Some(device.to_string());
+            }
+        }
+        if let Some(panel) = section(&root, "panel", &["model"])? {
+            if let Some(model) = string(panel, "panel", "model")? {
+                self.panel = panel::by_name(model)?;
+                self.geometry = self.panel.geometry();
+            }
+        }
+        Ok(self)
+    }
+
+    // these settings with the file at `path` laid over them
+    pub fn with_file(self, path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {e}", path.display()))?;
+        self.with_toml(&text)
+            .map_err(|e| format!("{}: {e}", path.display()))
+    }
+}
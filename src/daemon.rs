@@ -0,0 +1,592 @@
+// the modes that keep drawing: sources polled on an interval, composed
+// regions, the http daemon, sequence files and the endurance run
+
+use std::{
+    error::Error,
+    fs,
+    sync::{mpsc, Arc, Mutex},
+    thread::{self, sleep},
+    time::{Duration, Instant},
+};
+
+use rppal::gpio::{Gpio, Trigger};
+use serde_json::json;
+use tracing::{info, warn};
+
+#[cfg(feature = "climate")]
+use crate::climate;
+use crate::{
+    app::{count_clear, draw_dithered, draw_options, refresh, wake, Mode, Options},
+    cmd::{self, Command},
+    compose,
+    draw::{self, Color},
+    endurance, events, layout, notify, pages, profile,
+    render::{decorate, dither, fit_screen, load_image, render_scene},
+    script, serve, source, SpiDevice,
+};
+
+// the source a looping content mode draws from
+pub fn image_source(
+    mode: &Mode,
+    opts: &Options,
+) -> Result<Box<dyn source::ImageSource>, Box<dyn Error>> {
+    Ok(match mode {
+        Mode::Web { url } => Box::new(source::Url {
+            browser: opts.browser.clone(),
+            url: url.clone(),
+        }),
+        Mode::Dir { directory } => Box::new(source::Directory::new(directory)),
+        Mode::Slideshow { path } => source::slideshow(path),
+        Mode::Mqtt { broker, topic } => Box::new(source::Mqtt::new(broker, topic)),
+        #[cfg(feature = "climate")]
+        Mode::Climate { sensor, history } => Box::new(climate::Station::new(
+            *sensor,
+            climate::History::load(history)?,
+            opts.clock.clone(),
+        )),
+        _ => return Err("not an image source".into()),
+    })
+}
+
+// what cuts the wait for a source's next interval short
+enum Wakeup {
+    Invalidated,
+    Notify(notify::Notification),
+}
+
+// forwards everything sent on the returned channel to `to`, wrapped
+fn forward<T: Send + 'static>(to: &mpsc::Sender<Wakeup>, wrap: fn(T) -> Wakeup) -> mpsc::Sender<T> {
+    let (tx, rx) = mpsc::channel();
+    let to = to.clone();
+    thread::spawn(move || {
+        for t in rx {
+            if to.send(wrap(t)).is_err() {
+                return;
+            }
+        }
+    });
+    tx
+}
+
+// draws whatever the source has next, on its interval or as soon as it
+// says something changed. a notification posted to --notify takes over the
+// panel for its duration, then the source's latest frame comes back.
+pub fn show_source(
+    display: &mut impl SpiDevice,
+    source: &mut dyn source::ImageSource,
+    opts: &Options,
+) -> Result<(), Box<dyn Error>> {
+    let (wakeup, wakeups) = mpsc::channel();
+    source.notify(forward(&wakeup, |()| Wakeup::Invalidated));
+    if let Some(addr) = &opts.notify {
+        notify::listen(addr, forward(&wakeup, Wakeup::Notify))?;
+        info!("Listening for notifications on {addr}");
+    }
+    let mut drawn = false;
+    // a draw that fails is logged and the loop carries on, the panel keeping
+    // whatever it showed
+    let mut show = |display: &mut _, img: &bmp::Image| {
+        // the panel went to sleep after the previous refresh
+        let shown = if opts.deep_sleep && drawn {
+            wake(display, opts)
+        } else {
+            Ok(())
+        };
+        match shown.and_then(|()| draw_dithered(display, img, opts)) {
+            Ok(()) => drawn = true,
+            Err(e) => {
+                warn!("Could not draw the frame: {e}");
+                events::log("refresh_failed", json!({ "error": e.to_string() }));
+            }
+        }
+    };
+    // the card up and when it comes down, timed from the end of its refresh
+    let mut alert: Option<(notify::Notification, Instant)> = None;
+    let mut pending: Vec<notify::Notification> = Vec::new();
+    let mut latest: Option<bmp::Image> = None;
+    let mut next_poll = Instant::now();
+    loop {
+        let mut card = None;
+        match wakeups.recv_timeout(
+            alert
+                .as_ref()
+                .map_or(next_poll, |(_, until)| next_poll.min(*until))
+                .saturating_duration_since(Instant::now()),
+        ) {
+            Ok(Wakeup::Invalidated) => next_poll = Instant::now(),
+            Ok(Wakeup::Notify(n)) => match &alert {
+                Some((shown, _)) if n.priority < shown.priority => pending.push(n),
+                _ => card = Some(n),
+            },
+            Err(_) => {}
+        }
+        if card.is_none() && alert.as_ref().is_some_and(|(_, t)| Instant::now() >= *t) {
+            alert = None;
+            // the highest priority card waiting, the first posted of equals
+            let next = (0..pending.len())
+                .rev()
+                .max_by_key(|&i| pending[i].priority);
+            card = next.map(|i| pending.remove(i));
+            if let (None, Some(img)) = (&card, &latest) {
+                info!("Restoring {}", source.name());
+                show(display, img);
+            }
+        }
+        if let Some(n) = card {
+            info!("Notification: {}", n.title);
+            show(display, &n.card());
+            let until = Instant::now() + n.duration;
+            alert = Some((n, until));
+        }
+        if Instant::now() >= next_poll {
+            // a source that fails leaves the last good frame up until its
+            // next poll
+            let next = (source.next_frame().map_err(Box::<dyn Error>::from)).and_then(|img| {
+                img.map(|img| decorate(fit_screen(img, opts), opts))
+                    .transpose()
+            });
+            match next {
+                Ok(Some(img)) => {
+                    // held back until the card comes down
+                    if alert.is_none() {
+                        info!("Showing {}", source.name());
+                        show(display, &img);
+                    }
+                    latest = Some(img);
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    warn!("{} failed, keeping the last frame: {e}", source.name());
+                    events::log(
+                        "source_failed",
+                        json!({ "source": source.name(), "error": e.to_string() }),
+                    );
+                }
+            }
+            next_poll = Instant::now() + source.interval().unwrap_or(opts.interval);
+        }
+    }
+}
+
+// composes independently scheduled regions into one frame, refreshing the
+// panel at most once every --min-refresh
+pub fn show_composed(
+    display: &mut impl SpiDevice,
+    path: &str,
+    opts: &Options,
+) -> Result<(), Box<dyn Error>> {
+    let (bindings, leases) = compose::load(path)?;
+    let mut compositor = compose::Compositor::new(bindings, leases, &opts.browser);
+    let mut last_refresh: Option<Instant> = None;
+    let mut dirty = false;
+    loop {
+        dirty |= compositor.update() > 0;
+        let governed = last_refresh.is_some_and(|t| t.elapsed() < opts.min_refresh);
+        if dirty && !governed {
+            // the panel went to sleep after the previous refresh
+            if opts.deep_sleep && last_refresh.is_some() {
+                wake(display, opts)?;
+            }
+            let img = decorate(compositor.frame.clone(), opts)?;
+            draw_dithered(display, &img, opts)?;
+            last_refresh = Some(Instant::now());
+            dirty = false;
+        }
+        let mut wake_at = compositor.next_due();
+        if let Some(t) = last_refresh.filter(|_| dirty) {
+            wake_at = wake_at.min(t + opts.min_refresh);
+        }
+        sleep(wake_at.saturating_duration_since(Instant::now()));
+    }
+}
+
+// works through the jobs posted to the daemon on `port` one at a time, so
+// a client's refresh never cuts into another's. pictures for leased regions
+// are composed into the frame kept here, and a notification card stays up
+// for its duration before the frame comes back. the frame is the panel's
+// size, so regions and leases are in its pixels and dither leaves it be.
+pub fn serve(
+    display: &mut impl SpiDevice,
+    port: u16,
+    opts: &Options,
+) -> Result<(), Box<dyn Error>> {
+    let status = Arc::new(Mutex::new(serve::Status::default()));
+    let (queue, jobs) = mpsc::channel();
+    let addr = format!("0.0.0.0:{port}");
+    let area = opts.panel.geometry;
+    serve::listen(&addr, area, queue, Arc::clone(&status))?;
+    info!("Serving on {addr}");
+    let mut compositor = compose::Compositor::new(Vec::new(), Default::default(), &opts.browser);
+    compositor.frame = layout::blank_sized(area, Color::White);
+    let mut drawn = false;
+    let mut show = |display: &mut _, img: &bmp::Image| -> Result<(), Box<dyn Error>> {
+        // the panel went to sleep after the previous refresh
+        if opts.deep_sleep && drawn {
+            wake(display, opts)?;
+        }
+        let frame = dither(img, opts)?;
+        refresh(display, &frame, opts)?;
+        drawn = true;
+        let mut status = status.lock().unwrap();
+        status.refreshes += 1;
+        status.set_thumbnail(&frame);
+        Ok(())
+    };
+    let full = layout::Rect {
+        x: 0,
+        y: 0,
+        w: area.width as u32,
+        h: area.height as u32,
+    };
+    // when the card up comes down
+    let mut card_until: Option<Instant> = None;
+    loop {
+        let wait = card_until.map_or(Duration::MAX, |t| {
+            t.saturating_duration_since(Instant::now())
+        });
+        let serve::Queued { job, stream } = match jobs.recv_timeout(wait) {
+            Ok(queued) => queued,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                card_until = None;
+                info!("Restoring the frame");
+                show(display, &decorate(compositor.frame.clone(), opts)?)?;
+                continue;
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(()),
+        };
+        let kind = job.name();
+        {
+            let mut status = status.lock().unwrap();
+            status.busy = true;
+            status.queued -= 1;
+        }
+        let start = Instant::now();
+        let failed = |e: Box<dyn Error>| (500, e.to_string());
+        let result = match job {
+            serve::Job::Image {
+                img,
+                client,
+                region,
+            } => {
+                let (rect, img) = match region {
+                    Some(rect) => (rect, img),
+                    None => (full, fit_screen(img, opts)),
+                };
+                match compositor.write(&client, rect, &img) {
+                    Ok(()) => {
+                        info!("Showing a picture from {client}");
+                        card_until = None;
+                        decorate(compositor.frame.clone(), opts)
+                            .and_then(|img| show(display, &img))
+                            .map(|()| json!({ "drawn": true }))
+                            .map_err(failed)
+                    }
+                    Err(e) => Err((409, e)),
+                }
+            }
+            serve::Job::Clear { client } => match compositor.leases.check_write(&client, full) {
+                Ok(()) => {
+                    info!("Clearing for {client}");
+                    card_until = None;
+                    compositor.frame = layout::blank_sized(area, Color::White);
+                    refresh(display, &draw::SolidColor(Color::Clean), opts)
+                        .map(|()| json!({ "cleared": true }))
+                        .map_err(failed)
+                }
+                Err(e) => Err((409, e)),
+            },
+            serve::Job::Notify {
+                notification: n,
+                client,
+            } => match compositor.leases.check_write(&client, full) {
+                Ok(()) => {
+                    info!("Notification from {client}: {}", n.title);
+                    show(display, &n.card())
+                        .map(|()| {
+                            card_until = Some(Instant::now() + n.duration);
+                            json!({ "shown": true, "seconds": n.duration.as_secs() })
+                        })
+                        .map_err(failed)
+                }
+                Err(e) => Err((409, e)),
+            },
+            serve::Job::Lease {
+                client,
+                name,
+                rect,
+                ttl,
+            } => compositor
+                .leases
+                .acquire(&client, &name, rect, ttl)
+                .map(|()| json!({ "leased": name, "seconds": ttl.as_secs() }))
+                .map_err(|e| (409, e)),
+            serve::Job::Release { client, name } => {
+                compositor.leases.release(&client, &name);
+                Ok(json!({ "released": name }))
+            }
+        };
+        {
+            let mut status = status.lock().unwrap();
+            status.busy = false;
+            status.last = Some((kind, Instant::now(), start.elapsed()));
+            if let Err((_, e)) = &result {
+                warn!("{kind}: {e}");
+                status.last_error = Some(e.clone());
+            }
+        }
+        serve::answer(&stream, result);
+    }
+}
+
+// plays back a sequence file step by step
+pub fn run_script(
+    display: &mut impl SpiDevice,
+    path: &str,
+    opts: &Options,
+) -> Result<(), Box<dyn Error>> {
+    // parsed up front so a typo fails before anything is drawn
+    let steps = script::load(path)?;
+    for (i, step) in steps.iter().enumerate() {
+        let (n, total) = (i + 1, steps.len());
+        match step {
+            script::Step::Init => {
+                info!("Step {n}/{total}: init");
+                wake(display, opts)?;
+            }
+            script::Step::Clean(color) => {
+                info!("Step {n}/{total}: clean {}", color.name());
+                refresh(display, &draw::SolidColor(*color), opts)?;
+            }
+            script::Step::Draw(file) => {
+                info!("Step {n}/{total}: draw {file}");
+                let img = fit_screen(load_image(file, opts)?, opts);
+                draw_dithered(display, &decorate(img, opts)?, opts)?;
+            }
+            script::Step::Sleep(d) => {
+                info!("Step {n}/{total}: sleep {d:?}");
+                sleep(*d);
+            }
+            script::Step::DeepSleep => {
+                info!("Step {n}/{total}: deep sleep");
+                cmd::DeepSleep.send_retrying(display, opts.retries)?;
+            }
+            script::Step::Deghost(cycles) => {
+                info!("Step {n}/{total}: deghost x{cycles}");
+                cmd::Deghost {
+                    cycles: *cycles,
+                    progress: &|_, _, _| {},
+                    options: draw_options(opts),
+                }
+                .send_retrying(display, opts.retries)?;
+                count_clear(opts);
+            }
+        }
+    }
+    Ok(())
+}
+
+// refreshes through the stress patterns --cycles times, resting
+// --min-refresh in between, and logs how long each refresh took
+pub fn run_endurance(
+    display: &mut impl SpiDevice,
+    path: &str,
+    opts: &Options,
+) -> Result<(), Box<dyn Error>> {
+    let mut log = endurance::Log::create(path)?;
+    let patterns = endurance::patterns();
+    let mut failures = 0;
+    for cycle in 1..=opts.cycles {
+        if cycle > 1 {
+            sleep(opts.min_refresh);
+        }
+        let (pattern, frame) = &patterns[(cycle as usize - 1) % patterns.len()];
+        let temperature = match (&opts.temperature_file, opts.temperature) {
+            (Some(file), _) => Some(profile::read_temperature(file)?),
+            (None, t) => t,
+        };
+        let bounds = endurance::Bounds::at(temperature);
+        cmd::Upload {
+            frame: &**frame,
+            transfer: opts.transfer,
+        }
+        .send_retrying(display, opts.retries)?;
+        cmd::PowerOn.send(display)?;
+        let start = Instant::now();
+        let refreshed = cmd::DisplayRefresh.send(display);
+        let duration = refreshed.is_ok().then(|| start.elapsed());
+        let ok = duration.is_some_and(|d| bounds.contains(d));
+        log.write(&endurance::Record {
+            cycle,
+            pattern,
+            duration,
+            temperature,
+            ok,
+        })
+        .map_err(|e| format!("could not write {path}: {e}"))?;
+        events::log(
+            "endurance_cycle",
+            json!({
+                "cycle": cycle,
+                "pattern": pattern,
+                "duration_ms": duration.map(|d| d.as_millis() as u64),
+                "ok": ok,
+            }),
+        );
+        if let Err(e) = refreshed {
+            info!("Cycle {cycle}/{}: {pattern} failed: {e}", opts.cycles);
+            return Err(format!("panel stopped responding in cycle {cycle}, see {path}").into());
+        }
+        let took = start.elapsed();
+        if ok {
+            info!("Cycle {cycle}/{}: {pattern} took {took:.1?}", opts.cycles);
+        } else {
+            failures += 1;
+            info!(
+                "Cycle {cycle}/{}: {pattern} took {took:.1?}, expected {bounds}",
+                opts.cycles
+            );
+        }
+        cmd::PowerOff.send(display)?;
+    }
+    info!("Wrote refresh timings to {path}");
+    if failures > 0 {
+        return Err(format!(
+            "{failures} of {} refreshes took an unexpected time",
+            opts.cycles
+        )
+        .into());
+    }
+    Ok(())
+}
+
+// steps through a paginated document, advancing on a timer or a button press
+pub fn show_pages(
+    display: &mut impl SpiDevice,
+    path: &str,
+    opts: &Options,
+) -> Result<(), Box<dyn Error>> {
+    let doc = fs::read_to_string(path).map_err(|e| format!("could not read {path}: {e}"))?;
+    let pages = pages::paginate(&doc, opts.text_scale);
+    let mut button = match opts.button {
+        Some(pin) => {
+            let mut pin = Gpio::new()?.get(pin)?.into_input_pullup();
+            pin.set_interrupt(Trigger::FallingEdge)?;
+            Some(pin)
+        }
+        None => None,
+    };
+    for (i, page) in pages.iter().enumerate().cycle() {
+        info!("Page {}/{}", i + 1, pages.len());
+        let page = pages::TextPage {
+            page,
+            number: i + 1,
+            total: pages.len(),
+            scale: opts.text_scale,
+        };
+        refresh(display, &page, opts)?;
+        match &mut button {
+            Some(pin) => {
+                pin.poll_interrupt(true, Some(opts.interval))?;
+            }
+            None => sleep(opts.interval),
+        }
+        if opts.deep_sleep {
+            wake(display, opts)?;
+        }
+    }
+    Ok(())
+}
+
+// draws a scene, cycling through its pages if the policy paginated it
+pub fn show_scene(
+    display: &mut impl SpiDevice,
+    path: &str,
+    opts: &Options,
+) -> Result<(), Box<dyn Error>> {
+    let rendered = render_scene(path)?;
+    let total = rendered.pages.len();
+    for (i, page) in rendered.pages.iter().enumerate().cycle() {
+        if total > 1 {
+            info!("Page {}/{total}", i + 1);
+        }
+        draw_dithered(display, &decorate(page.clone(), opts)?, opts)?;
+        if total == 1 {
+            break;
+        }
+        sleep(opts.interval);
+        if opts.deep_sleep {
+            wake(display, opts)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockDevice, Transaction};
+
+    // draws straight away and leaves no state behind in the home directory
+    fn quick() -> Options {
+        Options {
+            cooldown: Duration::ZERO,
+            last_frame: None,
+            refresh_count: None,
+            min_refresh: Duration::ZERO,
+            ..Options::default()
+        }
+    }
+
+    fn temp(test: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("rpi-epaper-daemon-{test}-{}", std::process::id()));
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn scripts_play_back_in_order() {
+        let path = temp("script");
+        fs::write(&path, "init; clean white # a comment\nsleep 1ms; deepsleep").unwrap();
+        let mut display = MockDevice::new();
+        run_script(&mut display, &path, &quick()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(display.log()[0], Transaction::Reset);
+        let commands = display.commands();
+        assert_eq!(commands[0], 0x00);
+        assert!(commands.ends_with(&[0x10, 0x04, 0x12, 0x02, 0x07]));
+        assert!(display.image().is_some_and(|img| img
+            .coordinates()
+            .all(|(x, y)| img.get_pixel(x, y) == bmp::consts::WHITE)));
+    }
+
+    #[test]
+    fn scripts_with_a_typo_draw_nothing() {
+        let path = temp("typo");
+        fs::write(&path, "init; clean whit").unwrap();
+        let mut display = MockDevice::new();
+        assert!(run_script(&mut display, &path, &quick()).is_err());
+        fs::remove_file(&path).unwrap();
+        assert!(display.log().is_empty());
+    }
+
+    #[test]
+    fn endurance_logs_every_cycle_and_fails_fast_refreshes() {
+        let path = temp("endurance");
+        let opts = Options {
+            cycles: 3,
+            ..quick()
+        };
+        let mut display = MockDevice::new();
+        // the mock refreshes instantly, well under a healthy refresh
+        let e = run_endurance(&mut display, &path, &opts).err().unwrap();
+        assert_eq!(e.to_string(), "3 of 3 refreshes took an unexpected time");
+        let log = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let rows: Vec<_> = log.lines().collect();
+        assert_eq!(rows.len(), 4);
+        assert!(rows[0].starts_with("cycle,pattern"));
+        assert!(rows[1..].iter().all(|r| r.ends_with(",false")));
+        let refreshes = display.commands().iter().filter(|&&c| c == 0x12).count();
+        assert_eq!(refreshes, 3);
+    }
+}
@@ -0,0 +1,55 @@
+// loads pictures of any format the image crate knows into the rgb buffer
+// the dithering works on
+
+use std::{fs::File, io::BufReader, path::Path};
+
+use image::{codecs::gif::GifDecoder, AnimationDecoder, DynamicImage, Frames};
+
+use crate::error::{EpaperError, Result};
+
+// png, jpeg, gif (its first frame), webp or bmp, told apart by the file's
+// contents rather than its extension
+pub fn open(path: &Path) -> Result<bmp::Image> {
+    let err = |e: &dyn std::fmt::Display| {
+        EpaperError::Decode(format!("could not load {}: {e}", path.display()))
+    };
+    let img = image::ImageReader::open(path)
+        .map_err(|e| err(&e))?
+        .with_guessed_format()
+        .map_err(|e| err(&e))?
+        .decode()
+        .map_err(|e| err(&e))?;
+    Ok(from_rgb(&img.to_rgb8()))
+}
+
+// every frame of an animated gif, decoded one at a time as they're asked
+// for since a long animation wouldn't fit in memory at once
+pub fn frames(path: &Path) -> Result<Frames<'static>> {
+    let err = |e: &dyn std::fmt::Display| {
+        EpaperError::Decode(format!("could not load {}: {e}", path.display()))
+    };
+    let file = File::open(path).map_err(|e| err(&e))?;
+    let decoder = GifDecoder::new(BufReader::new(file)).map_err(|e| err(&e))?;
+    Ok(decoder.into_frames())
+}
+
+// a decoded frame, composited over the frames before it
+pub fn from_frame(frame: image::Frame) -> bmp::Image {
+    from_rgb(&DynamicImage::ImageRgba8(frame.into_buffer()).to_rgb8())
+}
+
+pub fn from_rgb(img: &image::RgbImage) -> bmp::Image {
+    let mut out = bmp::Image::new(img.width(), img.height());
+    for (x, y, px) in img.enumerate_pixels() {
+        let [r, g, b] = px.0;
+        out.set_pixel(x, y, bmp::Pixel::new(r, g, b));
+    }
+    out
+}
+
+pub fn to_rgb(img: &bmp::Image) -> image::RgbImage {
+    image::RgbImage::from_fn(img.get_width(), img.get_height(), |x, y| {
+        let px = img.get_pixel(x, y);
+        image::Rgb([px.r, px.g, px.b])
+    })
+}
@@ -0,0 +1,622 @@
+// dithering algorithms behind a common trait, picked by name from the cli
+
+use std::str::FromStr;
+
+use crate::{
+    draw::{Color, PaperImage},
+    Rgb, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+// maps an rgb image onto the panel colors. `quantize` picks the color for
+// a pixel, so callers can restrict the palette or match differently in
+// parts of the image.
+pub trait Ditherer {
+    fn name(&self) -> &str;
+
+    fn dither_with(
+        &self,
+        img: &bmp::Image,
+        quantize: &(dyn Fn(usize, usize, Rgb) -> Color + Sync),
+    ) -> PaperImage;
+
+    fn dither(&self, img: &bmp::Image) -> PaperImage {
+        self.dither_with(img, &|_, _, px| Color::closest(px))
+    }
+}
+
+pub struct FloydSteinberg(pub Diffusion);
+pub struct Atkinson(pub Diffusion);
+pub struct Ordered(pub Bayer);
+// the closest color per pixel, no dithering at all
+pub struct Threshold;
+
+// the built in algorithms by the name they go by on the command line. the
+// error diffusion ones spread their error by `diffusion`.
+pub fn by_name(name: &str, diffusion: Diffusion) -> Result<Box<dyn Ditherer>, String> {
+    Ok(match name {
+        "floyd-steinberg" | "fs" => Box::new(FloydSteinberg(diffusion)),
+        "atkinson" => Box::new(Atkinson(diffusion)),
+        "bayer4" => Box::new(Ordered(Bayer::X4)),
+        "bayer8" | "bayer" => Box::new(Ordered(Bayer::X8)),
+        "threshold" | "none" => Box::new(Threshold),
+        _ => {
+            return Err(format!(
+                "unknown dither '{name}' (expected floyd-steinberg, atkinson, bayer4, bayer8 or threshold)"
+            ))
+        }
+    })
+}
+
+impl Ditherer for FloydSteinberg {
+    fn name(&self) -> &str {
+        "floyd-steinberg"
+    }
+
+    fn dither_with(
+        &self,
+        img: &bmp::Image,
+        quantize: &(dyn Fn(usize, usize, Rgb) -> Color + Sync),
+    ) -> PaperImage {
+        diffuse(img, &FLOYD_STEINBERG, self.0, quantize)
+    }
+}
+
+impl Ditherer for Atkinson {
+    fn name(&self) -> &str {
+        "atkinson"
+    }
+
+    fn dither_with(
+        &self,
+        img: &bmp::Image,
+        quantize: &(dyn Fn(usize, usize, Rgb) -> Color + Sync),
+    ) -> PaperImage {
+        diffuse(img, &ATKINSON, self.0, quantize)
+    }
+}
+
+impl Ditherer for Ordered {
+    fn name(&self) -> &str {
+        match self.0 {
+            Bayer::X4 => "bayer4",
+            Bayer::X8 => "bayer8",
+        }
+    }
+
+    // flat areas get an even pattern instead of floyd-steinberg's worms
+    fn dither_with(
+        &self,
+        img: &bmp::Image,
+        quantize: &(dyn Fn(usize, usize, Rgb) -> Color + Sync),
+    ) -> PaperImage {
+        let mut out = PaperImage::new(Color::Clean);
+        per_pixel(&mut out.data[..], |x, y| {
+            let px: Rgb = img.get_pixel(x as u32, y as u32).into();
+            let t = self.0.threshold(x, y) * SPREAD;
+            let px = Rgb {
+                r: px.r + t,
+                g: px.g + t,
+                b: px.b + t,
+            };
+            quantize(x, y, px)
+        });
+        out
+    }
+}
+
+impl Ditherer for Threshold {
+    fn name(&self) -> &str {
+        "threshold"
+    }
+
+    fn dither_with(
+        &self,
+        img: &bmp::Image,
+        quantize: &(dyn Fn(usize, usize, Rgb) -> Color + Sync),
+    ) -> PaperImage {
+        let mut out = PaperImage::new(Color::Clean);
+        per_pixel(&mut out.data[..], |x, y| {
+            quantize(x, y, img.get_pixel(x as u32, y as u32).into())
+        });
+        out
+    }
+}
+
+// fills `out` with `pixel` for every x, y. no pixel depends on another,
+// so with the parallel feature the rows are shared out between the cores.
+fn per_pixel(out: &mut [Color], pixel: impl Fn(usize, usize) -> Color + Sync) {
+    #[cfg(feature = "parallel")]
+    parallel::per_pixel(out, &pixel);
+    #[cfg(not(feature = "parallel"))]
+    for (i, c) in out.iter_mut().enumerate() {
+        let width = SCREEN_WIDTH as usize;
+        *c = pixel(i % width, i / width);
+    }
+}
+
+// size of the threshold matrix for ordered dithering
+#[derive(Clone, Copy)]
+pub enum Bayer {
+    X4,
+    X8,
+}
+
+// how far the thresholds push a channel either way. about half the
+// distance between neighboring panel colors.
+const SPREAD: f32 = 128.0;
+
+impl Bayer {
+    fn size(self) -> usize {
+        match self {
+            Bayer::X4 => 4,
+            Bayer::X8 => 8,
+        }
+    }
+
+    // the threshold at (x, y) from -0.5 to 0.5. built up from the 2x2
+    // matrix, each level interleaving four copies of the one below.
+    fn threshold(self, x: usize, y: usize) -> f32 {
+        let n = self.size();
+        let (mut x, mut y) = (x % n, y % n);
+        let mut v = 0;
+        let mut bit = n * n / 4;
+        while bit > 0 {
+            v += bit * [0, 2, 3, 1][(x & 1) + (y & 1) * 2];
+            x >>= 1;
+            y >>= 1;
+            bit /= 4;
+        }
+        (v as f32 + 0.5) / (n * n) as f32 - 0.5
+    }
+}
+
+// what becomes of error diffused past the edge of the image. only pixels
+// not yet quantized can take it, so in practice this is about the left and
+// right edges: error pushed out of the last row has nowhere left to go.
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum Boundary {
+    // lost, so the pixels along the edges get less correction than the rest
+    #[default]
+    Drop,
+    // mirrored back in across the edge pixel
+    Reflect,
+    // carried over to the opposite edge
+    Wrap,
+}
+
+impl Boundary {
+    // where a neighbor at `n` lands in 0..len, None if its share is dropped
+    fn resolve(self, n: isize, len: usize) -> Option<usize> {
+        let len = len as isize;
+        let n = match self {
+            _ if (0..len).contains(&n) => n,
+            Boundary::Drop => return None,
+            Boundary::Reflect if n < 0 => -n,
+            Boundary::Reflect => 2 * (len - 1) - n,
+            Boundary::Wrap => n.rem_euclid(len),
+        };
+        // reflecting can still overshoot an image narrower than the kernel
+        (0..len).contains(&n).then_some(n as usize)
+    }
+}
+
+impl FromStr for Boundary {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "drop" => Ok(Boundary::Drop),
+            "reflect" => Ok(Boundary::Reflect),
+            "wrap" => Ok(Boundary::Wrap),
+            _ => Err(format!(
+                "unknown boundary '{s}' (expected drop, reflect or wrap)"
+            )),
+        }
+    }
+}
+
+// the values error is measured and spread in
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum Light {
+    // gamma encoded, as the image comes. half the error of a bright pixel
+    // isn't half as much light, so mid tones come out too dark.
+    #[default]
+    Srgb,
+    // linear light, scaled to 0-255 like srgb
+    Linear,
+}
+
+impl Light {
+    fn decode(self, px: Rgb) -> Rgb {
+        match self {
+            Light::Srgb => px,
+            Light::Linear => px.map(to_linear),
+        }
+    }
+
+    fn encode(self, px: Rgb) -> Rgb {
+        match self {
+            Light::Srgb => px,
+            Light::Linear => px.map(to_srgb),
+        }
+    }
+}
+
+// the srgb curve on 0-255. error can carry a value past either end, so the
+// curve carries on mirrored below 0 and along its last slope above 255
+// instead of clamping, keeping how far out it went.
+fn to_linear(c: f32) -> f32 {
+    let v = c.abs() / 255.0;
+    let lin = match v {
+        v if v <= 0.04045 => v / 12.92,
+        v if v <= 1.0 => ((v + 0.055) / 1.055).powf(2.4),
+        v => 1.0 + (v - 1.0) * 2.4 / 1.055,
+    };
+    255.0 * lin.copysign(c)
+}
+
+fn to_srgb(c: f32) -> f32 {
+    let v = c.abs() / 255.0;
+    let enc = match v {
+        v if v <= 0.0031308 => v * 12.92,
+        v if v <= 1.0 => 1.055 * v.powf(1.0 / 2.4) - 0.055,
+        v => 1.0 + (v - 1.0) * 1.055 / 2.4,
+    };
+    255.0 * enc.copysign(c)
+}
+
+// how an error diffusion ditherer goes over the image
+#[derive(Clone, Copy, Default)]
+pub struct Diffusion {
+    pub boundary: Boundary,
+    pub light: Light,
+    // every other row right to left with the kernel mirrored, which breaks
+    // up the diagonal worms a scan in one direction leaves
+    pub serpentine: bool,
+    // a band of rows per core, see `parallel`
+    #[cfg(feature = "parallel")]
+    pub parallel: bool,
+}
+
+// an error diffusion kernel: each neighbor at (dx, dy) gets weight / divisor
+// of the error. whatever the weights don't add up to is dropped.
+pub struct Kernel {
+    pub divisor: f32,
+    pub neighbors: &'static [(isize, usize, f32)],
+}
+
+pub const FLOYD_STEINBERG: Kernel = Kernel {
+    divisor: 16.0,
+    neighbors: &[(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)],
+};
+
+// 1/8 of the error to each of six neighbors and the remaining quarter
+// dropped, so highlights and shadows stay clean instead of filling up with
+// speckle
+pub const ATKINSON: Kernel = Kernel {
+    divisor: 8.0,
+    neighbors: &[
+        (1, 0, 1.0),
+        (2, 0, 1.0),
+        (-1, 1, 1.0),
+        (0, 1, 1.0),
+        (1, 1, 1.0),
+        (0, 2, 1.0),
+    ],
+};
+
+// quantizes top to bottom, pushing each pixel's error onto the neighbors
+// in `kernel`. in linear light the pixel and the color it got are both
+// decoded before the error between them is taken, while `quantize` still
+// sees srgb.
+pub fn diffuse(
+    img: &bmp::Image,
+    kernel: &Kernel,
+    diffusion: Diffusion,
+    quantize: impl Fn(usize, usize, Rgb) -> Color + Sync,
+) -> PaperImage {
+    let mut out = PaperImage::new(Color::Clean);
+    #[cfg(feature = "parallel")]
+    if diffusion.parallel {
+        parallel::diffuse(img, kernel, diffusion, &quantize, &mut out.data[..]);
+        return out;
+    }
+    diffuse_rows(
+        img,
+        kernel,
+        diffusion,
+        &quantize,
+        0,
+        0..SCREEN_HEIGHT as usize,
+        &mut out.data[..],
+    );
+    out
+}
+
+// dithers `rows` into `out` as if the image started at row `from`, so the
+// rows between take up error for the first of `rows` without being kept
+fn diffuse_rows(
+    img: &bmp::Image,
+    kernel: &Kernel,
+    diffusion: Diffusion,
+    quantize: &impl Fn(usize, usize, Rgb) -> Color,
+    from: usize,
+    rows: std::ops::Range<usize>,
+    out: &mut [Color],
+) {
+    let Diffusion {
+        boundary,
+        light,
+        serpentine,
+        ..
+    } = diffusion;
+    let width = SCREEN_WIDTH as usize;
+    let height = rows.end - from;
+    // on the heap, it's a few MB
+    let mut input: Vec<Rgb> = (0..width * height)
+        .map(|i| {
+            let (x, y) = (i % width, from + i / width);
+            light.decode(img.get_pixel(x as u32, y as u32).into())
+        })
+        .collect();
+    for y in 0..height {
+        let row = from + y;
+        let backwards = serpentine && row % 2 == 1;
+        for i in 0..width {
+            let x = if backwards { width - 1 - i } else { i };
+            let oldpixel = input[x + y * width];
+            let newpixel = quantize(x, row, light.encode(oldpixel));
+            if row >= rows.start {
+                out[x + (row - rows.start) * width] = newpixel;
+            }
+            let error = oldpixel - light.decode(Rgb::from(newpixel));
+            for &(dx, dy, weight) in kernel.neighbors {
+                let dx = if backwards { -dx } else { dx };
+                let nx = boundary.resolve(x as isize + dx, width);
+                let ny = boundary.resolve((y + dy) as isize, height);
+                if let (Some(nx), Some(ny)) = (nx, ny) {
+                    input[nx + ny * width] += Rgb {
+                        r: error.r * weight / kernel.divisor,
+                        g: error.g * weight / kernel.divisor,
+                        b: error.b * weight / kernel.divisor,
+                    };
+                }
+            }
+        }
+    }
+}
+
+// error diffusion with the image split into a band of rows per core. it is
+// sequential by nature, so each band starts a few rows early to build up
+// error like the rows above would have passed down, and only its own rows
+// are kept. the seams can still show faintly in flat areas.
+#[cfg(feature = "parallel")]
+mod parallel {
+    use rayon::prelude::*;
+
+    use super::*;
+
+    // rows dithered and thrown away above each band
+    const OVERLAP: usize = 16;
+
+    pub fn diffuse(
+        img: &bmp::Image,
+        kernel: &Kernel,
+        diffusion: Diffusion,
+        quantize: &(impl Fn(usize, usize, Rgb) -> Color + Sync),
+        out: &mut [Color],
+    ) {
+        let width = SCREEN_WIDTH as usize;
+        let height = SCREEN_HEIGHT as usize;
+        let band = height.div_ceil(rayon::current_num_threads());
+        out.par_chunks_mut(band * width)
+            .enumerate()
+            .for_each(|(i, out)| {
+                let start = i * band;
+                let rows = start..start + out.len() / width;
+                let from = start.saturating_sub(OVERLAP);
+                diffuse_rows(img, kernel, diffusion, quantize, from, rows, out);
+            });
+    }
+
+    pub fn per_pixel(out: &mut [Color], pixel: &(impl Fn(usize, usize) -> Color + Sync)) {
+        let width = SCREEN_WIDTH as usize;
+        out.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+            for (x, c) in row.iter_mut().enumerate() {
+                *c = pixel(x, y);
+            }
+        });
+    }
+}
+
+pub fn atkinson_dither(img: &bmp::Image) -> PaperImage {
+    atkinson_dither_with(img, |_, _, px| Color::closest(px))
+}
+
+pub fn atkinson_dither_with(
+    img: &bmp::Image,
+    quantize: impl Fn(usize, usize, Rgb) -> Color + Sync,
+) -> PaperImage {
+    diffuse(img, &ATKINSON, Diffusion::default(), quantize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout;
+
+    fn count(img: &PaperImage, color: Color) -> usize {
+        img.data.iter().filter(|&&c| c == color).count()
+    }
+
+    #[test]
+    fn bayer_thresholds_use_every_level_once() {
+        for bayer in [Bayer::X4, Bayer::X8] {
+            let n = bayer.size();
+            let mut levels: Vec<usize> = (0..n * n)
+                .map(|i| {
+                    let t = bayer.threshold(i % n, i / n);
+                    ((t + 0.5) * (n * n) as f32 - 0.5).round() as usize
+                })
+                .collect();
+            levels.sort();
+            assert_eq!(levels, (0..n * n).collect::<Vec<_>>());
+            // and the matrix tiles
+            assert_eq!(bayer.threshold(3, 2), bayer.threshold(3 + n, 2 + 2 * n));
+        }
+    }
+
+    // black and white only, so a grey can only become a mix of the two
+    fn mono(_: usize, _: usize, px: Rgb) -> Color {
+        Color::closest_in(px, &[Color::Black, Color::White])
+    }
+
+    #[test]
+    fn ordered_dithering_keeps_black_and_white_flat() {
+        for color in [Color::White, Color::Black] {
+            let out = Ordered(Bayer::X8).dither_with(&layout::blank(color), &mono);
+            assert_eq!(count(&out, color), out.data.len());
+        }
+    }
+
+    #[test]
+    fn ordered_dithering_mixes_a_mid_grey_evenly() {
+        let grey = bmp::Pixel::new(128, 128, 128);
+        let mut img = layout::blank(Color::White);
+        for (x, y) in img.coordinates() {
+            img.set_pixel(x, y, grey);
+        }
+        let out = Ordered(Bayer::X4).dither_with(&img, &mono);
+        let (black, white) = (count(&out, Color::Black), count(&out, Color::White));
+        assert_eq!(black + white, out.data.len());
+        // half and half, in the same pattern in every 4x4 tile
+        assert_eq!(black, white);
+        let width = SCREEN_WIDTH as usize;
+        assert!(out.data[5 + 6 * width] == out.data[1 + 2 * width]);
+    }
+
+    #[test]
+    fn ditherers_are_found_by_name() {
+        for name in [
+            "floyd-steinberg",
+            "atkinson",
+            "bayer4",
+            "bayer8",
+            "threshold",
+        ] {
+            let d = by_name(name, Diffusion::default()).unwrap();
+            assert_eq!(d.name(), name);
+        }
+        let alias = |name| {
+            by_name(name, Diffusion::default())
+                .unwrap()
+                .name()
+                .to_string()
+        };
+        assert_eq!(alias("fs"), "floyd-steinberg");
+        assert_eq!(alias("bayer"), "bayer8");
+        assert_eq!(alias("none"), "threshold");
+        assert!(by_name("jarvis", Diffusion::default())
+            .err()
+            .unwrap()
+            .contains("unknown dither 'jarvis'"));
+    }
+
+    #[test]
+    fn every_ditherer_hands_its_pixels_to_quantize() {
+        let img = layout::blank(Color::White);
+        for name in ["fs", "atkinson", "bayer4", "threshold"] {
+            let d = by_name(name, Diffusion::default()).unwrap();
+            let out = d.dither_with(&img, &|x, y, _| {
+                if (x + y) % 2 == 0 {
+                    Color::Red
+                } else {
+                    Color::Blue
+                }
+            });
+            assert!(out.data[0] == Color::Red, "{name}");
+            assert!(out.data[1] == Color::Blue, "{name}");
+            assert_eq!(count(&out, Color::Red), out.data.len() / 2, "{name}");
+        }
+    }
+
+    #[test]
+    fn boundaries_decide_where_error_past_the_edge_goes() {
+        for boundary in [Boundary::Drop, Boundary::Reflect, Boundary::Wrap] {
+            assert_eq!(boundary.resolve(3, 10), Some(3));
+        }
+        assert_eq!(Boundary::Drop.resolve(-1, 10), None);
+        assert_eq!(Boundary::Drop.resolve(10, 10), None);
+        assert_eq!(Boundary::Reflect.resolve(-1, 10), Some(1));
+        assert_eq!(Boundary::Reflect.resolve(11, 10), Some(7));
+        assert_eq!(Boundary::Wrap.resolve(-2, 10), Some(8));
+        assert_eq!(Boundary::Wrap.resolve(10, 10), Some(0));
+        // too far out to mirror back in
+        assert_eq!(Boundary::Reflect.resolve(2, 1), None);
+        assert!("reflect".parse::<Boundary>().unwrap() == Boundary::Reflect);
+        assert!("clamp".parse::<Boundary>().is_err());
+    }
+
+    #[test]
+    fn the_edges_keep_their_share_unless_dropped() {
+        let grey = bmp::Pixel::new(100, 100, 100);
+        let mut img = layout::blank(Color::Black);
+        for (x, y) in img.coordinates() {
+            img.set_pixel(x, y, grey);
+        }
+        let whites = |boundary| {
+            let diffusion = Diffusion {
+                boundary,
+                ..Diffusion::default()
+            };
+            count(
+                &diffuse(&img, &FLOYD_STEINBERG, diffusion, mono),
+                Color::White,
+            )
+        };
+        let (drop, reflect, wrap) = (
+            whites(Boundary::Drop),
+            whites(Boundary::Reflect),
+            whites(Boundary::Wrap),
+        );
+        // error dropped off the right and bottom edges leaves the picture
+        // a little darker than it should be
+        let expected = (img.get_width() * img.get_height() * 100 / 255) as usize;
+        assert!(drop < reflect && drop < wrap);
+        assert!(wrap <= expected);
+    }
+
+    #[test]
+    fn the_srgb_curve_round_trips() {
+        assert_eq!(to_linear(0.0), 0.0);
+        assert!((to_linear(255.0) - 255.0).abs() < 1e-3);
+        // mid grey is a fifth of the light of white
+        assert!((to_linear(128.0) - 55.0).abs() < 1.0);
+        for c in [-300.0, -40.0, -1.0, 1.0, 10.0, 128.0, 200.0, 254.0, 300.0] {
+            assert!((to_srgb(to_linear(c)) - c).abs() < 0.01, "{c}");
+        }
+        // past either end it keeps going instead of clamping
+        assert_eq!(to_linear(-64.0), -to_linear(64.0));
+        assert!(to_linear(300.0) > to_linear(260.0));
+    }
+
+    #[test]
+    fn mid_grey_in_linear_light_gets_a_fifth_white() {
+        let mut img = layout::blank(Color::Black);
+        for (x, y) in img.coordinates() {
+            img.set_pixel(x, y, bmp::Pixel::new(128, 128, 128));
+        }
+        let share = |light| {
+            let diffusion = Diffusion {
+                light,
+                ..Diffusion::default()
+            };
+            let whites = count(
+                &diffuse(&img, &FLOYD_STEINBERG, diffusion, mono),
+                Color::White,
+            );
+            whites as f32 / (img.get_width() * img.get_height()) as f32
+        };
+        assert!((share(Light::Srgb) - 0.5).abs() < 0.01);
+        assert!((share(Light::Linear) - 0.216).abs() < 0.01);
+    }
+}
@@ -1,8 +1,10 @@
+use std::{fmt, str::FromStr, sync::OnceLock};
+
 use rand::prelude::*;
 
-use crate::{Rgb, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::{error::EpaperError, layout, Geometry, Rgb, SCREEN_HEIGHT, SCREEN_WIDTH};
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum Color {
     Black = 0x00,  // 0, 0, 0
@@ -29,33 +31,319 @@ impl Color {
     }
 
     pub fn closest(pixel: Rgb) -> Color {
-        Color::all()
+        Color::closest_in(pixel, Color::all())
+    }
+
+    // closest of only the given colors, which must not be empty
+    pub fn closest_in(pixel: Rgb, colors: &[Color]) -> Color {
+        Color::closest_by(pixel, colors, Metric::Rgb)
+    }
+
+    pub fn closest_perceptual(pixel: Rgb) -> Color {
+        Color::closest_perceptual_in(pixel, Color::all())
+    }
+
+    pub fn closest_perceptual_in(pixel: Rgb, colors: &[Color]) -> Color {
+        Color::closest_by(pixel, colors, Metric::Redmean)
+    }
+
+    // closest of the given colors as `metric` measures it
+    pub fn closest_by(pixel: Rgb, colors: &[Color], metric: Metric) -> Color {
+        // the pixel's side of a lab distance only needs converting once
+        let lab_pixel = metric.is_lab().then(|| lab([pixel.r, pixel.g, pixel.b]));
+        colors
             .iter()
             .map(|c| -> (f32, Color) {
                 let [r, g, b] = c.as_rgb();
-                let dr = pixel.r - r as f32;
-                let dg = pixel.g as f32 - g as f32;
-                let db = pixel.b as f32 - b as f32;
-                let ed = dr * dr + dg * dg + db * db;
-                (ed, *c)
+                let d = match (metric, lab_pixel) {
+                    (Metric::Rgb, _) => {
+                        let dr = pixel.r - r;
+                        let dg = pixel.g - g;
+                        let db = pixel.b - b;
+                        dr * dr + dg * dg + db * db
+                    }
+                    (Metric::Redmean, _) => {
+                        let rmean = (pixel.r + r) / 2.0;
+                        let dr = pixel.r - r;
+                        let dg = pixel.g - g;
+                        let db = pixel.b - b;
+                        (2.0 + rmean / 256.0) * dr * dr
+                            + 4.0 * dg * dg
+                            + (2.0 + (255.0 - rmean) / 256.0) * db * db
+                    }
+                    (Metric::Cie76, Some(p)) => {
+                        let [l, a, b] = lab([r, g, b]);
+                        (p[0] - l).powi(2) + (p[1] - a).powi(2) + (p[2] - b).powi(2)
+                    }
+                    (_, Some(p)) => ciede2000(p, lab([r, g, b])),
+                    (_, None) => unreachable!("lab metrics convert the pixel"),
+                };
+                (d, *c)
             })
             .min_by(|(d1, _), (d2, _)| d1.total_cmp(d2))
             .unwrap()
             .1
     }
 
+    pub fn name(&self) -> &'static str {
+        match self {
+            Color::Black => "black",
+            Color::White => "white",
+            Color::Green => "green",
+            Color::Blue => "blue",
+            Color::Red => "red",
+            Color::Yellow => "yellow",
+            Color::Orange => "orange",
+            Color::Clean => "clean",
+        }
+    }
+
+    // the color as the panel actually shows it, after calibration
     pub fn as_rgb(&self) -> [f32; 3] {
-        const LOOKUP: &'static [[f32; 3]] = &[
-            [0.0, 0.0, 0.0],
-            [255.0, 255.0, 255.0],
-            [0.0, 255.0, 0.0],
-            [0.0, 0.0, 255.0],
-            [255.0, 0.0, 0.0],
-            [255.0, 255.0, 0.0],
-            [255.0, 170.0, 0.0],
-            [180.0, 180.0, 180.0],
-        ];
-        LOOKUP[*self as usize]
+        PALETTE.get().unwrap_or(&NOMINAL_PALETTE)[*self as usize]
+    }
+
+    // the nominal rgb value, ignoring calibration
+    pub fn nominal_rgb(&self) -> [f32; 3] {
+        NOMINAL_PALETTE[*self as usize]
+    }
+}
+
+// how far a pixel is from a palette color, for picking the closest
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum Metric {
+    // euclidean in srgb. cheap, but skin tones and sky land on the wrong
+    // colors
+    #[default]
+    Rgb,
+    // "redmean" weighted rgb, a cheap approximation of perceived
+    // difference that weights channels by how red the pair is
+    Redmean,
+    // euclidean in cielab
+    Cie76,
+    // cielab corrected for how differences in lightness, chroma and hue
+    // are perceived. the closest to the eye, and the slowest.
+    Ciede2000,
+}
+
+impl Metric {
+    pub fn name(self) -> &'static str {
+        match self {
+            Metric::Rgb => "rgb",
+            Metric::Redmean => "redmean",
+            Metric::Cie76 => "cie76",
+            Metric::Ciede2000 => "ciede2000",
+        }
+    }
+
+    fn is_lab(self) -> bool {
+        matches!(self, Metric::Cie76 | Metric::Ciede2000)
+    }
+}
+
+impl FromStr for Metric {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rgb" => Ok(Metric::Rgb),
+            "redmean" => Ok(Metric::Redmean),
+            "cie76" | "lab" => Ok(Metric::Cie76),
+            "ciede2000" => Ok(Metric::Ciede2000),
+            _ => Err(format!(
+                "unknown metric '{s}' (expected rgb, redmean, cie76 or ciede2000)"
+            )),
+        }
+    }
+}
+
+// srgb 0-255 to cielab under d65. diffused error can push a channel past
+// either end, so it's clamped before the gamma is undone.
+fn lab(rgb: [f32; 3]) -> [f32; 3] {
+    let [r, g, b] = rgb.map(|c| {
+        let c = (c / 255.0).clamp(0.0, 1.0);
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    });
+    // linear rgb to xyz, relative to the white point
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+    let f = |t: f32| {
+        if t > 216.0 / 24389.0 {
+            t.cbrt()
+        } else {
+            (24389.0 / 27.0 * t + 16.0) / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+// sharma, wu and dalal's formulation, in degrees like the paper
+fn ciede2000([l1, a1, b1]: [f32; 3], [l2, a2, b2]: [f32; 3]) -> f32 {
+    let pow7 = |c: f32| c.powi(7);
+    let cbar = (a1.hypot(b1) + a2.hypot(b2)) / 2.0;
+    let g = 0.5 * (1.0 - (pow7(cbar) / (pow7(cbar) + pow7(25.0))).sqrt());
+    let (a1, a2) = (a1 * (1.0 + g), a2 * (1.0 + g));
+    let (c1, c2) = (a1.hypot(b1), a2.hypot(b2));
+    let hue = |b: f32, a: f32| {
+        if a == 0.0 && b == 0.0 {
+            0.0
+        } else {
+            b.atan2(a).to_degrees().rem_euclid(360.0)
+        }
+    };
+    let (h1, h2) = (hue(b1, a1), hue(b2, a2));
+    let neutral = c1 * c2 == 0.0;
+    let dl = l2 - l1;
+    let dc = c2 - c1;
+    let dh = match h2 - h1 {
+        _ if neutral => 0.0,
+        d if d > 180.0 => d - 360.0,
+        d if d < -180.0 => d + 360.0,
+        d => d,
+    };
+    let dh = 2.0 * (c1 * c2).sqrt() * (dh.to_radians() / 2.0).sin();
+    let lbar = (l1 + l2) / 2.0;
+    let cbar = (c1 + c2) / 2.0;
+    let hbar = match h1 + h2 {
+        sum if neutral => sum,
+        sum if (h1 - h2).abs() <= 180.0 => sum / 2.0,
+        sum if sum < 360.0 => (sum + 360.0) / 2.0,
+        sum => (sum - 360.0) / 2.0,
+    };
+    let cos = |deg: f32| deg.to_radians().cos();
+    let t = 1.0 - 0.17 * cos(hbar - 30.0) + 0.24 * cos(2.0 * hbar) + 0.32 * cos(3.0 * hbar + 6.0)
+        - 0.20 * cos(4.0 * hbar - 63.0);
+    let rotation = 30.0 * (-((hbar - 275.0) / 25.0).powi(2)).exp();
+    let rc = 2.0 * (pow7(cbar) / (pow7(cbar) + pow7(25.0))).sqrt();
+    let sl = 1.0 + 0.015 * (lbar - 50.0).powi(2) / (20.0 + (lbar - 50.0).powi(2)).sqrt();
+    let sc = 1.0 + 0.045 * cbar;
+    let sh = 1.0 + 0.015 * cbar * t;
+    let rt = -(2.0 * rotation).to_radians().sin() * rc;
+    let (dl, dc, dh) = (dl / sl, dc / sc, dh / sh);
+    (dl * dl + dc * dc + dh * dh + rt * dc * dh).sqrt()
+}
+
+// how each color looks on the panel, indexed by its device code. a
+// palette file calibrates it.
+pub type Calibration = [[f32; 3]; 8];
+
+const NOMINAL_PALETTE: Calibration = [
+    [0.0, 0.0, 0.0],
+    [255.0, 255.0, 255.0],
+    [0.0, 255.0, 0.0],
+    [0.0, 0.0, 255.0],
+    [255.0, 0.0, 0.0],
+    [255.0, 255.0, 0.0],
+    [255.0, 170.0, 0.0],
+    [180.0, 180.0, 180.0],
+];
+
+static PALETTE: OnceLock<Calibration> = OnceLock::new();
+
+// replaces the palette used for color matching. only the first call has
+// any effect, so this should happen once at startup.
+pub fn set_palette(palette: Calibration) {
+    let _ = PALETTE.set(palette);
+}
+
+impl FromStr for Color {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Color::all()
+            .iter()
+            .find(|c| c.name() == s)
+            .copied()
+            .ok_or_else(|| format!("unknown color '{s}'"))
+    }
+}
+
+// the device codes 0x08 to 0x0f are reserved, anything above doesn't fit
+// in a nibble
+impl TryFrom<u8> for Color {
+    type Error = EpaperError;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0x00 => Color::Black,
+            0x01 => Color::White,
+            0x02 => Color::Green,
+            0x03 => Color::Blue,
+            0x04 => Color::Red,
+            0x05 => Color::Yellow,
+            0x06 => Color::Orange,
+            0x07 => Color::Clean,
+            _ => return Err(EpaperError::InvalidColor(value)),
+        })
+    }
+}
+
+// a device code known to be valid, as packed into each nibble sent to the
+// panel
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct PaletteIndex(Color);
+
+impl PaletteIndex {
+    pub fn get(self) -> u8 {
+        self.0 as u8
+    }
+}
+
+impl TryFrom<u8> for PaletteIndex {
+    type Error = EpaperError;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Color::try_from(value).map(PaletteIndex)
+    }
+}
+
+impl From<Color> for PaletteIndex {
+    fn from(value: Color) -> Self {
+        PaletteIndex(value)
+    }
+}
+
+impl From<PaletteIndex> for Color {
+    fn from(value: PaletteIndex) -> Self {
+        value.0
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner {
+    // top-left position of a w*h box inset `margin` px from this corner of
+    // an `area` sized image
+    pub fn place(&self, area: Geometry, w: u16, h: u16, margin: u16) -> (u16, u16) {
+        let right = area.width.saturating_sub(w + margin);
+        let bottom = area.height.saturating_sub(h + margin);
+        match self {
+            Corner::TopLeft => (margin, margin),
+            Corner::TopRight => (right, margin),
+            Corner::BottomLeft => (margin, bottom),
+            Corner::BottomRight => (right, bottom),
+        }
+    }
+}
+
+impl FromStr for Corner {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tl" | "top-left" => Ok(Corner::TopLeft),
+            "tr" | "top-right" => Ok(Corner::TopRight),
+            "bl" | "bottom-left" => Ok(Corner::BottomLeft),
+            "br" | "bottom-right" => Ok(Corner::BottomRight),
+            _ => Err(format!("unknown corner '{s}' (expected tl, tr, bl or br)")),
+        }
     }
 }
 
@@ -74,8 +362,222 @@ pub struct Partial<'a, D: Drawable> {
     pub h: u16,
     pub rest: &'a D,
 }
+// `inner` shown in a w*h window at x, y with its own top-left at the
+// window's, and `rest` everywhere else
+pub struct Window<'a, A: Drawable + ?Sized, B: Drawable + ?Sized> {
+    pub x: u16,
+    pub y: u16,
+    pub w: u16,
+    pub h: u16,
+    pub inner: &'a A,
+    pub rest: &'a B,
+}
+// clockwise
+#[derive(Clone, Copy, PartialEq)]
+pub enum Rotation {
+    R90,
+    R180,
+    R270,
+}
+// how a closed shape is drawn
+#[derive(Clone, Copy)]
+pub enum Paint {
+    Fill,
+    // an outline this many px wide, inside the shape's edge
+    Stroke(u16),
+}
+// shapes drawn over `rest`. coordinates may be off screen.
+pub struct Line<'a, D: Drawable + ?Sized> {
+    pub from: (i32, i32),
+    pub to: (i32, i32),
+    pub width: u16,
+    pub color: Color,
+    pub rest: &'a D,
+}
+pub struct Rect<'a, D: Drawable + ?Sized> {
+    pub x: i32,
+    pub y: i32,
+    pub w: u16,
+    pub h: u16,
+    pub paint: Paint,
+    pub color: Color,
+    pub rest: &'a D,
+}
+pub struct Circle<'a, D: Drawable + ?Sized> {
+    pub center: (i32, i32),
+    pub radius: u16,
+    pub paint: Paint,
+    pub color: Color,
+    pub rest: &'a D,
+}
+// filled by the even-odd rule, so self-intersecting outlines leave holes
+pub struct Polygon<'a, D: Drawable + ?Sized> {
+    pub points: Vec<(i32, i32)>,
+    pub paint: Paint,
+    pub color: Color,
+    pub rest: &'a D,
+}
+// mirrors the framebuffer, independent of the panel's ud/shl bits. only the
+// panel's `geometry` is mirrored, for a panel smaller than the screen.
+pub struct Flipped<'a, D: Drawable + ?Sized> {
+    pub horizontal: bool,
+    pub vertical: bool,
+    pub geometry: Geometry,
+    pub rest: &'a D,
+}
+const PIXELS: usize = SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize;
+
+// a whole frame, one color per pixel. on the heap, it's over 250k pixels
+// and threads get small stacks.
 pub struct PaperImage {
-    pub data: [Color; SCREEN_HEIGHT as usize * SCREEN_WIDTH as usize],
+    pub data: Box<[Color; PIXELS]>,
+}
+
+impl PaperImage {
+    // every pixel `color`
+    pub fn new(color: Color) -> Self {
+        Self::from_vec(vec![color; PIXELS])
+    }
+
+    // like new, but running out of memory is an error rather than an abort
+    pub fn try_new(color: Color) -> Result<Self, EpaperError> {
+        let mut data = Vec::new();
+        data.try_reserve_exact(PIXELS)
+            .map_err(|_| EpaperError::OutOfMemory(PIXELS * std::mem::size_of::<Color>()))?;
+        data.resize(PIXELS, color);
+        Ok(Self::from_vec(data))
+    }
+
+    fn from_vec(data: Vec<Color>) -> Self {
+        match data.into_boxed_slice().try_into() {
+            Ok(data) => Self { data },
+            Err(_) => unreachable!("frame buffers are always screen sized"),
+        }
+    }
+
+    // snapshots any drawable
+    pub fn from_drawable(d: &(impl Drawable + ?Sized)) -> Self {
+        let mut img = Self::new(Color::Clean);
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                img.data[x as usize + y as usize * SCREEN_WIDTH as usize] = d.get_pixel(x, y);
+            }
+        }
+        img
+    }
+
+    fn idx(x: usize, y: usize) -> usize {
+        x + y * SCREEN_WIDTH as usize
+    }
+
+    // replaces every pixel of one color with another
+    pub fn remap(&mut self, from: Color, to: Color) {
+        for c in self.data.iter_mut().filter(|c| **c == from) {
+            *c = to;
+        }
+    }
+
+    // replaces pixels that match none of their 4 neighbours with the most
+    // common neighbour, removing the lone dots dithering leaves in flat
+    // areas. returns how many changed.
+    pub fn despeckle(&mut self) -> usize {
+        let (w, h) = (SCREEN_WIDTH as usize, SCREEN_HEIGHT as usize);
+        let src = self.data.to_vec();
+        let mut changed = 0;
+        for y in 1..h - 1 {
+            for x in 1..w - 1 {
+                let c = src[Self::idx(x, y)];
+                let around = [
+                    src[Self::idx(x - 1, y)],
+                    src[Self::idx(x + 1, y)],
+                    src[Self::idx(x, y - 1)],
+                    src[Self::idx(x, y + 1)],
+                ];
+                if around.contains(&c) {
+                    continue;
+                }
+                let majority = *around
+                    .iter()
+                    .max_by_key(|n| around.iter().filter(|m| m == n).count())
+                    .unwrap();
+                self.data[Self::idx(x, y)] = majority;
+                changed += 1;
+            }
+        }
+        changed
+    }
+
+    // fills a rectangle, clipped to the frame
+    pub fn fill_rect(&mut self, rect: layout::Rect, color: Color) {
+        let x1 = (rect.x + rect.w).min(SCREEN_WIDTH as u32) as usize;
+        let y1 = (rect.y + rect.h).min(SCREEN_HEIGHT as u32) as usize;
+        for y in rect.y as usize..y1 {
+            for x in rect.x as usize..x1 {
+                self.data[Self::idx(x, y)] = color;
+            }
+        }
+    }
+
+    // recolors the 4-connected area of the same color around (x, y)
+    pub fn flood_fill(&mut self, x: u16, y: u16, color: Color) {
+        if x >= SCREEN_WIDTH || y >= SCREEN_HEIGHT {
+            return;
+        }
+        let target = self.data[Self::idx(x as usize, y as usize)];
+        if target == color {
+            return;
+        }
+        let mut stack = vec![(x as usize, y as usize)];
+        while let Some((x, y)) = stack.pop() {
+            let i = Self::idx(x, y);
+            if self.data[i] != target {
+                continue;
+            }
+            self.data[i] = color;
+            if x > 0 {
+                stack.push((x - 1, y));
+            }
+            if x + 1 < SCREEN_WIDTH as usize {
+                stack.push((x + 1, y));
+            }
+            if y > 0 {
+                stack.push((x, y - 1));
+            }
+            if y + 1 < SCREEN_HEIGHT as usize {
+                stack.push((x, y + 1));
+            }
+        }
+    }
+}
+
+// each pixel stored as its palette index, so quantized frames can go
+// through standard image tooling and come back without re-dithering
+impl From<&PaperImage> for image::GrayImage {
+    fn from(value: &PaperImage) -> Self {
+        image::GrayImage::from_fn(SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32, |x, y| {
+            image::Luma([PaletteIndex::from(value.get_pixel(x as u16, y as u16)).get()])
+        })
+    }
+}
+
+impl TryFrom<&image::GrayImage> for PaperImage {
+    type Error = EpaperError;
+    fn try_from(value: &image::GrayImage) -> Result<Self, Self::Error> {
+        let expected = (SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+        if value.dimensions() != expected {
+            return Err(EpaperError::Dimensions {
+                expected,
+                actual: value.dimensions(),
+            });
+        }
+        let mut img = PaperImage::try_new(Color::Clean)?;
+        for (x, y, px) in value.enumerate_pixels() {
+            let index = px.0[0];
+            img.data[x as usize + y as usize * SCREEN_WIDTH as usize] = Color::try_from(index)
+                .map_err(|e| EpaperError::Decode(format!("{e} at {x},{y}")))?;
+        }
+        Ok(img)
+    }
 }
 
 impl Drawable for PaperImage {
@@ -96,6 +598,159 @@ impl<D: Drawable> Drawable for Partial<'_, D> {
     }
 }
 
+impl FromStr for Rotation {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "90" => Ok(Rotation::R90),
+            "180" => Ok(Rotation::R180),
+            "270" => Ok(Rotation::R270),
+            _ => Err(format!("unknown rotation '{s}' (expected 90, 180 or 270)")),
+        }
+    }
+}
+
+impl fmt::Display for Rotation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let degrees = match self {
+            Rotation::R90 => 90,
+            Rotation::R180 => 180,
+            Rotation::R270 => 270,
+        };
+        write!(f, "{degrees}")
+    }
+}
+
+impl<A: Drawable + ?Sized, B: Drawable + ?Sized> Drawable for Window<'_, A, B> {
+    fn get_pixel(&self, x: u16, y: u16) -> Color {
+        if x >= self.x && y >= self.y && x < self.x + self.w && y < self.y + self.h {
+            self.inner.get_pixel(x - self.x, y - self.y)
+        } else {
+            self.rest.get_pixel(x, y)
+        }
+    }
+}
+
+// from the pixel's center to the closest point of the segment a-b
+fn segment_distance((x, y): (f32, f32), a: (i32, i32), b: (i32, i32)) -> f32 {
+    let (ax, ay, bx, by) = (a.0 as f32, a.1 as f32, b.0 as f32, b.1 as f32);
+    let (dx, dy) = (bx - ax, by - ay);
+    let len2 = dx * dx + dy * dy;
+    let t = if len2 == 0.0 {
+        0.0
+    } else {
+        (((x - ax) * dx + (y - ay) * dy) / len2).clamp(0.0, 1.0)
+    };
+    (x - (ax + t * dx)).hypot(y - (ay + t * dy))
+}
+
+fn center(x: u16, y: u16) -> (f32, f32) {
+    (x as f32 + 0.5, y as f32 + 0.5)
+}
+
+impl<D: Drawable + ?Sized> Drawable for Line<'_, D> {
+    fn get_pixel(&self, x: u16, y: u16) -> Color {
+        let half = self.width.max(1) as f32 / 2.0;
+        if segment_distance(center(x, y), self.from, self.to) <= half {
+            self.color
+        } else {
+            self.rest.get_pixel(x, y)
+        }
+    }
+}
+
+impl<D: Drawable + ?Sized> Drawable for Rect<'_, D> {
+    fn get_pixel(&self, x: u16, y: u16) -> Color {
+        let (dx, dy) = (x as i32 - self.x, y as i32 - self.y);
+        let (w, h) = (self.w as i32, self.h as i32);
+        let inside = (0..w).contains(&dx) && (0..h).contains(&dy);
+        let hit = match self.paint {
+            Paint::Fill => inside,
+            Paint::Stroke(s) => {
+                let s = s as i32;
+                inside && (dx < s || dy < s || dx >= w - s || dy >= h - s)
+            }
+        };
+        if hit {
+            self.color
+        } else {
+            self.rest.get_pixel(x, y)
+        }
+    }
+}
+
+impl<D: Drawable + ?Sized> Drawable for Circle<'_, D> {
+    fn get_pixel(&self, x: u16, y: u16) -> Color {
+        let (px, py) = center(x, y);
+        let d = (px - self.center.0 as f32).hypot(py - self.center.1 as f32);
+        let r = self.radius as f32;
+        let hit = match self.paint {
+            Paint::Fill => d <= r,
+            Paint::Stroke(s) => d <= r && d > r - s as f32,
+        };
+        if hit {
+            self.color
+        } else {
+            self.rest.get_pixel(x, y)
+        }
+    }
+}
+
+impl<D: Drawable + ?Sized> Polygon<'_, D> {
+    fn edges(&self) -> impl Iterator<Item = ((i32, i32), (i32, i32))> + '_ {
+        let next = self.points.iter().cycle().skip(1);
+        self.points.iter().copied().zip(next.copied())
+    }
+
+    // even-odd rule, casting a ray to the right of the pixel's center
+    fn contains(&self, (x, y): (f32, f32)) -> bool {
+        self.edges()
+            .filter(|&((ax, ay), (bx, by))| {
+                let (ax, ay, bx, by) = (ax as f32, ay as f32, bx as f32, by as f32);
+                (ay > y) != (by > y) && x < ax + (y - ay) * (bx - ax) / (by - ay)
+            })
+            .count()
+            % 2
+            == 1
+    }
+}
+
+impl<D: Drawable + ?Sized> Drawable for Polygon<'_, D> {
+    fn get_pixel(&self, x: u16, y: u16) -> Color {
+        let p = center(x, y);
+        let hit = self.points.len() >= 3
+            && self.contains(p)
+            && match self.paint {
+                Paint::Fill => true,
+                Paint::Stroke(s) => self
+                    .edges()
+                    .any(|(a, b)| segment_distance(p, a, b) < s as f32),
+            };
+        if hit {
+            self.color
+        } else {
+            self.rest.get_pixel(x, y)
+        }
+    }
+}
+
+impl<D: Drawable + ?Sized> Drawable for Flipped<'_, D> {
+    fn get_pixel(&self, x: u16, y: u16) -> Color {
+        let Geometry { width, height } = self.geometry;
+        let x = if self.horizontal && x < width {
+            width - 1 - x
+        } else {
+            x
+        };
+        let y = if self.vertical && y < height {
+            height - 1 - y
+        } else {
+            y
+        };
+        self.rest.get_pixel(x, y)
+    }
+}
+
 impl Drawable for SequentialColors {
     fn get_pixel(&self, x: u16, y: u16) -> Color {
         let i = ((x / 10) + (y / 2)) % Color::all().len() as u16;
@@ -120,8 +775,268 @@ impl Drawable for RandomColors {
     }
 }
 
+impl<D: Drawable + ?Sized> Drawable for &D {
+    fn get_pixel(&self, x: u16, y: u16) -> Color {
+        (**self).get_pixel(x, y)
+    }
+}
+
 impl Drawable for SolidColor {
     fn get_pixel(&self, _x: u16, _y: u16) -> Color {
         self.0
     }
 }
+
+// a snapshot of conway's game of life after some generations from a
+// seeded random soup, on a wrapping grid
+pub struct Life {
+    cells: Vec<bool>,
+    cols: u16,
+    cell: u16,
+    pub alive: Color,
+    pub dead: Color,
+}
+
+impl Life {
+    pub fn new(seed: u64, generations: u32, cell: u16) -> Self {
+        let cell = cell.max(1);
+        let (cols, rows) = (SCREEN_WIDTH / cell, SCREEN_HEIGHT / cell);
+        let (w, h) = (cols as usize, rows as usize);
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut cells: Vec<bool> = (0..w * h).map(|_| rng.gen_bool(0.3)).collect();
+        for _ in 0..generations {
+            let mut next = vec![false; w * h];
+            for y in 0..h {
+                for x in 0..w {
+                    let mut n = 0;
+                    for (dx, dy) in [
+                        (w - 1, h - 1),
+                        (0, h - 1),
+                        (1, h - 1),
+                        (w - 1, 0),
+                        (1, 0),
+                        (w - 1, 1),
+                        (0, 1),
+                        (1, 1),
+                    ] {
+                        n += cells[(x + dx) % w + ((y + dy) % h) * w] as u8;
+                    }
+                    let alive = cells[x + y * w];
+                    next[x + y * w] = n == 3 || (alive && n == 2);
+                }
+            }
+            cells = next;
+        }
+        Self {
+            cells,
+            cols,
+            cell,
+            alive: Color::Black,
+            dead: Color::White,
+        }
+    }
+}
+
+impl Drawable for Life {
+    fn get_pixel(&self, x: u16, y: u16) -> Color {
+        let (cx, cy) = (x / self.cell, y / self.cell);
+        match self
+            .cells
+            .get(cx as usize + cy as usize * self.cols as usize)
+        {
+            Some(true) if cx < self.cols => self.alive,
+            _ => self.dead,
+        }
+    }
+}
+
+// classic 2d perlin gradient noise over a seeded permutation table
+struct Perlin {
+    perm: [u8; 512],
+}
+
+impl Perlin {
+    fn new(seed: u64) -> Self {
+        let mut p: Vec<u8> = (0..=255).collect();
+        p.shuffle(&mut StdRng::seed_from_u64(seed));
+        let mut perm = [0; 512];
+        for i in 0..512 {
+            perm[i] = p[i % 256];
+        }
+        Self { perm }
+    }
+
+    fn grad(hash: u8, x: f32, y: f32) -> f32 {
+        match hash & 3 {
+            0 => x + y,
+            1 => -x + y,
+            2 => x - y,
+            _ => -x - y,
+        }
+    }
+
+    // roughly in -1.0..1.0
+    fn noise(&self, x: f32, y: f32) -> f32 {
+        let fade = |t: f32| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+        let lerp = |a: f32, b: f32, t: f32| a + t * (b - a);
+        let (xi, yi) = (x.floor() as i32 & 255, y.floor() as i32 & 255);
+        let (xf, yf) = (x - x.floor(), y - y.floor());
+        let (u, v) = (fade(xf), fade(yf));
+        let p = |i: i32| self.perm[i as usize] as i32;
+        let aa = p(p(xi) + yi) as u8;
+        let ab = p(p(xi) + yi + 1) as u8;
+        let ba = p(p(xi + 1) + yi) as u8;
+        let bb = p(p(xi + 1) + yi + 1) as u8;
+        lerp(
+            lerp(Self::grad(aa, xf, yf), Self::grad(ba, xf - 1.0, yf), u),
+            lerp(
+                Self::grad(ab, xf, yf - 1.0),
+                Self::grad(bb, xf - 1.0, yf - 1.0),
+                u,
+            ),
+            v,
+        )
+    }
+
+    // a few octaves of noise summed together
+    fn fbm(&self, x: f32, y: f32) -> f32 {
+        let (mut sum, mut amp, mut freq) = (0.0, 1.0, 1.0);
+        for _ in 0..5 {
+            sum += amp * self.noise(x * freq, y * freq);
+            amp *= 0.5;
+            freq *= 2.0;
+        }
+        sum
+    }
+}
+
+// a top-down terrain map with water, beaches, forest, hills and snow,
+// banded from a perlin noise height field
+pub struct Landscape {
+    data: Vec<Color>,
+}
+
+impl Landscape {
+    pub fn new(seed: u64) -> Self {
+        let perlin = Perlin::new(seed);
+        let mut data = Vec::with_capacity(SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize);
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                let h = perlin.fbm(x as f32 / 160.0, y as f32 / 160.0);
+                data.push(match h {
+                    h if h < -0.1 => Color::Blue,
+                    h if h < -0.02 => Color::Yellow,
+                    h if h < 0.25 => Color::Green,
+                    h if h < 0.45 => Color::Orange,
+                    _ => Color::White,
+                });
+            }
+        }
+        Self { data }
+    }
+}
+
+impl Drawable for Landscape {
+    fn get_pixel(&self, x: u16, y: u16) -> Color {
+        self.data[x as usize + y as usize * SCREEN_WIDTH as usize]
+    }
+}
+
+// smith truchet tiles: each tile holds two quarter circle arcs in one of
+// two orientations, which join into meandering paths
+pub struct Truchet {
+    pub seed: u64,
+    pub tile: u16,
+    pub fg: Color,
+    pub bg: Color,
+}
+
+impl Drawable for Truchet {
+    fn get_pixel(&self, x: u16, y: u16) -> Color {
+        let t = self.tile.max(4) as f32;
+        let (tx, ty) = (x / self.tile.max(4), y / self.tile.max(4));
+        let (mut lx, ly) = (x as f32 % t, y as f32 % t);
+        // splitmix64 of the tile position, so no rng state is needed
+        let mut h = self.seed ^ ((tx as u64) << 32 | ty as u64);
+        h = h.wrapping_add(0x9E3779B97F4A7C15);
+        h = (h ^ (h >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94D049BB133111EB);
+        h ^= h >> 31;
+        if h & 1 == 1 {
+            lx = t - lx;
+        }
+        let half = t / 2.0;
+        let d1 = (lx * lx + ly * ly).sqrt();
+        let d2 = ((t - lx).powi(2) + (t - ly).powi(2)).sqrt();
+        let stroke = t / 8.0;
+        if (d1 - half).abs() < stroke || (d2 - half).abs() < stroke {
+            self.fg
+        } else {
+            self.bg
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::panel::{Bwr420, Panel};
+
+    // pairs from sharma, wu and dalal's ciede2000 test data
+    #[test]
+    fn ciede2000_matches_the_reference_pairs() {
+        let pairs = [
+            ([50.0, 2.6772, -79.7751], [50.0, 0.0, -82.7485], 2.0425),
+            ([50.0, -1.3802, -84.2814], [50.0, 0.0, -82.7485], 1.0000),
+            ([50.0, 2.49, -0.001], [50.0, -2.49, 0.0009], 7.1792),
+            ([50.0, 2.5, 0.0], [73.0, 25.0, -18.0], 27.1492),
+            ([50.0, 2.5, 0.0], [50.0, 3.1736, 0.5854], 1.0000),
+            (
+                [60.2574, -34.0099, 36.2677],
+                [60.4626, -34.1751, 39.4387],
+                1.2644,
+            ),
+            (
+                [2.0776, 0.0795, -1.1350],
+                [0.9033, -0.0636, -0.5514],
+                0.9082,
+            ),
+        ];
+        for (a, b, want) in pairs {
+            let got = ciede2000(a, b);
+            assert!((got - want).abs() < 1e-3, "{a:?} {b:?}: {got}, want {want}");
+            let back = ciede2000(b, a);
+            assert!((back - got).abs() < 1e-4, "not symmetric for {a:?} {b:?}");
+        }
+        assert_eq!(ciede2000([50.0, 10.0, 10.0], [50.0, 10.0, 10.0]), 0.0);
+    }
+
+    #[test]
+    fn life_reaches_the_last_cell_with_small_cells() {
+        for cell in [1, 2] {
+            let life = Life::new(7, 3, cell);
+            let last = *life.cells.last().unwrap();
+            let want = if last { life.alive } else { life.dead };
+            let pixel = life.get_pixel(SCREEN_WIDTH - 1, SCREEN_HEIGHT - 1);
+            assert!(pixel == want, "cell {cell}");
+        }
+    }
+
+    #[test]
+    fn flipped_mirrors_across_a_smaller_panel() {
+        let mut img = PaperImage::new(Color::White);
+        img.data[0] = Color::Red;
+        let flipped = |horizontal, vertical| Flipped {
+            horizontal,
+            vertical,
+            geometry: Bwr420.geometry(),
+            rest: &img,
+        };
+        assert!(flipped(true, false).get_pixel(399, 0) == Color::Red);
+        assert!(flipped(false, true).get_pixel(0, 299) == Color::Red);
+        assert!(flipped(true, true).get_pixel(399, 299) == Color::Red);
+        // off the panel nothing moves
+        assert!(flipped(true, true).get_pixel(599, 447) == Color::White);
+        assert!(flipped(true, false).get_pixel(599, 0) == Color::White);
+    }
+}
@@ -0,0 +1,98 @@
+// repeated refreshes through a set of stress patterns, timing each one. a
+// refresh that finishes too quickly never really ran, one that drags points
+// at the supply or the enclosure getting too cold.
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::draw::{Color, Drawable, RandomColors, SequentialColors, SolidColor};
+
+// full black and white swings, then every color at once, then per pixel
+// noise
+pub fn patterns() -> Vec<(&'static str, Box<dyn Drawable>)> {
+    vec![
+        ("black", Box::new(SolidColor(Color::Black))),
+        ("white", Box::new(SolidColor(Color::White))),
+        ("stripes", Box::new(SequentialColors)),
+        ("noise", Box::new(RandomColors)),
+    ]
+}
+
+// how long a healthy refresh takes
+#[derive(Clone, Copy)]
+pub struct Bounds {
+    pub min: Duration,
+    pub max: Duration,
+}
+
+impl Bounds {
+    // the panel slows down by about 4% per degree below 25C
+    pub fn at(temperature: Option<f32>) -> Self {
+        let cold = temperature.map_or(1.0, |t| 1.0 + (25.0 - t).max(0.0) * 0.04);
+        Self {
+            min: Duration::from_secs(5),
+            max: Duration::from_secs(45).mul_f32(cold),
+        }
+    }
+
+    pub fn contains(&self, d: Duration) -> bool {
+        (self.min..=self.max).contains(&d)
+    }
+}
+
+impl fmt::Display for Bounds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.0?}..{:.0?}", self.min, self.max)
+    }
+}
+
+pub struct Record {
+    pub cycle: u32,
+    pub pattern: &'static str,
+    // None if the panel never came back
+    pub duration: Option<Duration>,
+    pub temperature: Option<f32>,
+    pub ok: bool,
+}
+
+// one csv row per refresh, flushed as it is written so an interrupted run
+// still leaves its results
+pub struct Log {
+    out: BufWriter<File>,
+}
+
+impl Log {
+    pub fn create(path: &str) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("could not create {path}: {e}"))?;
+        let mut log = Self {
+            out: BufWriter::new(file),
+        };
+        log.line("cycle,pattern,unix_time,duration_ms,temperature_c,ok")
+            .map_err(|e| format!("could not write {path}: {e}"))?;
+        Ok(log)
+    }
+
+    pub fn write(&mut self, r: &Record) -> io::Result<()> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let duration = r
+            .duration
+            .map_or(String::new(), |d| d.as_millis().to_string());
+        let temperature = r.temperature.map_or(String::new(), |t| format!("{t:.1}"));
+        self.line(&format!(
+            "{},{},{now},{duration},{temperature},{}",
+            r.cycle, r.pattern, r.ok
+        ))
+    }
+
+    fn line(&mut self, line: &str) -> io::Result<()> {
+        writeln!(self.out, "{line}")?;
+        self.out.flush()
+    }
+}
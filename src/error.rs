@@ -0,0 +1,91 @@
+use std::{fmt, io};
+
+use rppal::{gpio, spi};
+
+// everything the driver can fail with
+#[derive(Debug)]
+pub enum EpaperError {
+    Spi(spi::Error),
+    Gpio(gpio::Error),
+    // the gpio character device backend
+    Io(io::Error),
+    // a bus or pin of an embedded-hal backend, as its error kind
+    Hal(String),
+    // the panel held busy for longer than it ever should
+    BusyTimeout(std::time::Duration),
+    // a frame or image that couldn't be read
+    Decode(String),
+    // a pixel value that isn't one of the panel's colors
+    InvalidColor(u8),
+    // the allocation for a frame of this many bytes failed
+    OutOfMemory(usize),
+    // a Draw stopped through its CancelToken
+    Cancelled,
+    // a frame of the wrong size for the panel
+    Dimensions {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+}
+
+pub type Result<T> = std::result::Result<T, EpaperError>;
+
+impl EpaperError {
+    // whether a reset and another attempt could get the panel going again
+    pub fn is_recoverable(&self) -> bool {
+        matches!(self, EpaperError::BusyTimeout(_))
+    }
+}
+
+impl fmt::Display for EpaperError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EpaperError::Spi(e) => write!(f, "spi: {e}"),
+            EpaperError::Gpio(e) => write!(f, "gpio: {e}"),
+            EpaperError::Io(e) => write!(f, "{e}"),
+            EpaperError::Hal(e) => write!(f, "{e}"),
+            EpaperError::BusyTimeout(d) => write!(f, "panel still busy after {d:?}"),
+            EpaperError::Decode(e) => write!(f, "{e}"),
+            EpaperError::InvalidColor(n @ 0x08..=0x0F) => {
+                write!(f, "palette index {n:#04x} is reserved")
+            }
+            EpaperError::InvalidColor(n) => write!(f, "{n:#04x} is not a palette index"),
+            EpaperError::OutOfMemory(n) => write!(f, "could not allocate {n} bytes for a frame"),
+            EpaperError::Cancelled => write!(f, "draw cancelled"),
+            EpaperError::Dimensions { expected, actual } => write!(
+                f,
+                "frame is {}x{}, expected {}x{}",
+                actual.0, actual.1, expected.0, expected.1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EpaperError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EpaperError::Spi(e) => Some(e),
+            EpaperError::Gpio(e) => Some(e),
+            EpaperError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<spi::Error> for EpaperError {
+    fn from(e: spi::Error) -> Self {
+        EpaperError::Spi(e)
+    }
+}
+
+impl From<gpio::Error> for EpaperError {
+    fn from(e: gpio::Error) -> Self {
+        EpaperError::Gpio(e)
+    }
+}
+
+impl From<io::Error> for EpaperError {
+    fn from(e: io::Error) -> Self {
+        EpaperError::Io(e)
+    }
+}
@@ -0,0 +1,73 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    sync::{Mutex, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{Map, Value};
+use tracing::warn;
+
+// rotate once the log passes this size, keeping this many old files
+const MAX_BYTES: u64 = 1024 * 1024;
+const KEEP: u32 = 5;
+
+// append-only jsonl log of display operations
+struct EventLog {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+static LOG: OnceLock<EventLog> = OnceLock::new();
+
+pub fn init(path: PathBuf) {
+    let _ = LOG.set(EventLog {
+        path,
+        lock: Mutex::new(()),
+    });
+}
+
+impl EventLog {
+    // log -> log.1 -> log.2 ...
+    fn rotate(&self) {
+        let rotated = |n: u32| PathBuf::from(format!("{}.{n}", self.path.display()));
+        for n in (1..KEEP).rev() {
+            let _ = fs::rename(rotated(n), rotated(n + 1));
+        }
+        let _ = fs::rename(&self.path, rotated(1));
+    }
+
+    fn write(&self, line: &str) -> std::io::Result<()> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        if fs::metadata(&self.path).is_ok_and(|m| m.len() > MAX_BYTES) {
+            self.rotate();
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{line}")
+    }
+}
+
+// records an event with extra fields, if logging was enabled with init.
+// failing to log never fails the operation being logged.
+pub fn log(event: &str, fields: Value) {
+    let Some(log) = LOG.get() else {
+        return;
+    };
+    let ts = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or(0.0);
+    let mut obj = Map::new();
+    obj.insert("ts".into(), ts.into());
+    obj.insert("event".into(), event.into());
+    if let Value::Object(fields) = fields {
+        obj.extend(fields);
+    }
+    if let Err(e) = log.write(&Value::Object(obj).to_string()) {
+        warn!("could not write event log: {e}");
+    }
+}
@@ -0,0 +1,297 @@
+// 5x7 bitmap font covering printable ascii (0x20..=0x7E).
+// each glyph is 5 columns, bit 0 of a column is the top row.
+
+pub const GLYPH_WIDTH: u16 = 5;
+pub const GLYPH_HEIGHT: u16 = 7;
+// glyph width plus one column of spacing
+pub const ADVANCE: u16 = GLYPH_WIDTH + 1;
+
+const FIRST: u8 = 0x20;
+const GLYPHS: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x00, 0x5F, 0x00, 0x00], // !
+    [0x00, 0x07, 0x00, 0x07, 0x00], // "
+    [0x14, 0x7F, 0x14, 0x7F, 0x14], // #
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12], // $
+    [0x23, 0x13, 0x08, 0x64, 0x62], // %
+    [0x36, 0x49, 0x55, 0x22, 0x50], // &
+    [0x00, 0x05, 0x03, 0x00, 0x00], // '
+    [0x00, 0x1C, 0x22, 0x41, 0x00], // (
+    [0x00, 0x41, 0x22, 0x1C, 0x00], // )
+    [0x08, 0x2A, 0x1C, 0x2A, 0x08], // *
+    [0x08, 0x08, 0x3E, 0x08, 0x08], // +
+    [0x00, 0x50, 0x30, 0x00, 0x00], // ,
+    [0x08, 0x08, 0x08, 0x08, 0x08], // -
+    [0x00, 0x60, 0x60, 0x00, 0x00], // .
+    [0x20, 0x10, 0x08, 0x04, 0x02], // /
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], // 0
+    [0x00, 0x42, 0x7F, 0x40, 0x00], // 1
+    [0x42, 0x61, 0x51, 0x49, 0x46], // 2
+    [0x21, 0x41, 0x45, 0x4B, 0x31], // 3
+    [0x18, 0x14, 0x12, 0x7F, 0x10], // 4
+    [0x27, 0x45, 0x45, 0x45, 0x39], // 5
+    [0x3C, 0x4A, 0x49, 0x49, 0x30], // 6
+    [0x01, 0x71, 0x09, 0x05, 0x03], // 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], // 8
+    [0x06, 0x49, 0x49, 0x29, 0x1E], // 9
+    [0x00, 0x36, 0x36, 0x00, 0x00], // :
+    [0x00, 0x56, 0x36, 0x00, 0x00], // ;
+    [0x08, 0x14, 0x22, 0x41, 0x00], // <
+    [0x14, 0x14, 0x14, 0x14, 0x14], // =
+    [0x00, 0x41, 0x22, 0x14, 0x08], // >
+    [0x02, 0x01, 0x51, 0x09, 0x06], // ?
+    [0x32, 0x49, 0x79, 0x41, 0x3E], // @
+    [0x7E, 0x11, 0x11, 0x11, 0x7E], // A
+    [0x7F, 0x49, 0x49, 0x49, 0x36], // B
+    [0x3E, 0x41, 0x41, 0x41, 0x22], // C
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], // D
+    [0x7F, 0x49, 0x49, 0x49, 0x41], // E
+    [0x7F, 0x09, 0x09, 0x09, 0x01], // F
+    [0x3E, 0x41, 0x49, 0x49, 0x7A], // G
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], // H
+    [0x00, 0x41, 0x7F, 0x41, 0x00], // I
+    [0x20, 0x40, 0x41, 0x3F, 0x01], // J
+    [0x7F, 0x08, 0x14, 0x22, 0x41], // K
+    [0x7F, 0x40, 0x40, 0x40, 0x40], // L
+    [0x7F, 0x02, 0x0C, 0x02, 0x7F], // M
+    [0x7F, 0x04, 0x08, 0x10, 0x7F], // N
+    [0x3E, 0x41, 0x41, 0x41, 0x3E], // O
+    [0x7F, 0x09, 0x09, 0x09, 0x06], // P
+    [0x3E, 0x41, 0x51, 0x21, 0x5E], // Q
+    [0x7F, 0x09, 0x19, 0x29, 0x46], // R
+    [0x46, 0x49, 0x49, 0x49, 0x31], // S
+    [0x01, 0x01, 0x7F, 0x01, 0x01], // T
+    [0x3F, 0x40, 0x40, 0x40, 0x3F], // U
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], // V
+    [0x3F, 0x40, 0x38, 0x40, 0x3F], // W
+    [0x63, 0x14, 0x08, 0x14, 0x63], // X
+    [0x07, 0x08, 0x70, 0x08, 0x07], // Y
+    [0x61, 0x51, 0x49, 0x45, 0x43], // Z
+    [0x00, 0x7F, 0x41, 0x41, 0x00], // [
+    [0x02, 0x04, 0x08, 0x10, 0x20], // \
+    [0x00, 0x41, 0x41, 0x7F, 0x00], // ]
+    [0x04, 0x02, 0x01, 0x02, 0x04], // ^
+    [0x40, 0x40, 0x40, 0x40, 0x40], // _
+    [0x00, 0x01, 0x02, 0x04, 0x00], // `
+    [0x20, 0x54, 0x54, 0x54, 0x78], // a
+    [0x7F, 0x48, 0x44, 0x44, 0x38], // b
+    [0x38, 0x44, 0x44, 0x44, 0x20], // c
+    [0x38, 0x44, 0x44, 0x48, 0x7F], // d
+    [0x38, 0x54, 0x54, 0x54, 0x18], // e
+    [0x08, 0x7E, 0x09, 0x01, 0x02], // f
+    [0x0C, 0x52, 0x52, 0x52, 0x3E], // g
+    [0x7F, 0x08, 0x04, 0x04, 0x78], // h
+    [0x00, 0x44, 0x7D, 0x40, 0x00], // i
+    [0x20, 0x40, 0x44, 0x3D, 0x00], // j
+    [0x7F, 0x10, 0x28, 0x44, 0x00], // k
+    [0x00, 0x41, 0x7F, 0x40, 0x00], // l
+    [0x7C, 0x04, 0x18, 0x04, 0x78], // m
+    [0x7C, 0x08, 0x04, 0x04, 0x78], // n
+    [0x38, 0x44, 0x44, 0x44, 0x38], // o
+    [0x7C, 0x14, 0x14, 0x14, 0x08], // p
+    [0x08, 0x14, 0x14, 0x18, 0x7C], // q
+    [0x7C, 0x08, 0x04, 0x04, 0x08], // r
+    [0x48, 0x54, 0x54, 0x54, 0x20], // s
+    [0x04, 0x3F, 0x44, 0x40, 0x20], // t
+    [0x3C, 0x40, 0x40, 0x20, 0x7C], // u
+    [0x1C, 0x20, 0x40, 0x20, 0x1C], // v
+    [0x3C, 0x40, 0x30, 0x40, 0x3C], // w
+    [0x44, 0x28, 0x10, 0x28, 0x44], // x
+    [0x0C, 0x50, 0x50, 0x50, 0x3C], // y
+    [0x44, 0x64, 0x54, 0x4C, 0x44], // z
+    [0x00, 0x08, 0x36, 0x41, 0x00], // {
+    [0x00, 0x00, 0x7F, 0x00, 0x00], // |
+    [0x00, 0x41, 0x36, 0x08, 0x00], // }
+    [0x08, 0x04, 0x08, 0x10, 0x08], // ~
+];
+
+// accented latin letters render as their base letter and anything else
+// unknown as '?'
+pub fn glyph(c: char) -> &'static [u8; 5] {
+    let c = match c {
+        'à' | 'á' | 'â' | 'ä' | 'å' => 'a',
+        'ç' => 'c',
+        'è' | 'é' | 'ê' | 'ë' => 'e',
+        'ì' | 'í' | 'î' | 'ï' => 'i',
+        'ñ' => 'n',
+        'ò' | 'ó' | 'ô' | 'ö' => 'o',
+        'ù' | 'ú' | 'û' | 'ü' => 'u',
+        'Ä' => 'A',
+        'É' => 'E',
+        'Ö' => 'O',
+        'Ü' => 'U',
+        _ => c,
+    };
+    let i = match c {
+        ' '..='~' => c as u8 - FIRST,
+        _ => b'?' - FIRST,
+    };
+    &GLYPHS[i as usize]
+}
+
+pub fn glyph_pixel(c: char, gx: u16, gy: u16) -> bool {
+    gx < GLYPH_WIDTH && gy < GLYPH_HEIGHT && glyph(c)[gx as usize] & (1 << gy) != 0
+}
+
+// size in pixels of a single line of text at the given scale
+pub fn text_size(text: &str, scale: u16) -> (u16, u16) {
+    let chars = text.chars().count() as u16;
+    let w = (chars * ADVANCE).saturating_sub(1) * scale;
+    (w, GLYPH_HEIGHT * scale)
+}
+
+// draws a single line of text onto an rgb image, clipped to its bounds
+pub fn draw_text(img: &mut bmp::Image, text: &str, x: u16, y: u16, scale: u16, px: bmp::Pixel) {
+    for (i, c) in text.chars().enumerate() {
+        let ox = x as u32 + i as u32 * (ADVANCE * scale) as u32;
+        for gx in 0..GLYPH_WIDTH {
+            for gy in 0..GLYPH_HEIGHT {
+                if !glyph_pixel(c, gx, gy) {
+                    continue;
+                }
+                for sx in 0..scale as u32 {
+                    for sy in 0..scale as u32 {
+                        let px_x = ox + (gx * scale) as u32 + sx;
+                        let px_y = y as u32 + (gy * scale) as u32 + sy;
+                        if px_x < img.get_width() && px_y < img.get_height() {
+                            img.set_pixel(px_x, px_y, px);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// draws one glyph turned `angle` degrees clockwise around its top left
+// corner at (x, y). every covered pixel is mapped back into the glyph, so
+// the edges stay solid at any angle.
+fn draw_glyph_turned(
+    img: &mut bmp::Image,
+    c: char,
+    (x, y): (f32, f32),
+    scale: f32,
+    angle: f32,
+    px: bmp::Pixel,
+) {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let w = GLYPH_WIDTH as f32 * scale;
+    let h = GLYPH_HEIGHT as f32 * scale;
+    let corners = [(0.0, 0.0), (w, 0.0), (0.0, h), (w, h)]
+        .map(|(u, v)| (x + u * cos - v * sin, y + u * sin + v * cos));
+    let min = |f: fn(&(f32, f32)) -> f32| corners.iter().map(f).fold(f32::MAX, f32::min);
+    let max = |f: fn(&(f32, f32)) -> f32| corners.iter().map(f).fold(f32::MIN, f32::max);
+    let x0 = min(|c| c.0).floor().max(0.0) as u32;
+    let y0 = min(|c| c.1).floor().max(0.0) as u32;
+    let x1 = (max(|c| c.0).ceil().max(0.0) as u32).min(img.get_width());
+    let y1 = (max(|c| c.1).ceil().max(0.0) as u32).min(img.get_height());
+    for py in y0..y1 {
+        for px_x in x0..x1 {
+            let dx = px_x as f32 + 0.5 - x;
+            let dy = py as f32 + 0.5 - y;
+            let u = dx * cos + dy * sin;
+            let v = dy * cos - dx * sin;
+            if u < 0.0 || v < 0.0 {
+                continue;
+            }
+            if glyph_pixel(c, (u / scale) as u16, (v / scale) as u16) {
+                img.set_pixel(px_x, py, px);
+            }
+        }
+    }
+}
+
+// draws a single line of text turned `angle` degrees clockwise around its
+// top left corner, clipped to the image
+pub fn draw_text_rotated(
+    img: &mut bmp::Image,
+    text: &str,
+    (x, y): (f32, f32),
+    scale: f32,
+    angle: f32,
+    px: bmp::Pixel,
+) {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let advance = ADVANCE as f32 * scale;
+    for (i, c) in text.chars().enumerate() {
+        let u = i as f32 * advance;
+        draw_glyph_turned(img, c, (x + u * cos, y + u * sin), scale, angle, px);
+    }
+}
+
+// the point `dist` pixels along a polyline and the direction of the
+// segment it lies on, in degrees clockwise from the x axis
+fn point_along(path: &[(f32, f32)], mut dist: f32) -> Option<((f32, f32), f32)> {
+    for pair in path.windows(2) {
+        let [(ax, ay), (bx, by)] = [pair[0], pair[1]];
+        let len = (bx - ax).hypot(by - ay);
+        if dist <= len && len > 0.0 {
+            let t = dist / len;
+            let angle = (by - ay).atan2(bx - ax).to_degrees();
+            return Some(((ax + (bx - ax) * t, ay + (by - ay) * t), angle));
+        }
+        dist -= len;
+    }
+    None
+}
+
+// lays text along a polyline, each glyph centered on the line and turned
+// to follow it. glyphs past the end of the path are dropped.
+pub fn draw_text_along(
+    img: &mut bmp::Image,
+    text: &str,
+    path: &[(f32, f32)],
+    scale: f32,
+    px: bmp::Pixel,
+) {
+    let advance = ADVANCE as f32 * scale;
+    let (w, h) = (GLYPH_WIDTH as f32 * scale, GLYPH_HEIGHT as f32 * scale);
+    for (i, c) in text.chars().enumerate() {
+        let Some(((mx, my), angle)) = point_along(path, i as f32 * advance + w / 2.0) else {
+            break;
+        };
+        // back from the glyph's center to its top left corner
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let (u, v) = (w / 2.0, h / 2.0);
+        let corner = (mx - u * cos + v * sin, my - u * sin - v * cos);
+        draw_glyph_turned(img, c, corner, scale, angle, px);
+    }
+}
+
+// a polyline around a circle from `from` to `to` degrees, clockwise from
+// 3 o'clock. the reverse order runs counterclockwise.
+pub fn arc(center: (f32, f32), radius: f32, from: f32, to: f32) -> Vec<(f32, f32)> {
+    // about one point every 2px along the circle
+    let steps = ((to - from).abs().to_radians() * radius / 2.0)
+        .ceil()
+        .max(1.0) as usize;
+    (0..=steps)
+        .map(|i| {
+            let a = (from + (to - from) * i as f32 / steps as f32).to_radians();
+            (center.0 + radius * a.cos(), center.1 + radius * a.sin())
+        })
+        .collect()
+}
+
+// draws text along a circle, centered on `angle` degrees clockwise from
+// 3 o'clock. it reads clockwise with the tops of the glyphs facing out, so
+// a label at the top of a dial is upright.
+pub fn draw_text_on_arc(
+    img: &mut bmp::Image,
+    text: &str,
+    center: (f32, f32),
+    radius: f32,
+    angle: f32,
+    scale: f32,
+    px: bmp::Pixel,
+) {
+    let len = text.chars().count() as f32 * ADVANCE as f32 * scale;
+    let half = (len / 2.0 / radius).to_degrees();
+    draw_text_along(
+        img,
+        text,
+        &arc(center, radius, angle - half, angle + half),
+        scale,
+        px,
+    );
+}
@@ -0,0 +1,171 @@
+use std::io::{self, Read, Write};
+
+use crate::{
+    draw::{Color, Drawable, PaletteIndex},
+    SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+pub const MAGIC: &[u8; 4] = b"EPF1";
+// the content type a packed frame is uploaded to `serve` with
+pub const CONTENT_TYPE: &str = "application/x-epaper-frame";
+// each byte fits 2 px
+pub const PACKED_LEN: usize = SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize / 2;
+
+// ieee crc32 table, built at compile time
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xEDB88320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+};
+
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |c, b| {
+        CRC_TABLE[((c ^ *b as u32) & 0xFF) as usize] ^ (c >> 8)
+    })
+}
+
+// a frame in the controller's wire format, two pixels per byte
+pub struct PackedFrame {
+    pub data: Vec<u8>,
+}
+
+impl PackedFrame {
+    pub fn pack(d: &(impl Drawable + ?Sized)) -> Self {
+        let mut data = Vec::with_capacity(PACKED_LEN);
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH / 2 {
+                let c1 = PaletteIndex::from(d.get_pixel(x * 2, y)).get();
+                let c2 = PaletteIndex::from(d.get_pixel(x * 2 + 1, y)).get();
+                data.push((c1 << 4) | c2);
+            }
+        }
+        Self { data }
+    }
+
+    // magic, width and height (u16 le), crc32 of the data (u32 le), data
+    pub fn write_to(&self, w: &mut impl Write) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&SCREEN_WIDTH.to_le_bytes())?;
+        w.write_all(&SCREEN_HEIGHT.to_le_bytes())?;
+        w.write_all(&crc32(&self.data).to_le_bytes())?;
+        w.write_all(&self.data)
+    }
+
+    // rejects frames with the wrong size, truncated data or a bad checksum
+    // before anything is sent to the panel
+    pub fn read_from(r: &mut impl Read) -> Result<Self, String> {
+        let mut header = [0u8; 12];
+        r.read_exact(&mut header)
+            .map_err(|e| format!("could not read frame header: {e}"))?;
+        if &header[0..4] != MAGIC {
+            return Err("not a packed frame (bad magic)".into());
+        }
+        let w = u16::from_le_bytes([header[4], header[5]]);
+        let h = u16::from_le_bytes([header[6], header[7]]);
+        if (w, h) != (SCREEN_WIDTH, SCREEN_HEIGHT) {
+            return Err(format!(
+                "frame is {w}x{h}, expected {SCREEN_WIDTH}x{SCREEN_HEIGHT}"
+            ));
+        }
+        let crc = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+        let mut data = vec![0u8; PACKED_LEN];
+        r.read_exact(&mut data)
+            .map_err(|_| "frame data is truncated".to_string())?;
+        let actual = crc32(&data);
+        if actual != crc {
+            return Err(format!(
+                "frame checksum mismatch (expected {crc:08x}, got {actual:08x})"
+            ));
+        }
+        for (i, b) in data.iter().enumerate() {
+            for nibble in [b >> 4, b & 0x0F] {
+                Color::try_from(nibble).map_err(|e| format!("frame byte {i}: {e}"))?;
+            }
+        }
+        Ok(Self { data })
+    }
+}
+
+impl Drawable for PackedFrame {
+    fn get_pixel(&self, x: u16, y: u16) -> Color {
+        let b = self.data[(x as usize + y as usize * SCREEN_WIDTH as usize) / 2];
+        let nibble = if x.is_multiple_of(2) {
+            b >> 4
+        } else {
+            b & 0x0F
+        };
+        // only frames that weren't read through read_from can hold reserved
+        // codes
+        Color::try_from(nibble).unwrap_or(Color::Clean)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::draw::SolidColor;
+
+    fn written(frame: &PackedFrame) -> Vec<u8> {
+        let mut out = Vec::new();
+        frame.write_to(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn crc32_matches_the_ieee_check_value() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn round_trips_through_write_and_read() {
+        let frame = PackedFrame::pack(&SolidColor(Color::Orange));
+        let bytes = written(&frame);
+        assert_eq!(bytes.len(), 12 + PACKED_LEN);
+        let read = PackedFrame::read_from(&mut &bytes[..]).unwrap();
+        assert_eq!(read.data, frame.data);
+        assert!(read.get_pixel(599, 447) == Color::Orange);
+    }
+
+    #[test]
+    fn rejects_broken_frames() {
+        let frame = written(&PackedFrame::pack(&SolidColor(Color::Blue)));
+        let read = |bytes: &[u8]| PackedFrame::read_from(&mut &bytes[..]).err().unwrap();
+
+        let mut magic = frame.clone();
+        magic[0] = b'X';
+        assert!(read(&magic).contains("bad magic"));
+
+        let mut size = frame.clone();
+        size[4..6].copy_from_slice(&400u16.to_le_bytes());
+        assert!(read(&size).contains("frame is 400x448"));
+
+        assert!(read(&frame[..frame.len() - 1]).contains("truncated"));
+        assert!(read(&frame[..6]).contains("header"));
+
+        let mut flipped = frame.clone();
+        flipped[100] ^= 0x01;
+        assert!(read(&flipped).contains("checksum mismatch"));
+
+        // a reserved color code with a checksum to match
+        let mut reserved = frame.clone();
+        reserved[12 + 7] = 0x38;
+        let crc = crc32(&reserved[12..]);
+        reserved[8..12].copy_from_slice(&crc.to_le_bytes());
+        assert!(read(&reserved).contains("frame byte 7"));
+    }
+}
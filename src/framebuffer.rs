@@ -0,0 +1,79 @@
+// a frame to draw into pixel by pixel, for composing without writing a
+// Drawable for every layout. it is a Drawable itself, so it goes straight
+// into cmd::Draw once it's done.
+
+use crate::{
+    draw::{Color, Drawable},
+    layout::Rect,
+    SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+pub struct FrameBuffer {
+    data: Vec<Color>,
+}
+
+impl Default for FrameBuffer {
+    // a freshly cleaned panel
+    fn default() -> Self {
+        Self::new(Color::White)
+    }
+}
+
+impl FrameBuffer {
+    pub fn new(color: Color) -> Self {
+        Self {
+            data: vec![color; SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize],
+        }
+    }
+
+    // snapshots any drawable
+    pub fn from_drawable(d: &(impl Drawable + ?Sized)) -> Self {
+        let mut fb = Self::new(Color::Clean);
+        fb.blit(d, 0, 0, SCREEN_WIDTH, SCREEN_HEIGHT);
+        fb
+    }
+
+    fn idx(x: u16, y: u16) -> usize {
+        x as usize + y as usize * SCREEN_WIDTH as usize
+    }
+
+    // pixels off the screen are ignored
+    pub fn set_pixel(&mut self, x: u16, y: u16, color: Color) {
+        if x < SCREEN_WIDTH && y < SCREEN_HEIGHT {
+            self.data[Self::idx(x, y)] = color;
+        }
+    }
+
+    pub fn clear(&mut self, color: Color) {
+        self.data.fill(color);
+    }
+
+    // fills a rectangle, clipped to the screen
+    pub fn fill_rect(&mut self, rect: Rect, color: Color) {
+        let x1 = (rect.x + rect.w).min(SCREEN_WIDTH as u32) as usize;
+        let y1 = (rect.y + rect.h).min(SCREEN_HEIGHT as u32) as usize;
+        let x0 = (rect.x as usize).min(x1);
+        for y in rect.y as usize..y1 {
+            let row = y * SCREEN_WIDTH as usize;
+            self.data[row + x0..row + x1].fill(color);
+        }
+    }
+
+    // copies a w x h region of `src`, starting at its top-left, to (x, y).
+    // whatever falls off the screen is skipped rather than asked for.
+    pub fn blit(&mut self, src: &(impl Drawable + ?Sized), x: u16, y: u16, w: u16, h: u16) {
+        let w = w.min(SCREEN_WIDTH.saturating_sub(x));
+        let h = h.min(SCREEN_HEIGHT.saturating_sub(y));
+        for dy in 0..h {
+            for dx in 0..w {
+                self.data[Self::idx(x + dx, y + dy)] = src.get_pixel(dx, dy);
+            }
+        }
+    }
+}
+
+impl Drawable for FrameBuffer {
+    fn get_pixel(&self, x: u16, y: u16) -> Color {
+        self.data[Self::idx(x, y)]
+    }
+}
@@ -0,0 +1,84 @@
+// rasterized glyphs kept between draws. a clock redrawn every minute sets
+// the same dozen characters each time and rasterizing is the slow part, so
+// each glyph is made once per (font, size, char) and handed out again after.
+// the key is the owner's call: a cache living on a font leaves the font out.
+
+use std::{cell::RefCell, collections::HashMap, hash::Hash, rc::Rc};
+
+pub struct GlyphCache<K, G> {
+    glyphs: RefCell<HashMap<K, Rc<G>>>,
+}
+
+impl<K, G> Default for GlyphCache<K, G> {
+    fn default() -> Self {
+        Self {
+            glyphs: RefCell::new(HashMap::new()),
+        }
+    }
+}
+
+impl<K: Hash + Eq, G> GlyphCache<K, G> {
+    // the glyph kept under `key`, rasterizing it on the first ask
+    pub fn get(&self, key: K, rasterize: impl FnOnce() -> G) -> Rc<G> {
+        self.glyphs
+            .borrow_mut()
+            .entry(key)
+            .or_insert_with(|| Rc::new(rasterize()))
+            .clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.glyphs.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.glyphs.borrow().is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    // (font, size in px as bits, char), counting how often it rasterizes
+    fn fetch(
+        cache: &GlyphCache<(u8, u32, char), Vec<u8>>,
+        made: &Cell<u32>,
+        key: (u8, u32, char),
+    ) -> Rc<Vec<u8>> {
+        cache.get(key, || {
+            made.set(made.get() + 1);
+            vec![key.2 as u8; 4]
+        })
+    }
+
+    #[test]
+    fn repeated_glyphs_hit_the_cache() {
+        let cache = GlyphCache::default();
+        let made = Cell::new(0);
+        let first = fetch(&cache, &made, (0, 24f32.to_bits(), 'a'));
+        for _ in 0..10 {
+            let again = fetch(&cache, &made, (0, 24f32.to_bits(), 'a'));
+            assert!(Rc::ptr_eq(&first, &again));
+        }
+        assert_eq!(made.get(), 1);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn font_size_and_char_each_make_a_new_glyph() {
+        let cache = GlyphCache::default();
+        let made = Cell::new(0);
+        assert!(cache.is_empty());
+        fetch(&cache, &made, (0, 24f32.to_bits(), 'a'));
+        fetch(&cache, &made, (1, 24f32.to_bits(), 'a'));
+        fetch(&cache, &made, (0, 32f32.to_bits(), 'a'));
+        fetch(&cache, &made, (0, 24f32.to_bits(), 'b'));
+        assert_eq!(made.get(), 4);
+        fetch(&cache, &made, (1, 24f32.to_bits(), 'a'));
+        assert_eq!(made.get(), 4);
+        assert_eq!(cache.len(), 4);
+    }
+}
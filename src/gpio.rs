@@ -0,0 +1,275 @@
+use std::{
+    io,
+    os::fd::AsRawFd,
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use gpio_cdev::{Chip, EventRequestFlags, LineEventHandle, LineHandle, LineRequestFlags};
+#[cfg(feature = "spidev")]
+use linux_embedded_hal::CdevPin;
+use rppal::gpio::{Gpio, InputPin, OutputPin, Trigger};
+
+use crate::error::{self, EpaperError};
+
+pub trait Output: Send {
+    fn set_high(&mut self);
+    fn set_low(&mut self);
+}
+
+pub trait Input: Send {
+    fn is_high(&self) -> bool;
+    fn is_low(&self) -> bool {
+        !self.is_high()
+    }
+    // blocks until the line reads `high` or `timeout` has passed, and says
+    // whether it got there. polls unless the backend can sleep on an edge.
+    fn wait_for(&mut self, high: bool, timeout: Duration) -> bool {
+        poll_for(self, high, timeout)
+    }
+}
+
+// between looks at a line that can't be waited on
+const POLL: Duration = Duration::from_millis(10);
+
+fn poll_for(pin: &(impl Input + ?Sized), high: bool, timeout: Duration) -> bool {
+    let start = Instant::now();
+    while pin.is_high() != high {
+        let Some(left) = timeout.checked_sub(start.elapsed()) else {
+            return false;
+        };
+        thread::sleep(POLL.min(left));
+    }
+    true
+}
+
+impl Output for OutputPin {
+    fn set_high(&mut self) {
+        OutputPin::set_high(self)
+    }
+
+    fn set_low(&mut self) {
+        OutputPin::set_low(self)
+    }
+}
+
+impl Input for InputPin {
+    fn is_high(&self) -> bool {
+        InputPin::is_high(self)
+    }
+
+    // sleeps on an edge interrupt, or polls where rppal can't set one up
+    fn wait_for(&mut self, high: bool, timeout: Duration) -> bool {
+        let trigger = if high {
+            Trigger::RisingEdge
+        } else {
+            Trigger::FallingEdge
+        };
+        if self.set_interrupt(trigger).is_err() {
+            return poll_for(self, high, timeout);
+        }
+        let start = Instant::now();
+        // armed before the level is read, so an edge in between isn't lost
+        let reached = loop {
+            if Input::is_high(self) == high {
+                break true;
+            }
+            let Some(left) = timeout.checked_sub(start.elapsed()) else {
+                break false;
+            };
+            if self.poll_interrupt(false, Some(left)).is_err() {
+                break poll_for(self, high, left);
+            }
+        };
+        let _ = self.clear_interrupt();
+        reached
+    }
+}
+
+// how the dc, busy and reset lines are driven
+#[derive(Clone, Copy)]
+pub enum Backend {
+    // rppal's memory mapped registers, needs /dev/gpiomem or root
+    Rppal,
+    // the gpio character device through gpio-cdev, needs only
+    // /dev/gpiochipN
+    Cdev,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rppal" => Ok(Backend::Rppal),
+            "cdev" | "gpiod" => Ok(Backend::Cdev),
+            _ => Err(format!(
+                "unknown gpio backend '{s}' (expected rppal or cdev)"
+            )),
+        }
+    }
+}
+
+pub type Pins = (Box<dyn Output>, Box<dyn Input>, Box<dyn Output>);
+
+// requests the panel's dc, busy and reset lines
+pub fn open(backend: Backend, chip: &str, dc: u8, busy: u8, reset: u8) -> error::Result<Pins> {
+    Ok(match backend {
+        Backend::Rppal => {
+            let gpio = Gpio::new()?;
+            (
+                Box::new(gpio.get(dc)?.into_output()),
+                Box::new(gpio.get(busy)?.into_input()),
+                Box::new(gpio.get(reset)?.into_output()),
+            )
+        }
+        Backend::Cdev => {
+            let mut chip = Chip::new(chip).map_err(|e| {
+                EpaperError::Io(io::Error::other(format!("could not open {chip}: {e}")))
+            })?;
+            (
+                output(&mut chip, dc)?,
+                Box::new(Line::events(&mut chip, busy)?),
+                output(&mut chip, reset)?,
+            )
+        }
+    })
+}
+
+const CONSUMER: &str = "rpi-epaper";
+
+// a single line requested through gpio-cdev, as a plain handle or, for an
+// input, with its edges queued as events
+pub enum Line {
+    Handle(LineHandle),
+    Events(LineEventHandle),
+}
+
+fn line_error(offset: u8, e: gpio_cdev::Error) -> io::Error {
+    io::Error::other(format!("gpio line {offset}: {e}"))
+}
+
+impl Line {
+    fn request(chip: &mut Chip, offset: u8, output: bool) -> io::Result<Self> {
+        let flags = if output {
+            LineRequestFlags::OUTPUT
+        } else {
+            LineRequestFlags::INPUT
+        };
+        chip.get_line(offset as u32)
+            .and_then(|line| line.request(flags, 0, CONSUMER))
+            .map(Line::Handle)
+            .map_err(|e| line_error(offset, e))
+    }
+
+    // an input whose edges can be slept on, falling back to a plain handle
+    // on a chip that can't report them
+    fn events(chip: &mut Chip, offset: u8) -> io::Result<Self> {
+        let events = chip.get_line(offset as u32).and_then(|line| {
+            line.events(
+                LineRequestFlags::INPUT,
+                EventRequestFlags::BOTH_EDGES,
+                CONSUMER,
+            )
+        });
+        match events {
+            Ok(events) => Ok(Line::Events(events)),
+            Err(_) => Self::request(chip, offset, false),
+        }
+    }
+
+    fn set(&mut self, high: bool) {
+        if let Line::Handle(handle) = self {
+            let _ = handle.set_value(high as u8);
+        }
+    }
+}
+
+// dc and reset. with the spidev feature they are linux-embedded-hal's
+// CdevPin, like the rest of that backend. busy stays a Line either way,
+// CdevPin can't sleep on an edge.
+fn output(chip: &mut Chip, offset: u8) -> io::Result<Box<dyn Output>> {
+    #[cfg(feature = "spidev")]
+    {
+        let pin = chip
+            .get_line(offset as u32)
+            .and_then(|line| line.request(LineRequestFlags::OUTPUT, 0, CONSUMER))
+            .and_then(CdevPin::new)
+            .map_err(|e| line_error(offset, e))?;
+        Ok(Box::new(pin))
+    }
+    #[cfg(not(feature = "spidev"))]
+    Ok(Box::new(Line::request(chip, offset, true)?))
+}
+
+#[cfg(feature = "spidev")]
+impl Output for CdevPin {
+    // a failed write shows up as a busy timeout, as with the other backends
+    fn set_high(&mut self) {
+        let _ = embedded_hal::digital::OutputPin::set_high(self);
+    }
+
+    fn set_low(&mut self) {
+        let _ = embedded_hal::digital::OutputPin::set_low(self);
+    }
+}
+
+// waits up to `timeout` for an edge and takes it off the queue, or says
+// there wasn't one. gpio-cdev only blocks without a timeout, so the wait
+// is a poll on its fd.
+fn next_edge(events: &mut LineEventHandle, timeout: Duration) -> io::Result<bool> {
+    let mut poll = libc::pollfd {
+        fd: events.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // rounded up, so a wait under a millisecond doesn't spin
+    let ms = timeout.as_micros().div_ceil(1000);
+    let ms = ms.min(libc::c_int::MAX as u128) as libc::c_int;
+    // SAFETY: poll is a single valid pollfd
+    match unsafe { libc::poll(&mut poll, 1, ms) } {
+        n if n < 0 => return Err(io::Error::last_os_error()),
+        0 => return Ok(false),
+        _ => {}
+    }
+    events.get_event().map_err(io::Error::other)?;
+    Ok(true)
+}
+
+impl Output for Line {
+    fn set_high(&mut self) {
+        self.set(true)
+    }
+
+    fn set_low(&mut self) {
+        self.set(false)
+    }
+}
+
+impl Input for Line {
+    fn is_high(&self) -> bool {
+        let value = match self {
+            Line::Handle(handle) => handle.get_value(),
+            Line::Events(events) => events.get_value(),
+        };
+        value.is_ok_and(|v| v != 0)
+    }
+
+    fn wait_for(&mut self, high: bool, timeout: Duration) -> bool {
+        let start = Instant::now();
+        // edges queue up from the request on, so one between the read and
+        // the wait is still seen
+        while self.is_high() != high {
+            let Some(left) = timeout.checked_sub(start.elapsed()) else {
+                return false;
+            };
+            let Line::Events(events) = self else {
+                return poll_for(self, high, left);
+            };
+            if next_edge(events, left).is_err() {
+                return poll_for(self, high, left);
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,79 @@
+use std::convert::Infallible;
+
+use embedded_graphics::{
+    pixelcolor::{BinaryColor, Gray8, GrayColor, Rgb565, Rgb888, RgbColor},
+    prelude::*,
+};
+
+use crate::{
+    draw::{Color, PaperImage},
+    Rgb, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+// panel colors are indexed, there's no raw bit layout to expose
+impl PixelColor for Color {
+    type Raw = ();
+}
+
+impl From<Rgb888> for Color {
+    fn from(c: Rgb888) -> Self {
+        Color::closest(Rgb {
+            r: c.r() as f32,
+            g: c.g() as f32,
+            b: c.b() as f32,
+        })
+    }
+}
+
+impl From<Rgb565> for Color {
+    fn from(c: Rgb565) -> Self {
+        Rgb888::from(c).into()
+    }
+}
+
+impl From<Gray8> for Color {
+    fn from(c: Gray8) -> Self {
+        let l = c.luma();
+        Rgb888::new(l, l, l).into()
+    }
+}
+
+// on is ink
+impl From<BinaryColor> for Color {
+    fn from(c: BinaryColor) -> Self {
+        match c {
+            BinaryColor::On => Color::Black,
+            BinaryColor::Off => Color::White,
+        }
+    }
+}
+
+impl OriginDimensions for PaperImage {
+    fn size(&self) -> Size {
+        Size::new(SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32)
+    }
+}
+
+// draw with any embedded-graphics primitive, font or image, then send the
+// result with cmd::Draw. pixels off the panel are dropped.
+impl DrawTarget for PaperImage {
+    type Color = Color;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(p, color) in pixels {
+            if (0..SCREEN_WIDTH as i32).contains(&p.x) && (0..SCREEN_HEIGHT as i32).contains(&p.y) {
+                self.data[p.x as usize + p.y as usize * SCREEN_WIDTH as usize] = color;
+            }
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.data.fill(color);
+        Ok(())
+    }
+}
@@ -0,0 +1,119 @@
+// several panels tiled into one large surface. a source image is split
+// across the members, and their refreshes are started together so the
+// whole surface changes at once.
+
+use std::thread::sleep;
+
+use crate::{
+    cmd::{Command, DeepSleep, DrawOptions, PowerOff, PowerOn, StartRefresh, Upload},
+    draw::PaperImage,
+    error::Result,
+    SpiDevice,
+};
+
+// how a panel is mounted, turned clockwise from upright
+#[derive(Clone, Copy, Default)]
+pub enum Rotation {
+    #[default]
+    R0,
+    R90,
+    R180,
+    R270,
+}
+
+pub struct Member<D> {
+    pub display: D,
+    // top left corner of the member's area in the source image
+    pub offset: (u32, u32),
+    pub rotation: Rotation,
+}
+
+impl<D: SpiDevice> Member<D> {
+    // size of the member's area in the source image
+    pub fn area(&self) -> (u32, u32) {
+        let g = self.display.geometry();
+        let (w, h) = (g.width as u32, g.height as u32);
+        match self.rotation {
+            Rotation::R0 | Rotation::R180 => (w, h),
+            Rotation::R90 | Rotation::R270 => (h, w),
+        }
+    }
+
+    // the member's part of `src`, turned upright for the panel. anything
+    // past the edge of the source is white.
+    pub fn crop(&self, src: &bmp::Image) -> bmp::Image {
+        let g = self.display.geometry();
+        let (w, h) = (g.width as u32, g.height as u32);
+        let (ox, oy) = self.offset;
+        let mut out = bmp::Image::new(w, h);
+        for y in 0..h {
+            for x in 0..w {
+                let (sx, sy) = match self.rotation {
+                    Rotation::R0 => (x, y),
+                    Rotation::R90 => (h - 1 - y, x),
+                    Rotation::R180 => (w - 1 - x, h - 1 - y),
+                    Rotation::R270 => (y, w - 1 - x),
+                };
+                let (sx, sy) = (ox + sx, oy + sy);
+                let px = if sx < src.get_width() && sy < src.get_height() {
+                    src.get_pixel(sx, sy)
+                } else {
+                    bmp::consts::WHITE
+                };
+                out.set_pixel(x, y, px);
+            }
+        }
+        out
+    }
+}
+
+pub struct Group<D> {
+    pub members: Vec<Member<D>>,
+}
+
+impl<D: SpiDevice> Group<D> {
+    // size of a source image covering every member
+    pub fn size(&self) -> (u32, u32) {
+        self.members.iter().fold((0, 0), |(w, h), m| {
+            let (mw, mh) = m.area();
+            (w.max(m.offset.0 + mw), h.max(m.offset.1 + mh))
+        })
+    }
+
+    // cuts `src` into one upright image per member, in member order
+    pub fn split(&self, src: &bmp::Image) -> Vec<bmp::Image> {
+        self.members.iter().map(|m| m.crop(src)).collect()
+    }
+
+    // shows one frame per member. every frame is uploaded first, then the
+    // refreshes are started back to back and waited on together.
+    pub fn draw(&mut self, frames: &[PaperImage], options: &DrawOptions) -> Result<()> {
+        assert_eq!(frames.len(), self.members.len(), "one frame per member");
+        for (m, frame) in self.members.iter_mut().zip(frames) {
+            Upload {
+                frame,
+                transfer: options.transfer,
+            }
+            .send(&mut m.display)?;
+            PowerOn.send(&mut m.display)?;
+        }
+        for m in &mut self.members {
+            StartRefresh.send(&mut m.display)?;
+        }
+        for m in &self.members {
+            m.display.wait_busy_high()?;
+        }
+        if options.power_off {
+            for m in &mut self.members {
+                PowerOff.send(&mut m.display)?;
+            }
+        }
+        sleep(options.cooldown);
+        if options.deep_sleep {
+            for m in &mut self.members {
+                DeepSleep.send(&mut m.display)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,412 @@
+// the panel driven through embedded-hal 1.0 traits, so any board with a
+// hal for its spi bus and pins can run it, and a test can hand it fake
+// pins. EPaper drives it with rppal's spi and one of the gpio backends.
+//
+// the busy wait is counted in polls rather than timed, so it needs nothing
+// but the delay.
+
+use std::{cell::RefCell, convert::Infallible, fmt::Debug, thread, time::Duration};
+
+use embedded_hal::{
+    delay::DelayNs,
+    digital::{ErrorType, InputPin, OutputPin},
+    spi,
+};
+
+#[cfg(feature = "spidev")]
+use linux_embedded_hal::{
+    spidev::{SpiModeFlags, SpidevOptions},
+    SPIError, SpidevDevice,
+};
+use rppal::spi::{SimpleHalSpiDevice, Spi};
+
+use crate::{
+    error::{self, EpaperError},
+    gpio::{Input, Output},
+    panel::{self, Panel},
+    Geometry, SpiDevice, DEFAULT_BUSY_TIMEOUT,
+};
+
+// between looks at the busy line
+const POLL_MS: u32 = 10;
+
+pub struct HalDevice<S, DC, BUSY, RST, D> {
+    spi: S,
+    dc: DC,
+    // the embedded-hal pin and delay traits read and wait through &mut,
+    // SpiDevice waits through &
+    busy: RefCell<BUSY>,
+    reset: RST,
+    delay: RefCell<D>,
+    // longest the busy line may hold before giving up on the panel
+    pub busy_timeout: Duration,
+    // longer data is split into writes of this many bytes
+    pub max_write: usize,
+    pub geometry: Geometry,
+    pub panel: &'static dyn Panel,
+}
+
+fn hal_error(what: &str, e: impl Debug) -> EpaperError {
+    EpaperError::Hal(format!("{what}: {e:?}"))
+}
+
+impl<S, DC, BUSY, RST, D> HalDevice<S, DC, BUSY, RST, D>
+where
+    S: spi::SpiDevice,
+    DC: OutputPin,
+    BUSY: InputPin,
+    RST: OutputPin,
+    D: DelayNs,
+{
+    // the 5.65" acep on `spi`, with the default busy timeout and writes of
+    // at most 4096 bytes
+    pub fn new(spi: S, dc: DC, busy: BUSY, reset: RST, delay: D) -> Self {
+        Self {
+            spi,
+            dc,
+            busy: RefCell::new(busy),
+            reset,
+            delay: RefCell::new(delay),
+            busy_timeout: DEFAULT_BUSY_TIMEOUT,
+            max_write: 4096,
+            geometry: Geometry::default(),
+            panel: &panel::Acep565,
+        }
+    }
+
+    pub fn busy_is_high(&self) -> error::Result<bool> {
+        let mut busy = self.busy.borrow_mut();
+        busy.is_high().map_err(|e| hal_error("busy pin", e))
+    }
+
+    fn set_dc(&mut self, high: bool) -> error::Result<()> {
+        let set = if high {
+            self.dc.set_high()
+        } else {
+            self.dc.set_low()
+        };
+        set.map_err(|e| hal_error("dc pin", e))
+    }
+
+    fn set_reset(&mut self, high: bool) -> error::Result<()> {
+        let set = if high {
+            self.reset.set_high()
+        } else {
+            self.reset.set_low()
+        };
+        set.map_err(|e| hal_error("reset pin", e))
+    }
+
+    fn write(&mut self, data: &[u8]) -> error::Result<()> {
+        self.spi.write(data).map_err(|e| hal_error("spi", e))
+    }
+
+    // polls until the busy line reads `high`
+    fn wait_until(&self, high: bool) -> error::Result<()> {
+        let polls = self.busy_timeout.as_millis() / POLL_MS as u128;
+        let mut n = 0;
+        while self.busy_is_high()? != high {
+            if n >= polls {
+                return Err(EpaperError::BusyTimeout(self.busy_timeout));
+            }
+            n += 1;
+            self.delay.borrow_mut().delay_ms(POLL_MS);
+        }
+        Ok(())
+    }
+}
+
+impl<S, DC, BUSY, RST, D> SpiDevice for HalDevice<S, DC, BUSY, RST, D>
+where
+    S: spi::SpiDevice,
+    DC: OutputPin,
+    BUSY: InputPin,
+    RST: OutputPin,
+    D: DelayNs,
+{
+    fn send_cmd(&mut self, cmd: u8) -> error::Result<()> {
+        self.set_dc(false)?;
+        self.write(&[cmd])
+    }
+
+    fn send_data(&mut self, data: &[u8]) -> error::Result<()> {
+        self.set_dc(true)?;
+        for chunk in data.chunks(self.max_write.max(1)) {
+            self.write(chunk)?;
+        }
+        Ok(())
+    }
+
+    fn read_data(&mut self, buf: &mut [u8]) -> error::Result<()> {
+        self.set_dc(true)?;
+        self.spi.read(buf).map_err(|e| hal_error("spi", e))
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> error::Result<()> {
+        self.set_dc(true)?;
+        self.spi
+            .transfer(read, write)
+            .map_err(|e| hal_error("spi", e))
+    }
+
+    fn wait_busy_high(&self) -> error::Result<()> {
+        self.wait_until(true)
+    }
+
+    fn wait_busy_low(&self) -> error::Result<()> {
+        self.wait_until(false)
+    }
+
+    // a failing reset line shows up as a busy timeout on the next wait
+    fn reset(&mut self) {
+        let _ = self.set_reset(true);
+        self.delay.get_mut().delay_ms(600);
+        let _ = self.set_reset(false);
+        self.delay.get_mut().delay_ms(2);
+        let _ = self.set_reset(true);
+        self.delay.get_mut().delay_ms(200);
+    }
+
+    fn geometry(&self) -> Geometry {
+        self.geometry
+    }
+
+    fn panel(&self) -> &'static dyn Panel {
+        self.panel
+    }
+}
+
+impl<S, DC, RST, D> HalDevice<S, DC, Box<dyn Input>, RST, D> {
+    // blocks until the busy line reads `high` or `timeout` has passed, on
+    // an edge where the gpio backend can, and says whether it got there
+    pub(crate) fn wait_busy_for(&self, high: bool, timeout: Duration) -> bool {
+        self.busy.borrow_mut().wait_for(high, timeout)
+    }
+}
+
+// the thread sleeps rather than spinning, which is plenty for the panel's
+// millisecond waits
+pub struct StdDelay;
+
+impl DelayNs for StdDelay {
+    fn delay_ns(&mut self, ns: u32) {
+        thread::sleep(Duration::from_nanos(ns.into()));
+    }
+}
+
+// the gpio backends' pins, as embedded-hal pins
+impl ErrorType for Box<dyn Output> {
+    type Error = Infallible;
+}
+
+impl OutputPin for Box<dyn Output> {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        Output::set_low(&mut **self);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        Output::set_high(&mut **self);
+        Ok(())
+    }
+}
+
+impl ErrorType for Box<dyn Input> {
+    type Error = Infallible;
+}
+
+impl InputPin for Box<dyn Input> {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        Ok(Input::is_high(&**self))
+    }
+
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+        Ok(Input::is_low(&**self))
+    }
+}
+
+// the spi bus EPaper drives, rppal's or, on boards rppal doesn't know, any
+// spidev node through linux-embedded-hal
+pub(crate) enum SpiBus {
+    Rppal(SimpleHalSpiDevice<Spi>),
+    #[cfg(feature = "spidev")]
+    Spidev(SpidevDevice),
+}
+
+#[derive(Debug)]
+pub(crate) enum SpiBusError {
+    Rppal(rppal::spi::Error),
+    #[cfg(feature = "spidev")]
+    Spidev(SPIError),
+}
+
+impl spi::Error for SpiBusError {
+    fn kind(&self) -> spi::ErrorKind {
+        match self {
+            SpiBusError::Rppal(e) => e.kind(),
+            #[cfg(feature = "spidev")]
+            SpiBusError::Spidev(e) => e.kind(),
+        }
+    }
+}
+
+impl spi::ErrorType for SpiBus {
+    type Error = SpiBusError;
+}
+
+impl spi::SpiDevice for SpiBus {
+    fn transaction(
+        &mut self,
+        operations: &mut [spi::Operation<'_, u8>],
+    ) -> Result<(), SpiBusError> {
+        match self {
+            SpiBus::Rppal(spi) => spi.transaction(operations).map_err(SpiBusError::Rppal),
+            #[cfg(feature = "spidev")]
+            SpiBus::Spidev(spi) => spi.transaction(operations).map_err(SpiBusError::Spidev),
+        }
+    }
+}
+
+impl SpiBus {
+    // `path` in spi mode 0 at `clock` hz
+    #[cfg(feature = "spidev")]
+    pub(crate) fn spidev(path: &str, clock: u32) -> error::Result<Self> {
+        let open = || -> Result<SpidevDevice, SPIError> {
+            let mut spi = SpidevDevice::open(path)?;
+            let options = SpidevOptions::new()
+                .bits_per_word(8)
+                .max_speed_hz(clock)
+                .mode(SpiModeFlags::SPI_MODE_0)
+                .build();
+            spi.configure(&options)?;
+            Ok(spi)
+        };
+        open().map(SpiBus::Spidev).map_err(|e| {
+            let e = e.inner();
+            EpaperError::Io(std::io::Error::new(
+                e.kind(),
+                format!("could not open {path}: {e}"),
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use super::*;
+    use crate::{
+        cmd::{Command, Transfer, Upload},
+        draw::{Color, PaperImage},
+        frame::PACKED_LEN,
+    };
+
+    // every write on the bus, and how often dc was set
+    #[derive(Default)]
+    struct Counts {
+        writes: Vec<Vec<u8>>,
+        dc: Cell<usize>,
+    }
+
+    struct Bus(Rc<RefCell<Counts>>);
+
+    impl spi::ErrorType for Bus {
+        type Error = Infallible;
+    }
+
+    impl spi::SpiDevice for Bus {
+        fn transaction(&mut self, ops: &mut [spi::Operation<'_, u8>]) -> Result<(), Infallible> {
+            for op in ops {
+                if let spi::Operation::Write(data) = op {
+                    self.0.borrow_mut().writes.push(data.to_vec());
+                }
+            }
+            Ok(())
+        }
+    }
+
+    // dc counts its sets, the others do nothing. busy always reads high.
+    struct Pin(Option<Rc<RefCell<Counts>>>);
+
+    impl ErrorType for Pin {
+        type Error = Infallible;
+    }
+
+    impl OutputPin for Pin {
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            self.set_high()
+        }
+
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            if let Some(counts) = &self.0 {
+                let counts = counts.borrow();
+                counts.dc.set(counts.dc.get() + 1);
+            }
+            Ok(())
+        }
+    }
+
+    impl InputPin for Pin {
+        fn is_high(&mut self) -> Result<bool, Infallible> {
+            Ok(true)
+        }
+
+        fn is_low(&mut self) -> Result<bool, Infallible> {
+            Ok(false)
+        }
+    }
+
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        fn delay_ns(&mut self, _: u32) {}
+    }
+
+    // the writes and dc sets it takes to get a frame to the panel's ram,
+    // counted from the start of the pixel data
+    fn upload(chunk: usize) -> (Vec<usize>, usize) {
+        let counts = Rc::new(RefCell::new(Counts::default()));
+        let bus = Bus(Rc::clone(&counts));
+        let dc = Pin(Some(Rc::clone(&counts)));
+        let mut dev = HalDevice::new(bus, dc, Pin(None), Pin(None), NoDelay);
+        Upload {
+            frame: &PaperImage::new(Color::White),
+            transfer: Transfer {
+                chunk,
+                ..Default::default()
+            },
+        }
+        .send(&mut dev)
+        .unwrap();
+        let counts = counts.borrow();
+        let start = counts.writes.iter().position(|w| w == &[0x10]).unwrap();
+        let sizes = counts.writes[start + 1..].iter().map(Vec::len).collect();
+        // dc was set once per write before the frame, and once for its
+        // command
+        (sizes, counts.dc.get() - start - 1)
+    }
+
+    #[test]
+    fn the_frame_goes_out_in_bufsiz_writes() {
+        let (writes, dc) = upload(Transfer::default().chunk);
+        assert_eq!(writes.len(), PACKED_LEN.div_ceil(4096));
+        assert!(writes.iter().all(|&n| n <= 4096));
+        assert_eq!(writes.iter().sum::<usize>(), PACKED_LEN);
+        assert_eq!(dc, writes.len());
+    }
+
+    #[test]
+    fn a_chunk_of_one_writes_byte_by_byte() {
+        // how every frame went out before it was packed up front
+        let (writes, dc) = upload(1);
+        assert_eq!(writes.len(), PACKED_LEN);
+        assert_eq!(dc, PACKED_LEN);
+    }
+
+    #[test]
+    fn a_chunk_over_bufsiz_is_split() {
+        let (writes, _) = upload(PACKED_LEN);
+        assert_eq!(writes.len(), PACKED_LEN.div_ceil(4096));
+    }
+}
@@ -0,0 +1,293 @@
+// the little http/1.1 the daemon endpoints need: one request per
+// connection, bodies sized by content-length
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+};
+
+// requests bigger than this are refused rather than buffered
+pub const MAX_BODY: usize = 16 << 20;
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    // case-insensitive, as header names are
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    // the path without its query string
+    pub fn route(&self) -> &str {
+        self.path
+            .split_once('?')
+            .map_or(&self.path, |(route, _)| route)
+    }
+
+    // a query string parameter, percent-decoded
+    pub fn query(&self, name: &str) -> Option<String> {
+        let (_, query) = self.path.split_once('?')?;
+        query
+            .split('&')
+            .map(|pair| pair.split_once('=').unwrap_or((pair, "")))
+            .find(|(k, _)| *k == name)
+            .map(|(_, v)| decode(v))
+    }
+}
+
+// %xx escapes and + for space
+fn decode(s: &str) -> String {
+    let mut out = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'+' => out.push(b' '),
+            b'%' => {
+                let hex: Vec<u8> = bytes.by_ref().take(2).collect();
+                match std::str::from_utf8(&hex)
+                    .ok()
+                    .and_then(|h| u8::from_str_radix(h, 16).ok())
+                {
+                    Some(c) => out.push(c),
+                    None => {
+                        out.push(b'%');
+                        out.extend(hex);
+                    }
+                }
+            }
+            _ => out.push(b),
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+// the contents of the first file in a multipart/form-data body, or of the
+// first part if none of them is a file
+pub fn multipart_file<'a>(body: &'a [u8], content_type: &str) -> Result<&'a [u8], String> {
+    let boundary = content_type
+        .split(';')
+        .filter_map(|p| p.trim().strip_prefix("boundary="))
+        .next()
+        .map(|b| b.trim_matches('"'))
+        .ok_or("multipart body without a boundary")?;
+    let delimiter = format!("--{boundary}");
+    let mut parts = Vec::new();
+    let mut rest = body;
+    while let Some(at) = find(rest, delimiter.as_bytes()) {
+        rest = &rest[at + delimiter.len()..];
+        if rest.starts_with(b"--") {
+            break;
+        }
+        let end = find(rest, delimiter.as_bytes()).unwrap_or(rest.len());
+        let part = &rest[..end];
+        let Some(split) = find(part, b"\r\n\r\n") else {
+            continue;
+        };
+        let headers = String::from_utf8_lossy(&part[..split]);
+        // the line break before the next delimiter belongs to it
+        let content = &part[split + 4..];
+        let content = content.strip_suffix(b"\r\n").unwrap_or(content);
+        parts.push((headers.contains("filename="), content));
+    }
+    parts
+        .iter()
+        .find(|(file, _)| *file)
+        .or(parts.first())
+        .map(|(_, content)| *content)
+        .ok_or_else(|| "multipart body without any parts".to_string())
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+pub fn read_request(stream: &TcpStream) -> Result<Request, String> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader
+        .read_line(&mut line)
+        .map_err(|e| format!("could not read request: {e}"))?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(format!("malformed request line '{}'", line.trim()));
+    };
+    let (method, path) = (method.to_string(), path.to_string());
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        reader
+            .read_line(&mut line)
+            .map_err(|e| format!("could not read headers: {e}"))?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let (k, v) = header
+            .split_once(':')
+            .ok_or_else(|| format!("malformed header '{header}'"))?;
+        headers.push((k.trim().to_string(), v.trim().to_string()));
+    }
+    let mut request = Request {
+        method,
+        path,
+        headers,
+        body: Vec::new(),
+    };
+    let len = match request.header("content-length") {
+        Some(n) => n
+            .parse()
+            .map_err(|_| format!("invalid content-length '{n}'"))?,
+        None => 0,
+    };
+    if len > MAX_BODY {
+        return Err(format!(
+            "body of {len} bytes is over the {MAX_BODY} byte limit"
+        ));
+    }
+    request.body = vec![0; len];
+    reader
+        .read_exact(&mut request.body)
+        .map_err(|e| format!("could not read body: {e}"))?;
+    Ok(request)
+}
+
+pub fn respond(stream: &TcpStream, status: u16, body: &serde_json::Value) {
+    respond_with(
+        stream,
+        status,
+        "application/json",
+        body.to_string().as_bytes(),
+    );
+}
+
+pub fn respond_with(mut stream: &TcpStream, status: u16, content_type: &str, body: &[u8]) {
+    let reason = match status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        500 => "Internal Server Error",
+        _ => "",
+    };
+    // the client hanging up first is no concern of ours
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )
+    .and_then(|_| stream.write_all(body));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, thread};
+
+    use super::*;
+
+    // `raw` sent over a loopback connection and read back as a request
+    fn request(raw: &[u8]) -> Result<Request, String> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let raw = raw.to_vec();
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            // the server may hang up before it has all of a refused body
+            let _ = stream.write_all(&raw);
+        });
+        let (stream, _) = listener.accept().unwrap();
+        let req = read_request(&stream);
+        drop(stream);
+        client.join().unwrap();
+        req
+    }
+
+    #[test]
+    fn reads_the_body_content_length_says() {
+        let req =
+            request(b"POST /notify HTTP/1.1\r\nContent-Length: 5\r\nX-Test: a: b\r\n\r\nhello")
+                .unwrap();
+        assert_eq!(
+            (req.method.as_str(), req.path.as_str()),
+            ("POST", "/notify")
+        );
+        assert_eq!(req.header("content-length"), Some("5"));
+        assert_eq!(req.header("x-test"), Some("a: b"));
+        assert_eq!(req.body, b"hello");
+        let req = request(b"GET /status HTTP/1.1\r\n\r\n").unwrap();
+        assert!(req.body.is_empty());
+    }
+
+    #[test]
+    fn refuses_bodies_over_the_limit() {
+        let head = format!(
+            "POST /notify HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY + 1
+        );
+        let e = request(head.as_bytes()).err().unwrap();
+        assert!(e.contains("over the"), "{e}");
+        let e = request(b"POST /notify HTTP/1.1\r\nContent-Length: lots\r\n\r\n")
+            .err()
+            .unwrap();
+        assert!(e.contains("invalid content-length"), "{e}");
+        assert!(request(b"POST\r\n\r\n").is_err());
+        assert!(request(b"POST / HTTP/1.1\r\nno colon\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn query_values_are_percent_decoded() {
+        let req = Request {
+            method: "POST".into(),
+            path: "/image?client=kitchen%20panel&region=1%2C2%2c3%2C4&name=a+b&bad=%zz&flag".into(),
+            headers: Vec::new(),
+            body: Vec::new(),
+        };
+        assert_eq!(req.route(), "/image");
+        assert_eq!(req.query("client").as_deref(), Some("kitchen panel"));
+        assert_eq!(req.query("region").as_deref(), Some("1,2,3,4"));
+        assert_eq!(req.query("name").as_deref(), Some("a b"));
+        // escapes that aren't hex are kept as they are
+        assert_eq!(req.query("bad").as_deref(), Some("%zz"));
+        assert_eq!(req.query("flag").as_deref(), Some(""));
+        assert_eq!(req.query("ttl"), None);
+        assert_eq!(decode("100%"), "100%");
+        assert_eq!(decode("%e2%9c%93"), "\u{2713}");
+    }
+
+    #[test]
+    fn multipart_takes_the_file_part() {
+        let body = b"--xyz\r\n\
+            Content-Disposition: form-data; name=\"note\"\r\n\r\n\
+            hello\r\n\
+            --xyz\r\n\
+            Content-Disposition: form-data; name=\"image\"; filename=\"a.png\"\r\n\
+            Content-Type: image/png\r\n\r\n\
+            \x89PNG\r\n--not-the-end\r\n\
+            --xyz--\r\n";
+        let file = multipart_file(body, "multipart/form-data; boundary=\"xyz\"").unwrap();
+        assert_eq!(file, b"\x89PNG\r\n--not-the-end");
+        // without a file, the first part
+        let body = b"--b\r\nContent-Disposition: form-data; name=\"x\"\r\n\r\n123\r\n--b--";
+        assert_eq!(
+            multipart_file(body, "multipart/form-data; boundary=b").unwrap(),
+            b"123"
+        );
+        assert!(multipart_file(body, "multipart/form-data")
+            .err()
+            .unwrap()
+            .contains("without a boundary"));
+        assert!(multipart_file(b"--b--", "multipart/form-data; boundary=b")
+            .err()
+            .unwrap()
+            .contains("without any parts"));
+    }
+}
@@ -0,0 +1,65 @@
+// a fingerprint of the frame the panel last showed, kept on disk so drawing
+// the same frame again, like a cron job's unchanged dashboard, can skip the
+// refresh and spare the panel
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{draw::Drawable, frame::crc32, panel::Panel, write_atomic, Geometry};
+
+// ~/.local/state/rpi-epaper, where what the panel went through is kept
+pub fn state_dir() -> PathBuf {
+    let base = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".local/state")))
+        .unwrap_or_else(|| PathBuf::from("."));
+    base.join("rpi-epaper")
+}
+
+pub fn default_path() -> PathBuf {
+    state_dir().join("last-frame")
+}
+
+// the panel, its size and a crc32 of every pixel
+pub fn fingerprint(frame: &dyn Drawable, geometry: Geometry, panel: &dyn Panel) -> String {
+    let (w, h) = (geometry.width, geometry.height);
+    let mut pixels = Vec::with_capacity(w as usize * h as usize);
+    for y in 0..h {
+        for x in 0..w {
+            pixels.push(frame.get_pixel(x, y) as u8);
+        }
+    }
+    format!("{} {w}x{h} {:08x}", panel.name(), crc32(&pixels))
+}
+
+#[derive(Clone)]
+pub struct LastFrame {
+    path: PathBuf,
+}
+
+impl LastFrame {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // whether the panel was last seen showing `fingerprint`
+    pub fn shows(&self, fingerprint: &str) -> bool {
+        fs::read_to_string(&self.path).is_ok_and(|s| s.trim() == fingerprint)
+    }
+
+    pub fn save(&self, fingerprint: &str) -> Result<(), String> {
+        write_atomic(&self.path, format!("{fingerprint}\n").as_bytes())
+    }
+
+    // for when the panel is drawn some other way, or a draw fails partway
+    // and what it shows is anyone's guess
+    pub fn forget(&self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,85 @@
+// drawables stacked into one frame, for putting a dashboard together
+// without nesting a Partial or Window per piece:
+//
+//     let frame = Layers::new(Color::White)
+//         .with(Layer::new(&photo, 0, 0, 600, 448))
+//         .with(Layer::new(&clock, 20, 20, 200, 60).z(1));
+//
+// a layer shows through wherever it draws Clean, so a piece only has to
+// draw its own pixels. the panel can't show Clean inside a stack, only as
+// the background.
+
+use crate::draw::{Color, Drawable};
+
+pub struct Layer<'a> {
+    content: Box<dyn Drawable + 'a>,
+    // the content's own top-left lands here
+    pub x: u16,
+    pub y: u16,
+    pub w: u16,
+    pub h: u16,
+    // higher is on top, the later added of two equal ones
+    pub z: i32,
+}
+
+impl<'a> Layer<'a> {
+    // `content` in a w*h window at x, y, at z 0
+    pub fn new(content: impl Drawable + 'a, x: u16, y: u16, w: u16, h: u16) -> Self {
+        Self {
+            content: Box::new(content),
+            x,
+            y,
+            w,
+            h,
+            z: 0,
+        }
+    }
+
+    pub fn z(mut self, z: i32) -> Self {
+        self.z = z;
+        self
+    }
+
+    fn get(&self, x: u16, y: u16) -> Option<Color> {
+        let inside = x >= self.x && y >= self.y && (x - self.x) < self.w && (y - self.y) < self.h;
+        inside
+            .then(|| self.content.get_pixel(x - self.x, y - self.y))
+            .filter(|&c| c != Color::Clean)
+    }
+}
+
+pub struct Layers<'a> {
+    // top first
+    layers: Vec<Layer<'a>>,
+    background: Color,
+}
+
+impl<'a> Layers<'a> {
+    // an empty stack, `background` wherever no layer draws
+    pub fn new(background: Color) -> Self {
+        Self {
+            layers: Vec::new(),
+            background,
+        }
+    }
+
+    pub fn with(mut self, layer: Layer<'a>) -> Self {
+        self.push(layer);
+        self
+    }
+
+    pub fn push(&mut self, layer: Layer<'a>) {
+        // in front of every layer it isn't below
+        let at = self.layers.partition_point(|l| l.z > layer.z);
+        self.layers.insert(at, layer);
+    }
+}
+
+impl Drawable for Layers<'_> {
+    fn get_pixel(&self, x: u16, y: u16) -> Color {
+        self.layers
+            .iter()
+            .find_map(|l| l.get(x, y))
+            .unwrap_or(self.background)
+    }
+}
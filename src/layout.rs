@@ -0,0 +1,310 @@
+use std::str::FromStr;
+
+use image::imageops::{self, FilterType};
+
+use crate::{
+    decode,
+    draw::{Color, Corner},
+    Geometry, Rgb, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+// a named screen region used by the split layouts
+#[derive(Clone, Copy)]
+pub enum Region {
+    Full,
+    Left,
+    Right,
+    Top,
+    Bottom,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Region {
+    pub fn rect(&self) -> Rect {
+        let (w, h) = (SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+        let (hw, hh) = (w / 2, h / 2);
+        let (x, y, w, h) = match self {
+            Region::Full => (0, 0, w, h),
+            Region::Left => (0, 0, hw, h),
+            Region::Right => (hw, 0, w - hw, h),
+            Region::Top => (0, 0, w, hh),
+            Region::Bottom => (0, hh, w, h - hh),
+            Region::TopLeft => (0, 0, hw, hh),
+            Region::TopRight => (hw, 0, w - hw, hh),
+            Region::BottomLeft => (0, hh, hw, h - hh),
+            Region::BottomRight => (hw, hh, w - hw, h - hh),
+        };
+        Rect { x, y, w, h }
+    }
+}
+
+impl FromStr for Region {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "full" => Ok(Region::Full),
+            "left" => Ok(Region::Left),
+            "right" => Ok(Region::Right),
+            "top" => Ok(Region::Top),
+            "bottom" => Ok(Region::Bottom),
+            "tl" | "top-left" => Ok(Region::TopLeft),
+            "tr" | "top-right" => Ok(Region::TopRight),
+            "bl" | "bottom-left" => Ok(Region::BottomLeft),
+            "br" | "bottom-right" => Ok(Region::BottomRight),
+            _ => Err(format!("unknown region '{s}'")),
+        }
+    }
+}
+
+pub fn blank(bg: Color) -> bmp::Image {
+    blank_sized(Geometry::default(), bg)
+}
+
+// as blank, `area` sized, for composing in a smaller panel's own pixels
+pub fn blank_sized(area: Geometry, bg: Color) -> bmp::Image {
+    let mut img = bmp::Image::new(area.width as u32, area.height as u32);
+    let px: bmp::Pixel = Rgb::from(bg).into();
+    for (x, y) in img.coordinates() {
+        img.set_pixel(x, y, px);
+    }
+    img
+}
+
+// the size of `img`, for placing things in it
+pub fn area(img: &bmp::Image) -> Geometry {
+    Geometry {
+        width: img.get_width() as u16,
+        height: img.get_height() as u16,
+    }
+}
+
+// nearest neighbour scales `src` to fit inside `rect` keeping its aspect
+// ratio, centered. whatever isn't covered is left untouched.
+pub fn fit_into(dst: &mut bmp::Image, src: &bmp::Image, rect: Rect) {
+    let (sw, sh) = (src.get_width(), src.get_height());
+    if sw == 0 || sh == 0 || rect.w == 0 || rect.h == 0 {
+        return;
+    }
+    // pick the limiting axis
+    let (w, h) = if sw as u64 * rect.h as u64 > sh as u64 * rect.w as u64 {
+        (rect.w, (sh as u64 * rect.w as u64 / sw as u64) as u32)
+    } else {
+        ((sw as u64 * rect.h as u64 / sh as u64) as u32, rect.h)
+    };
+    let ox = rect.x.saturating_add((rect.w - w) / 2);
+    let oy = rect.y.saturating_add((rect.h - h) / 2);
+    // only the part that lands on `dst` is scaled
+    let visible_w = w.min(dst.get_width().saturating_sub(ox));
+    let visible_h = h.min(dst.get_height().saturating_sub(oy));
+    for x in 0..visible_w {
+        for y in 0..visible_h {
+            let sx = (x as u64 * sw as u64 / w as u64) as u32;
+            let sy = (y as u64 * sh as u64 / h as u64) as u32;
+            dst.set_pixel(ox + x, oy + y, src.get_pixel(sx, sy));
+        }
+    }
+}
+
+// how an image that isn't screen sized is made to fit
+#[derive(Clone, Copy, PartialEq)]
+pub enum Scale {
+    // all of it, letterboxed
+    Fit,
+    // covers the screen, the overflow cropped evenly off both sides
+    Fill,
+    // exactly the screen, ignoring the aspect ratio
+    Stretch,
+    // the center at its own size, letterboxed if it's smaller
+    Crop,
+}
+
+impl FromStr for Scale {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fit" => Ok(Scale::Fit),
+            "fill" => Ok(Scale::Fill),
+            "stretch" => Ok(Scale::Stretch),
+            "crop" | "center" => Ok(Scale::Crop),
+            _ => Err(format!(
+                "unknown scale mode '{s}' (expected fit, fill, stretch or crop)"
+            )),
+        }
+    }
+}
+
+impl Scale {
+    pub fn name(self) -> &'static str {
+        match self {
+            Scale::Fit => "fit",
+            Scale::Fill => "fill",
+            Scale::Stretch => "stretch",
+            Scale::Crop => "crop",
+        }
+    }
+}
+
+// the resampling filter for resize
+pub fn parse_filter(s: &str) -> Result<FilterType, String> {
+    match s {
+        "nearest" => Ok(FilterType::Nearest),
+        "linear" | "triangle" => Ok(FilterType::Triangle),
+        "cubic" | "catmull-rom" => Ok(FilterType::CatmullRom),
+        "gaussian" => Ok(FilterType::Gaussian),
+        "lanczos" => Ok(FilterType::Lanczos3),
+        _ => Err(format!(
+            "unknown filter '{s}' (expected nearest, linear, cubic, gaussian or lanczos)"
+        )),
+    }
+}
+
+// resamples `src` to a screen sized frame. `bg` shows wherever the image
+// doesn't reach.
+pub fn resize(src: &bmp::Image, mode: Scale, filter: FilterType, bg: Color) -> bmp::Image {
+    resize_into(src, Geometry::default(), mode, filter, bg)
+}
+
+// as resize, into the top-left `area` of a screen sized frame, where a
+// panel smaller than the screen shows it
+pub fn resize_into(
+    src: &bmp::Image,
+    area: Geometry,
+    mode: Scale,
+    filter: FilterType,
+    bg: Color,
+) -> bmp::Image {
+    let (sw, sh) = (src.get_width() as u64, src.get_height() as u64);
+    let tw = area.width.min(SCREEN_WIDTH) as u64;
+    let th = area.height.min(SCREEN_HEIGHT) as u64;
+    let mut out = blank(bg);
+    if sw == 0 || sh == 0 {
+        return out;
+    }
+    // whether the source is wider than the screen, relative to its height
+    let wider = sw * th > sh * tw;
+    let (w, h) = match mode {
+        Scale::Stretch => (tw, th),
+        Scale::Crop => (sw, sh),
+        Scale::Fit if wider => (tw, (sh * tw / sw).max(1)),
+        Scale::Fit => ((sw * th / sh).max(1), th),
+        Scale::Fill if wider => ((sw * th / sh).max(1), th),
+        Scale::Fill => (tw, (sh * tw / sw).max(1)),
+    };
+    let scaled = if (w, h) == (sw, sh) {
+        decode::to_rgb(src)
+    } else {
+        imageops::resize(&decode::to_rgb(src), w as u32, h as u32, filter)
+    };
+    // centered, either cropping or letterboxing each axis
+    let (w, h) = (w as i64, h as i64);
+    let ox = (tw as i64 - w) / 2;
+    let oy = (th as i64 - h) / 2;
+    for y in 0..th as i64 {
+        for x in 0..tw as i64 {
+            let (sx, sy) = (x - ox, y - oy);
+            if (0..w).contains(&sx) && (0..h).contains(&sy) {
+                let [r, g, b] = scaled.get_pixel(sx as u32, sy as u32).0;
+                out.set_pixel(x as u32, y as u32, bmp::Pixel::new(r, g, b));
+            }
+        }
+    }
+    out
+}
+
+// composes several images into a single screen-sized frame
+pub fn split(panes: &[(Region, bmp::Image)], bg: Color) -> bmp::Image {
+    let mut out = blank(bg);
+    for (region, img) in panes {
+        fit_into(&mut out, img, region.rect());
+    }
+    out
+}
+
+// insets `src` over `dst` in a corner, sized to `scale` of dst's width
+pub fn picture_in_picture(dst: &mut bmp::Image, src: &bmp::Image, corner: Corner, scale: f32) {
+    let size = (src.get_width(), src.get_height());
+    if let Some(rect) = pip_rect(size, area(dst), corner, scale) {
+        fit_into(dst, src, rect);
+    }
+}
+
+// where picture_in_picture puts a `size` image over an `area` sized one
+pub fn pip_rect(size: (u32, u32), area: Geometry, corner: Corner, scale: f32) -> Option<Rect> {
+    const MARGIN: u16 = 8;
+    let (sw, sh) = size;
+    if sw == 0 || sh == 0 {
+        return None;
+    }
+    let w = (area.width as f32 * scale.clamp(0.0, 1.0)) as u32;
+    let h = (w * sh / sw).min(area.height as u32);
+    let (x, y) = corner.place(area, w as u16, h as u16, MARGIN);
+    Some(Rect {
+        x: x as u32,
+        y: y as u32,
+        w,
+        h,
+    })
+}
+
+// nearest neighbour upscales by the largest whole factor that fits the
+// screen and centers the result, so every source pixel stays square
+pub fn integer_upscale(src: &bmp::Image, bg: Color) -> (bmp::Image, u32) {
+    let mut out = blank(bg);
+    let (sw, sh) = (src.get_width().max(1), src.get_height().max(1));
+    let factor = (SCREEN_WIDTH as u32 / sw)
+        .min(SCREEN_HEIGHT as u32 / sh)
+        .max(1);
+    let (w, h) = (sw * factor, sh * factor);
+    let ox = (SCREEN_WIDTH as u32).saturating_sub(w) / 2;
+    let oy = (SCREEN_HEIGHT as u32).saturating_sub(h) / 2;
+    for x in 0..w.min(SCREEN_WIDTH as u32) {
+        for y in 0..h.min(SCREEN_HEIGHT as u32) {
+            out.set_pixel(ox + x, oy + y, src.get_pixel(x / factor, y / factor));
+        }
+    }
+    (out, factor)
+}
+
+// whether every pixel is exactly one of the panel colors
+pub fn uses_palette_only(img: &bmp::Image) -> bool {
+    let palette: Vec<bmp::Pixel> = Color::all().iter().map(|c| Rgb::from(*c).into()).collect();
+    img.coordinates()
+        .all(|(x, y)| palette.contains(&img.get_pixel(x, y)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resize_into_keeps_to_the_area() {
+        let src = blank(Color::Black);
+        let area = Geometry {
+            width: 400,
+            height: 300,
+        };
+        let out = resize_into(
+            &src,
+            area,
+            Scale::Stretch,
+            FilterType::Nearest,
+            Color::White,
+        );
+        let black = bmp::Pixel::new(0, 0, 0);
+        for (x, y) in out.coordinates() {
+            let inside = x < 400 && y < 300;
+            assert_eq!(out.get_pixel(x, y) == black, inside, "{x},{y}");
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use std::time::{Duration, Instant};
+
+use crate::layout::Rect;
+
+// a client's claim on a screen region until it expires
+pub struct Lease {
+    pub client: String,
+    pub name: String,
+    pub rect: Rect,
+    pub expires: Instant,
+}
+
+// saturating, so a rect running off the end of u32 can't wrap round
+fn right(r: Rect) -> u32 {
+    r.x.saturating_add(r.w)
+}
+
+fn bottom(r: Rect) -> u32 {
+    r.y.saturating_add(r.h)
+}
+
+fn overlaps(a: Rect, b: Rect) -> bool {
+    a.x < right(b) && b.x < right(a) && a.y < bottom(b) && b.y < bottom(a)
+}
+
+// the live leases. expired ones are dropped lazily on every call.
+#[derive(Default)]
+pub struct Leases {
+    leases: Vec<Lease>,
+}
+
+impl Leases {
+    fn expire(&mut self) {
+        let now = Instant::now();
+        self.leases.retain(|l| l.expires > now);
+    }
+
+    // takes or renews `name` for `client`. fails if another client holds an
+    // overlapping region.
+    pub fn acquire(
+        &mut self,
+        client: &str,
+        name: &str,
+        rect: Rect,
+        ttl: Duration,
+    ) -> Result<(), String> {
+        self.expire();
+        if let Some(held) = self
+            .leases
+            .iter()
+            .find(|l| l.client != client && (l.name == name || overlaps(l.rect, rect)))
+        {
+            return Err(format!(
+                "region {name} conflicts with {} held by {}",
+                held.name, held.client
+            ));
+        }
+        self.leases
+            .retain(|l| !(l.client == client && l.name == name));
+        self.leases.push(Lease {
+            client: client.to_string(),
+            name: name.to_string(),
+            rect,
+            expires: Instant::now() + ttl,
+        });
+        Ok(())
+    }
+
+    pub fn release(&mut self, client: &str, name: &str) {
+        self.leases
+            .retain(|l| !(l.client == client && l.name == name));
+    }
+
+    // a write must stay clear of every other client's lease, and a client
+    // holding leases may only write inside them
+    pub fn check_write(&mut self, client: &str, rect: Rect) -> Result<(), String> {
+        self.expire();
+        if let Some(held) = self
+            .leases
+            .iter()
+            .find(|l| l.client != client && overlaps(l.rect, rect))
+        {
+            return Err(format!(
+                "{client} may not draw over {} held by {}",
+                held.name, held.client
+            ));
+        }
+        let own: Vec<&Lease> = self.leases.iter().filter(|l| l.client == client).collect();
+        let inside = |l: &&Lease| {
+            rect.x >= l.rect.x
+                && rect.y >= l.rect.y
+                && right(rect) <= right(l.rect)
+                && bottom(rect) <= bottom(l.rect)
+        };
+        if !own.is_empty() && !own.iter().any(inside) {
+            return Err(format!("{client} may only draw inside its leased regions"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    fn rect(x: u32, y: u32, w: u32, h: u32) -> Rect {
+        Rect { x, y, w, h }
+    }
+
+    #[test]
+    fn anyone_may_write_while_nothing_is_leased() {
+        let mut leases = Leases::default();
+        assert!(leases.check_write("a", rect(0, 0, 600, 448)).is_ok());
+    }
+
+    #[test]
+    fn holders_write_only_inside_their_leases() {
+        let mut leases = Leases::default();
+        leases
+            .acquire("a", "clock", rect(0, 0, 100, 50), TTL)
+            .unwrap();
+        assert!(leases.check_write("a", rect(10, 10, 90, 40)).is_ok());
+        let outside = leases.check_write("a", rect(50, 0, 100, 50)).unwrap_err();
+        assert!(outside.contains("only draw inside"));
+        // others stay clear of it, touching its edge is fine
+        let over = leases.check_write("b", rect(99, 49, 10, 10)).unwrap_err();
+        assert!(over.contains("clock held by a"));
+        assert!(leases.check_write("b", rect(100, 0, 10, 10)).is_ok());
+        assert!(leases.check_write("b", rect(0, 50, 10, 10)).is_ok());
+    }
+
+    #[test]
+    fn conflicting_leases_are_refused() {
+        let mut leases = Leases::default();
+        leases
+            .acquire("a", "clock", rect(0, 0, 100, 50), TTL)
+            .unwrap();
+        assert!(leases
+            .acquire("b", "temp", rect(50, 25, 100, 50), TTL)
+            .is_err());
+        assert!(leases
+            .acquire("b", "clock", rect(300, 300, 10, 10), TTL)
+            .is_err());
+        // a holder can move its own lease
+        leases
+            .acquire("a", "clock", rect(200, 0, 100, 50), TTL)
+            .unwrap();
+        assert!(leases
+            .acquire("b", "temp", rect(50, 25, 100, 50), TTL)
+            .is_ok());
+    }
+
+    #[test]
+    fn released_and_expired_leases_are_dropped() {
+        let mut leases = Leases::default();
+        leases
+            .acquire("a", "clock", rect(0, 0, 100, 50), TTL)
+            .unwrap();
+        leases.release("a", "clock");
+        assert!(leases.check_write("b", rect(0, 0, 10, 10)).is_ok());
+        leases
+            .acquire("a", "clock", rect(0, 0, 100, 50), Duration::ZERO)
+            .unwrap();
+        assert!(leases.check_write("b", rect(0, 0, 10, 10)).is_ok());
+    }
+
+    #[test]
+    fn rects_at_the_end_of_u32_dont_wrap() {
+        let mut leases = Leases::default();
+        leases
+            .acquire("a", "edge", rect(u32::MAX - 5, 0, 100, 10), TTL)
+            .unwrap();
+        assert!(leases.check_write("b", rect(0, 0, 10, 10)).is_ok());
+        assert!(leases
+            .check_write("b", rect(u32::MAX - 2, 0, 1, 10))
+            .is_err());
+    }
+}
@@ -0,0 +1,729 @@
+//! driver and rendering for the waveshare 5.65" 7 color acep e-paper panel.
+//! `EPaper` talks to the panel, `cmd` holds the commands it accepts and
+//! `draw` the frames it can show. `app`, `render`, `daemon` and `report`
+//! hold the modes the binary runs over them.
+
+use std::{
+    fs,
+    ops::{AddAssign, Sub},
+    panic,
+    path::Path,
+    process,
+    sync::{Arc, Mutex},
+    thread::{self, sleep},
+    time::{Duration, Instant},
+};
+
+use draw::PaperImage;
+use rppal::spi::{Bus, Mode, SimpleHalSpiDevice, SlaveSelect, Spi};
+use tracing::{error, info, warn};
+
+pub mod annotate;
+pub mod app;
+pub mod ascii;
+#[cfg(feature = "async")]
+pub mod asynch;
+#[cfg(feature = "battery")]
+pub mod battery;
+pub mod calibrate;
+#[cfg(feature = "climate")]
+pub mod climate;
+pub mod cmd;
+pub mod compose;
+pub mod config;
+pub mod daemon;
+pub mod decode;
+pub mod dither;
+pub mod draw;
+pub mod endurance;
+pub mod error;
+pub mod events;
+pub mod font;
+pub mod frame;
+pub mod framebuffer;
+pub mod glyphs;
+pub mod gpio;
+#[cfg(feature = "embedded-graphics")]
+pub mod graphics;
+pub mod group;
+pub mod hal;
+pub mod http;
+pub mod lastframe;
+pub mod layers;
+pub mod layout;
+pub mod lease;
+#[cfg(feature = "light")]
+pub mod light;
+pub mod localtime;
+pub mod lut;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod mqtt;
+pub mod notify;
+pub mod overlay;
+pub mod pages;
+pub mod palette;
+pub mod panel;
+pub mod pattern;
+pub mod payload;
+pub mod pipeline;
+pub mod preview;
+pub mod profile;
+#[cfg(feature = "qr")]
+pub mod qr;
+pub mod reduce;
+pub mod refreshcount;
+pub mod render;
+pub mod report;
+pub mod retained;
+pub mod roi;
+pub mod rtc;
+pub mod scene;
+pub mod script;
+pub mod serve;
+pub mod sim;
+pub mod source;
+pub mod splash;
+pub mod store;
+pub mod term;
+#[cfg(feature = "ttf")]
+pub mod text;
+pub mod web;
+
+use crate::{
+    cmd::{Clear, Command, DeepSleep, DrawOptions, Init, PowerOff},
+    draw::Color,
+    gpio::{Input, Output},
+    hal::{HalDevice, SpiBus, StdDelay},
+    panel::Panel,
+};
+
+const _DIN: u8 = 10; // spi0 mosi
+const _CLK: u8 = 11; // spi0 sclk
+const _CS: u8 = 8; // spi0 ce0 (chip select)
+const DC: u8 = 25; // data (high)/command (low)
+const BUSY: u8 = 24;
+const RESET: u8 = 17;
+// the 5.65" acep panel. frames and dithering are built for this size.
+pub const SCREEN_WIDTH: u16 = 600;
+pub const SCREEN_HEIGHT: u16 = 448;
+
+// panel size in pixels, as sent with SetResolution
+#[derive(Clone, Copy)]
+pub struct Geometry {
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Default for Geometry {
+    fn default() -> Self {
+        Self {
+            width: SCREEN_WIDTH,
+            height: SCREEN_HEIGHT,
+        }
+    }
+}
+
+// how to reach the panel. the defaults match the waveshare hat.
+#[derive(Clone)]
+pub struct Config {
+    // data (high)/command (low)
+    pub dc: u8,
+    pub busy: u8,
+    pub reset: u8,
+    pub gpio: gpio::Backend,
+    // only used by the cdev backend
+    pub gpiochip: String,
+    pub spi_bus: Bus,
+    pub spi_select: SlaveSelect,
+    pub spi_clock: u32,
+    // a spidev node opened through linux-embedded-hal in place of rppal's
+    // bus and select, for boards rppal doesn't know
+    pub spidev: Option<String>,
+    pub panel: &'static dyn Panel,
+    pub geometry: Geometry,
+    // longest the busy line may hold before giving up on the panel
+    pub busy_timeout: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            dc: DC,
+            busy: BUSY,
+            reset: RESET,
+            gpio: gpio::Backend::Rppal,
+            gpiochip: "/dev/gpiochip0".to_string(),
+            spi_bus: Bus::Spi0,
+            spi_select: SlaveSelect::Ss0,
+            spi_clock: 5_000_000,
+            spidev: None,
+            panel: &panel::Acep565,
+            geometry: Geometry::default(),
+            busy_timeout: DEFAULT_BUSY_TIMEOUT,
+        }
+    }
+}
+
+// spidev fails a write longer than its bufsiz outright
+const SPIDEV_BUFSIZ: &str = "/sys/module/spidev/parameters/bufsiz";
+
+// the most bytes spidev takes in one write. 4096 unless the module was
+// loaded with another bufsiz.
+pub fn spi_write_limit() -> usize {
+    fs::read_to_string(SPIDEV_BUFSIZ)
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(4096)
+}
+
+// written to a temp file beside `path` and renamed over it, so a crash or
+// power cut mid write leaves either the old file or the new one, never half
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    let err = |e| format!("could not write {}: {e}", path.display());
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(err)?;
+    }
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, bytes).map_err(err)?;
+    fs::rename(&tmp, path).map_err(err)
+}
+
+// a full refresh takes about 30s, longer in the cold
+pub const DEFAULT_BUSY_TIMEOUT: Duration = Duration::from_secs(90);
+
+// the longest the lock is held waiting on the busy line, before it is let
+// go for the hooks to get in
+const BUSY_SLICE: Duration = Duration::from_millis(100);
+
+// rppal's or a spidev spi bus with the lines from either gpio backend
+type RpiDevice = HalDevice<SpiBus, Box<dyn Output>, Box<dyn Input>, Box<dyn Output>, StdDelay>;
+
+struct Hardware {
+    dev: RpiDevice,
+    // DeepSleep was sent and no reset since
+    asleep: bool,
+}
+
+// the pins sit behind a shared lock so the panic hook can reach them
+pub struct EPaper {
+    hw: Arc<Mutex<Option<Hardware>>>,
+    // deep sleep rather than only power off when dropped
+    sleep_on_drop: bool,
+}
+
+impl EPaper {
+    pub fn init(spi: Spi, pins: gpio::Pins, geometry: Geometry) -> Self {
+        Self::on_bus(SpiBus::Rppal(SimpleHalSpiDevice::new(spi)), pins, geometry)
+    }
+
+    fn on_bus(spi: SpiBus, (dc, busy, reset): gpio::Pins, geometry: Geometry) -> Self {
+        let mut dev = HalDevice::new(spi, dc, busy, reset, StdDelay);
+        dev.max_write = spi_write_limit();
+        dev.geometry = geometry;
+        let mut s = Self {
+            hw: Arc::new(Mutex::new(Some(Hardware { dev, asleep: false }))),
+            sleep_on_drop: true,
+        };
+        s.reset();
+        s
+    }
+
+    // opens the spi bus and pins described by `config`
+    pub fn open(config: &Config) -> error::Result<Self> {
+        let spi = match &config.spidev {
+            #[cfg(feature = "spidev")]
+            Some(path) => SpiBus::spidev(path, config.spi_clock)?,
+            #[cfg(not(feature = "spidev"))]
+            Some(path) => {
+                return Err(error::EpaperError::Io(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    format!("{path} needs rpi-epaper built with the spidev feature"),
+                )))
+            }
+            None => SpiBus::Rppal(SimpleHalSpiDevice::new(Spi::new(
+                config.spi_bus,
+                config.spi_select,
+                config.spi_clock,
+                Mode::Mode0,
+            )?)),
+        };
+        let pins = gpio::open(
+            config.gpio,
+            &config.gpiochip,
+            config.dc,
+            config.busy,
+            config.reset,
+        )?;
+        let mut s = Self::on_bus(spi, pins, config.geometry);
+        s.set_busy_timeout(config.busy_timeout);
+        s.set_panel(config.panel);
+        Ok(s)
+    }
+
+    // how long a busy wait may take before it fails with BusyTimeout
+    pub fn set_busy_timeout(&mut self, timeout: Duration) {
+        self.with_hw(|hw| hw.dev.busy_timeout = timeout);
+    }
+
+    // the model Init and the frame packing are for, the 5.65" acep unless
+    // set. the geometry goes with it, so frames are sized for the new panel
+    pub fn set_panel(&mut self, panel: &'static dyn Panel) {
+        self.with_hw(|hw| {
+            hw.dev.panel = panel;
+            hw.dev.geometry = panel.geometry();
+        });
+    }
+
+    fn with_hw<R>(&self, f: impl FnOnce(&mut Hardware) -> R) -> R {
+        let mut hw = self.hw.lock().unwrap_or_else(|e| e.into_inner());
+        f(hw.as_mut().expect("display was parked after a panic"))
+    }
+
+    // sleeps until the busy line reads `high`, or fails once the busy
+    // timeout has passed
+    fn wait_busy(&self, high: bool) -> error::Result<()> {
+        let timeout = self.with_hw(|hw| hw.dev.busy_timeout);
+        let start = Instant::now();
+        loop {
+            let slice = timeout.saturating_sub(start.elapsed()).min(BUSY_SLICE);
+            if self.with_hw(|hw| hw.dev.wait_busy_for(high, slice)) {
+                return Ok(());
+            }
+            if start.elapsed() >= timeout {
+                return Err(error::EpaperError::BusyTimeout(timeout));
+            }
+            // a thread waiting on the lock would otherwise lose it straight back
+            sleep(Duration::from_millis(1));
+        }
+    }
+
+    pub fn reset(&mut self) {
+        SpiDevice::reset(self)
+    }
+
+    // powers the panel off and puts it in deep sleep, where it draws next
+    // to nothing until the next wake. a no-op if it already sleeps.
+    pub fn sleep(&mut self) -> error::Result<()> {
+        if self.is_asleep() {
+            return Ok(());
+        }
+        PowerOff.send(self)?;
+        DeepSleep.send(self)
+    }
+
+    // hardware reset and init, the only way out of deep sleep
+    pub fn wake(&mut self) -> error::Result<()> {
+        self.reset();
+        self.wait_busy_high()?;
+        Init.send(self)
+    }
+
+    // `n` flashing clears, for when ghosts of earlier frames show through.
+    // the panel is left blank.
+    pub fn clear_cycles(&mut self, n: u32) -> error::Result<()> {
+        Clear {
+            cycles: n,
+            options: DrawOptions::default(),
+        }
+        .send(self)
+    }
+
+    // whether dropping the display puts it in deep sleep or only powers it
+    // off, leaving it ready for commands without a reset
+    pub fn set_sleep_on_drop(&mut self, sleep: bool) {
+        self.sleep_on_drop = sleep;
+    }
+
+    pub fn is_asleep(&self) -> bool {
+        self.with_hw(|hw| hw.asleep)
+    }
+
+    // on panic, powers the panel off and puts it in deep sleep before
+    // unwinding, then releases the pins. skipped if the panic happened
+    // while the pins were in use.
+    pub fn install_panic_hook(&self) {
+        let hw = Arc::clone(&self.hw);
+        let prev = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if let Ok(mut guard) = hw.try_lock() {
+                if let Some(mut hw) = guard.take() {
+                    warn!("Parking display after panic");
+                    hw.park(true);
+                }
+            }
+            prev(info);
+        }));
+    }
+
+    // on SIGINT or SIGTERM, draws `frame` with `options` and parks the
+    // panel, then exits.
+    // the lock is held throughout so the main thread can't interleave
+    // commands. must be called before any other thread is started, as the
+    // signals are blocked for every thread but the waiting one.
+    pub fn install_shutdown_screen(&self, frame: Box<PaperImage>, options: DrawOptions) {
+        self.on_signal(Some((frame, options)), true);
+    }
+
+    // on SIGINT or SIGTERM, powers the panel off, and puts it in deep sleep
+    // if `deep_sleep`, then exits, so stopping a looping mode leaves it
+    // showing the last frame unpowered. the same rules as
+    // install_shutdown_screen apply.
+    pub fn install_shutdown_handler(&self, deep_sleep: bool) {
+        self.on_signal(None, deep_sleep);
+    }
+
+    fn on_signal(&self, frame: Option<(Box<PaperImage>, DrawOptions)>, deep_sleep: bool) {
+        // SAFETY: plain libc signal mask calls on a zeroed, initialized set
+        let set = unsafe {
+            let mut set: libc::sigset_t = std::mem::zeroed();
+            libc::sigemptyset(&mut set);
+            libc::sigaddset(&mut set, libc::SIGINT);
+            libc::sigaddset(&mut set, libc::SIGTERM);
+            libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut());
+            set
+        };
+        let hw = Arc::clone(&self.hw);
+        thread::spawn(move || {
+            let mut sig = 0;
+            // SAFETY: the set outlives the call and sig is a valid out pointer
+            unsafe { libc::sigwait(&set, &mut sig) };
+            let mut guard = hw.lock().unwrap_or_else(|e| e.into_inner());
+            match (guard.take(), frame) {
+                (Some(mut hw), Some((frame, options))) => {
+                    info!("Drawing offline screen");
+                    hw.reset();
+                    let drawn = hw
+                        .wait_busy_high()
+                        .and_then(|_| Init.send(&mut hw))
+                        .and_then(|_| {
+                            cmd::Draw {
+                                frame: &*frame,
+                                // parked below
+                                options: options.awake(),
+                                progress: None,
+                                cancel: None,
+                            }
+                            .send(&mut hw)
+                        });
+                    if let Err(e) = drawn {
+                        error!("could not draw offline screen: {e}");
+                    }
+                    hw.park(deep_sleep);
+                }
+                // already parked between refreshes
+                (Some(hw), None) if hw.asleep => {}
+                (Some(mut hw), None) => {
+                    info!("Powering display off");
+                    hw.park(deep_sleep);
+                }
+                (None, _) => {}
+            }
+            process::exit(0);
+        });
+    }
+}
+
+impl Hardware {
+    fn wait_busy(&self, high: bool) -> error::Result<()> {
+        let timeout = self.dev.busy_timeout;
+        match self.dev.wait_busy_for(high, timeout) {
+            true => Ok(()),
+            false => Err(error::EpaperError::BusyTimeout(timeout)),
+        }
+    }
+
+    // best-effort PowerOff, then DeepSleep if `deep_sleep`, with a bounded
+    // busy wait so a wedged panel can't hang the panic
+    fn park(&mut self, deep_sleep: bool) {
+        let _ = self.dev.send_cmd(0x02);
+        self.dev.wait_busy_for(false, Duration::from_secs(2));
+        if deep_sleep {
+            let _ = self.dev.send_cmd(0x07);
+            let _ = self.dev.send_data(&[0xA5]);
+            self.asleep = true;
+        }
+    }
+}
+
+pub trait SpiDevice {
+    fn send_cmd(&mut self, cmd: u8) -> error::Result<()>;
+    fn send_data(&mut self, data: &[u8]) -> error::Result<()>;
+    // clocks in what the controller answers the last command with. the
+    // panel's data line has to be wired back to the bus for this.
+    fn read_data(&mut self, buf: &mut [u8]) -> error::Result<()> {
+        let _ = buf;
+        Err(error::EpaperError::Io(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "this device can't be read from",
+        )))
+    }
+    // writes `write` while reading as many bytes into `read`, both as data
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> error::Result<()> {
+        let _ = (read, write);
+        Err(error::EpaperError::Io(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "this device can't be read from",
+        )))
+    }
+    fn wait_busy_high(&self) -> error::Result<()>;
+    fn wait_busy_low(&self) -> error::Result<()>;
+    // hardware reset line
+    fn reset(&mut self);
+    // the size of the attached panel
+    fn geometry(&self) -> Geometry {
+        Geometry::default()
+    }
+    // the model attached, for Init and packing frames
+    fn panel(&self) -> &'static dyn Panel {
+        &panel::Acep565
+    }
+    // a rest a command takes between sends, like a refresh's cooldown
+    fn pause(&self, time: Duration) {
+        sleep(time)
+    }
+}
+
+// so a panel's init can send commands through a `&mut dyn SpiDevice`
+impl<T: SpiDevice + ?Sized> SpiDevice for &mut T {
+    fn send_cmd(&mut self, cmd: u8) -> error::Result<()> {
+        (**self).send_cmd(cmd)
+    }
+
+    fn send_data(&mut self, data: &[u8]) -> error::Result<()> {
+        (**self).send_data(data)
+    }
+
+    fn read_data(&mut self, buf: &mut [u8]) -> error::Result<()> {
+        (**self).read_data(buf)
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> error::Result<()> {
+        (**self).transfer(read, write)
+    }
+
+    fn wait_busy_high(&self) -> error::Result<()> {
+        (**self).wait_busy_high()
+    }
+
+    fn wait_busy_low(&self) -> error::Result<()> {
+        (**self).wait_busy_low()
+    }
+
+    fn reset(&mut self) {
+        (**self).reset()
+    }
+
+    fn geometry(&self) -> Geometry {
+        (**self).geometry()
+    }
+
+    fn panel(&self) -> &'static dyn Panel {
+        (**self).panel()
+    }
+
+    fn pause(&self, time: Duration) {
+        (**self).pause(time)
+    }
+}
+
+impl SpiDevice for Hardware {
+    fn send_cmd(&mut self, cmd: u8) -> error::Result<()> {
+        self.dev.send_cmd(cmd)?;
+        if cmd == 0x07 {
+            self.asleep = true;
+        }
+        Ok(())
+    }
+
+    fn send_data(&mut self, data: &[u8]) -> error::Result<()> {
+        self.dev.send_data(data)
+    }
+
+    fn read_data(&mut self, buf: &mut [u8]) -> error::Result<()> {
+        self.dev.read_data(buf)
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> error::Result<()> {
+        self.dev.transfer(read, write)
+    }
+
+    fn wait_busy_high(&self) -> error::Result<()> {
+        self.wait_busy(true)
+    }
+
+    fn wait_busy_low(&self) -> error::Result<()> {
+        self.wait_busy(false)
+    }
+
+    fn reset(&mut self) {
+        self.asleep = false;
+        self.dev.reset();
+    }
+
+    fn geometry(&self) -> Geometry {
+        self.dev.geometry()
+    }
+
+    fn panel(&self) -> &'static dyn Panel {
+        self.dev.panel()
+    }
+}
+
+// powers the panel down however the display goes away, so an error or a
+// panic mid-draw doesn't leave the charge pumps running. only PowerOff and
+// DeepSleep are sent, never a frame or a refresh, so the panel keeps
+// showing whatever it last drew.
+impl Drop for EPaper {
+    fn drop(&mut self) {
+        let mut hw = self.hw.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(hw) = hw.as_mut().filter(|hw| !hw.asleep) {
+            hw.park(self.sleep_on_drop);
+        }
+    }
+}
+
+// the lock is only held per call, so the hooks can get in between
+impl SpiDevice for EPaper {
+    fn send_cmd(&mut self, cmd: u8) -> error::Result<()> {
+        self.with_hw(|hw| hw.send_cmd(cmd))
+    }
+
+    fn send_data(&mut self, data: &[u8]) -> error::Result<()> {
+        self.with_hw(|hw| hw.send_data(data))
+    }
+
+    // rppal's bus reads through its full duplex transfer ioctl
+    fn read_data(&mut self, buf: &mut [u8]) -> error::Result<()> {
+        self.with_hw(|hw| hw.read_data(buf))
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> error::Result<()> {
+        self.with_hw(|hw| hw.transfer(read, write))
+    }
+
+    fn wait_busy_high(&self) -> error::Result<()> {
+        self.wait_busy(true)
+    }
+
+    fn wait_busy_low(&self) -> error::Result<()> {
+        self.wait_busy(false)
+    }
+
+    fn reset(&mut self) {
+        self.with_hw(|hw| hw.reset());
+    }
+
+    fn geometry(&self) -> Geometry {
+        self.with_hw(|hw| hw.dev.geometry)
+    }
+
+    fn panel(&self) -> &'static dyn Panel {
+        self.with_hw(|hw| hw.dev.panel)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Rgb {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+}
+
+impl Rgb {
+    // `f` applied to each channel
+    pub fn map(self, f: impl Fn(f32) -> f32) -> Rgb {
+        Rgb {
+            r: f(self.r),
+            g: f(self.g),
+            b: f(self.b),
+        }
+    }
+}
+
+impl From<bmp::Pixel> for Rgb {
+    fn from(value: bmp::Pixel) -> Self {
+        Self {
+            r: value.r.into(),
+            g: value.g.into(),
+            b: value.b.into(),
+        }
+    }
+}
+
+impl From<Color> for Rgb {
+    fn from(value: Color) -> Self {
+        let [r, g, b] = value.as_rgb();
+        Self { r, g, b }
+    }
+}
+
+impl From<Rgb> for bmp::Pixel {
+    fn from(value: Rgb) -> Self {
+        bmp::Pixel {
+            r: value.r.clamp(0.0, 255.0) as u8,
+            g: value.g.clamp(0.0, 255.0) as u8,
+            b: value.b.clamp(0.0, 255.0) as u8,
+        }
+    }
+}
+
+impl AddAssign for Rgb {
+    fn add_assign(&mut self, rhs: Rgb) {
+        self.r += rhs.r;
+        self.g += rhs.g;
+        self.b += rhs.b;
+    }
+}
+
+impl Sub for Rgb {
+    type Output = Rgb;
+    fn sub(self, rhs: Rgb) -> Rgb {
+        Rgb {
+            r: self.r - rhs.r,
+            g: self.g - rhs.g,
+            b: self.b - rhs.b,
+        }
+    }
+}
+
+pub fn floyd_steinberg_dither(img: &bmp::Image) -> PaperImage {
+    floyd_steinberg_dither_with(img, |_, _, px| Color::closest(px))
+}
+
+// dithers with the perceptual color match inside the masked region of
+// interest and the cheaper euclidean match everywhere else
+pub fn floyd_steinberg_dither_roi(img: &bmp::Image, mask: &roi::Mask) -> PaperImage {
+    floyd_steinberg_dither_with(img, |x, y, px| {
+        if mask.contains(x, y) {
+            Color::closest_perceptual(px)
+        } else {
+            Color::closest(px)
+        }
+    })
+}
+
+// maps each pixel to its closest color without diffusing any error
+pub fn quantize(img: &bmp::Image) -> PaperImage {
+    let mut out = PaperImage::new(Color::Clean);
+    for y in 0..SCREEN_HEIGHT as usize {
+        for x in 0..SCREEN_WIDTH as usize {
+            out.data[x + y * SCREEN_WIDTH as usize] =
+                Color::closest(img.get_pixel(x as u32, y as u32).into());
+        }
+    }
+    out
+}
+
+pub fn floyd_steinberg_dither_with(
+    img: &bmp::Image,
+    quantize: impl Fn(usize, usize, Rgb) -> Color + Sync,
+) -> PaperImage {
+    dither::diffuse(
+        img,
+        &dither::FLOYD_STEINBERG,
+        dither::Diffusion::default(),
+        quantize,
+    )
+}
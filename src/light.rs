@@ -0,0 +1,85 @@
+use std::{str::FromStr, thread::sleep, time::Duration};
+
+use rppal::i2c::{self, I2c};
+
+use crate::draw::Color;
+
+// what to dither with in direct sunlight, where the mid tones wash out
+pub const HIGH_CONTRAST: &[Color] = &[Color::Black, Color::White, Color::Red];
+
+#[derive(Clone, Copy)]
+pub enum Sensor {
+    // bh1750, reports lux directly
+    Bh1750,
+    // tsl2561, broadband and infrared channels combined into lux
+    Tsl2561,
+}
+
+impl FromStr for Sensor {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bh1750" => Ok(Sensor::Bh1750),
+            "tsl2561" => Ok(Sensor::Tsl2561),
+            _ => Err(format!(
+                "unknown light sensor '{s}' (expected bh1750 or tsl2561)"
+            )),
+        }
+    }
+}
+
+// the datasheet's piecewise fit for the T/FN/CL package
+fn tsl2561_lux(ch0: f32, ch1: f32) -> f32 {
+    if ch0 == 0.0 {
+        return 0.0;
+    }
+    let ratio = ch1 / ch0;
+    let lux = match ratio {
+        r if r <= 0.50 => 0.0304 * ch0 - 0.062 * ch0 * r.powf(1.4),
+        r if r <= 0.61 => 0.0224 * ch0 - 0.031 * ch1,
+        r if r <= 0.80 => 0.0128 * ch0 - 0.0153 * ch1,
+        r if r <= 1.30 => 0.00146 * ch0 - 0.00112 * ch1,
+        _ => 0.0,
+    };
+    lux.max(0.0)
+}
+
+impl Sensor {
+    fn address(&self) -> u16 {
+        match self {
+            Sensor::Bh1750 => 0x23,
+            Sensor::Tsl2561 => 0x39,
+        }
+    }
+
+    // takes a single measurement, which blocks for up to half a second
+    pub fn read(&self) -> i2c::Result<f32> {
+        let mut i2c = I2c::new()?;
+        i2c.set_slave_address(self.address())?;
+        Ok(match self {
+            Sensor::Bh1750 => {
+                // one time high resolution mode, then powers down by itself
+                i2c.write(&[0x20])?;
+                sleep(Duration::from_millis(180));
+                let mut buf = [0u8; 2];
+                i2c.read(&mut buf)?;
+                u16::from_be_bytes(buf) as f32 / 1.2
+            }
+            Sensor::Tsl2561 => {
+                // power on, 402ms integration at 1x gain
+                i2c.write(&[0x80, 0x03])?;
+                i2c.write(&[0x81, 0x02])?;
+                sleep(Duration::from_millis(450));
+                let mut ch0 = [0u8; 2];
+                let mut ch1 = [0u8; 2];
+                i2c.write_read(&[0xAC], &mut ch0)?;
+                i2c.write_read(&[0xAE], &mut ch1)?;
+                i2c.write(&[0x80, 0x00])?;
+                tsl2561_lux(
+                    u16::from_le_bytes(ch0) as f32,
+                    u16::from_le_bytes(ch1) as f32,
+                )
+            }
+        })
+    }
+}
@@ -0,0 +1,181 @@
+use std::{
+    fmt::Write,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use chrono::DateTime;
+use chrono_tz::Tz;
+
+// a timezone from the tz database chrono-tz carries
+#[derive(Clone, Copy)]
+pub struct Zone(pub Tz);
+
+impl Zone {
+    pub fn utc() -> Self {
+        Self(Tz::UTC)
+    }
+
+    // `Europe/Berlin` style names
+    pub fn load(name: &str) -> Result<Self, String> {
+        name.parse()
+            .map(Self)
+            .map_err(|_| format!("unknown timezone '{name}'"))
+    }
+
+    // $TZ if set, else the system's own zone, else utc
+    pub fn local() -> Self {
+        let tz = std::env::var("TZ").map(|name| name.trim_start_matches(':').to_string());
+        tz.into_iter()
+            .chain(iana_time_zone::get_timezone())
+            .find_map(|name| Self::load(&name).ok())
+            .unwrap_or_else(Self::utc)
+    }
+}
+
+// month and day names for the formatted clock, from chrono's locale data
+#[derive(Clone, Copy)]
+pub struct Locale {
+    names: chrono::Locale,
+    pub updated: &'static str,
+}
+
+impl FromStr for Locale {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // en_US.UTF-8 and de-DE style names, or just the language, which
+        // gets the region of the same name
+        let name = s.split('.').next().unwrap_or(s).replace('-', "_");
+        let (lang, region) = match name.split_once('_') {
+            Some((lang, region)) => (lang.to_string(), region.to_string()),
+            None => (name.clone(), name.to_uppercase()),
+        };
+        let names = match lang.as_str() {
+            "C" | "POSIX" => Ok(chrono::Locale::POSIX),
+            "en" if region == "EN" => Ok(chrono::Locale::en_US),
+            _ => chrono::Locale::try_from(format!("{lang}_{region}").as_str()),
+        }
+        .map_err(|_| format!("unknown locale '{s}'"))?;
+        // the word before the time in the updated overlay. languages
+        // without one of their own get the english
+        let updated = match lang.as_str() {
+            "de" => "aktualisiert",
+            "fr" => "mis à jour",
+            "es" => "actualizado",
+            "nl" => "bijgewerkt",
+            _ => "last updated",
+        };
+        Ok(Locale { names, updated })
+    }
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self {
+            names: chrono::Locale::en_US,
+            updated: "last updated",
+        }
+    }
+}
+
+// how time based overlays show the time
+#[derive(Clone)]
+pub struct Clock {
+    pub zone: Zone,
+    pub locale: Locale,
+    // strftime, as chrono reads it
+    pub format: String,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self {
+            zone: Zone::local(),
+            locale: Locale::default(),
+            format: "%H:%M".to_string(),
+        }
+    }
+}
+
+impl Clock {
+    pub fn now(&self) -> String {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        self.format_at(secs)
+    }
+
+    // a format chrono can't read is shown as it was given
+    pub fn format_at(&self, t: i64) -> String {
+        let Some(utc) = DateTime::from_timestamp(t, 0) else {
+            return self.format.clone();
+        };
+        let local = utc.with_timezone(&self.zone.0);
+        let mut out = String::new();
+        match write!(
+            out,
+            "{}",
+            local.format_localized(&self.format, self.locale.names)
+        ) {
+            Ok(()) => out,
+            Err(_) => self.format.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock(zone: &str, locale: &str, format: &str) -> Clock {
+        Clock {
+            zone: Zone::load(zone).unwrap(),
+            locale: locale.parse().unwrap(),
+            format: format.to_string(),
+        }
+    }
+
+    // 2024-07-01 12:00:00 utc, a monday
+    const SUMMER: i64 = 1_719_835_200;
+    // 2024-01-15 12:00:00 utc, a monday
+    const WINTER: i64 = 1_705_320_000;
+
+    #[test]
+    fn follows_daylight_saving() {
+        let c = clock("Europe/Berlin", "en", "%H:%M %Z");
+        assert_eq!(c.format_at(SUMMER), "14:00 CEST");
+        assert_eq!(c.format_at(WINTER), "13:00 CET");
+        let c = clock("America/New_York", "en", "%H:%M %Z");
+        assert_eq!(c.format_at(SUMMER), "08:00 EDT");
+        assert_eq!(c.format_at(WINTER), "07:00 EST");
+    }
+
+    #[test]
+    fn names_days_and_months_in_the_locale() {
+        let format = "%A %e %B";
+        assert_eq!(
+            clock("UTC", "en", format).format_at(SUMMER),
+            "Monday  1 July"
+        );
+        assert_eq!(
+            clock("UTC", "de_DE.UTF-8", format).format_at(SUMMER),
+            "Montag  1 Juli"
+        );
+        assert_eq!(
+            clock("UTC", "fr", format).format_at(WINTER),
+            "lundi 15 janvier"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_zones_and_locales() {
+        assert!(Zone::load("Mars/Olympus_Mons").is_err());
+        assert!("xx".parse::<Locale>().is_err());
+    }
+
+    #[test]
+    fn shows_a_bad_format_as_given() {
+        assert_eq!(clock("UTC", "en", "%Q").format_at(SUMMER), "%Q");
+    }
+}
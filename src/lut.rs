@@ -0,0 +1,86 @@
+// the closest palette color for every cell of a coarse rgb cube, so the
+// dither's per pixel match is an index instead of a distance to each color.
+// pixels are matched by the center of their cell, which can pick a
+// different color than the exact match for a pixel near the line between
+// two colors.
+
+use crate::{
+    draw::{Color, Metric},
+    Rgb,
+};
+
+// cells along each channel
+const LEVELS: usize = 32;
+const CELL: f32 = 256.0 / LEVELS as f32;
+
+pub struct Lut {
+    table: Vec<Color>,
+}
+
+impl Lut {
+    // the closest of `colors` by `metric` for every cell
+    pub fn new(colors: &[Color], metric: Metric) -> Self {
+        let center = |i: usize| (i as f32 + 0.5) * CELL;
+        let mut table = Vec::with_capacity(LEVELS * LEVELS * LEVELS);
+        for r in 0..LEVELS {
+            for g in 0..LEVELS {
+                for b in 0..LEVELS {
+                    let px = Rgb {
+                        r: center(r),
+                        g: center(g),
+                        b: center(b),
+                    };
+                    table.push(Color::closest_by(px, colors, metric));
+                }
+            }
+        }
+        Self { table }
+    }
+
+    // diffused error can carry a pixel outside the cube, it gets the
+    // closest cell on the surface
+    pub fn get(&self, px: Rgb) -> Color {
+        let cell = |c: f32| ((c / CELL) as usize).min(LEVELS - 1);
+        let (r, g, b) = (cell(px.r), cell(px.g), cell(px.b));
+        self.table[(r * LEVELS + g) * LEVELS + b]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLORS: [Color; 7] = [
+        Color::Black,
+        Color::White,
+        Color::Green,
+        Color::Blue,
+        Color::Red,
+        Color::Yellow,
+        Color::Orange,
+    ];
+
+    #[test]
+    fn palette_colors_map_to_themselves() {
+        for metric in [
+            Metric::Rgb,
+            Metric::Redmean,
+            Metric::Cie76,
+            Metric::Ciede2000,
+        ] {
+            let lut = Lut::new(&COLORS, metric);
+            for c in COLORS {
+                assert!(lut.get(Rgb::from(c)) == c, "{}", metric.name());
+            }
+        }
+    }
+
+    #[test]
+    fn pixels_outside_the_cube_clamp_to_its_surface() {
+        let lut = Lut::new(&COLORS, Metric::Rgb);
+        let px = |r, g, b| Rgb { r, g, b };
+        assert!(lut.get(px(-90.0, -40.0, -300.0)) == Color::Black);
+        assert!(lut.get(px(400.0, 300.0, 1000.0)) == Color::White);
+        assert!(lut.get(px(f32::NAN, 0.0, 0.0)) == Color::Black);
+    }
+}
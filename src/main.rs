@@ -1,225 +1,786 @@
+// the command line: the clap flags, folded over the config file into an
+// app::Options, and which of the library's modes it asks for
+
 use std::{
     env,
     error::Error,
-    ops::{AddAssign, Sub},
-    thread::sleep,
-    time::{Duration, Instant},
+    io::{self, IsTerminal},
+    num::{NonZeroU32, NonZeroUsize},
+    path::PathBuf,
+    time::Duration,
 };
 
-use draw::PaperImage;
-use rppal::{
-    gpio::{Gpio, InputPin, OutputPin},
-    spi::{self, Bus, Mode, SlaveSelect, Spi},
+use clap::{ArgAction, ArgMatches, CommandFactory, FromArgMatches, Parser};
+#[cfg(feature = "battery")]
+use rpi_epaper::battery;
+#[cfg(feature = "light")]
+use rpi_epaper::light;
+#[cfg(feature = "ttf")]
+use rpi_epaper::text;
+use rpi_epaper::{
+    app::{self, Mode, Options, Output, Touchup},
+    calibrate, cmd, config, dither,
+    draw::{self, Color, Corner},
+    events, gpio, layout, localtime, mqtt, pipeline, preview, render, report, roi, script,
 };
+use serde_json::json;
+use tracing::Level;
+use tracing_subscriber::fmt::{format::FmtSpan, time::Uptime};
 
-mod cmd;
-use crate::{
-    cmd::{Command, Init},
-    draw::Color,
-};
+// splits `lhs=color`
+fn color_assignment<'a>(flag: &str, s: &'a str) -> Result<(&'a str, Color), String> {
+    let (lhs, color) = s
+        .split_once('=')
+        .ok_or_else(|| format!("{flag} expects ...=color, got '{s}'"))?;
+    Ok((lhs, color.parse()?))
+}
+
+// every flag can come before or after the mode
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Draws on a 7-color Waveshare e-paper panel",
+    args_override_self = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    mode: Option<Mode>,
+
+    /// Mirror the frame left to right
+    #[arg(long, help_heading = "Framing")]
+    flip_h: bool,
+    /// Mirror the frame top to bottom
+    #[arg(long, help_heading = "Framing")]
+    flip_v: bool,
+    /// Stamp the time in a corner (tl, tr, bl or br)
+    #[arg(long, value_name = "CORNER", help_heading = "Framing")]
+    timestamp: Option<Corner>,
+    /// White space around the image in px
+    #[arg(long, value_name = "PX", help_heading = "Framing")]
+    margin: Option<u32>,
+    /// Frame the image in this color
+    #[arg(long, value_name = "COLOR", help_heading = "Framing")]
+    border: Option<Color>,
+    /// Width of --border in px [default: 2]
+    #[arg(long, value_name = "PX", help_heading = "Framing")]
+    border_width: Option<u32>,
+    /// Picture in picture, scaled into --pip-corner
+    #[arg(long, value_name = "IMAGE", help_heading = "Framing")]
+    pip: Option<String>,
+    /// Corner for --pip [default: br]
+    #[arg(long, value_name = "CORNER", help_heading = "Framing")]
+    pip_corner: Option<Corner>,
+    /// Width of --pip as a fraction of the screen [default: 0.3]
+    #[arg(long, value_name = "FRACTION", help_heading = "Framing")]
+    pip_scale: Option<f32>,
+    /// Left half for split
+    #[arg(long, value_name = "IMAGE", help_heading = "Framing")]
+    left: Vec<String>,
+    /// Right half for split
+    #[arg(long, value_name = "IMAGE", help_heading = "Framing")]
+    right: Vec<String>,
+    /// Top half for split
+    #[arg(long, value_name = "IMAGE", help_heading = "Framing")]
+    top: Vec<String>,
+    /// Bottom half for split
+    #[arg(long, value_name = "IMAGE", help_heading = "Framing")]
+    bottom: Vec<String>,
+    /// Top left quarter for split
+    #[arg(long, value_name = "IMAGE", help_heading = "Framing")]
+    tl: Vec<String>,
+    /// Top right quarter for split
+    #[arg(long, value_name = "IMAGE", help_heading = "Framing")]
+    tr: Vec<String>,
+    /// Bottom left quarter for split
+    #[arg(long, value_name = "IMAGE", help_heading = "Framing")]
+    bl: Vec<String>,
+    /// Bottom right quarter for split
+    #[arg(long, value_name = "IMAGE", help_heading = "Framing")]
+    br: Vec<String>,
+    /// Dither these rectangles with the slower, closer color match (the whole
+    /// panel still refreshes)
+    #[arg(long, value_name = "X,Y,W,H", value_parser = roi::parse_rect, help_heading = "Framing")]
+    roi: Vec<layout::Rect>,
+    /// Dither where this mask image is white with the slower, closer color
+    /// match (the whole panel still refreshes)
+    #[arg(long, value_name = "IMAGE", help_heading = "Framing")]
+    roi_mask: Option<String>,
+
+    /// How images that aren't panel sized are scaled [default: fit]
+    #[arg(long, value_name = "MODE", help_heading = "Image")]
+    fit: Option<layout::Scale>,
+    /// Resampling filter for --fit [default: catmull-rom]
+    #[arg(long, value_name = "FILTER", value_parser = layout::parse_filter, help_heading = "Image")]
+    filter: Option<image::imageops::FilterType>,
+    /// Color of the bars left by --fit [default: white]
+    #[arg(long, value_name = "COLOR", help_heading = "Image")]
+    letterbox: Option<Color>,
+    /// Preprocessing stages, e.g. "crop=0,0,800,600 saturation=1.3"
+    #[arg(long, value_name = "STAGES", help_heading = "Image")]
+    pipeline: Vec<pipeline::Pipeline>,
+    /// Scale the colors apart, 0 is grayscale. Runs after --pipeline, like
+    /// --contrast, --brightness and --gamma
+    #[arg(long, value_name = "FACTOR", value_parser = |s: &str| stage("saturation", s), help_heading = "Image")]
+    saturation: Option<pipeline::Stage>,
+    /// Scale the tones away from mid gray
+    #[arg(long, value_name = "FACTOR", value_parser = |s: &str| stage("contrast", s), help_heading = "Image")]
+    contrast: Option<pipeline::Stage>,
+    /// Scale every channel
+    #[arg(long, value_name = "FACTOR", value_parser = |s: &str| stage("brightness", s), help_heading = "Image")]
+    brightness: Option<pipeline::Stage>,
+    /// Above 1 brightens the mid tones, below darkens them
+    #[arg(long, value_name = "FACTOR", value_parser = |s: &str| stage("gamma", s), help_heading = "Image")]
+    gamma: Option<pipeline::Stage>,
+    /// A named set of stages, run before --pipeline
+    #[arg(long, value_name = "NAME", help_heading = "Image")]
+    look: Option<String>,
+    /// Where looks are saved
+    #[arg(long, value_name = "PATH", help_heading = "Image")]
+    looks: Option<PathBuf>,
+    /// Turn the picture for a panel mounted on its side (90, 180 or 270)
+    #[arg(long, value_name = "DEGREES", help_heading = "Image")]
+    rotate: Option<draw::Rotation>,
+    /// Where @name images are looked up
+    #[arg(long, value_name = "DIR", help_heading = "Image")]
+    images: Option<PathBuf>,
 
-mod draw;
-
-const _DIN: u8 = 10; // spi0 mosi
-const _CLK: u8 = 11; // spi0 sclk
-const _CS: u8 = 8; // spi0 ce0 (chip select)
-const DC: u8 = 25; // data (high)/command (low)
-const BUSY: u8 = 24;
-const RESET: u8 = 17;
-pub const SCREEN_WIDTH: u16 = 600;
-pub const SCREEN_HEIGHT: u16 = 448;
-
-pub struct EPaper {
-    spi: Spi,
-    dc: OutputPin,
-    busy: InputPin,
-    reset: OutputPin,
+    /// floyd-steinberg, atkinson, bayer4, bayer8 or threshold [default: floyd-steinberg]
+    #[arg(long, value_name = "ALGORITHM", help_heading = "Color")]
+    dither: Option<String>,
+    /// What error diffusion does at the edges: drop, reflect or wrap
+    #[arg(long, value_name = "MODE", help_heading = "Color")]
+    dither_edges: Option<dither::Boundary>,
+    /// Diffuse the error in linear light rather than srgb, so mid tones in
+    /// photos don't come out too dark
+    #[arg(long, help_heading = "Color")]
+    linear: bool,
+    /// Diffuse every other row right to left, against floyd-steinberg's
+    /// diagonal artifacts
+    #[arg(long, help_heading = "Color")]
+    serpentine: bool,
+    /// Diffuse the error in a band of rows per core. faster, though the
+    /// seams between bands can show faintly in flat areas
+    #[cfg(feature = "parallel")]
+    #[arg(long, help_heading = "Color")]
+    parallel: bool,
+    /// How the closest color is picked: rgb, redmean, cie76 or ciede2000.
+    /// rgb is the fastest, ciede2000 keeps skin tones and sky closest to
+    /// the eye [default: rgb]
+    #[arg(long, value_name = "METRIC", help_heading = "Color")]
+    metric: Option<draw::Metric>,
+    /// Match colors through a lookup table built before dithering, much
+    /// faster but a pixel close to halfway between two colors can get the
+    /// other one
+    #[arg(long, help_heading = "Color")]
+    lut: bool,
+    /// Reduce the image to this many colors before dithering
+    #[arg(long, value_name = "N", help_heading = "Color")]
+    colors: Option<NonZeroUsize>,
+    /// A calibrated palette file
+    #[arg(long, value_name = "PATH", help_heading = "Color")]
+    palette: Option<PathBuf>,
+    /// Calibrate the palette from a photo of the chart
+    #[arg(long, value_name = "IMAGE", help_heading = "Color")]
+    photo: Option<String>,
+    /// Calibrate one palette color by hand
+    #[arg(long, value_name = "NAME=R,G,B", help_heading = "Color")]
+    set: Vec<String>,
+    /// Replace one color with another in the dithered frame
+    #[arg(long, value_name = "FROM=TO", value_parser = remap, help_heading = "Color")]
+    remap: Vec<Touchup>,
+    /// Remove lone pixels from the dithered frame
+    #[arg(long, action = ArgAction::Count, help_heading = "Color")]
+    despeckle: u8,
+    /// Fill a rectangle of the dithered frame
+    #[arg(long, value_name = "X,Y,W,H=COLOR", value_parser = fill, help_heading = "Color")]
+    fill: Vec<Touchup>,
+    /// Flood fill the dithered frame from a point
+    #[arg(long, value_name = "X,Y=COLOR", value_parser = flood, help_heading = "Color")]
+    flood: Vec<Touchup>,
+    /// Pick the palette and cooldown by temperature from a profiles file
+    #[arg(long, value_name = "PATH", help_heading = "Color")]
+    profiles: Option<PathBuf>,
+    /// Ambient temperature in C for --profiles
+    #[arg(long, value_name = "C", help_heading = "Color")]
+    temperature: Option<f32>,
+    /// Read the temperature for --profiles from a file
+    #[arg(long, value_name = "PATH", help_heading = "Color")]
+    temperature_file: Option<PathBuf>,
+
+    /// Text size for pages, term and ascii [default: 2]
+    #[arg(long, value_name = "N", help_heading = "Text")]
+    scale: Option<u16>,
+    /// Timezone for the time overlays, like Europe/Berlin
+    #[arg(long, value_name = "ZONE", value_parser = localtime::Zone::load, help_heading = "Text")]
+    tz: Option<localtime::Zone>,
+    /// Language of month and day names
+    #[arg(long, value_name = "LANGUAGE", help_heading = "Text")]
+    locale: Option<localtime::Locale>,
+    /// strftime style format for the time overlays
+    #[arg(long, value_name = "FORMAT", help_heading = "Text")]
+    time_format: Option<String>,
+    /// Color the characters in ascii mode
+    #[arg(long, help_heading = "Text")]
+    ascii_color: bool,
+    /// A .ttf or .otf for the text mode
+    #[cfg(feature = "ttf")]
+    #[arg(long, value_name = "PATH", help_heading = "Text")]
+    font: Option<PathBuf>,
+    /// Line height for the text mode [default: 32]
+    #[cfg(feature = "ttf")]
+    #[arg(long, value_name = "PX", help_heading = "Text")]
+    font_size: Option<f32>,
+    /// left, center or right
+    #[cfg(feature = "ttf")]
+    #[arg(long, value_name = "ALIGN", help_heading = "Text")]
+    align: Option<text::Align>,
+    /// Color of the text mode's text [default: black]
+    #[cfg(feature = "ttf")]
+    #[arg(long, value_name = "COLOR", help_heading = "Text")]
+    text_color: Option<Color>,
+
+    /// Seconds between redraws in the looping modes [default: 60]
+    #[arg(long, value_name = "SECS", help_heading = "Looping")]
+    interval: Option<u64>,
+    /// Seconds the panel rests between refreshes [default: 180]
+    #[arg(long, value_name = "SECS", help_heading = "Looping")]
+    min_refresh: Option<u64>,
+    /// Gpio pin of a button that turns the page
+    #[arg(long, value_name = "PIN", help_heading = "Looping")]
+    button: Option<u8>,
+    /// Capture this tmux pane in term mode
+    #[arg(long, value_name = "TARGET", help_heading = "Looping")]
+    tmux: Option<String>,
+    /// Screenshots web pages [default: chromium]
+    #[arg(long, value_name = "COMMAND", help_heading = "Looping")]
+    browser: Option<String>,
+    /// Take POST /notify on this address, like :8080
+    #[arg(long, value_name = "ADDR", help_heading = "Looping")]
+    notify: Option<String>,
+    /// Show the splash screen before the first frame
+    #[arg(long, help_heading = "Looping")]
+    splash: bool,
+    /// Leave an offline screen up when the program stops
+    #[arg(long, help_heading = "Looping")]
+    offline_screen: bool,
+    /// Set the rtc to wake the pi again after this many minutes
+    #[arg(long, value_name = "MINS", help_heading = "Looping")]
+    wake_every: Option<u64>,
+    /// Gpio pin that tells the power controller to cut power
+    #[arg(long, value_name = "PIN", help_heading = "Looping")]
+    power_pin: Option<u8>,
+    /// Shut the pi down after --wake-every is set
+    #[arg(long, help_heading = "Looping")]
+    shutdown: bool,
+    /// Battery gauge to read
+    #[cfg(feature = "battery")]
+    #[arg(long, value_name = "GAUGE", help_heading = "Looping")]
+    battery: Option<battery::Gauge>,
+    /// Voltage below which only the low battery screen is drawn [default: 3.3]
+    #[cfg(feature = "battery")]
+    #[arg(long, value_name = "VOLTS", help_heading = "Looping")]
+    battery_critical: Option<f32>,
+    /// Light sensor to read
+    #[cfg(feature = "light")]
+    #[arg(long, value_name = "SENSOR", help_heading = "Looping")]
+    light: Option<light::Sensor>,
+    /// Skip refreshes below this many lux
+    #[cfg(feature = "light")]
+    #[arg(long, value_name = "LUX", help_heading = "Looping")]
+    dark_below: Option<f32>,
+    /// Dither with the high contrast colors above this many lux
+    #[cfg(feature = "light")]
+    #[arg(long, value_name = "LUX", help_heading = "Looping")]
+    bright_above: Option<f32>,
+
+    /// Write a picture of the frame instead of drawing it
+    #[arg(long, value_name = "PATH", help_heading = "Output")]
+    preview: Option<String>,
+    /// Show --preview as seen with a color deficiency
+    #[arg(long, value_name = "KIND", help_heading = "Output")]
+    simulate: Option<preview::ColorBlindness>,
+    /// Mark layout boxes, baselines and a grid in --preview and --sim
+    #[arg(long, help_heading = "Output")]
+    debug_layout: bool,
+    /// Write the packed frame instead of drawing it
+    #[arg(long, value_name = "PATH", help_heading = "Output")]
+    save_frame: Option<String>,
+    /// Write a png of palette indices instead of drawing it
+    #[arg(long, value_name = "PATH", help_heading = "Output")]
+    save_indexed: Option<String>,
+    /// Keep a picture of what is on the panel here
+    #[arg(long, value_name = "PATH", help_heading = "Output")]
+    thumbnail: Option<PathBuf>,
+    /// Publish a picture of what is on the panel to this mqtt topic, retained,
+    /// after each refresh
+    #[arg(long, value_name = "HOST[:PORT]/TOPIC", value_parser = mqtt::parse_target, help_heading = "Output")]
+    thumbnail_topic: Option<(String, String)>,
+    /// Refresh even when the panel already shows the frame
+    #[arg(long, help_heading = "Output")]
+    force: bool,
+    /// Flash the panel clear every N refreshes to keep ghosting down
+    #[arg(long, value_name = "N", help_heading = "Output")]
+    clear_every: Option<NonZeroU32>,
+    /// text or json, for the reporting modes
+    #[arg(long, value_name = "FORMAT", help_heading = "Output")]
+    output: Option<Output>,
+    /// Append what happens to a log of json lines
+    #[arg(long, value_name = "PATH", help_heading = "Output")]
+    event_log: Option<PathBuf>,
+    /// Say more, with how long each stage took; twice for everything
+    #[arg(short, long, action = ArgAction::Count, help_heading = "Output")]
+    verbose: u8,
+    /// Only print warnings and errors
+    #[arg(short, long, conflicts_with = "verbose", help_heading = "Output")]
+    quiet: bool,
+    /// Drive a simulated panel and write what it shows
+    #[arg(long, value_name = "PATH", help_heading = "Output")]
+    sim: Option<String>,
+    /// Record the simulated refreshes
+    #[arg(long, value_name = "PATH", help_heading = "Output")]
+    sim_video: Option<PathBuf>,
+    /// Let the simulated panel take as long as a real one
+    #[arg(long, help_heading = "Output")]
+    sim_realtime: bool,
+    /// Temperature of the simulated panel in C [default: 25]
+    #[arg(long, value_name = "C", help_heading = "Output")]
+    sim_temperature: Option<f32>,
+    /// Print what would be sent to the panel instead of sending it
+    #[cfg(feature = "mock")]
+    #[arg(long, help_heading = "Output")]
+    mock: bool,
+    /// Seed for art and the random patterns
+    #[arg(long, value_name = "N", help_heading = "Output")]
+    seed: Option<u64>,
+    /// Repeats for deghost, endurance and bench [default: 1]
+    #[arg(long, value_name = "N", help_heading = "Output")]
+    cycles: Option<u32>,
+
+    /// Toml file with the panel wiring [default: /etc/rpi-epaper.toml]
+    #[arg(long, value_name = "PATH", help_heading = "Panel")]
+    config: Option<PathBuf>,
+    /// Gpio pins of the panel's lines
+    #[arg(long, value_name = "DC,BUSY,RESET", value_parser = parse_pins, help_heading = "Panel")]
+    pins: Option<(u8, u8, u8)>,
+    /// How the pins are driven: rppal or cdev
+    #[arg(long, value_name = "BACKEND", help_heading = "Panel")]
+    gpio: Option<gpio::Backend>,
+    /// Gpio chip for the cdev backend
+    #[arg(long, value_name = "DEVICE", help_heading = "Panel")]
+    gpiochip: Option<String>,
+    /// Spidev node to drive the panel through in place of rppal's bus, like
+    /// /dev/spidev1.0 (needs the spidev feature)
+    #[arg(long, value_name = "DEVICE", help_heading = "Panel")]
+    spidev: Option<String>,
+    /// Bytes per spi write
+    #[arg(long, value_name = "BYTES", help_heading = "Panel")]
+    spi_chunk: Option<usize>,
+    /// Wait between spi writes, like 1ms
+    #[arg(long, value_name = "DURATION", value_parser = script::parse_duration, help_heading = "Panel")]
+    spi_pause: Option<Duration>,
+    /// Longest the panel may stay busy, like 90s
+    #[arg(long, value_name = "DURATION", value_parser = script::parse_duration, help_heading = "Panel")]
+    busy_timeout: Option<Duration>,
+    /// How often a wedged panel is reset and the command sent again [default: 1]
+    #[arg(long, value_name = "N", help_heading = "Panel")]
+    retries: Option<u32>,
+    /// Read the panel's temperature before each refresh, warning when it is
+    /// too hot or cold to refresh well
+    #[arg(long, help_heading = "Panel")]
+    check_temperature: bool,
+    /// Rest after a refresh in ms
+    #[arg(long, value_name = "MS", help_heading = "Panel")]
+    cooldown: Option<u64>,
+    /// Leave the panel powered on after a refresh
+    #[arg(long, help_heading = "Panel")]
+    no_power_off: bool,
+    /// Deep sleep the panel between refreshes
+    #[arg(long, help_heading = "Panel")]
+    deep_sleep: bool,
+    /// Power the panel off but skip deep sleep once the mode is done
+    #[arg(long, help_heading = "Panel")]
+    stay_awake: bool,
 }
 
-impl EPaper {
-    pub fn init(spi: Spi, dc: OutputPin, busy: InputPin, reset: OutputPin) -> Self {
-        let mut s = Self {
-            spi,
-            dc,
-            busy,
-            reset,
-        };
-        s.reset();
-        s
-    }
-
-    pub fn reset(&mut self) {
-        self.reset.set_high();
-        sleep(Duration::from_millis(600));
-        self.reset.set_low();
-        sleep(Duration::from_millis(2));
-        self.reset.set_high();
-        sleep(Duration::from_millis(200));
-    }
+fn parse_pins(s: &str) -> Result<(u8, u8, u8), String> {
+    let invalid = || format!("invalid pins '{s}', expected dc,busy,reset");
+    let parsed: Vec<u8> = s
+        .split(',')
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .map_err(|_| invalid())?;
+    let [dc, busy, reset] = parsed[..] else {
+        return Err(invalid());
+    };
+    Ok((dc, busy, reset))
 }
 
-pub trait SpiDevice {
-    fn send_cmd(&mut self, cmd: u8) -> spi::Result<()>;
-    fn send_data(&mut self, data: &[u8]) -> spi::Result<()>;
-    fn wait_busy_high(&self);
-    fn wait_busy_low(&self);
+// the pipeline stage a shorthand flag stands for
+fn stage(name: &str, value: &str) -> Result<pipeline::Stage, String> {
+    format!("{name}={value}").parse()
 }
 
-impl SpiDevice for EPaper {
-    fn send_cmd(&mut self, cmd: u8) -> spi::Result<()> {
-        self.dc.set_low();
-        self.spi.write(&[cmd])?;
-        Ok(())
-    }
+fn remap(s: &str) -> Result<Touchup, String> {
+    let (from, to) = color_assignment("--remap", s)?;
+    Ok(Touchup::Remap(from.parse()?, to))
+}
 
-    fn send_data(&mut self, data: &[u8]) -> spi::Result<()> {
-        self.dc.set_high();
-        self.spi.write(data)?;
-        Ok(())
-    }
+fn fill(s: &str) -> Result<Touchup, String> {
+    let (rect, color) = color_assignment("--fill", s)?;
+    Ok(Touchup::Fill(roi::parse_rect(rect)?, color))
+}
 
-    fn wait_busy_high(&self) {
-        while self.busy.is_low() {
-            sleep(Duration::from_millis(10));
-        }
-    }
+fn flood(s: &str) -> Result<Touchup, String> {
+    let (at, color) = color_assignment("--flood", s)?;
+    let (x, y) = roi::parse_point(at)?;
+    Ok(Touchup::Flood(x, y, color))
+}
 
-    fn wait_busy_low(&self) {
-        while self.busy.is_high() {
-            sleep(Duration::from_millis(10));
+// clap keeps only the values given after the mode when a repeatable flag
+// is given on both sides of it, so the flags before it are moved to just
+// after it
+fn hoist_flags(cli: &clap::Command, mut args: Vec<String>) -> Vec<String> {
+    let mut i = 1;
+    while let Some(arg) = args.get(i) {
+        if arg == "--" {
+            break;
+        }
+        if let Some(flag) = arg.strip_prefix("--") {
+            let takes_value = !flag.contains('=')
+                && cli
+                    .get_arguments()
+                    .find(|a| a.get_long() == Some(flag))
+                    .is_some_and(|a| a.get_action().takes_values());
+            i += 1 + takes_value as usize;
+        } else if arg.starts_with('-') {
+            i += 1;
+        } else {
+            if cli.find_subcommand(arg).is_some() {
+                let flags: Vec<String> = args.drain(1..i).collect();
+                args.splice(2..2, flags);
+            }
+            break;
         }
     }
+    args
 }
 
-#[derive(Clone, Copy)]
-pub struct Rgb {
-    r: f32,
-    g: f32,
-    b: f32,
+// each value of a repeatable flag with its place on the command line
+fn indexed<T>(matches: &ArgMatches, id: &str, values: Vec<T>) -> Vec<(usize, T)> {
+    matches
+        .indices_of(id)
+        .into_iter()
+        .flatten()
+        .zip(values)
+        .collect()
 }
 
-impl From<bmp::Pixel> for Rgb {
-    fn from(value: bmp::Pixel) -> Self {
-        Self {
-            r: value.r.into(),
-            g: value.g.into(),
-            b: value.b.into(),
-        }
+// what happens goes to stderr, leaving stdout to the reporting modes. with
+// -v the spans for init, dithering, transfer and refresh print how long
+// they took when they close.
+fn init_logging(verbose: u8, quiet: bool) {
+    let level = match (quiet, verbose) {
+        (true, _) => Level::WARN,
+        (false, 0) => Level::INFO,
+        (false, 1) => Level::DEBUG,
+        (false, _) => Level::TRACE,
+    };
+    let log = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal());
+    match verbose {
+        0 => log.without_time().with_target(false).init(),
+        _ => log
+            .with_timer(Uptime::default())
+            .with_span_events(FmtSpan::CLOSE)
+            .with_target(verbose > 1)
+            .init(),
     }
 }
 
-impl From<Color> for Rgb {
-    fn from(value: Color) -> Self {
-        let [r, g, b] = value.as_rgb();
-        Self { r, g, b }
+fn parse_args() -> Result<Options, Box<dyn Error>> {
+    let app = Cli::command().mut_args(|a| a.global(true));
+    let matches = app
+        .clone()
+        .get_matches_from(hoist_flags(&app, env::args().collect()));
+    let cli = Cli::from_arg_matches(&matches)?;
+    init_logging(cli.verbose, cli.quiet);
+    let d = Options::default();
+
+    // the config file goes under the flags. the default one may be missing,
+    // one given with --config may not.
+    let config = cli
+        .config
+        .or_else(|| Some(PathBuf::from(config::DEFAULT_PATH)).filter(|p| p.exists()));
+    let mut panel = match &config {
+        Some(path) => d.panel.clone().with_file(path)?,
+        None => d.panel.clone(),
+    };
+    if let Some((dc, busy, reset)) = cli.pins {
+        (panel.dc, panel.busy, panel.reset) = (dc, busy, reset);
     }
-}
+    panel.gpio = cli.gpio.unwrap_or(panel.gpio);
+    panel.gpiochip = cli.gpiochip.unwrap_or(panel.gpiochip);
+    panel.spidev = cli.spidev.or(panel.spidev);
+    panel.busy_timeout = cli.busy_timeout.unwrap_or(panel.busy_timeout);
 
-impl From<Rgb> for bmp::Pixel {
-    fn from(value: Rgb) -> Self {
-        bmp::Pixel {
-            r: value.r.clamp(0.0, 255.0) as u8,
-            g: value.g.clamp(0.0, 255.0) as u8,
-            b: value.b.clamp(0.0, 255.0) as u8,
+    // panes and touch-ups apply in the order they were given
+    let mut panes = Vec::new();
+    for (id, paths) in [
+        ("left", cli.left),
+        ("right", cli.right),
+        ("top", cli.top),
+        ("bottom", cli.bottom),
+        ("tl", cli.tl),
+        ("tr", cli.tr),
+        ("bl", cli.bl),
+        ("br", cli.br),
+    ] {
+        let region: layout::Region = id.parse()?;
+        panes.extend(
+            indexed(&matches, id, paths)
+                .into_iter()
+                .map(|(i, path)| (i, (region, path))),
+        );
+    }
+    panes.sort_by_key(|(i, _)| *i);
+    let mut touchups = indexed(&matches, "remap", cli.remap);
+    touchups.extend(indexed(&matches, "fill", cli.fill));
+    touchups.extend(indexed(&matches, "flood", cli.flood));
+    touchups.extend(indexed(
+        &matches,
+        "despeckle",
+        vec![Touchup::Despeckle; cli.despeckle as usize],
+    ));
+    touchups.sort_by_key(|(i, _)| *i);
+
+    let looks = cli.looks.unwrap_or(d.looks.clone());
+    let mut stages: Vec<pipeline::Stage> = match &cli.look {
+        // a look goes first, so --pipeline can adjust it
+        Some(name) => pipeline::look(name, &looks)?.stages,
+        None => Vec::new(),
+    };
+    stages.extend(cli.pipeline.into_iter().flat_map(|p| p.stages));
+    stages.extend(
+        [cli.saturation, cli.contrast, cli.brightness, cli.gamma]
+            .into_iter()
+            .flatten(),
+    );
+    let edges = cli.dither_edges.unwrap_or(d.edges);
+    let diffusion = dither::Diffusion {
+        boundary: edges,
+        light: match cli.linear {
+            true => dither::Light::Linear,
+            false => dither::Light::Srgb,
+        },
+        serpentine: cli.serpentine,
+        #[cfg(feature = "parallel")]
+        parallel: cli.parallel,
+    };
+    let dither = match &cli.dither {
+        Some(name) => dither::by_name(name, diffusion)?,
+        None => dither::by_name("floyd-steinberg", diffusion)?,
+    };
+
+    let mut words = Vec::new();
+    if let Some((name, sub)) = matches.subcommand() {
+        words.push(name.to_string());
+        for arg in app
+            .find_subcommand(name)
+            .into_iter()
+            .flat_map(|c| c.get_positionals())
+        {
+            let values = sub.get_raw(arg.get_id().as_str()).into_iter().flatten();
+            words.extend(values.map(|v| v.to_string_lossy().into_owned()));
         }
     }
+
+    // only the real panel's last frame is remembered
+    #[cfg(feature = "mock")]
+    let offline = cli.sim.is_some() || cli.mock;
+    #[cfg(not(feature = "mock"))]
+    let offline = cli.sim.is_some();
+    let last_frame = d.last_frame.filter(|_| !cli.force && !offline);
+    let refresh_count = d.refresh_count.filter(|_| !offline);
+
+    Ok(Options {
+        mode: cli.mode,
+        words,
+        flip_h: cli.flip_h,
+        flip_v: cli.flip_v,
+        timestamp: cli.timestamp,
+        margin: cli.margin.unwrap_or(d.margin),
+        border: cli.border,
+        border_width: cli.border_width.unwrap_or(d.border_width),
+        panes: panes.into_iter().map(|(_, pane)| pane).collect(),
+        pip: cli.pip,
+        pip_corner: cli.pip_corner.unwrap_or(d.pip_corner),
+        pip_scale: cli.pip_scale.unwrap_or(d.pip_scale),
+        text_scale: cli.scale.unwrap_or(d.text_scale),
+        interval: cli.interval.map_or(d.interval, Duration::from_secs),
+        button: cli.button,
+        tmux: cli.tmux,
+        browser: cli.browser.unwrap_or(d.browser),
+        wake_every: cli.wake_every.map(|mins| Duration::from_secs(mins * 60)),
+        power_pin: cli.power_pin,
+        shutdown: cli.shutdown,
+        preview: cli.preview,
+        simulate: cli.simulate,
+        roi: cli.roi,
+        roi_mask: cli.roi_mask,
+        ascii_color: cli.ascii_color,
+        seed: cli.seed.unwrap_or(d.seed),
+        cycles: cli.cycles.unwrap_or(d.cycles),
+        retries: cli.retries.unwrap_or(d.retries),
+        check_temperature: cli.check_temperature,
+        palette: cli.palette,
+        photo: cli.photo,
+        palette_overrides: cli.set,
+        save_frame: cli.save_frame,
+        event_log: cli.event_log,
+        debug_layout: cli.debug_layout,
+        scale: cli.fit.unwrap_or(d.scale),
+        filter: cli.filter.unwrap_or(d.filter),
+        letterbox: cli.letterbox.unwrap_or(d.letterbox),
+        pipeline: pipeline::Pipeline { stages },
+        looks,
+        rotate: cli.rotate,
+        images: cli.images.unwrap_or(d.images),
+        output: cli.output.unwrap_or(d.output),
+        cooldown: cli.cooldown.map_or(d.cooldown, Duration::from_millis),
+        no_power_off: cli.no_power_off,
+        deep_sleep: cli.deep_sleep,
+        stay_awake: cli.stay_awake,
+        transfer: cmd::Transfer {
+            chunk: cli.spi_chunk.unwrap_or(d.transfer.chunk),
+            pause: cli.spi_pause.unwrap_or(d.transfer.pause),
+        },
+        save_indexed: cli.save_indexed,
+        sim: cli.sim,
+        thumbnail: cli.thumbnail,
+        thumbnail_topic: cli.thumbnail_topic,
+        last_frame,
+        refresh_count,
+        clear_every: cli.clear_every.map(NonZeroU32::get),
+        clock: localtime::Clock {
+            zone: cli.tz.unwrap_or(d.clock.zone),
+            locale: cli.locale.unwrap_or(d.clock.locale),
+            format: cli.time_format.unwrap_or(d.clock.format),
+        },
+        colors: cli.colors.map(NonZeroUsize::get),
+        dither,
+        edges,
+        metric: cli.metric.unwrap_or(d.metric),
+        lut: cli.lut,
+        splash: cli.splash,
+        panel,
+        config,
+        profiles: cli.profiles,
+        touchups: touchups.into_iter().map(|(_, t)| t).collect(),
+        temperature: cli.temperature,
+        temperature_file: cli.temperature_file,
+        offline_screen: cli.offline_screen,
+        sim_realtime: cli.sim_realtime,
+        sim_video: cli.sim_video,
+        sim_temperature: cli.sim_temperature.unwrap_or(d.sim_temperature),
+        notify: cli.notify,
+        #[cfg(feature = "mock")]
+        mock: cli.mock,
+        min_refresh: cli.min_refresh.map_or(d.min_refresh, Duration::from_secs),
+        #[cfg(feature = "battery")]
+        battery: cli.battery,
+        #[cfg(feature = "battery")]
+        battery_critical: cli.battery_critical.unwrap_or(d.battery_critical),
+        #[cfg(feature = "light")]
+        light: cli.light,
+        #[cfg(feature = "light")]
+        dark_below: cli.dark_below,
+        #[cfg(feature = "light")]
+        bright_above: cli.bright_above,
+        #[cfg(feature = "ttf")]
+        font: cli.font,
+        #[cfg(feature = "ttf")]
+        text_style: text::Style {
+            size: cli.font_size.unwrap_or(d.text_style.size),
+            align: cli.align.unwrap_or(d.text_style.align),
+            color: cli.text_color.unwrap_or(d.text_style.color),
+            ..d.text_style
+        },
+    })
 }
 
-impl AddAssign for Rgb {
-    fn add_assign(&mut self, rhs: Rgb) {
-        self.r += rhs.r;
-        self.g += rhs.g;
-        self.b += rhs.b;
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut opts = parse_args()?;
+    // the reporting commands only read the log
+    let reporting = opts.mode.as_ref().is_some_and(Mode::is_reporting);
+    if let (Some(path), false) = (&opts.event_log, reporting) {
+        events::init(path.clone());
     }
+    app::apply_profile(&mut opts)?;
+    let mode = opts.words.first().map_or("image", String::as_str);
+    events::log(
+        "draw_requested",
+        json!({ "mode": mode, "args": opts.words }),
+    );
+    let result = run(opts.mode.as_ref(), &opts);
+    if let Err(e) = &result {
+        events::log("error", json!({ "message": e.to_string() }));
+    }
+    result
 }
 
-impl Sub for Rgb {
-    type Output = Rgb;
-    fn sub(self, rhs: Rgb) -> Rgb {
-        Rgb {
-            r: self.r - rhs.r,
-            g: self.g - rhs.g,
-            b: self.b - rhs.b,
-        }
+fn run(mode: Option<&Mode>, opts: &Options) -> Result<(), Box<dyn Error>> {
+    if (opts.preview.is_some() || opts.save_frame.is_some() || opts.save_indexed.is_some())
+        && mode.is_some_and(|m| {
+            m.is_looping()
+                || matches!(
+                    m,
+                    Mode::Deghost
+                        | Mode::Endurance { .. }
+                        | Mode::Sleep
+                        | Mode::Temperature
+                        | Mode::Status
+                )
+        })
+    {
+        return Err("saving frames is only supported for single frame modes".into());
     }
-}
 
-fn floyd_steinberg_dither(img: &bmp::Image) -> PaperImage {
-    // weight is out of 16
-    fn diffuse_error(error: Rgb, weight: f32) -> Rgb {
-        Rgb {
-            r: error.r * weight / 16.0,
-            g: error.g * weight / 16.0,
-            b: error.b * weight / 16.0,
-        }
+    let palette_path = opts.palette.clone().unwrap_or_else(calibrate::default_path);
+    match mode {
+        Some(Mode::Info) => return report::info(opts, &palette_path),
+        Some(Mode::History) => return report::history(opts),
+        Some(Mode::Bench { image }) => return report::bench(image.as_deref(), opts),
+        Some(Mode::List) => return report::list_images(opts),
+        Some(Mode::Looks) => return report::list_looks(opts),
+        Some(Mode::SaveLook { name }) => return report::save_look(name, opts),
+        _ => {}
     }
-    // create temp pixel data to modify in place during algo
-    let mut input = [Rgb {
-        r: 0.0,
-        g: 0.0,
-        b: 0.0,
-    }; SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize];
-    for x in 0..SCREEN_WIDTH as u32 {
-        for y in 0..SCREEN_HEIGHT as u32 {
-            input[x as usize + y as usize * SCREEN_WIDTH as usize] = img.get_pixel(x, y).into();
-        }
+    if palette_path.exists() {
+        draw::set_palette(calibrate::load(&palette_path)?);
     }
-    let mut out = [Color::Clean; SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize];
-    let width = SCREEN_WIDTH as usize;
-    let height = SCREEN_HEIGHT as usize;
-    let idx = |x, y| -> usize { x + y * width };
-    for y in 0..SCREEN_HEIGHT as usize {
-        for x in 0..SCREEN_WIDTH as usize {
-            let oldpixel = input[idx(x, y)];
-            let newpixel = Color::closest(oldpixel.into());
-            out[x + y * width] = newpixel;
-            let error = Rgb::from(oldpixel) - Rgb::from(newpixel);
-            // todo: clean up bounds check
-            if x + 1 < width {
-                input[idx(x + 1, y)] += diffuse_error(error, 7.0);
-            }
-            if x + 1 < width && y + 1 < height {
-                input[idx(x + 1, y + 1)] += diffuse_error(error, 1.0);
-            }
-            if x != 0 && y + 1 < height {
-                input[idx(x - 1, y + 1)] += diffuse_error(error, 3.0);
-            }
-            if y + 1 < height {
-                input[idx(x, y + 1)] += diffuse_error(error, 5.0);
-            }
-        }
+    if matches!(mode, Some(Mode::Calibrate))
+        && (opts.photo.is_some() || !opts.palette_overrides.is_empty())
+    {
+        return app::calibrate(opts, &palette_path);
     }
-    PaperImage { data: out }
-}
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let mut args = env::args().skip(1);
-    let clean = args.next();
-
-    let spi = Spi::new(Bus::Spi0, SlaveSelect::Ss0, 5_000_000, Mode::Mode0)?;
-    let dc = Gpio::new()?.get(DC)?.into_output();
-    let busy = Gpio::new()?.get(BUSY)?.into_input();
-    let reset = Gpio::new()?.get(RESET)?.into_output();
-    let mut display = EPaper::init(spi, dc, busy, reset);
-
-    let mut image_bmp: &'static [u8] = include_bytes!("image.bmp");
-
-    let img = bmp::from_reader(&mut image_bmp)?;
-    assert!(img.get_width() as u16 == SCREEN_WIDTH && img.get_height() as u16 == SCREEN_HEIGHT);
-
-    println!("Reset display");
-    display.reset();
-    display.wait_busy_high();
-    println!("Init display");
-    Init.send(&mut display)?;
-    let now = Instant::now();
-    println!("Printing image");
-    if clean.is_some_and(|c| c == "clean") {
-        cmd::Draw(&draw::SolidColor(Color::Clean)).send(&mut display)?;
-    } else {
-        cmd::Draw(&floyd_steinberg_dither(&img)).send(&mut display)?;
-    }
-    println!("Took {:?}", now.elapsed());
-
-    Ok(())
+    if let Some(path) = &opts.save_frame {
+        return render::save_frame(path, mode, opts);
+    }
+    if let Some(path) = &opts.save_indexed {
+        return render::save_indexed(path, mode, opts);
+    }
+    if let Some(path) = &opts.preview {
+        return render::save_preview(path, mode, opts);
+    }
+    #[cfg(feature = "mock")]
+    if opts.mock {
+        return app::print_mock(mode, opts);
+    }
+    if let Some(path) = &opts.sim {
+        return app::simulate(path, mode, opts);
+    }
+    app::draw_on_panel(mode, opts)
 }
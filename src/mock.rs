@@ -0,0 +1,180 @@
+// a SpiDevice that records everything sent to it, for running the command
+// stack off the pi and checking what it sends
+
+use std::{
+    cell::{Cell, RefCell},
+    fmt,
+    time::Duration,
+};
+
+use crate::{
+    cmd,
+    error::{self, EpaperError},
+    frame::{PackedFrame, PACKED_LEN},
+    panel::Panel,
+    preview, Geometry, SpiDevice,
+};
+
+// one call made on the device, in the order they were made
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Transaction {
+    Cmd(u8),
+    Data(Vec<u8>),
+    WaitBusyHigh,
+    WaitBusyLow,
+    Reset,
+    // this many bytes read back
+    Read(usize),
+    // data written while this many bytes were read
+    Transfer(Vec<u8>, usize),
+}
+
+impl fmt::Display for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Transaction::Cmd(c) => write!(f, "cmd  {c:#04x}"),
+            Transaction::Data(d) if d.len() <= 8 => write!(f, "data {d:02x?}"),
+            Transaction::Data(d) => write!(f, "data {} bytes", d.len()),
+            Transaction::WaitBusyHigh => write!(f, "wait busy high"),
+            Transaction::WaitBusyLow => write!(f, "wait busy low"),
+            Transaction::Reset => write!(f, "reset"),
+            Transaction::Read(n) => write!(f, "read {n} bytes"),
+            Transaction::Transfer(d, n) => write!(f, "transfer {d:02x?}, read {n} bytes"),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct MockDevice {
+    // busy waits fail with a timeout while this is above zero, counting
+    // down once per wait
+    pub busy_failures: Cell<u32>,
+    log: RefCell<Vec<Transaction>>,
+    // the model it stands in for, the acep unless set
+    panel: Option<&'static dyn Panel>,
+    cmd: u8,
+    ram: Vec<u8>,
+}
+
+impl MockDevice {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_panel(panel: &'static dyn Panel) -> Self {
+        Self {
+            panel: Some(panel),
+            ..Self::default()
+        }
+    }
+
+    pub fn log(&self) -> Vec<Transaction> {
+        self.log.borrow().clone()
+    }
+
+    pub fn clear(&mut self) {
+        self.log.get_mut().clear();
+    }
+
+    // the command bytes sent, without their data
+    pub fn commands(&self) -> Vec<u8> {
+        self.log
+            .borrow()
+            .iter()
+            .filter_map(|t| match t {
+                Transaction::Cmd(c) => Some(*c),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // the frame last written to the panel's ram, if a whole one was sent
+    pub fn frame(&self) -> Option<PackedFrame> {
+        (self.ram.len() == PACKED_LEN).then(|| PackedFrame {
+            data: self.ram.clone(),
+        })
+    }
+
+    // the frame expanded to palette rgb
+    pub fn image(&self) -> Option<bmp::Image> {
+        self.frame().map(|f| preview::render(&f))
+    }
+
+    fn wait(&self, t: Transaction) -> error::Result<()> {
+        self.log.borrow_mut().push(t);
+        match self.busy_failures.get() {
+            0 => Ok(()),
+            n => {
+                self.busy_failures.set(n - 1);
+                Err(EpaperError::BusyTimeout(Duration::ZERO))
+            }
+        }
+    }
+}
+
+impl MockDevice {
+    // the temperature reads as 25C and the status as idle, anything else
+    // as zeros
+    fn answer(&self, buf: &mut [u8]) {
+        let answer = match self.cmd {
+            0x40 => cmd::temperature_bytes(25.0).to_vec(),
+            0x71 => vec![0x11],
+            _ => Vec::new(),
+        };
+        buf.fill(0);
+        let n = buf.len().min(answer.len());
+        buf[..n].copy_from_slice(&answer[..n]);
+    }
+}
+
+impl SpiDevice for MockDevice {
+    fn send_cmd(&mut self, cmd: u8) -> error::Result<()> {
+        self.log.get_mut().push(Transaction::Cmd(cmd));
+        self.cmd = cmd;
+        if cmd == 0x10 {
+            self.ram.clear();
+        }
+        Ok(())
+    }
+
+    fn send_data(&mut self, data: &[u8]) -> error::Result<()> {
+        self.log.get_mut().push(Transaction::Data(data.to_vec()));
+        if self.cmd == 0x10 {
+            self.ram.extend_from_slice(data);
+        }
+        Ok(())
+    }
+
+    fn read_data(&mut self, buf: &mut [u8]) -> error::Result<()> {
+        self.log.get_mut().push(Transaction::Read(buf.len()));
+        self.answer(buf);
+        Ok(())
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> error::Result<()> {
+        (self.log.get_mut()).push(Transaction::Transfer(write.to_vec(), read.len()));
+        self.answer(read);
+        Ok(())
+    }
+
+    fn wait_busy_high(&self) -> error::Result<()> {
+        self.wait(Transaction::WaitBusyHigh)
+    }
+
+    fn wait_busy_low(&self) -> error::Result<()> {
+        self.wait(Transaction::WaitBusyLow)
+    }
+
+    fn reset(&mut self) {
+        self.log.get_mut().push(Transaction::Reset);
+        self.cmd = 0;
+    }
+
+    fn geometry(&self) -> Geometry {
+        self.panel().geometry()
+    }
+
+    fn panel(&self) -> &'static dyn Panel {
+        self.panel.unwrap_or(&crate::panel::Acep565)
+    }
+}
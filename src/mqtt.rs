@@ -0,0 +1,287 @@
+// just enough of mqtt 3.1.1 to subscribe to a topic and publish to one at
+// qos 0
+
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+pub const DEFAULT_PORT: u16 = 1883;
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const SUBSCRIBE: u8 = 0x82;
+const PINGREQ: u8 = 0xC0;
+const DISCONNECT: u8 = 0xE0;
+// a publish the broker keeps for whoever subscribes later
+const RETAIN: u8 = 0x01;
+
+pub struct Message {
+    pub topic: String,
+    pub payload: Vec<u8>,
+}
+
+pub struct Client {
+    stream: TcpStream,
+    keep_alive: Duration,
+    next_id: u16,
+}
+
+// the variable length encoding of a packet's remaining length
+fn encode_len(mut n: usize, out: &mut Vec<u8>) {
+    loop {
+        let mut b = (n % 128) as u8;
+        n /= 128;
+        if n > 0 {
+            b |= 0x80;
+        }
+        out.push(b);
+        if n == 0 {
+            break;
+        }
+    }
+}
+
+fn push_str(s: &str, out: &mut Vec<u8>) {
+    out.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    out.extend_from_slice(s.as_bytes());
+}
+
+// `host/topic` or `host:port/topic`, the topic being everything after the
+// first slash
+pub fn parse_target(s: &str) -> Result<(String, String), String> {
+    match s.split_once('/') {
+        Some((broker, topic)) if !broker.is_empty() && !topic.is_empty() => {
+            Ok((broker.to_string(), topic.to_string()))
+        }
+        _ => Err(format!("'{s}' should be host[:port]/topic")),
+    }
+}
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+impl Client {
+    // `host` or `host:port`
+    pub fn connect(addr: &str, client_id: &str) -> io::Result<Self> {
+        let addr = if addr.contains(':') {
+            addr.to_string()
+        } else {
+            format!("{addr}:{DEFAULT_PORT}")
+        };
+        let keep_alive = Duration::from_secs(60);
+        let mut client = Self {
+            stream: TcpStream::connect(&addr)?,
+            keep_alive,
+            next_id: 1,
+        };
+        let mut body = Vec::new();
+        push_str("MQTT", &mut body);
+        // protocol level 4, clean session
+        body.extend_from_slice(&[4, 0x02]);
+        body.extend_from_slice(&(keep_alive.as_secs() as u16).to_be_bytes());
+        push_str(client_id, &mut body);
+        client.write_packet(CONNECT, &body)?;
+        let (kind, body) = client.read_packet()?;
+        match (kind & 0xF0, body.get(1)) {
+            (CONNACK, Some(0)) => Ok(client),
+            (CONNACK, Some(rc)) => Err(invalid(format!("{addr} refused the connection ({rc})"))),
+            _ => Err(invalid(format!(
+                "{addr} did not acknowledge the connection"
+            ))),
+        }
+    }
+
+    pub fn subscribe(&mut self, topic: &str) -> io::Result<()> {
+        let mut body = self.next_id.to_be_bytes().to_vec();
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        push_str(topic, &mut body);
+        body.push(0);
+        self.write_packet(SUBSCRIBE, &body)
+    }
+
+    pub fn publish(&mut self, topic: &str, payload: &[u8], retain: bool) -> io::Result<()> {
+        let mut body = Vec::new();
+        push_str(topic, &mut body);
+        body.extend_from_slice(payload);
+        let kind = if retain { PUBLISH | RETAIN } else { PUBLISH };
+        self.write_packet(kind, &body)
+    }
+
+    // a clean goodbye, so the broker doesn't wait out the keep alive
+    pub fn disconnect(mut self) -> io::Result<()> {
+        self.write_packet(DISCONNECT, &[])
+    }
+
+    // blocks until the next message on a subscribed topic, pinging the
+    // broker while it waits
+    pub fn next_message(&mut self) -> io::Result<Message> {
+        loop {
+            let (kind, body) = self.read_packet()?;
+            if kind & 0xF0 != PUBLISH {
+                continue;
+            }
+            let len = match body[..] {
+                [a, b, ..] => u16::from_be_bytes([a, b]) as usize,
+                _ => return Err(invalid("truncated publish")),
+            };
+            // qos 1 and 2 carry a packet id before the payload
+            let start = 2 + len + if kind & 0x06 != 0 { 2 } else { 0 };
+            if body.len() < start {
+                return Err(invalid("truncated publish"));
+            }
+            let topic = String::from_utf8_lossy(&body[2..2 + len]).into_owned();
+            return Ok(Message {
+                topic,
+                payload: body[start..].to_vec(),
+            });
+        }
+    }
+
+    fn write_packet(&mut self, kind: u8, body: &[u8]) -> io::Result<()> {
+        let mut packet = vec![kind];
+        encode_len(body.len(), &mut packet);
+        packet.extend_from_slice(body);
+        self.stream.write_all(&packet)
+    }
+
+    fn read_packet(&mut self) -> io::Result<(u8, Vec<u8>)> {
+        let mut kind = [0];
+        // only the wait for a packet to start times out, so a ping is never
+        // sent in the middle of one
+        self.stream.set_read_timeout(Some(self.keep_alive / 2))?;
+        let mut pinged = false;
+        loop {
+            match self.stream.read_exact(&mut kind) {
+                Ok(()) => break,
+                // a broker that went away without closing the connection
+                // doesn't answer the ping
+                Err(e)
+                    if pinged
+                        && matches!(
+                            e.kind(),
+                            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                        ) =>
+                {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "broker stopped answering pings",
+                    ))
+                }
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    self.write_packet(PINGREQ, &[])?;
+                    pinged = true;
+                }
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    return Err(io::Error::new(e.kind(), "the broker closed the connection"))
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        self.stream.set_read_timeout(None)?;
+        let mut len = 0;
+        for shift in (0..4).map(|i| i * 7) {
+            let mut b = [0];
+            self.stream.read_exact(&mut b)?;
+            len |= ((b[0] & 0x7F) as usize) << shift;
+            if b[0] & 0x80 == 0 {
+                let mut body = vec![0; len];
+                self.stream.read_exact(&mut body)?;
+                return Ok((kind[0], body));
+            }
+        }
+        Err(invalid("malformed packet length"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, thread};
+
+    use super::*;
+
+    // a broker on loopback that acks the connect with `rc`, then sends
+    // `packets`. hands back what the client sent before the first one.
+    fn broker(rc: u8, packets: Vec<Vec<u8>>) -> (String, thread::JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut connect = [0; 2];
+            stream.read_exact(&mut connect).unwrap();
+            let mut body = vec![0; connect[1] as usize];
+            stream.read_exact(&mut body).unwrap();
+            stream.write_all(&[CONNACK, 2, 0, rc]).unwrap();
+            for p in packets {
+                stream.write_all(&p).unwrap();
+            }
+            [&connect[..], &body].concat()
+        });
+        (addr, handle)
+    }
+
+    fn publish(topic: &str, payload: &[u8]) -> Vec<u8> {
+        let mut body = Vec::new();
+        push_str(topic, &mut body);
+        body.extend_from_slice(payload);
+        let mut packet = vec![PUBLISH];
+        encode_len(body.len(), &mut packet);
+        packet.extend(body);
+        packet
+    }
+
+    #[test]
+    fn lengths_use_seven_bits_a_byte() {
+        let encoded = |n| {
+            let mut out = Vec::new();
+            encode_len(n, &mut out);
+            out
+        };
+        assert_eq!(encoded(0), [0x00]);
+        assert_eq!(encoded(127), [0x7F]);
+        assert_eq!(encoded(128), [0x80, 0x01]);
+        assert_eq!(encoded(16_383), [0xFF, 0x7F]);
+        assert_eq!(encoded(2_097_152), [0x80, 0x80, 0x80, 0x01]);
+    }
+
+    #[test]
+    fn targets_are_a_broker_and_a_topic() {
+        assert_eq!(
+            parse_target("pi.local:1884/home/panel").unwrap(),
+            ("pi.local:1884".to_string(), "home/panel".to_string())
+        );
+        assert!(parse_target("pi.local").is_err());
+        assert!(parse_target("/topic").is_err());
+        assert!(parse_target("broker/").is_err());
+    }
+
+    #[test]
+    fn receives_what_the_broker_publishes() {
+        let payload = vec![7; 300];
+        let (addr, broker) = broker(0, vec![publish("home/panel", &payload)]);
+        let mut client = Client::connect(&addr, "epaper").unwrap();
+        let message = client.next_message().unwrap();
+        assert_eq!(message.topic, "home/panel");
+        assert_eq!(message.payload, payload);
+        let connect = broker.join().unwrap();
+        assert_eq!(connect[0], CONNECT);
+        assert_eq!(&connect[2..8], b"\x00\x04MQTT");
+        assert!(connect.ends_with(b"\x00\x06epaper"));
+    }
+
+    #[test]
+    fn a_refused_connection_fails() {
+        let (addr, broker) = broker(5, Vec::new());
+        let e = Client::connect(&addr, "epaper").err().unwrap();
+        assert!(e.to_string().contains("refused the connection (5)"), "{e}");
+        broker.join().unwrap();
+    }
+}
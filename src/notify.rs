@@ -0,0 +1,234 @@
+// alerts pushed to a running daemon with `POST /notify`. each is drawn as
+// a card over whatever was showing and taken down again after its duration.
+
+use std::{net::TcpListener, str::FromStr, sync::mpsc::Sender, thread, time::Duration};
+
+use serde_json::{json, Value};
+
+use crate::{
+    draw::{Color, Corner},
+    http, overlay,
+    scene::{Scene, Step, Widget},
+};
+
+const BORDER: u32 = 8;
+
+// sets the card's accent color and corner label
+#[derive(Clone, Copy, PartialEq)]
+pub enum Icon {
+    Info,
+    Ok,
+    Warning,
+    Error,
+}
+
+impl Icon {
+    fn color(self) -> Color {
+        match self {
+            Icon::Info => Color::Blue,
+            Icon::Ok => Color::Green,
+            Icon::Warning => Color::Orange,
+            Icon::Error => Color::Red,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Icon::Info => "info",
+            Icon::Ok => "ok",
+            Icon::Warning => "warning",
+            Icon::Error => "error",
+        }
+    }
+}
+
+impl FromStr for Icon {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "info" => Ok(Icon::Info),
+            "ok" | "success" => Ok(Icon::Ok),
+            "warning" => Ok(Icon::Warning),
+            "error" => Ok(Icon::Error),
+            _ => Err(format!(
+                "unknown icon '{s}' (expected info, ok, warning or error)"
+            )),
+        }
+    }
+}
+
+pub struct Notification {
+    pub title: String,
+    pub body: String,
+    pub icon: Icon,
+    // how long the card stays up once drawn
+    pub duration: Duration,
+    // a card only takes over from one of the same or lower priority. the
+    // others wait their turn.
+    pub priority: i64,
+}
+
+impl Notification {
+    // `{title, body, icon, duration, priority}`, only the title is required.
+    // the duration is in seconds and defaults to a minute.
+    pub fn from_json(v: &Value) -> Result<Self, String> {
+        let field = |name: &str| v.get(name).filter(|f| !f.is_null());
+        let title = field("title")
+            .and_then(Value::as_str)
+            .ok_or("notification needs a title")?;
+        let string = |name: &str| match field(name) {
+            None => Ok(None),
+            Some(f) => f
+                .as_str()
+                .map(Some)
+                .ok_or(format!("{name} should be a string")),
+        };
+        let number = |name: &str| match field(name) {
+            None => Ok(None),
+            Some(f) => f
+                .as_f64()
+                .map(Some)
+                .ok_or(format!("{name} should be a number")),
+        };
+        let duration = number("duration")?.unwrap_or(60.0);
+        if !(duration > 0.0 && duration.is_finite()) {
+            return Err("duration should be a positive number of seconds".into());
+        }
+        Ok(Self {
+            title: title.to_string(),
+            body: string("body")?.unwrap_or_default().to_string(),
+            icon: string("icon")?.map_or(Ok(Icon::Info), str::parse)?,
+            duration: Duration::from_secs_f64(duration),
+            priority: number("priority")?.unwrap_or(0.0) as i64,
+        })
+    }
+
+    // the title in the accent color over the body, inside an accent border,
+    // shrunk until it fits
+    pub fn card(&self) -> bmp::Image {
+        let color = self.icon.color();
+        let scene = Scene {
+            widgets: vec![
+                Widget {
+                    text: self.title.clone(),
+                    scale: 5,
+                    priority: 1,
+                    color,
+                },
+                Widget {
+                    text: self.body.clone(),
+                    scale: 3,
+                    priority: 0,
+                    color: Color::Black,
+                },
+            ],
+            policy: vec![Step::Shrink],
+        };
+        let mut img = scene.render().pages.swap_remove(0);
+        overlay::draw_border(&mut img, 0, BORDER, color);
+        overlay::stamp_label(
+            &mut img,
+            self.icon.name(),
+            Corner::BottomRight,
+            Color::White,
+            color,
+        );
+        img
+    }
+}
+
+// answers `POST /notify` on `addr`, handing each valid notification to
+// `to`. stops once the receiving end is gone.
+pub fn listen(addr: &str, to: Sender<Notification>) -> Result<(), String> {
+    let listener =
+        TcpListener::bind(addr).map_err(|e| format!("could not listen on {addr}: {e}"))?;
+    thread::spawn(move || {
+        for stream in listener.incoming().filter_map(Result::ok) {
+            let req = match http::read_request(&stream) {
+                Ok(req) => req,
+                Err(e) => {
+                    http::respond(&stream, 400, &json!({ "error": e }));
+                    continue;
+                }
+            };
+            if req.path != "/notify" {
+                http::respond(&stream, 404, &json!({ "error": "not found" }));
+                continue;
+            }
+            if req.method != "POST" {
+                http::respond(&stream, 405, &json!({ "error": "use POST" }));
+                continue;
+            }
+            let parsed = serde_json::from_slice(&req.body)
+                .map_err(|e| format!("invalid json: {e}"))
+                .and_then(|v| Notification::from_json(&v));
+            match parsed {
+                Ok(n) => {
+                    if to.send(n).is_err() {
+                        return;
+                    }
+                    http::respond(&stream, 202, &json!({ "queued": true }));
+                }
+                Err(e) => http::respond(&stream, 400, &json!({ "error": e })),
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_title_is_required() {
+        let n = Notification::from_json(&json!({ "title": "door" })).unwrap();
+        assert_eq!((n.title.as_str(), n.body.as_str()), ("door", ""));
+        assert!(n.icon == Icon::Info);
+        assert_eq!(n.duration, Duration::from_secs(60));
+        assert_eq!(n.priority, 0);
+        let n = Notification::from_json(&json!({
+            "title": "door",
+            "body": "left open",
+            "icon": "success",
+            "duration": 1.5,
+            "priority": 3,
+            "extra": null,
+        }))
+        .unwrap();
+        assert!(n.icon == Icon::Ok);
+        assert_eq!(n.duration, Duration::from_millis(1500));
+        assert_eq!(n.priority, 3);
+    }
+
+    #[test]
+    fn rejects_bad_fields() {
+        let fails = |v: Value, message: &str| {
+            let e = Notification::from_json(&v).err().unwrap();
+            assert!(e.contains(message), "{e}");
+        };
+        fails(json!({}), "needs a title");
+        fails(json!({ "title": null }), "needs a title");
+        fails(
+            json!({ "title": "a", "body": 1 }),
+            "body should be a string",
+        );
+        fails(json!({ "title": "a", "icon": "fire" }), "unknown icon");
+        fails(
+            json!({ "title": "a", "duration": "soon" }),
+            "should be a number",
+        );
+        fails(json!({ "title": "a", "duration": 0 }), "positive number");
+        fails(json!({ "title": "a", "duration": -3 }), "positive number");
+    }
+
+    #[test]
+    fn cards_are_bordered_in_the_icon_color() {
+        let card = Notification::from_json(&json!({ "title": "hot", "icon": "error" }))
+            .unwrap()
+            .card();
+        let red: bmp::Pixel = crate::Rgb::from(Color::Red).into();
+        assert_eq!(card.get_pixel(0, 0), red);
+        assert_eq!(card.get_pixel(card.get_width() - 1, 0), red);
+    }
+}
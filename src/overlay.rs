@@ -0,0 +1,104 @@
+use crate::{
+    draw::{Color, Corner},
+    font,
+    layout::{self, Rect},
+    localtime::Clock,
+    Geometry, Rgb,
+};
+
+const LABEL_SCALE: u16 = 2;
+const LABEL_PAD: u16 = 3;
+const LABEL_MARGIN: u16 = 4;
+
+// stamps text on a solid backing box in the given corner.
+// done on the source image so the label is dithered with everything else.
+pub fn stamp_label(img: &mut bmp::Image, text: &str, corner: Corner, fg: Color, bg: Color) {
+    let rect = label_rect(text, layout::area(img), corner);
+    fill_rect(img, rect.x, rect.y, rect.w, rect.h, Rgb::from(bg).into());
+    font::draw_text(
+        img,
+        text,
+        (rect.x + LABEL_PAD as u32) as u16,
+        (rect.y + LABEL_PAD as u32) as u16,
+        LABEL_SCALE,
+        Rgb::from(fg).into(),
+    );
+}
+
+// the backing box stamp_label draws on an `area` sized image
+pub fn label_rect(text: &str, area: Geometry, corner: Corner) -> Rect {
+    let (tw, th) = font::text_size(text, LABEL_SCALE);
+    let (w, h) = (tw + LABEL_PAD * 2, th + LABEL_PAD * 2);
+    let (x, y) = corner.place(area, w, h, LABEL_MARGIN);
+    Rect {
+        x: x as u32,
+        y: y as u32,
+        w: w as u32,
+        h: h as u32,
+    }
+}
+
+pub fn timestamp_text(clock: &Clock) -> String {
+    format!("{} {}", clock.locale.updated, clock.now())
+}
+
+pub fn stamp_timestamp(img: &mut bmp::Image, corner: Corner, clock: &Clock) {
+    stamp_label(
+        img,
+        &timestamp_text(clock),
+        corner,
+        Color::Black,
+        Color::White,
+    );
+}
+
+pub fn fill_rect(img: &mut bmp::Image, x: u32, y: u32, w: u32, h: u32, px: bmp::Pixel) {
+    for bx in x..(x + w).min(img.get_width()) {
+        for by in y..(y + h).min(img.get_height()) {
+            img.set_pixel(bx, by, px);
+        }
+    }
+}
+
+// shrinks the whole image (nearest neighbour) into the area `margin` px in
+// from each edge, filling the margin with `bg`
+pub fn inset(img: &bmp::Image, margin: u32, bg: Color) -> bmp::Image {
+    let (w, h) = (img.get_width(), img.get_height());
+    let mut out = bmp::Image::new(w, h);
+    fill_rect(&mut out, 0, 0, w, h, Rgb::from(bg).into());
+    let iw = w.saturating_sub(margin * 2);
+    let ih = h.saturating_sub(margin * 2);
+    for x in 0..iw {
+        for y in 0..ih {
+            let px = img.get_pixel(x * w / iw, y * h / ih);
+            out.set_pixel(x + margin, y + margin, px);
+        }
+    }
+    out
+}
+
+// draws a `width` px rectangle outline `margin` px in from the image edges
+pub fn draw_border(img: &mut bmp::Image, margin: u32, width: u32, color: Color) {
+    let px: bmp::Pixel = Rgb::from(color).into();
+    let (w, h) = (img.get_width(), img.get_height());
+    let iw = w.saturating_sub(margin * 2);
+    let ih = h.saturating_sub(margin * 2);
+    fill_rect(img, margin, margin, iw, width, px);
+    fill_rect(
+        img,
+        margin,
+        (margin + ih).saturating_sub(width),
+        iw,
+        width,
+        px,
+    );
+    fill_rect(img, margin, margin, width, ih, px);
+    fill_rect(
+        img,
+        (margin + iw).saturating_sub(width),
+        margin,
+        width,
+        ih,
+        px,
+    );
+}
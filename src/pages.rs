@@ -0,0 +1,137 @@
+use crate::{
+    draw::{Color, Drawable},
+    font::{self, ADVANCE, GLYPH_HEIGHT},
+    SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+const MARGIN: u16 = 12;
+// glyph height plus 3 rows of spacing, before scaling
+const LINE_HEIGHT: u16 = GLYPH_HEIGHT + 3;
+
+pub struct Line {
+    pub text: String,
+    pub color: Color,
+}
+
+pub struct Page {
+    pub lines: Vec<Line>,
+}
+
+// how many characters per line and lines per page fit at a scale.
+// the last row is reserved for the page number.
+fn page_dimensions(scale: u16) -> (usize, usize) {
+    let cols = (SCREEN_WIDTH - MARGIN * 2 + scale) / (ADVANCE * scale);
+    let rows = (SCREEN_HEIGHT - MARGIN * 2) / (LINE_HEIGHT * scale);
+    (cols as usize, rows.saturating_sub(1) as usize)
+}
+
+// the y of the bottom glyph row of every line on a page, footer last
+pub fn baselines(scale: u16) -> Vec<u32> {
+    let (_, rows) = page_dimensions(scale);
+    (0..=rows as u32)
+        .map(|row| (MARGIN + (row as u16 * LINE_HEIGHT + GLYPH_HEIGHT) * scale) as u32 - 1)
+        .collect()
+}
+
+// greedy word wrap, hard-breaking words longer than a line
+pub fn wrap(text: &str, cols: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let mut word = word.to_string();
+        while word.chars().count() > cols {
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            let split = word.char_indices().nth(cols).map_or(word.len(), |(i, _)| i);
+            lines.push(word[..split].to_string());
+            word = word[split..].to_string();
+        }
+        let needed = line.chars().count() + usize::from(!line.is_empty()) + word.chars().count();
+        if needed > cols && !line.is_empty() {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&word);
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+// splits a text or light markdown document into screen-sized pages.
+// markdown headings are drawn in red with their `#`s stripped.
+pub fn paginate(doc: &str, scale: u16) -> Vec<Page> {
+    let (cols, rows) = page_dimensions(scale);
+    let mut lines = Vec::new();
+    for src in doc.lines() {
+        let trimmed = src.trim_start();
+        let (text, color) = if trimmed.starts_with('#') {
+            (trimmed.trim_start_matches('#').trim(), Color::Red)
+        } else {
+            (src, Color::Black)
+        };
+        for text in wrap(text, cols) {
+            lines.push(Line { text, color });
+        }
+    }
+    let mut pages = Vec::new();
+    let mut lines = lines.into_iter().peekable();
+    while lines.peek().is_some() {
+        pages.push(Page {
+            lines: lines.by_ref().take(rows.max(1)).collect(),
+        });
+    }
+    if pages.is_empty() {
+        pages.push(Page { lines: Vec::new() });
+    }
+    pages
+}
+
+pub struct TextPage<'a> {
+    pub page: &'a Page,
+    // 1-based page number and total, shown in the footer
+    pub number: usize,
+    pub total: usize,
+    pub scale: u16,
+}
+
+impl TextPage<'_> {
+    fn char_at(text: &str, x: u16, y: u16, scale: u16) -> bool {
+        let col = x / (ADVANCE * scale);
+        let gx = (x % (ADVANCE * scale)) / scale;
+        let gy = y / scale;
+        text.chars()
+            .nth(col as usize)
+            .is_some_and(|c| font::glyph_pixel(c, gx, gy))
+    }
+}
+
+impl Drawable for TextPage<'_> {
+    fn get_pixel(&self, x: u16, y: u16) -> Color {
+        let line_h = LINE_HEIGHT * self.scale;
+        if x < MARGIN || y < MARGIN {
+            return Color::White;
+        }
+        let (cx, cy) = (x - MARGIN, y - MARGIN);
+        let row = (cy / line_h) as usize;
+        let (_, rows) = page_dimensions(self.scale);
+        if row == rows {
+            // footer, right aligned
+            let footer = format!("{}/{}", self.number, self.total);
+            let (w, _) = font::text_size(&footer, self.scale);
+            let fx = SCREEN_WIDTH - MARGIN - w;
+            if x >= fx && Self::char_at(&footer, x - fx, cy % line_h, self.scale) {
+                return Color::Blue;
+            }
+            return Color::White;
+        }
+        match self.page.lines.get(row) {
+            Some(line) if Self::char_at(&line.text, cx, cy % line_h, self.scale) => line.color,
+            _ => Color::White,
+        }
+    }
+}
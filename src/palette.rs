@@ -0,0 +1,168 @@
+// the inks a panel can show and the code its ram takes for each, so the
+// closest color match and the framebuffer packing work the same whether a
+// panel has 2, 3, 4 or 7 colors. frames are still drawn in `Color`, a
+// palette only says which of them a panel has and how they're packed.
+
+use crate::{
+    draw::{Color, Drawable},
+    Rgb,
+};
+
+#[derive(Clone, Copy)]
+pub struct Entry {
+    pub color: Color,
+    // what the panel's ram takes for it
+    pub code: u8,
+}
+
+impl Entry {
+    // how it looks on the panel, after calibration
+    pub fn rgb(&self) -> Rgb {
+        Rgb::from(self.color)
+    }
+}
+
+pub struct Palette {
+    // the order breaks ties when matching
+    pub entries: &'static [Entry],
+    // how many bits a code takes in the panel's ram
+    pub bits: u8,
+}
+
+const fn entry(color: Color, code: u8) -> Entry {
+    Entry { color, code }
+}
+
+// the 7 color acep, whose device codes are the `Color` values
+pub const ACEP: Palette = Palette {
+    entries: &[
+        entry(Color::Clean, Color::Clean as u8),
+        entry(Color::Black, Color::Black as u8),
+        entry(Color::White, Color::White as u8),
+        entry(Color::Green, Color::Green as u8),
+        entry(Color::Blue, Color::Blue as u8),
+        entry(Color::Red, Color::Red as u8),
+        entry(Color::Yellow, Color::Yellow as u8),
+        entry(Color::Orange, Color::Orange as u8),
+    ],
+    bits: 4,
+};
+
+pub const BW: Palette = Palette {
+    entries: &[entry(Color::Black, 0), entry(Color::White, 1)],
+    bits: 1,
+};
+
+// the high bit goes to the black plane and the low bit to the red one,
+// each cleared where that ink goes
+pub const BWR: Palette = Palette {
+    entries: &[
+        entry(Color::Black, 0b01),
+        entry(Color::White, 0b11),
+        entry(Color::Red, 0b10),
+    ],
+    bits: 2,
+};
+
+// as the waveshare 4 color panels number them
+pub const BWRY: Palette = Palette {
+    entries: &[
+        entry(Color::Black, 0b00),
+        entry(Color::White, 0b01),
+        entry(Color::Yellow, 0b10),
+        entry(Color::Red, 0b11),
+    ],
+    bits: 2,
+};
+
+impl Palette {
+    pub fn colors(&self) -> Vec<Color> {
+        self.entries.iter().map(|e| e.color).collect()
+    }
+
+    // the code for every color a frame can hold, indexed by `Color as
+    // usize`. a color the panel lacks gets the code of the closest it has.
+    pub fn codes(&self) -> [u8; 8] {
+        let colors = self.colors();
+        let mut codes = [0; 8];
+        for &c in Color::all() {
+            let shown = match self.entries.iter().any(|e| e.color == c) {
+                true => c,
+                false => Color::closest_in(Rgb::from(c), &colors),
+            };
+            codes[c as usize] = self.entries.iter().find(|e| e.color == shown).unwrap().code;
+        }
+        codes
+    }
+
+    // the w*h area of `frame` at x, y as one plane of codes, packed `bits`
+    // at a time from the top of each byte. w must fill whole bytes.
+    pub fn pack(&self, frame: &dyn Drawable, x: u16, y: u16, w: u16, h: u16) -> Vec<u8> {
+        let codes = self.codes();
+        let per_byte = 8 / self.bits as u16;
+        let mut data = Vec::with_capacity((w / per_byte) as usize * h as usize);
+        for y in y..y + h {
+            for x in (x..x + w).step_by(per_byte as usize) {
+                let mut byte = 0;
+                for i in 0..per_byte {
+                    byte = byte << self.bits | codes[frame.get_pixel(x + i, y) as usize];
+                }
+                data.push(byte);
+            }
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // one color per column, repeating
+    struct Columns(&'static [Color]);
+
+    impl Drawable for Columns {
+        fn get_pixel(&self, x: u16, _: u16) -> Color {
+            self.0[x as usize % self.0.len()]
+        }
+    }
+
+    #[test]
+    fn bw_packs_eight_pixels_to_a_byte() {
+        let frame = Columns(&[Color::White, Color::Black]);
+        assert_eq!(BW.pack(&frame, 0, 0, 16, 2), vec![0b1010_1010; 4]);
+    }
+
+    #[test]
+    fn bwry_packs_four_pixels_to_a_byte() {
+        let frame = Columns(&[Color::Black, Color::White, Color::Yellow, Color::Red]);
+        assert_eq!(BWRY.pack(&frame, 0, 0, 8, 1), vec![0b00_01_10_11; 2]);
+    }
+
+    #[test]
+    fn acep_packs_the_color_values() {
+        let frame = Columns(&[Color::Green, Color::Orange]);
+        assert_eq!(ACEP.pack(&frame, 0, 0, 4, 1), vec![0x26, 0x26]);
+    }
+
+    #[test]
+    fn pack_takes_the_window_asked_for() {
+        let frame = Columns(&[Color::Black, Color::White, Color::Black, Color::Black]);
+        // starting a column in, so the window begins on white
+        assert_eq!(BW.pack(&frame, 1, 5, 8, 1), vec![0b1000_1000]);
+    }
+
+    #[test]
+    fn missing_colors_get_the_closest_code() {
+        let codes = BW.codes();
+        assert_eq!(codes[Color::Yellow as usize], 1);
+        assert_eq!(codes[Color::Blue as usize], 0);
+        let codes = BWRY.codes();
+        assert_eq!(codes[Color::Orange as usize], 0b10);
+        // every code the acep is sent is its own color
+        let codes = ACEP.codes();
+        for &c in Color::all() {
+            assert_eq!(codes[c as usize], c as u8);
+        }
+    }
+}
@@ -0,0 +1,184 @@
+// what sets one panel model apart from another behind the same controller
+// commands: its size, the colors it can show, the commands that bring it up
+// and how pixels are packed into its ram. frames are still drawn at
+// SCREEN_WIDTH x SCREEN_HEIGHT and a smaller panel shows their top-left
+// corner, so pictures are shrunk into that corner before they are dithered.
+// patterns and packed frames are drawn as they are.
+
+use std::{ops::RangeInclusive, thread::sleep, time::Duration};
+
+use crate::{
+    cmd::{
+        BoosterSoftStart, Command, InternalPower, KwrPanelSetting, PLLControl, PanelSetting,
+        PowerOffSequence, PowerOn, SetResolution, TempSensor, Unknown6022, UnknownE3AA,
+        VCOMDataInterval,
+    },
+    draw::{Color, Drawable},
+    error::Result,
+    palette::{self, Palette},
+    Geometry, SpiDevice, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+// one of the panel's ram planes: the command that starts it and its bytes
+pub struct Plane {
+    pub cmd: u8,
+    pub data: Vec<u8>,
+}
+
+pub trait Panel: Sync {
+    // as given to [panel] model in the config
+    fn name(&self) -> &'static str;
+    fn geometry(&self) -> Geometry;
+    // every color the panel's ram can hold, Clean included if it has one
+    fn palette(&self) -> &'static Palette;
+    // the setup sent after a reset, before the first frame
+    fn init(&self, to: &mut dyn SpiDevice) -> Result<()>;
+    // the w*h area of `frame` at x, y as the panel's ram takes it, plane by
+    // plane. x and w are multiples of 8.
+    fn pack(&self, frame: &dyn Drawable, x: u16, y: u16, w: u16, h: u16) -> Vec<Plane>;
+    // the temperatures in C it is made to refresh at. outside them colors
+    // come out washed or streaked.
+    fn refresh_range(&self) -> RangeInclusive<f32>;
+    // whether PartialIn, PartialWindow and PartialOut take a window of its
+    // ram, or the whole panel has to be sent
+    fn supports_partial(&self) -> bool;
+}
+
+// the waveshare 5.65" 7 color acep, 600x448
+pub struct Acep565;
+
+impl Panel for Acep565 {
+    fn name(&self) -> &'static str {
+        "acep565"
+    }
+
+    fn geometry(&self) -> Geometry {
+        Geometry {
+            width: SCREEN_WIDTH,
+            height: SCREEN_HEIGHT,
+        }
+    }
+
+    fn palette(&self) -> &'static Palette {
+        &palette::ACEP
+    }
+
+    fn init(&self, mut to: &mut dyn SpiDevice) -> Result<()> {
+        let to = &mut to;
+        PanelSetting::default().send(to)?;
+        InternalPower.send(to)?;
+        PowerOffSequence.send(to)?;
+        BoosterSoftStart.send(to)?;
+        PLLControl.send(to)?;
+        TempSensor.send(to)?;
+        VCOMDataInterval {
+            border_output: Color::Clean,
+        }
+        .send(to)?;
+        Unknown6022.send(to)?;
+        SetResolution.send(to)?;
+        UnknownE3AA.send(to)?;
+        sleep(Duration::from_millis(100));
+        VCOMDataInterval {
+            border_output: Color::Clean,
+        }
+        .send(to)?;
+        Ok(())
+    }
+
+    // a single plane, 2 px to a byte, the left one in the high nibble
+    fn pack(&self, frame: &dyn Drawable, x: u16, y: u16, w: u16, h: u16) -> Vec<Plane> {
+        let data = self.palette().pack(frame, x, y, w, h);
+        vec![Plane { cmd: 0x10, data }]
+    }
+
+    fn refresh_range(&self) -> RangeInclusive<f32> {
+        15.0..=35.0
+    }
+
+    fn supports_partial(&self) -> bool {
+        true
+    }
+}
+
+// the waveshare 4.2" black/white/red (b v2), 400x300. the same commands
+// drive the other uc8176 tri-color modules.
+pub struct Bwr420;
+
+impl Panel for Bwr420 {
+    fn name(&self) -> &'static str {
+        "bwr420"
+    }
+
+    fn geometry(&self) -> Geometry {
+        Geometry {
+            width: 400,
+            height: 300,
+        }
+    }
+
+    fn palette(&self) -> &'static Palette {
+        &palette::BWR
+    }
+
+    fn init(&self, mut to: &mut dyn SpiDevice) -> Result<()> {
+        let to = &mut to;
+        PowerOn.send(to)?;
+        KwrPanelSetting(PanelSetting::default()).send(to)?;
+        SetResolution.send(to)?;
+        Ok(())
+    }
+
+    // a black and a red plane, 8 px to a byte with the leftmost in the top
+    // bit, split from the two bits of each code
+    fn pack(&self, frame: &dyn Drawable, x: u16, y: u16, w: u16, h: u16) -> Vec<Plane> {
+        let codes = self.palette().codes();
+        let len = w as usize / 8 * h as usize;
+        let (mut black, mut red) = (Vec::with_capacity(len), Vec::with_capacity(len));
+        for y in y..y + h {
+            for x in (x..x + w).step_by(8) {
+                let (mut b, mut r) = (0, 0);
+                for i in 0..8 {
+                    let code = codes[frame.get_pixel(x + i, y) as usize];
+                    b = b << 1 | code >> 1;
+                    r = r << 1 | code & 1;
+                }
+                black.push(b);
+                red.push(r);
+            }
+        }
+        vec![
+            Plane {
+                cmd: 0x10,
+                data: black,
+            },
+            Plane {
+                cmd: 0x13,
+                data: red,
+            },
+        ]
+    }
+
+    fn refresh_range(&self) -> RangeInclusive<f32> {
+        0.0..=50.0
+    }
+
+    // the uc8176 lays its partial window out differently, so frames go whole
+    fn supports_partial(&self) -> bool {
+        false
+    }
+}
+
+// every panel model that can be picked by name
+pub const PANELS: &[&dyn Panel] = &[&Acep565, &Bwr420];
+
+pub fn by_name(name: &str) -> std::result::Result<&'static dyn Panel, String> {
+    PANELS
+        .iter()
+        .copied()
+        .find(|p| p.name() == name)
+        .ok_or_else(|| {
+            let names: Vec<_> = PANELS.iter().map(|p| p.name()).collect();
+            format!("unknown panel {name} (expected {})", names.join(", "))
+        })
+}
@@ -0,0 +1,186 @@
+// diagnostics for a newly assembled panel: which colors it shows, whether
+// it lines up with its frame and whether the edges are reached. each is laid
+// out over the panel's `geometry`, the screen past it stays white.
+
+use crate::{
+    draw::{Color, Drawable},
+    layout, Geometry, Rgb, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+// past the panel, on a panel smaller than the screen
+fn off(geometry: Geometry, x: u16, y: u16) -> bool {
+    x >= geometry.width || y >= geometry.height
+}
+
+// a full height bar of each color, left to right
+pub struct Bars {
+    pub colors: Vec<Color>,
+    pub geometry: Geometry,
+}
+
+impl Drawable for Bars {
+    fn get_pixel(&self, x: u16, y: u16) -> Color {
+        if off(self.geometry, x, y) {
+            return Color::White;
+        }
+        let i = x as usize * self.colors.len() / self.geometry.width as usize;
+        self.colors[i]
+    }
+}
+
+// the colors in a grid as near square as fits, so each can be measured or
+// photographed on its own
+pub struct Swatches {
+    pub colors: Vec<Color>,
+    pub geometry: Geometry,
+}
+
+impl Swatches {
+    fn cols(&self) -> usize {
+        (self.colors.len() as f32).sqrt().ceil() as usize
+    }
+}
+
+impl Drawable for Swatches {
+    fn get_pixel(&self, x: u16, y: u16) -> Color {
+        if off(self.geometry, x, y) {
+            return Color::White;
+        }
+        let cols = self.cols();
+        let rows = self.colors.len().div_ceil(cols);
+        let col = x as usize * cols / self.geometry.width as usize;
+        let row = y as usize * rows / self.geometry.height as usize;
+        // the last row can be short, its gaps stay white
+        self.colors
+            .get(row * cols + col)
+            .copied()
+            .unwrap_or(Color::White)
+    }
+}
+
+// lines every `spacing` px with a heavier cross through the middle, to
+// check the frame isn't shifted or skewed on the panel
+pub struct Crosshatch {
+    pub spacing: u16,
+    pub color: Color,
+    pub geometry: Geometry,
+}
+
+impl Drawable for Crosshatch {
+    fn get_pixel(&self, x: u16, y: u16) -> Color {
+        let Geometry { width, height } = self.geometry;
+        if off(self.geometry, x, y) {
+            return Color::White;
+        }
+        let (cx, cy) = (width / 2, height / 2);
+        let center = x.abs_diff(cx) < 2 || y.abs_diff(cy) < 2;
+        // measured out from the middle so the grid is symmetric
+        let line = x.abs_diff(cx) % self.spacing == 0 || y.abs_diff(cy) % self.spacing == 0;
+        let edge = x == 0 || y == 0 || x == width - 1 || y == height - 1;
+        if center || line || edge {
+            self.color
+        } else {
+            Color::White
+        }
+    }
+}
+
+// a `width` px frame around the edge and nothing else, for seeing whether
+// the outermost pixels make it onto the panel
+pub struct Border {
+    pub width: u16,
+    pub color: Color,
+    pub geometry: Geometry,
+}
+
+impl Drawable for Border {
+    fn get_pixel(&self, x: u16, y: u16) -> Color {
+        let Geometry { width, height } = self.geometry;
+        if off(self.geometry, x, y) {
+            return Color::White;
+        }
+        let w = self.width;
+        if x < w || y < w || x >= width.saturating_sub(w) || y >= height.saturating_sub(w) {
+            self.color
+        } else {
+            Color::White
+        }
+    }
+}
+
+// a band per color running from black through it to white, with a grey
+// band on top, to be dithered
+pub fn ramps(colors: &[Color]) -> bmp::Image {
+    let mut img = layout::blank(Color::White);
+    let grey = Rgb {
+        r: 127.5,
+        g: 127.5,
+        b: 127.5,
+    };
+    let mut bands = vec![grey];
+    bands.extend(
+        (colors.iter())
+            .filter(|c| !matches!(c, Color::Black | Color::White | Color::Clean))
+            .map(|&c| Rgb::from(c)),
+    );
+    let h = SCREEN_HEIGHT as u32 / bands.len() as u32;
+    for (i, to) in bands.iter().enumerate() {
+        for x in 0..SCREEN_WIDTH as u32 {
+            let t = x as f32 / (SCREEN_WIDTH - 1) as f32 * 2.0;
+            // black to the color over the first half, then on to white
+            let px = match t {
+                t if t < 1.0 => to.map(|c| c * t),
+                t => {
+                    let t = t - 1.0;
+                    Rgb {
+                        r: to.r + (255.0 - to.r) * t,
+                        g: to.g + (255.0 - to.g) * t,
+                        b: to.b + (255.0 - to.b) * t,
+                    }
+                }
+            };
+            for y in i as u32 * h..(i as u32 + 1) * h {
+                img.set_pixel(x, y, px.into());
+            }
+        }
+    }
+    img
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::panel::{Bwr420, Panel};
+
+    #[test]
+    fn patterns_reach_the_edges_of_a_smaller_panel() {
+        let geometry = Bwr420.geometry();
+        let border = Border {
+            width: 4,
+            color: Color::Black,
+            geometry,
+        };
+        assert!(border.get_pixel(399, 150) == Color::Black);
+        assert!(border.get_pixel(396, 299) == Color::Black);
+        assert!(border.get_pixel(395, 150) == Color::White);
+        assert!(border.get_pixel(200, 295) == Color::White);
+        assert!(border.get_pixel(599, 447) == Color::White);
+        let hatch = Crosshatch {
+            spacing: 50,
+            color: Color::Black,
+            geometry,
+        };
+        // the heavy cross runs through the panel's middle
+        assert!(hatch.get_pixel(200, 1) == Color::Black);
+        assert!(hatch.get_pixel(1, 150) == Color::Black);
+        assert!(hatch.get_pixel(399, 1) == Color::Black);
+        assert!(hatch.get_pixel(225, 1) == Color::White);
+        let bars = Bars {
+            colors: vec![Color::Black, Color::White, Color::Red],
+            geometry,
+        };
+        assert!(bars.get_pixel(0, 0) == Color::Black);
+        assert!(bars.get_pixel(399, 299) == Color::Red);
+        assert!(bars.get_pixel(400, 0) == Color::White);
+    }
+}
@@ -0,0 +1,141 @@
+// what a message published to the mqtt topic can carry: a picture in any
+// format the image crate reads, as raw bytes or base64 text, a packed frame
+// as written by --save-frame, or a json draw command for automations that
+// have no picture to send:
+//
+//     {"text": "Front door open", "color": "red", "size": 5}
+//     {"fill": "blue"}
+//     {"clear": true}
+
+use serde_json::Value;
+
+use crate::{
+    decode,
+    draw::Color,
+    frame::{self, PackedFrame},
+    layout, preview,
+    scene::{Scene, Step, Widget},
+};
+
+// the scale text is set at without a size, shrunk if it doesn't fit
+const TEXT_SIZE: u16 = 4;
+
+// the picture a payload asks for
+pub fn picture(payload: &[u8]) -> Result<bmp::Image, String> {
+    // checked against its checksum, so a truncated message is refused
+    // rather than drawn
+    if payload.starts_with(frame::MAGIC) {
+        return PackedFrame::read_from(&mut &payload[..]).map(|f| preview::render(&f));
+    }
+    let text = std::str::from_utf8(payload).ok().map(str::trim);
+    if let Some(json) = text.filter(|t| t.starts_with('{')) {
+        let command = serde_json::from_str(json).map_err(|e| format!("invalid json: {e}"))?;
+        return draw_command(&command);
+    }
+    let img = match image::load_from_memory(payload) {
+        Ok(img) => img,
+        // a data: url or bare base64
+        Err(e) => match text.and_then(|t| base64(t.rsplit(',').next()?)) {
+            Some(bytes) => image::load_from_memory(&bytes)
+                .map_err(|e| format!("could not decode base64 picture: {e}"))?,
+            None => return Err(format!("could not decode picture: {e}")),
+        },
+    };
+    Ok(decode::from_rgb(&img.to_rgb8()))
+}
+
+fn color(command: &Value, key: &str) -> Result<Option<Color>, String> {
+    match command.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(s)) => match s.parse()? {
+            // it can't be drawn as a pixel
+            Color::Clean => Err("clean is not a color to draw with".into()),
+            c => Ok(Some(c)),
+        },
+        Some(v) => Err(format!("{key} should be a color name, got {v}")),
+    }
+}
+
+fn draw_command(command: &Value) -> Result<bmp::Image, String> {
+    if let Some(text) = command.get("text") {
+        let text = text.as_str().ok_or("text should be a string")?;
+        let scale = match command.get("size") {
+            None => TEXT_SIZE,
+            Some(v) => v
+                .as_u64()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| format!("size should be a whole number above 0, got {v}"))?
+                .min(u16::MAX as u64) as u16,
+        };
+        let scene = Scene {
+            widgets: vec![Widget {
+                text: text.to_string(),
+                scale,
+                priority: 0,
+                color: color(command, "color")?.unwrap_or(Color::Black),
+            }],
+            policy: vec![Step::Shrink],
+        };
+        return Ok(scene.render().pages.swap_remove(0));
+    }
+    if let Some(fill) = color(command, "fill")? {
+        return Ok(layout::blank(fill));
+    }
+    if command.get("clear").and_then(Value::as_bool) == Some(true) {
+        return Ok(layout::blank(Color::White));
+    }
+    Err("expected a draw command with text, fill or clear".into())
+}
+
+// standard base64, with or without padding. whitespace is skipped so
+// wrapped text decodes too.
+fn base64(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut bits, mut n) = (0u32, 0);
+    for c in text.bytes().filter(|c| !c.is_ascii_whitespace()) {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            _ => return None,
+        };
+        bits = bits << 6 | v as u32;
+        n += 6;
+        if n >= 8 {
+            n -= 8;
+            out.push((bits >> n) as u8);
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::draw::PaperImage;
+
+    fn packed() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        PackedFrame::pack(&PaperImage::new(Color::Red))
+            .write_to(&mut bytes)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn a_packed_frame_is_drawn_as_is() {
+        let img = picture(&packed()).unwrap();
+        let red: bmp::Pixel = crate::Rgb::from(Color::Red).into();
+        assert!(img.coordinates().all(|(x, y)| img.get_pixel(x, y) == red));
+    }
+
+    #[test]
+    fn a_truncated_packed_frame_is_refused() {
+        let bytes = packed();
+        let e = picture(&bytes[..bytes.len() - 1]).unwrap_err();
+        assert!(e.contains("truncated"), "{e}");
+    }
+}
@@ -0,0 +1,416 @@
+// preprocessing applied to a picture before it is fitted and dithered, as an
+// ordered list of stages. a pipeline is written as its stages separated by
+// spaces, e.g. `crop=0,0,800,600 resize=fill saturation=1.3`, and prints
+// back the same way, so it can be saved and reused as a named look.
+
+use std::{
+    env, fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use image::{imageops, imageops::FilterType, RgbImage};
+
+use crate::{
+    decode,
+    draw::{Color, Rotation},
+    layout, roi,
+};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum WhiteBalance {
+    // gray world: scales each channel so the picture averages out to gray
+    Auto,
+    // red, green and blue gains
+    Gains(f32, f32, f32),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Stage {
+    // to the panel size by the given mode, instead of by --fit at the end
+    Resize(layout::Scale),
+    // keeps a rectangle of the source, clipped to it
+    Crop(layout::Rect),
+    // clockwise
+    Rotate(Rotation),
+    WhiteBalance(WhiteBalance),
+    // 0 is grayscale, 1 leaves it, more pushes colors apart
+    Saturation(f32),
+    // around mid gray, 1 leaves it
+    Contrast(f32),
+    // scales every channel, 1 leaves it
+    Brightness(f32),
+    // unsharp mask with this blur radius in px
+    Sharpen(f32),
+    // above 1 brightens the mid tones, below darkens them
+    Gamma(f32),
+}
+
+fn factor(stage: &str, value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(v) if v >= 0.0 && v.is_finite() => Ok(v),
+        _ => Err(format!(
+            "{stage} expects a number of 0 or more, got '{value}'"
+        )),
+    }
+}
+
+impl FromStr for Stage {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("invalid stage '{s}', expected name=value"))?;
+        Ok(match name {
+            "resize" => Stage::Resize(value.parse()?),
+            "crop" => Stage::Crop(roi::parse_rect(value)?),
+            "rotate" => Stage::Rotate(value.parse()?),
+            "wb" | "white-balance" => Stage::WhiteBalance(match value {
+                "auto" => WhiteBalance::Auto,
+                _ => match value.split(',').collect::<Vec<_>>()[..] {
+                    [r, g, b] => WhiteBalance::Gains(
+                        factor(name, r)?,
+                        factor(name, g)?,
+                        factor(name, b)?,
+                    ),
+                    _ => return Err(format!("{name} expects auto or r,g,b gains")),
+                },
+            }),
+            "saturation" => Stage::Saturation(factor(name, value)?),
+            "contrast" => Stage::Contrast(factor(name, value)?),
+            "brightness" => Stage::Brightness(factor(name, value)?),
+            "sharpen" => Stage::Sharpen(factor(name, value)?),
+            "gamma" => match factor(name, value)? {
+                0.0 => return Err("gamma can't be 0".into()),
+                g => Stage::Gamma(g),
+            },
+            _ => {
+                return Err(format!(
+                    "unknown stage '{name}' (expected resize, crop, rotate, wb, saturation, contrast, brightness, sharpen or gamma)"
+                ))
+            }
+        })
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stage::Resize(scale) => write!(f, "resize={}", scale.name()),
+            Stage::Crop(r) => write!(f, "crop={},{},{},{}", r.x, r.y, r.w, r.h),
+            Stage::Rotate(r) => write!(f, "rotate={r}"),
+            Stage::WhiteBalance(WhiteBalance::Auto) => write!(f, "wb=auto"),
+            Stage::WhiteBalance(WhiteBalance::Gains(r, g, b)) => write!(f, "wb={r},{g},{b}"),
+            Stage::Saturation(v) => write!(f, "saturation={v}"),
+            Stage::Contrast(v) => write!(f, "contrast={v}"),
+            Stage::Brightness(v) => write!(f, "brightness={v}"),
+            Stage::Sharpen(v) => write!(f, "sharpen={v}"),
+            Stage::Gamma(v) => write!(f, "gamma={v}"),
+        }
+    }
+}
+
+// every channel of every pixel through `f`
+fn map_channels(img: &mut RgbImage, f: impl Fn(f32) -> f32) {
+    for px in img.pixels_mut() {
+        for c in px.0.iter_mut() {
+            *c = f(*c as f32).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+impl Stage {
+    fn apply(&self, mut img: RgbImage, filter: FilterType, letterbox: Color) -> RgbImage {
+        match *self {
+            Stage::Resize(scale) => {
+                let resized = layout::resize(&decode::from_rgb(&img), scale, filter, letterbox);
+                return decode::to_rgb(&resized);
+            }
+            Stage::Crop(r) => {
+                let x = r.x.min(img.width());
+                let y = r.y.min(img.height());
+                let w = r.w.min(img.width() - x);
+                let h = r.h.min(img.height() - y);
+                return imageops::crop_imm(&img, x, y, w, h).to_image();
+            }
+            Stage::Rotate(Rotation::R90) => return imageops::rotate90(&img),
+            Stage::Rotate(Rotation::R180) => return imageops::rotate180(&img),
+            Stage::Rotate(Rotation::R270) => return imageops::rotate270(&img),
+            Stage::WhiteBalance(wb) => {
+                let gains = match wb {
+                    WhiteBalance::Gains(r, g, b) => [r, g, b],
+                    WhiteBalance::Auto => {
+                        let mut sum = [0.0f64; 3];
+                        for px in img.pixels() {
+                            for (s, c) in sum.iter_mut().zip(px.0) {
+                                *s += c as f64;
+                            }
+                        }
+                        let gray = sum.iter().sum::<f64>() / 3.0;
+                        sum.map(|s| if s > 0.0 { (gray / s) as f32 } else { 1.0 })
+                    }
+                };
+                for px in img.pixels_mut() {
+                    for (c, gain) in px.0.iter_mut().zip(gains) {
+                        *c = (*c as f32 * gain).round().clamp(0.0, 255.0) as u8;
+                    }
+                }
+            }
+            Stage::Saturation(s) => {
+                for px in img.pixels_mut() {
+                    let [r, g, b] = px.0.map(|c| c as f32);
+                    let luma = 0.299 * r + 0.587 * g + 0.114 * b;
+                    px.0 =
+                        [r, g, b].map(|c| (luma + (c - luma) * s).round().clamp(0.0, 255.0) as u8);
+                }
+            }
+            Stage::Contrast(c) => map_channels(&mut img, |v| (v - 127.5) * c + 127.5),
+            Stage::Brightness(b) => map_channels(&mut img, |v| v * b),
+            Stage::Sharpen(radius) if radius > 0.0 => return imageops::unsharpen(&img, radius, 0),
+            Stage::Sharpen(_) => {}
+            Stage::Gamma(g) => map_channels(&mut img, |v| 255.0 * (v / 255.0).powf(1.0 / g)),
+        }
+        img
+    }
+}
+
+#[derive(Clone, Default, PartialEq)]
+pub struct Pipeline {
+    pub stages: Vec<Stage>,
+}
+
+impl Pipeline {
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    // with a turn for a panel mounted on its side. it goes after cropping,
+    // which is in upright coordinates, but before resizing so a portrait
+    // picture fills the panel.
+    pub fn rotated(&self, rotation: Rotation) -> Self {
+        let mut stages = self.stages.clone();
+        let at = stages
+            .iter()
+            .position(|s| matches!(s, Stage::Resize(_)))
+            .unwrap_or(stages.len());
+        stages.insert(at, Stage::Rotate(rotation));
+        Self { stages }
+    }
+
+    // runs the stages in order. `filter` and `letterbox` are for resize.
+    pub fn apply(&self, img: &bmp::Image, filter: FilterType, letterbox: Color) -> bmp::Image {
+        if self.is_empty() {
+            return img.clone();
+        }
+        let rgb = self.stages.iter().fold(decode::to_rgb(img), |rgb, stage| {
+            stage.apply(rgb, filter, letterbox)
+        });
+        decode::from_rgb(&rgb)
+    }
+}
+
+impl FromStr for Pipeline {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self {
+            stages: s
+                .split_whitespace()
+                .map(str::parse)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let stages: Vec<String> = self.stages.iter().map(Stage::to_string).collect();
+        write!(f, "{}", stages.join(" "))
+    }
+}
+
+// looks that come with the driver, overridden by a looks file entry of the
+// same name
+pub fn builtin(name: &str) -> Option<Pipeline> {
+    let spec = match name {
+        // livelier than the panel shows it by default
+        "photo" => "saturation=1.3 contrast=1.1 sharpen=0.8",
+        // black text on a white page, without the paper's tint
+        "document" => "wb=auto saturation=0 contrast=1.6 sharpen=1",
+        // few flat colors that should stay apart
+        "map" => "saturation=1.5 contrast=1.2",
+        _ => return None,
+    };
+    spec.parse().ok()
+}
+
+// default location of the saved looks
+pub fn default_path() -> PathBuf {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+        .unwrap_or_else(|| PathBuf::from("."));
+    base.join("rpi-epaper").join("looks")
+}
+
+// one look per line: its name followed by its stages. blank lines and lines
+// starting with # are ignored.
+pub fn load(path: &Path) -> Result<Vec<(String, Pipeline)>, String> {
+    let text =
+        fs::read_to_string(path).map_err(|e| format!("could not read {}: {e}", path.display()))?;
+    let mut looks = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (name, spec) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let pipeline = spec
+            .parse()
+            .map_err(|e| format!("look '{name}' in {}: {e}", path.display()))?;
+        looks.push((name.to_string(), pipeline));
+    }
+    Ok(looks)
+}
+
+// the look called `name`, from the file at `path` if there is one, else
+// built in
+pub fn look(name: &str, path: &Path) -> Result<Pipeline, String> {
+    if path.exists() {
+        if let Some((_, p)) = load(path)?.into_iter().find(|(n, _)| n == name) {
+            return Ok(p);
+        }
+    }
+    builtin(name).ok_or_else(|| {
+        format!(
+            "no look called '{name}' in {} (built in: photo, document, map)",
+            path.display()
+        )
+    })
+}
+
+// adds a look to the file, replacing one of the same name. comments in the
+// file are not kept.
+pub fn save(name: &str, pipeline: &Pipeline, path: &Path) -> Result<(), String> {
+    let mut looks = if path.exists() {
+        load(path)?
+    } else {
+        Vec::new()
+    };
+    looks.retain(|(n, _)| n != name);
+    looks.push((name.to_string(), pipeline.clone()));
+    let text: String = looks.iter().map(|(n, p)| format!("{n} {p}\n")).collect();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("could not create {}: {e}", dir.display()))?;
+    }
+    fs::write(path, text).map_err(|e| format!("could not write {}: {e}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotating_goes_after_the_crop_and_before_the_resize() {
+        let p: Pipeline = "crop=0,0,10,10 resize=fill gamma=2".parse().unwrap();
+        assert_eq!(
+            p.rotated(Rotation::R90).to_string(),
+            "crop=0,0,10,10 rotate=90 resize=fill gamma=2"
+        );
+        let p: Pipeline = "saturation=2".parse().unwrap();
+        assert_eq!(
+            p.rotated(Rotation::R270).to_string(),
+            "saturation=2 rotate=270"
+        );
+    }
+
+    #[test]
+    fn a_portrait_picture_turns_to_fill_the_panel() {
+        let mut img = bmp::Image::new(448, 600);
+        for (x, y) in img.coordinates() {
+            img.set_pixel(x, y, bmp::consts::WHITE);
+        }
+        img.set_pixel(0, 0, bmp::consts::RED);
+        img.set_pixel(447, 599, bmp::consts::BLUE);
+        for (rotation, red, blue) in [
+            (Rotation::R90, (599, 0), (0, 447)),
+            (Rotation::R270, (0, 447), (599, 0)),
+        ] {
+            let turned = Pipeline::default().rotated(rotation).apply(
+                &img,
+                FilterType::Nearest,
+                Color::White,
+            );
+            assert_eq!((turned.get_width(), turned.get_height()), (600, 448));
+            assert_eq!(turned.get_pixel(red.0, red.1), bmp::consts::RED);
+            assert_eq!(turned.get_pixel(blue.0, blue.1), bmp::consts::BLUE);
+        }
+    }
+
+    #[test]
+    fn pipelines_print_back_the_way_they_were_written() {
+        let spec = "crop=0,0,800,600 resize=fill rotate=180 wb=auto wb=1,0.9,1.2 \
+                    saturation=1.3 contrast=1.1 brightness=0.9 sharpen=0.8 gamma=2.2";
+        let p: Pipeline = spec.parse().unwrap();
+        assert_eq!(p.stages.len(), 10);
+        assert_eq!(
+            p.to_string(),
+            spec.split_whitespace().collect::<Vec<_>>().join(" ")
+        );
+        assert!(
+            "white-balance=auto".parse::<Pipeline>().unwrap().stages
+                == [Stage::WhiteBalance(WhiteBalance::Auto)]
+        );
+        assert!("".parse::<Pipeline>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn bad_stages_are_refused() {
+        for (spec, error) in [
+            ("saturation", "expected name=value"),
+            ("blur=2", "unknown stage 'blur'"),
+            ("contrast=-1", "0 or more"),
+            ("brightness=nan", "0 or more"),
+            ("gamma=0", "can't be 0"),
+            ("wb=1,2", "auto or r,g,b"),
+            ("photo saturation=1", "expected name=value"),
+        ] {
+            let e = spec.parse::<Pipeline>().err().unwrap();
+            assert!(e.contains(error), "{spec}: {e}");
+        }
+    }
+
+    #[test]
+    fn crops_are_clipped_to_the_picture() {
+        let img = bmp::Image::new(20, 10);
+        let cropped = "crop=15,5,100,100".parse::<Pipeline>().unwrap().apply(
+            &img,
+            FilterType::Nearest,
+            Color::White,
+        );
+        assert_eq!((cropped.get_width(), cropped.get_height()), (5, 5));
+    }
+
+    #[test]
+    fn saved_looks_override_the_built_in_ones() {
+        let path = env::temp_dir()
+            .join(format!("rpi-epaper-looks-{}", std::process::id()))
+            .join("looks");
+        let _ = fs::remove_file(&path);
+        assert!(look("photo", &path).unwrap() == builtin("photo").unwrap());
+        assert!(look("mine", &path)
+            .err()
+            .unwrap()
+            .contains("no look called 'mine'"));
+
+        save("mine", &"gamma=2".parse().unwrap(), &path).unwrap();
+        save("photo", &"saturation=0".parse().unwrap(), &path).unwrap();
+        save("mine", &"gamma=1.5".parse().unwrap(), &path).unwrap();
+        assert_eq!(look("mine", &path).unwrap().to_string(), "gamma=1.5");
+        assert_eq!(look("photo", &path).unwrap().to_string(), "saturation=0");
+        assert!(look("map", &path).unwrap() == builtin("map").unwrap());
+        assert_eq!(load(&path).unwrap().len(), 2);
+
+        fs::write(&path, "# mine\n\nplain\nbroken blur=1\n").unwrap();
+        assert!(load(&path).err().unwrap().contains("look 'broken'"));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
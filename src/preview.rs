@@ -0,0 +1,126 @@
+use std::{path::Path, str::FromStr};
+
+use crate::{decode, draw::Drawable, Rgb, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// expands a drawable to palette rgb, i.e. what the panel would show
+pub fn render(d: &(impl Drawable + ?Sized)) -> bmp::Image {
+    let mut img = bmp::Image::new(SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+    for (x, y) in img.coordinates() {
+        img.set_pixel(x, y, Rgb::from(d.get_pixel(x as u16, y as u16)).into());
+    }
+    img
+}
+
+// writes a rendered frame as bmp, or as png, jpeg, ... going by the
+// extension
+pub fn save(img: &bmp::Image, path: &str) -> Result<(), String> {
+    let bmp = Path::new(path)
+        .extension()
+        .is_none_or(|e| e.eq_ignore_ascii_case("bmp"));
+    if bmp {
+        img.save(path).map_err(|e| e.to_string())
+    } else {
+        decode::to_rgb(img).save(path).map_err(|e| e.to_string())
+    }
+    .map_err(|e| format!("could not write {path}: {e}"))
+}
+
+// box-filtered downscale of what the panel shows, for status updates
+pub fn thumbnail(d: &(impl Drawable + ?Sized), factor: u32) -> image::RgbImage {
+    let (w, h) = (SCREEN_WIDTH as u32 / factor, SCREEN_HEIGHT as u32 / factor);
+    image::RgbImage::from_fn(w, h, |tx, ty| {
+        let mut sum = Rgb {
+            r: 0.0,
+            g: 0.0,
+            b: 0.0,
+        };
+        for y in ty * factor..(ty + 1) * factor {
+            for x in tx * factor..(tx + 1) * factor {
+                sum += Rgb::from(d.get_pixel(x as u16, y as u16));
+            }
+        }
+        let n = (factor * factor) as f32;
+        image::Rgb([
+            (sum.r / n).round() as u8,
+            (sum.g / n).round() as u8,
+            (sum.b / n).round() as u8,
+        ])
+    })
+}
+
+#[derive(Clone, Copy)]
+pub enum ColorBlindness {
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+}
+
+impl FromStr for ColorBlindness {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "protanopia" => Ok(ColorBlindness::Protanopia),
+            "deuteranopia" => Ok(ColorBlindness::Deuteranopia),
+            "tritanopia" => Ok(ColorBlindness::Tritanopia),
+            _ => Err(format!(
+                "unknown simulation '{s}' (expected protanopia, deuteranopia or tritanopia)"
+            )),
+        }
+    }
+}
+
+impl ColorBlindness {
+    // machado et al. 2009, full severity, applied in linear rgb
+    fn matrix(&self) -> [[f32; 3]; 3] {
+        match self {
+            ColorBlindness::Protanopia => [
+                [0.152286, 1.052583, -0.204868],
+                [0.114503, 0.786281, 0.099216],
+                [-0.003882, -0.048116, 1.051998],
+            ],
+            ColorBlindness::Deuteranopia => [
+                [0.367322, 0.860646, -0.227968],
+                [0.280085, 0.672501, 0.047413],
+                [-0.011820, 0.042940, 0.968881],
+            ],
+            ColorBlindness::Tritanopia => [
+                [1.255528, -0.076749, -0.178779],
+                [-0.078411, 0.930809, 0.147602],
+                [0.004733, 0.691367, 0.303900],
+            ],
+        }
+    }
+}
+
+fn to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn to_srgb(c: f32) -> u8 {
+    let c = c.clamp(0.0, 1.0);
+    let c = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (c * 255.0).round() as u8
+}
+
+// approximates how the image looks to a viewer with the given deficiency
+pub fn simulate(img: &mut bmp::Image, kind: ColorBlindness) {
+    let m = kind.matrix();
+    for (x, y) in img.coordinates() {
+        let px = img.get_pixel(x, y);
+        let lin = [to_linear(px.r), to_linear(px.g), to_linear(px.b)];
+        let out: Vec<u8> = m
+            .iter()
+            .map(|row| to_srgb(row[0] * lin[0] + row[1] * lin[1] + row[2] * lin[2]))
+            .collect();
+        img.set_pixel(x, y, bmp::Pixel::new(out[0], out[1], out[2]));
+    }
+}
@@ -0,0 +1,78 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+// palette and timing to use up to a given ambient temperature
+pub struct Profile {
+    pub name: String,
+    // highest temperature in C this profile covers, None for no limit
+    pub max_temp: Option<f32>,
+    pub palette: Option<PathBuf>,
+    pub cooldown: Option<Duration>,
+}
+
+// one profile per line: `name max_c palette cooldown_ms`, coldest first.
+// `-` leaves a field unset (no upper limit, default palette or cooldown).
+// relative palette paths are resolved against the profiles file. blank
+// lines and lines starting with # are ignored.
+pub fn load(path: &Path) -> Result<Vec<Profile>, String> {
+    let text =
+        fs::read_to_string(path).map_err(|e| format!("could not read {}: {e}", path.display()))?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut profiles = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let [name, max_temp, palette, cooldown] = parts[..] else {
+            return Err(format!(
+                "invalid profile '{line}', expected: name max_c palette cooldown_ms"
+            ));
+        };
+        let field = |s: &str| (s != "-").then_some(s.to_string());
+        profiles.push(Profile {
+            name: name.to_string(),
+            max_temp: field(max_temp)
+                .map(|t| t.parse())
+                .transpose()
+                .map_err(|e| format!("invalid temperature in '{line}': {e}"))?,
+            palette: field(palette).map(|p| dir.join(p)),
+            cooldown: field(cooldown)
+                .map(|ms| ms.parse().map(Duration::from_millis))
+                .transpose()
+                .map_err(|e| format!("invalid cooldown in '{line}': {e}"))?,
+        });
+    }
+    if profiles.is_empty() {
+        return Err(format!("{} has no profiles", path.display()));
+    }
+    Ok(profiles)
+}
+
+// the first profile whose range covers `temp`, or the last one
+pub fn select(profiles: &[Profile], temp: f32) -> &Profile {
+    profiles
+        .iter()
+        .find(|p| p.max_temp.is_none_or(|max| temp <= max))
+        .unwrap_or(&profiles[profiles.len() - 1])
+}
+
+// reads a temperature in C from a sensor file. handles plain degrees,
+// sysfs style millidegrees and 1-wire `t=23125` readings.
+pub fn read_temperature(path: &Path) -> Result<f32, String> {
+    let text =
+        fs::read_to_string(path).map_err(|e| format!("could not read {}: {e}", path.display()))?;
+    let value = match text.rfind("t=") {
+        Some(i) => &text[i + 2..],
+        None => &text,
+    };
+    let n: f32 = value
+        .trim()
+        .parse()
+        .map_err(|_| format!("{} doesn't hold a temperature", path.display()))?;
+    // nothing on earth is room temperature above 200C
+    Ok(if n.abs() > 200.0 { n / 1000.0 } else { n })
+}
@@ -0,0 +1,74 @@
+// a qr code over another frame, for a link or wi-fi credentials a phone
+// can pick up from a kiosk. light modules and the quiet zone around the
+// code are drawn white whatever is under them, so it scans on any
+// background.
+
+use qrcodegen::QrCodeEcc;
+
+use crate::{
+    draw::{Color, Drawable},
+    layout::Rect,
+};
+
+// modules of light margin a reader needs around the code
+const QUIET_ZONE: u16 = 4;
+
+pub struct QrCode<'a, D: Drawable + ?Sized> {
+    code: qrcodegen::QrCode,
+    // top-left of the quiet zone
+    pub x: u16,
+    pub y: u16,
+    // pixels to a module
+    pub module: u16,
+    pub color: Color,
+    pub rest: &'a D,
+}
+
+impl<'a, D: Drawable + ?Sized> QrCode<'a, D> {
+    // `text` in `color` over `rest`, a pixel to a module in the top-left
+    // corner until placed otherwise
+    pub fn new(text: &str, color: Color, rest: &'a D) -> Result<Self, String> {
+        let code = qrcodegen::QrCode::encode_text(text, QrCodeEcc::Medium)
+            .map_err(|_| format!("{} bytes is too long for a qr code", text.len()))?;
+        Ok(Self {
+            code,
+            x: 0,
+            y: 0,
+            module: 1,
+            color,
+            rest,
+        })
+    }
+
+    // modules across, the quiet zone included
+    pub fn modules(&self) -> u16 {
+        self.code.size() as u16 + 2 * QUIET_ZONE
+    }
+
+    pub fn bounds(&self) -> Rect {
+        let side = self.modules() as u32 * self.module as u32;
+        Rect {
+            x: self.x as u32,
+            y: self.y as u32,
+            w: side,
+            h: side,
+        }
+    }
+}
+
+impl<D: Drawable + ?Sized> Drawable for QrCode<'_, D> {
+    fn get_pixel(&self, x: u16, y: u16) -> Color {
+        let b = self.bounds();
+        let (px, py) = (x as u32, y as u32);
+        if px < b.x || py < b.y || px >= b.x + b.w || py >= b.y + b.h {
+            return self.rest.get_pixel(x, y);
+        }
+        let module =
+            |p: u32, from: u32| ((p - from) / self.module as u32) as i32 - QUIET_ZONE as i32;
+        if self.code.get_module(module(px, b.x), module(py, b.y)) {
+            self.color
+        } else {
+            Color::White
+        }
+    }
+}
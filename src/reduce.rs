@@ -0,0 +1,77 @@
+use crate::{draw::Color, Rgb};
+
+// median cut: repeatedly splits the box of pixels with the widest channel
+// range at its median, until there are `n` boxes. returns each box's mean.
+pub fn median_cut(img: &bmp::Image, n: usize) -> Vec<Rgb> {
+    let pixels: Vec<[u8; 3]> = img
+        .coordinates()
+        .map(|(x, y)| {
+            let px = img.get_pixel(x, y);
+            [px.r, px.g, px.b]
+        })
+        .collect();
+    // widest channel of a box and its range
+    fn widest(pixels: &[[u8; 3]]) -> (usize, u8) {
+        (0..3)
+            .map(|c| {
+                let min = pixels.iter().map(|p| p[c]).min().unwrap_or(0);
+                let max = pixels.iter().map(|p| p[c]).max().unwrap_or(0);
+                (c, max - min)
+            })
+            .max_by_key(|&(_, range)| range)
+            .unwrap()
+    }
+    let mut boxes = vec![pixels];
+    while boxes.len() < n {
+        let Some((i, channel)) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .map(|(i, b)| (i, widest(b)))
+            .filter(|(_, (_, range))| *range > 0)
+            .max_by_key(|(_, (_, range))| *range)
+            .map(|(i, (c, _))| (i, c))
+        else {
+            // every box is a single color already
+            break;
+        };
+        let mut b = boxes.swap_remove(i);
+        b.sort_unstable_by_key(|p| p[channel]);
+        let upper = b.split_off(b.len() / 2);
+        boxes.push(b);
+        boxes.push(upper);
+    }
+    boxes
+        .iter()
+        .filter(|b| !b.is_empty())
+        .map(|b| {
+            let sum = b.iter().fold([0u64; 3], |acc, p| {
+                [
+                    acc[0] + p[0] as u64,
+                    acc[1] + p[1] as u64,
+                    acc[2] + p[2] as u64,
+                ]
+            });
+            let n = b.len() as f32;
+            Rgb {
+                r: sum[0] as f32 / n,
+                g: sum[1] as f32 / n,
+                b: sum[2] as f32 / n,
+            }
+        })
+        .collect()
+}
+
+// the ones of `shown` an image needs when reduced to `n` representative
+// colors. dithering between only these keeps stray confetti out of flat
+// graphics.
+pub fn panel_subset(img: &bmp::Image, n: usize, shown: &[Color]) -> Vec<Color> {
+    let mut colors: Vec<Color> = Vec::new();
+    for rgb in median_cut(img, n) {
+        let c = Color::closest_in(rgb, shown);
+        if !colors.iter().any(|&have| have as u8 == c as u8) {
+            colors.push(c);
+        }
+    }
+    colors
+}
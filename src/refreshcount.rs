@@ -0,0 +1,80 @@
+// how many refreshes the panel has been through, kept on disk across runs
+// so the occasional clear to shake off ghosting comes every so many
+// refreshes however often the program is started:
+//
+//     1042 17
+//
+// is 1042 refreshes in all, 17 since the last clear.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{lastframe, write_atomic};
+
+pub fn default_path() -> PathBuf {
+    lastframe::state_dir().join("refreshes")
+}
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct Count {
+    pub total: u64,
+    pub since_clear: u64,
+}
+
+#[derive(Clone)]
+pub struct RefreshCount {
+    path: PathBuf,
+}
+
+impl RefreshCount {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // nothing yet when the file is missing
+    pub fn read(&self) -> Result<Count, String> {
+        let text = match fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Count::default()),
+            Err(e) => return Err(format!("could not read {}: {e}", self.path.display())),
+        };
+        let mut words = text.split_whitespace().map(|w| w.parse::<u64>());
+        match (words.next(), words.next(), words.next()) {
+            (Some(Ok(total)), Some(Ok(since_clear)), None) => Ok(Count { total, since_clear }),
+            _ => Err(format!(
+                "{} should hold two counts, got '{}'",
+                self.path.display(),
+                text.trim()
+            )),
+        }
+    }
+
+    pub fn write(&self, count: Count) -> Result<(), String> {
+        let text = format!("{} {}\n", count.total, count.since_clear);
+        write_atomic(&self.path, text.as_bytes())
+    }
+
+    // one more refresh, and the count after it
+    pub fn bump(&self) -> Result<Count, String> {
+        let mut count = self.read()?;
+        count.total += 1;
+        count.since_clear += 1;
+        self.write(count)?;
+        Ok(count)
+    }
+
+    // the panel was just cleared
+    pub fn cleared(&self) -> Result<(), String> {
+        let count = self.read()?;
+        self.write(Count {
+            since_clear: 0,
+            ..count
+        })
+    }
+}
@@ -0,0 +1,522 @@
+// builds frames from the options, without the panel: loading and fitting
+// pictures, the overlays, dithering, and the frames of the modes that draw
+// once
+
+use std::{error::Error, fs, io, time::Instant};
+
+use serde_json::json;
+#[cfg(feature = "light")]
+use tracing::warn;
+use tracing::{info, info_span};
+
+#[cfg(feature = "battery")]
+use crate::battery;
+#[cfg(feature = "light")]
+use crate::light;
+#[cfg(feature = "qr")]
+use crate::qr;
+#[cfg(feature = "ttf")]
+use crate::text;
+use crate::{
+    annotate,
+    app::{Art, Mode, Options, Pattern, Touchup},
+    ascii, calibrate, decode,
+    draw::{self, Color, Drawable, PaperImage},
+    events, frame, layout, lut, overlay, pages, pattern, preview, quantize, reduce, roi, scene,
+    splash, store, term, Geometry, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+pub fn load_bmp(path: &str) -> Result<bmp::Image, Box<dyn Error>> {
+    bmp::open(path).map_err(|e| format!("could not load {path}: {e}").into())
+}
+
+// any picture the image crate can read. `@name` is looked up in the image
+// directory.
+pub fn load_image(arg: &str, opts: &Options) -> Result<bmp::Image, Box<dyn Error>> {
+    let path = store::Store::new(&opts.images).resolve(arg)?;
+    if !path.is_file() {
+        return Err(format!("{} does not exist or is not a file", path.display()).into());
+    }
+    Ok(decode::open(&path)?)
+}
+
+pub fn source_image(mode: Option<&Mode>, opts: &Options) -> Result<bmp::Image, Box<dyn Error>> {
+    let mut image_bmp: &'static [u8] = include_bytes!("image.bmp");
+
+    let img = match mode {
+        Some(Mode::Show { image }) => load_image(image, opts)?,
+        // only the first page of a paginated scene
+        Some(Mode::Scene { file }) => render_scene(file)?.pages.swap_remove(0),
+        Some(Mode::Split) => {
+            if opts.panes.is_empty() {
+                return Err(
+                    "split expects at least one of --left/--right/--top/--bottom/--tl/--tr/--bl/--br"
+                        .into(),
+                );
+            }
+            let mut panes = Vec::new();
+            for (region, path) in &opts.panes {
+                panes.push((*region, load_bmp(path)?));
+            }
+            layout::split(&panes, Color::White)
+        }
+        _ => bmp::from_reader(&mut image_bmp)?,
+    };
+    Ok(fit_screen(img, opts))
+}
+
+// scales images of any other size to the panel with --fit, after running
+// them through the --pipeline stages
+pub fn fit_screen(img: bmp::Image, opts: &Options) -> bmp::Image {
+    let pipeline = match opts.rotate {
+        Some(r) => opts.pipeline.rotated(r),
+        None => opts.pipeline.clone(),
+    };
+    let img = if pipeline.is_empty() {
+        img
+    } else {
+        pipeline.apply(&img, opts.filter, opts.letterbox)
+    };
+    if (img.get_width(), img.get_height()) == (SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32) {
+        return img;
+    }
+    layout::resize(&img, opts.scale, opts.filter, opts.letterbox)
+}
+
+// applies the framing and overlay options to a frame before dithering
+pub fn decorate(mut img: bmp::Image, opts: &Options) -> Result<bmp::Image, Box<dyn Error>> {
+    let inner_margin = opts.margin + opts.border.map_or(0, |_| opts.border_width);
+    if inner_margin > 0 {
+        img = overlay::inset(&img, inner_margin, Color::White);
+    }
+    if let Some(color) = opts.border {
+        overlay::draw_border(&mut img, opts.margin, opts.border_width, color);
+    }
+    if let Some(path) = &opts.pip {
+        layout::picture_in_picture(&mut img, &load_bmp(path)?, opts.pip_corner, opts.pip_scale);
+    }
+    if let Some(corner) = opts.timestamp {
+        overlay::stamp_timestamp(&mut img, corner, &opts.clock);
+    }
+    #[cfg(feature = "battery")]
+    if let Some(gauge) = opts.battery {
+        let reading = gauge.read()?;
+        info!(
+            "Battery at {:.2}V ({:.0}%)",
+            reading.volts,
+            reading.charge * 100.0
+        );
+        // a refresh near brownout can leave the panel half driven
+        if reading.volts < opts.battery_critical {
+            return Err(format!(
+                "battery at {:.2}V is below {:.2}V, refusing to refresh",
+                reading.volts, opts.battery_critical
+            )
+            .into());
+        }
+        battery::draw_glyph(&mut img, &reading);
+    }
+    Ok(img)
+}
+
+// the layout decorate and the text modes produce, for --debug-layout
+pub fn annotations(
+    mode: Option<&Mode>,
+    opts: &Options,
+) -> Result<annotate::Annotations, Box<dyn Error>> {
+    let (w, h) = (SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+    // what decorate draws on, before dither shrinks it to the panel
+    let screen = Geometry::default();
+    let mut notes = annotate::Annotations {
+        grid: 50,
+        ..Default::default()
+    };
+    let inner_margin = opts.margin + opts.border.map_or(0, |_| opts.border_width);
+    if let Some(Mode::Split) = mode {
+        // shrunk along with the rest of the source by the inset
+        let (iw, ih) = (w - inner_margin * 2, h - inner_margin * 2);
+        notes.boxes.extend(opts.panes.iter().map(|(region, _)| {
+            let r = region.rect();
+            layout::Rect {
+                x: inner_margin + r.x * iw / w,
+                y: inner_margin + r.y * ih / h,
+                w: r.w * iw / w,
+                h: r.h * ih / h,
+            }
+        }));
+    }
+    if inner_margin > 0 {
+        notes.boxes.push(layout::Rect {
+            x: inner_margin,
+            y: inner_margin,
+            w: w.saturating_sub(inner_margin * 2),
+            h: h.saturating_sub(inner_margin * 2),
+        });
+    }
+    if let Some(path) = &opts.pip {
+        let src = load_bmp(path)?;
+        let size = (src.get_width(), src.get_height());
+        notes.boxes.extend(layout::pip_rect(
+            size,
+            screen,
+            opts.pip_corner,
+            opts.pip_scale,
+        ));
+    }
+    if let Some(corner) = opts.timestamp {
+        let text = overlay::timestamp_text(&opts.clock);
+        notes.boxes.push(overlay::label_rect(&text, screen, corner));
+    }
+    notes.boxes.extend(opts.roi.iter().copied());
+    #[cfg(feature = "ttf")]
+    if let Some(Mode::Text { words }) = mode {
+        notes.boxes.push(text_frame(words, opts)?.bounds());
+    }
+    #[cfg(feature = "qr")]
+    if let Some(Mode::Qr {
+        words,
+        module,
+        at,
+        color,
+    }) = mode
+    {
+        notes
+            .boxes
+            .push(qr_frame(words, *module, *at, *color, opts.panel.geometry)?.bounds());
+    }
+    let baselines = match mode {
+        Some(Mode::Pages { .. }) => pages::baselines(opts.text_scale),
+        Some(Mode::Term { .. }) => term::Terminal::baselines(opts.text_scale),
+        _ => Vec::new(),
+    };
+    notes
+        .baselines
+        .extend(baselines.into_iter().map(|y| (y, 0, w)));
+    notes.flip(opts.flip_h, opts.flip_v);
+    Ok(notes)
+}
+
+// lays out the scene file and reports what it had to give up to fit
+pub fn render_scene(path: &str) -> Result<scene::Rendered, Box<dyn Error>> {
+    let rendered = scene::load(path)?.render();
+    for d in &rendered.degradations {
+        info!("Scene {d}");
+    }
+    let degradations: Vec<String> = rendered
+        .degradations
+        .iter()
+        .map(|d| d.to_string())
+        .collect();
+    events::log(
+        "scene_rendered",
+        json!({ "pages": rendered.pages.len(), "degradations": degradations }),
+    );
+    Ok(rendered)
+}
+
+#[cfg(feature = "ttf")]
+pub const TEXT_MARGIN: u32 = 20;
+
+// the words set in --font, wrapped to the screen. a literal \n starts a new
+// line.
+#[cfg(feature = "ttf")]
+fn text_frame(
+    words: &[String],
+    opts: &Options,
+) -> Result<text::Text<'static, draw::SolidColor>, Box<dyn Error>> {
+    let font = text::Font::open(opts.font.as_ref().ok_or("text needs --font")?)?;
+    Ok(text::Text::new(
+        &font,
+        &words.join(" ").replace("\\n", "\n"),
+        (TEXT_MARGIN, TEXT_MARGIN),
+        &opts.text_style,
+        &draw::SolidColor(Color::White),
+    ))
+}
+
+// the words as a qr code on white
+#[cfg(feature = "qr")]
+fn qr_frame(
+    words: &[String],
+    module: Option<u16>,
+    at: Option<(u16, u16)>,
+    color: Color,
+    area: Geometry,
+) -> Result<qr::QrCode<'static, draw::SolidColor>, Box<dyn Error>> {
+    let mut code = qr::QrCode::new(&words.join(" "), color, &draw::SolidColor(Color::White))?;
+    let n = code.modules();
+    code.module = match module {
+        Some(0) => return Err("--module can't be 0".into()),
+        Some(px) => px,
+        None => (area.width.min(area.height) / n).max(1),
+    };
+    let side = n.saturating_mul(code.module);
+    (code.x, code.y) = at.unwrap_or((
+        area.width.saturating_sub(side) / 2,
+        area.height.saturating_sub(side) / 2,
+    ));
+    Ok(code)
+}
+
+// renders the output of a command or a tmux pane
+fn terminal_frame(command: &[String], opts: &Options) -> Result<term::Terminal, Box<dyn Error>> {
+    let text = match &opts.tmux {
+        Some(target) => term::capture_tmux(target)?,
+        None if !command.is_empty() => term::run(&command.join(" "))?,
+        None => return Err("term expects a command or --tmux <target>".into()),
+    };
+    Ok(term::Terminal::parse(&text, opts.text_scale))
+}
+
+pub fn dither(img: &bmp::Image, opts: &Options) -> Result<PaperImage, Box<dyn Error>> {
+    let _span = info_span!("dither", algorithm = opts.dither.name()).entered();
+    let now = Instant::now();
+    // a panel smaller than the screen shows only the frame's top-left, so
+    // the frame, overlays and all, is shrunk into that corner
+    let area = opts.panel.geometry;
+    let shrunk = ((area.width, area.height) != (SCREEN_WIDTH, SCREEN_HEIGHT))
+        .then(|| layout::resize_into(img, area, layout::Scale::Fit, opts.filter, opts.letterbox));
+    let img = shrunk.as_ref().unwrap_or(img);
+    let roi = !opts.roi.is_empty() || opts.roi_mask.is_some();
+    let shown = opts.panel.panel.palette().colors();
+    let subset = sunlight_colors(opts)
+        .map(|high| high.into_iter().filter(|c| shown.contains(c)).collect())
+        .or_else(|| opts.colors.map(|n| reduce::panel_subset(img, n, &shown)));
+    let colors = subset.as_deref().unwrap_or(&shown);
+    let mask = if roi {
+        roi_mask(opts)?
+    } else {
+        roi::Mask::empty()
+    };
+    // the region of interest gets at least the redmean match
+    let inside = match opts.metric {
+        draw::Metric::Rgb => draw::Metric::Redmean,
+        metric => metric,
+    };
+    let outside = opts.metric;
+    let lut = |metric| opts.lut.then(|| lut::Lut::new(colors, metric));
+    let (lut_inside, lut_outside) = (lut(inside).filter(|_| roi), lut(outside));
+    let pick = |x, y, px| {
+        let (metric, lut) = if mask.contains(x, y) {
+            (inside, &lut_inside)
+        } else {
+            (outside, &lut_outside)
+        };
+        match lut {
+            Some(lut) => lut.get(px),
+            None => Color::closest_by(px, colors, metric),
+        }
+    };
+    let mut out = opts.dither.dither_with(img, &pick);
+    for touchup in &opts.touchups {
+        match *touchup {
+            Touchup::Remap(from, to) => out.remap(from, to),
+            Touchup::Despeckle => {
+                let n = out.despeckle();
+                events::log("despeckle", json!({ "pixels": n }));
+            }
+            Touchup::Fill(rect, color) => out.fill_rect(rect, color),
+            Touchup::Flood(x, y, color) => out.flood_fill(x, y, color),
+        }
+    }
+    events::log(
+        "dither",
+        json!({
+            "algorithm": opts.dither.name(),
+            "metric": opts.metric.name(),
+            "lut": opts.lut,
+            "roi": roi,
+            "colors": opts.colors,
+            "duration_ms": now.elapsed().as_millis() as u64,
+        }),
+    );
+    Ok(out)
+}
+
+fn roi_mask(opts: &Options) -> Result<roi::Mask, Box<dyn Error>> {
+    let mut mask = match &opts.roi_mask {
+        Some(path) => roi::Mask::from_image(&load_bmp(path)?),
+        None => roi::Mask::empty(),
+    };
+    for rect in &opts.roi {
+        mask.add_rect(*rect);
+    }
+    Ok(mask)
+}
+
+// a lux reading if a sensor is configured. a failing sensor only warns,
+// it shouldn't stop the panel from updating.
+pub fn ambient_lux(_opts: &Options) -> Option<f32> {
+    #[cfg(feature = "light")]
+    if let Some(sensor) = _opts.light {
+        match sensor.read() {
+            Ok(lux) => return Some(lux),
+            Err(e) => warn!("could not read light sensor: {e}"),
+        }
+    }
+    None
+}
+
+// the high contrast colors while above --bright-above
+fn sunlight_colors(_opts: &Options) -> Option<Vec<Color>> {
+    #[cfg(feature = "light")]
+    if let Some(bright) = _opts.bright_above {
+        if ambient_lux(_opts).is_some_and(|lux| lux > bright) {
+            return Some(light::HIGH_CONTRAST.to_vec());
+        }
+    }
+    None
+}
+
+fn test_pattern(pattern: Pattern, opts: &Options) -> Result<Box<dyn Drawable>, Box<dyn Error>> {
+    let colors = opts.panel.panel.palette().colors();
+    let shown: Vec<Color> = (colors.iter().copied())
+        .filter(|&c| c != Color::Clean)
+        .collect();
+    let geometry = opts.panel.geometry;
+    Ok(match pattern {
+        Pattern::Stripes => Box::new(draw::SequentialColors),
+        Pattern::Bars => Box::new(pattern::Bars {
+            colors: shown,
+            geometry,
+        }),
+        Pattern::Swatches => Box::new(pattern::Swatches { colors, geometry }),
+        Pattern::Ramps => Box::new(dither(&pattern::ramps(&shown), opts)?),
+        Pattern::Crosshatch => Box::new(pattern::Crosshatch {
+            spacing: 50,
+            color: Color::Black,
+            geometry,
+        }),
+        Pattern::Border => Box::new(pattern::Border {
+            width: 4,
+            color: Color::Black,
+            geometry,
+        }),
+        Pattern::Random => Box::new(draw::RandomColors),
+    })
+}
+
+// builds the frame for the modes that draw once
+pub fn single_frame(
+    mode: Option<&Mode>,
+    opts: &Options,
+) -> Result<Box<dyn Drawable>, Box<dyn Error>> {
+    Ok(match mode {
+        Some(Mode::Clean) => Box::new(draw::SolidColor(Color::Clean)),
+        Some(Mode::TestPattern { pattern }) => test_pattern(*pattern, opts)?,
+        Some(Mode::Term { command }) => Box::new(terminal_frame(command, opts)?),
+        #[cfg(feature = "ttf")]
+        Some(Mode::Text { words }) => Box::new(text_frame(words, opts)?),
+        #[cfg(feature = "qr")]
+        Some(Mode::Qr {
+            words,
+            module,
+            at,
+            color,
+        }) => Box::new(qr_frame(words, *module, *at, *color, opts.panel.geometry)?),
+        Some(Mode::Calibrate) => Box::new(calibrate::chart()),
+        Some(Mode::Splash) => Box::new(dither(&splash::splash(&opts.clock), opts)?),
+        // a png of palette indices, as written by --save-indexed
+        Some(Mode::Indexed { image: path }) => {
+            let gray = image::open(path)
+                .map_err(|e| format!("could not load {path}: {e}"))?
+                .into_luma8();
+            Box::new(PaperImage::try_from(&gray)?)
+        }
+        // a packed frame from a file, or from stdin with "-"
+        Some(Mode::Frame { path }) => {
+            let frame = match path.as_str() {
+                "-" => frame::PackedFrame::read_from(&mut io::stdin().lock())?,
+                path => frame::PackedFrame::read_from(
+                    &mut fs::File::open(path).map_err(|e| format!("could not open {path}: {e}"))?,
+                )?,
+            };
+            Box::new(frame)
+        }
+        Some(Mode::Art { kind: Art::Life }) => Box::new(draw::Life::new(opts.seed, 40, 4)),
+        Some(Mode::Art { kind: Art::Noise }) => Box::new(draw::Landscape::new(opts.seed)),
+        Some(Mode::Art { kind: Art::Truchet }) => Box::new(draw::Truchet {
+            seed: opts.seed,
+            tile: 32,
+            fg: Color::Blue,
+            bg: Color::White,
+        }),
+        Some(Mode::Pixel { image: path }) => {
+            let (img, factor) = layout::integer_upscale(&load_bmp(path)?, Color::White);
+            info!("Upscaled {factor}x");
+            let img = decorate(img, opts)?;
+            if layout::uses_palette_only(&img) {
+                Box::new(quantize(&img))
+            } else {
+                Box::new(dither(&img, opts)?)
+            }
+        }
+        Some(Mode::Ascii { image }) => {
+            let img = match image {
+                Some(path) => load_bmp(path)?,
+                None => source_image(None, opts)?,
+            };
+            let img = decorate(img, opts)?;
+            Box::new(ascii::AsciiArt::from_image(
+                &img,
+                opts.text_scale,
+                opts.ascii_color,
+            ))
+        }
+        _ => {
+            let img = decorate(source_image(mode, opts)?, opts)?;
+            Box::new(dither(&img, opts)?)
+        }
+    })
+}
+
+// the frame packed as the panel is sent it, for `frame` to draw later
+pub fn save_frame(path: &str, mode: Option<&Mode>, opts: &Options) -> Result<(), Box<dyn Error>> {
+    let frame = single_frame(mode, opts)?;
+    let packed = frame::PackedFrame::pack(&draw::Flipped {
+        horizontal: opts.flip_h,
+        vertical: opts.flip_v,
+        geometry: opts.panel.geometry,
+        rest: &*frame,
+    });
+    let mut file = fs::File::create(path).map_err(|e| format!("could not create {path}: {e}"))?;
+    packed.write_to(&mut file)?;
+    info!("Wrote packed frame to {path}");
+    Ok(())
+}
+
+// the frame as a png of palette indices, for `indexed` to draw later
+pub fn save_indexed(path: &str, mode: Option<&Mode>, opts: &Options) -> Result<(), Box<dyn Error>> {
+    let frame = single_frame(mode, opts)?;
+    let frame = PaperImage::from_drawable(&draw::Flipped {
+        horizontal: opts.flip_h,
+        vertical: opts.flip_v,
+        geometry: opts.panel.geometry,
+        rest: &*frame,
+    });
+    image::GrayImage::from(&frame)
+        .save(path)
+        .map_err(|e| format!("could not write {path}: {e}"))?;
+    info!("Wrote indexed frame to {path}");
+    Ok(())
+}
+
+// the frame in the palette's rgb, as the panel would show it
+pub fn save_preview(path: &str, mode: Option<&Mode>, opts: &Options) -> Result<(), Box<dyn Error>> {
+    let frame = single_frame(mode, opts)?;
+    let mut img = preview::render(&draw::Flipped {
+        horizontal: opts.flip_h,
+        vertical: opts.flip_v,
+        geometry: opts.panel.geometry,
+        rest: &*frame,
+    });
+    if let Some(kind) = opts.simulate {
+        preview::simulate(&mut img, kind);
+    }
+    if opts.debug_layout {
+        annotations(mode, opts)?.draw(&mut img);
+    }
+    preview::save(&img, path)?;
+    info!("Wrote preview to {path}");
+    Ok(())
+}
@@ -0,0 +1,296 @@
+// the modes that print what they find instead of drawing, as text or as
+// json with --output json
+
+use std::{
+    error::Error,
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use serde_json::json;
+use tracing::{info, warn};
+
+use crate::{
+    app::{panel_temperature, Options, Output},
+    cmd::{self, Query},
+    config, pipeline,
+    render::{dither, fit_screen, load_image, source_image},
+    spi_write_limit, store, SpiDevice,
+};
+
+pub fn report_temperature(
+    display: &mut impl SpiDevice,
+    opts: &Options,
+) -> Result<(), Box<dyn Error>> {
+    let (c, ok) = panel_temperature(display)?;
+    if opts.output == Output::Json {
+        println!("{}", json!({ "celsius": c, "ok": ok }));
+    } else {
+        println!("{c:.1} C");
+    }
+    Ok(())
+}
+
+pub fn report_status(display: &mut impl SpiDevice, opts: &Options) -> Result<(), Box<dyn Error>> {
+    let status = cmd::GetStatus.read(display)?;
+    let flags = [
+        (status.idle(), "idle"),
+        (status.powered_on(), "powered on"),
+        (status.powered_off(), "powered off"),
+        (status.data_received(), "frame received"),
+        (status.i2c_busy(), "i2c busy"),
+        (status.i2c_error(), "i2c error"),
+    ];
+    if opts.output == Output::Json {
+        let mut info = json!({ "raw": status.0 });
+        for (on, name) in flags {
+            info[name.replace(' ', "_")] = json!(on);
+        }
+        println!("{info}");
+        return Ok(());
+    }
+    let set: Vec<&str> = (flags.iter())
+        .filter_map(|&(on, name)| on.then_some(name))
+        .collect();
+    println!("{:#04x}: {}", status.0, set.join(", "));
+    Ok(())
+}
+
+// the panel setup this invocation would use, without touching the hardware
+pub fn info(opts: &Options, palette: &Path) -> Result<(), Box<dyn Error>> {
+    let p = &opts.panel;
+    let gpio = match p.gpio {
+        crate::gpio::Backend::Rppal => "rppal",
+        crate::gpio::Backend::Cdev => "cdev",
+    };
+    let features: Vec<&str> = [
+        (cfg!(feature = "battery"), "battery"),
+        (cfg!(feature = "light"), "light"),
+        (cfg!(feature = "climate"), "climate"),
+        (cfg!(feature = "embedded-graphics"), "embedded-graphics"),
+        (cfg!(feature = "ttf"), "ttf"),
+        (cfg!(feature = "parallel"), "parallel"),
+        (cfg!(feature = "qr"), "qr"),
+        (cfg!(feature = "spidev"), "spidev"),
+        (cfg!(feature = "async"), "async"),
+    ]
+    .into_iter()
+    .filter_map(|(on, name)| on.then_some(name))
+    .collect();
+    let calibrated = palette.exists();
+    let count =
+        (opts.refresh_count.as_ref()).and_then(|count| count.read().map_err(|e| warn!("{e}")).ok());
+    if opts.output == Output::Json {
+        let info = json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "config": opts.config,
+            "panel": p.panel.name(),
+            "geometry": { "width": p.geometry.width, "height": p.geometry.height },
+            "gpio": gpio,
+            "gpiochip": p.gpiochip,
+            "pins": { "dc": p.dc, "busy": p.busy, "reset": p.reset },
+            "spi": {
+                "bus": p.spi_bus as u8,
+                "select": p.spi_select as u8,
+                "device": p.spidev,
+                "clock_hz": p.spi_clock,
+                "chunk": opts.transfer.chunk,
+                "max_write": spi_write_limit(),
+            },
+            "busy_timeout_ms": p.busy_timeout.as_millis() as u64,
+            "retries": opts.retries,
+            "palette": { "path": palette, "calibrated": calibrated },
+            "refreshes": count.map(|c| json!({
+                "total": c.total,
+                "since_clear": c.since_clear,
+                "clear_every": opts.clear_every,
+            })),
+            "features": features,
+        });
+        println!("{info}");
+        return Ok(());
+    }
+    println!("rpi-epaper {}", env!("CARGO_PKG_VERSION"));
+    println!(
+        "Panel: {} {}x{}",
+        p.panel.name(),
+        p.geometry.width,
+        p.geometry.height
+    );
+    match &opts.config {
+        Some(path) => println!("Config: {}", path.display()),
+        None => println!("Config: defaults ({} not found)", config::DEFAULT_PATH),
+    }
+    println!(
+        "Pins: dc {} busy {} reset {} via {gpio}",
+        p.dc, p.busy, p.reset
+    );
+    let bus = match &p.spidev {
+        Some(device) => device.clone(),
+        None => format!("bus {} select {}", p.spi_bus as u8, p.spi_select as u8),
+    };
+    println!(
+        "SPI: {bus} at {} Hz, {} byte chunks (spidev takes up to {})",
+        p.spi_clock,
+        opts.transfer.chunk,
+        spi_write_limit()
+    );
+    println!(
+        "Busy timeout: {:?}, {} retries",
+        p.busy_timeout, opts.retries
+    );
+    println!(
+        "Palette: {} ({})",
+        palette.display(),
+        if calibrated { "calibrated" } else { "defaults" }
+    );
+    if let Some(c) = count {
+        let every = match opts.clear_every {
+            Some(n) => format!(", clearing every {n}"),
+            None => String::new(),
+        };
+        println!(
+            "Refreshes: {}, {} since the last clear{every}",
+            c.total, c.since_clear
+        );
+    }
+    println!(
+        "Features: {}",
+        if features.is_empty() {
+            "none".to_string()
+        } else {
+            features.join(", ")
+        }
+    );
+    Ok(())
+}
+
+// the names `@name` can refer to
+pub fn list_images(opts: &Options) -> Result<(), Box<dyn Error>> {
+    let images = store::Store::new(&opts.images).list()?;
+    if opts.output == Output::Json {
+        let list: Vec<_> = images
+            .iter()
+            .map(|(name, path)| json!({ "name": name, "path": path }))
+            .collect();
+        println!("{}", json!(list));
+        return Ok(());
+    }
+    for (name, path) in &images {
+        println!("@{name}: {}", path.display());
+    }
+    Ok(())
+}
+
+// past events from the --event-log file, oldest first
+pub fn history(opts: &Options) -> Result<(), Box<dyn Error>> {
+    let path = opts
+        .event_log
+        .as_ref()
+        .ok_or("history reads the log given with --event-log")?;
+    let text =
+        fs::read_to_string(path).map_err(|e| format!("could not read {}: {e}", path.display()))?;
+    let events: Vec<serde_json::Value> = text
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("invalid event log {}: {e}", path.display()))?;
+    if opts.output == Output::Json {
+        println!("{}", serde_json::Value::Array(events));
+        return Ok(());
+    }
+    for mut event in events {
+        let Some(obj) = event.as_object_mut() else {
+            continue;
+        };
+        let ts = obj.remove("ts").and_then(|t| t.as_f64()).unwrap_or(0.0);
+        let name = obj.remove("event");
+        let name = name.as_ref().and_then(|e| e.as_str()).unwrap_or("?");
+        let fields: Vec<String> = obj.iter().map(|(k, v)| format!("{k}={v}")).collect();
+        println!("{ts:.0} {name} {}", fields.join(" "));
+    }
+    Ok(())
+}
+
+// times the steps of a refresh that don't need the panel, the dither and
+// the packing into the panel's planes, each --cycles times
+pub fn bench(image: Option<&str>, opts: &Options) -> Result<(), Box<dyn Error>> {
+    let img = match image {
+        Some(image) => fit_screen(load_image(image, opts)?, opts),
+        None => source_image(None, opts)?,
+    };
+    let cycles = opts.cycles.max(1);
+    let area = opts.panel.geometry;
+    let (mut dithering, mut packing) = (Vec::new(), Vec::new());
+    for _ in 0..cycles {
+        let now = Instant::now();
+        let frame = dither(&img, opts)?;
+        dithering.push(now.elapsed());
+        let now = Instant::now();
+        let planes = opts.panel.panel.pack(&frame, 0, 0, area.width, area.height);
+        packing.push(now.elapsed());
+        std::hint::black_box(planes);
+    }
+    // min, mean and max in ms
+    let stats = |times: &[Duration]| {
+        let ms: Vec<f64> = times.iter().map(|t| t.as_secs_f64() * 1000.0).collect();
+        let min = ms.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = ms.iter().copied().fold(0.0, f64::max);
+        (min, ms.iter().sum::<f64>() / ms.len() as f64, max)
+    };
+    let steps = [("dither", stats(&dithering)), ("pack", stats(&packing))];
+    if opts.output == Output::Json {
+        let mut report = json!({
+            "cycles": cycles,
+            "algorithm": opts.dither.name(),
+            "panel": opts.panel.panel.name(),
+        });
+        for (name, (min, mean, max)) in steps {
+            report[format!("{name}_ms")] = json!({ "min": min, "mean": mean, "max": max });
+        }
+        println!("{report}");
+        return Ok(());
+    }
+    println!(
+        "{} on the {}, {cycles} cycles",
+        opts.dither.name(),
+        opts.panel.panel.name()
+    );
+    for (name, (min, mean, max)) in steps {
+        println!("{name:>7}: min {min:.1} ms, mean {mean:.1} ms, max {max:.1} ms");
+    }
+    Ok(())
+}
+
+// the saved looks, then the built in ones they don't replace
+pub fn list_looks(opts: &Options) -> Result<(), Box<dyn Error>> {
+    let saved = if opts.looks.exists() {
+        pipeline::load(&opts.looks)?
+    } else {
+        Vec::new()
+    };
+    for (name, p) in &saved {
+        println!("{name}: {p}");
+    }
+    for name in ["photo", "document", "map"] {
+        if let (false, Some(p)) = (
+            saved.iter().any(|(n, _)| n == name),
+            pipeline::builtin(name),
+        ) {
+            println!("{name}: {p} (built in)");
+        }
+    }
+    Ok(())
+}
+
+// saves the stages given with --pipeline or --look under `name`
+pub fn save_look(name: &str, opts: &Options) -> Result<(), Box<dyn Error>> {
+    if opts.pipeline.is_empty() {
+        return Err("save-look saves the stages given with --pipeline or --look".into());
+    }
+    pipeline::save(name, &opts.pipeline, &opts.looks)?;
+    info!("Saved {name} to {}", opts.looks.display());
+    Ok(())
+}
@@ -0,0 +1,220 @@
+// keeps a copy of what the panel shows so part of it can be redrawn. a
+// region update is composed onto the retained frame, which fills in the
+// pixels the controller's 8 px alignment widens the window by, and only the
+// window is sent. a panel without partial windows is sent the composed
+// frame whole.
+
+use crate::{
+    cmd::{Command, Draw, DrawOptions},
+    draw::{Color, Drawable, PaperImage, Window},
+    error::Result,
+    layout::Rect,
+    SpiDevice, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+pub struct Retained {
+    frame: PaperImage,
+}
+
+impl Default for Retained {
+    // a freshly cleaned panel
+    fn default() -> Self {
+        Self {
+            frame: PaperImage::new(Color::White),
+        }
+    }
+}
+
+impl Retained {
+    // starts from a frame already on the panel
+    pub fn new(frame: &(impl Drawable + ?Sized)) -> Self {
+        Self {
+            frame: PaperImage::from_drawable(frame),
+        }
+    }
+
+    // what the panel shows after the last draw
+    pub fn frame(&self) -> &PaperImage {
+        &self.frame
+    }
+
+    // draws a whole new frame
+    pub fn draw(
+        &mut self,
+        to: &mut impl SpiDevice,
+        frame: &(impl Drawable + ?Sized),
+        options: DrawOptions,
+    ) -> Result<()> {
+        Draw {
+            frame,
+            options,
+            progress: None,
+            cancel: None,
+        }
+        .send(to)?;
+        self.frame = PaperImage::from_drawable(frame);
+        Ok(())
+    }
+
+    // redraws only `rect`, with `content`'s top-left at the rect's, through
+    // a partial window. the rest of the panel keeps what it showed. the rect
+    // is clipped to the screen.
+    pub fn update_region(
+        &mut self,
+        to: &mut impl SpiDevice,
+        rect: Rect,
+        content: &(impl Drawable + ?Sized),
+        options: DrawOptions,
+    ) -> Result<()> {
+        let x = rect.x.min(SCREEN_WIDTH as u32) as u16;
+        let y = rect.y.min(SCREEN_HEIGHT as u32) as u16;
+        let w = (rect.w.min(SCREEN_WIDTH as u32) as u16).min(SCREEN_WIDTH - x);
+        let h = (rect.h.min(SCREEN_HEIGHT as u32) as u16).min(SCREEN_HEIGHT - y);
+        let composed = PaperImage::from_drawable(&Window {
+            x,
+            y,
+            w,
+            h,
+            inner: content,
+            rest: &self.frame,
+        });
+        let draw = Draw {
+            frame: &composed,
+            options,
+            progress: None,
+            cancel: None,
+        };
+        if to.panel().supports_partial() {
+            draw.partial(x, y, w, h).send(to)?;
+        } else {
+            draw.send(to)?;
+        }
+        self.frame = composed;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        draw::SolidColor,
+        mock::{MockDevice, Transaction},
+        panel,
+    };
+
+    fn quick() -> DrawOptions {
+        DrawOptions {
+            cooldown: Duration::ZERO,
+            ..DrawOptions::default()
+        }
+    }
+
+    fn rect(x: u32, y: u32, w: u32, h: u32) -> Rect {
+        Rect { x, y, w, h }
+    }
+
+    #[test]
+    fn region_updates_fill_the_window_from_the_retained_frame() {
+        let mut mock = MockDevice::new();
+        let mut retained = Retained::default();
+        retained
+            .update_region(
+                &mut mock,
+                rect(4, 0, 4, 1),
+                &SolidColor(Color::Black),
+                quick(),
+            )
+            .unwrap();
+        // the window widens to columns 0..8, the first four kept white
+        let log = mock.log();
+        let upload = log
+            .iter()
+            .position(|t| *t == Transaction::Cmd(0x10))
+            .unwrap();
+        assert_eq!(
+            log[upload + 1],
+            Transaction::Data(vec![0x11, 0x11, 0x00, 0x00])
+        );
+        assert!(mock.commands().starts_with(&[0x91, 0x90, 0x10]));
+        assert!(retained.frame().get_pixel(3, 0) == Color::White);
+        assert!(retained.frame().get_pixel(4, 0) == Color::Black);
+        assert!(retained.frame().get_pixel(8, 0) == Color::White);
+        assert!(retained.frame().get_pixel(4, 1) == Color::White);
+    }
+
+    #[test]
+    fn region_updates_land_on_the_last_frame() {
+        let mut mock = MockDevice::new();
+        let mut retained = Retained::default();
+        retained
+            .draw(&mut mock, &SolidColor(Color::Red), quick())
+            .unwrap();
+        assert!(mock.frame().unwrap().data.iter().all(|&b| b == 0x44));
+        mock.clear();
+        retained
+            .update_region(
+                &mut mock,
+                rect(2, 0, 2, 1),
+                &SolidColor(Color::Blue),
+                quick(),
+            )
+            .unwrap();
+        let log = mock.log();
+        let upload = log
+            .iter()
+            .position(|t| *t == Transaction::Cmd(0x10))
+            .unwrap();
+        assert_eq!(
+            log[upload + 1],
+            Transaction::Data(vec![0x44, 0x33, 0x44, 0x44])
+        );
+    }
+
+    #[test]
+    fn region_updates_clip_to_the_screen() {
+        let mut mock = MockDevice::new();
+        let mut retained = Retained::default();
+        retained
+            .update_region(
+                &mut mock,
+                rect(596, 446, 100, 100),
+                &SolidColor(Color::Green),
+                quick(),
+            )
+            .unwrap();
+        assert!(retained.frame().get_pixel(599, 447) == Color::Green);
+        assert!(retained.frame().get_pixel(595, 447) == Color::White);
+        // columns 592..600 of rows 446 and 447
+        let log = mock.log();
+        let window = log
+            .iter()
+            .position(|t| *t == Transaction::Cmd(0x90))
+            .unwrap();
+        assert_eq!(
+            log[window + 1],
+            Transaction::Data(vec![0x02, 0x50, 0x02, 0x57, 0x01, 0xBE, 0x01, 0xBF, 0x01])
+        );
+    }
+
+    #[test]
+    fn panels_without_windows_get_the_composed_frame_whole() {
+        let mut mock = MockDevice::with_panel(&panel::Bwr420);
+        let mut retained = Retained::default();
+        retained
+            .update_region(
+                &mut mock,
+                rect(0, 0, 8, 8),
+                &SolidColor(Color::Black),
+                quick(),
+            )
+            .unwrap();
+        let commands = mock.commands();
+        assert!(!commands.contains(&0x91));
+        assert!(commands.starts_with(&[0x61]));
+        assert!(retained.frame().get_pixel(7, 7) == Color::Black);
+        assert!(retained.frame().get_pixel(8, 0) == Color::White);
+    }
+}
@@ -0,0 +1,114 @@
+use crate::{layout::Rect, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// per-pixel importance mask over the screen
+pub struct Mask {
+    data: Vec<bool>,
+}
+
+impl Mask {
+    pub fn empty() -> Self {
+        Self {
+            data: vec![false; SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize],
+        }
+    }
+
+    // the white (lighter than mid grey) pixels of a screen sized image mark
+    // the region
+    pub fn from_image(img: &bmp::Image) -> Self {
+        let mut mask = Self::empty();
+        for (x, y) in img.coordinates() {
+            if x < SCREEN_WIDTH as u32 && y < SCREEN_HEIGHT as u32 {
+                let px = img.get_pixel(x, y);
+                mask.data[x as usize + y as usize * SCREEN_WIDTH as usize] =
+                    px.r >= 128 && px.g >= 128 && px.b >= 128;
+            }
+        }
+        mask
+    }
+
+    pub fn add_rect(&mut self, rect: Rect) {
+        let x1 = (rect.x + rect.w).min(SCREEN_WIDTH as u32);
+        let y1 = (rect.y + rect.h).min(SCREEN_HEIGHT as u32);
+        for y in rect.y..y1 {
+            for x in rect.x..x1 {
+                self.data[x as usize + y as usize * SCREEN_WIDTH as usize] = true;
+            }
+        }
+    }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        self.data[x + y * SCREEN_WIDTH as usize]
+    }
+}
+
+// parses "x,y,w,h"
+pub fn parse_rect(s: &str) -> Result<Rect, String> {
+    let parts = s
+        .split(',')
+        .map(|p| p.trim().parse::<u32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("invalid rect '{s}': {e}"))?;
+    match parts[..] {
+        [x, y, w, h] => Ok(Rect { x, y, w, h }),
+        _ => Err(format!("invalid rect '{s}', expected x,y,w,h")),
+    }
+}
+
+// parses "x,y"
+pub fn parse_point(s: &str) -> Result<(u16, u16), String> {
+    let invalid = || format!("invalid point '{s}', expected x,y");
+    let (x, y) = s.split_once(',').ok_or_else(invalid)?;
+    Ok((
+        x.parse().map_err(|_| invalid())?,
+        y.parse().map_err(|_| invalid())?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_rects() {
+        let r = parse_rect("10, 20,30 ,40").unwrap();
+        assert_eq!((r.x, r.y, r.w, r.h), (10, 20, 30, 40));
+        assert!(parse_rect("1,2,3")
+            .err()
+            .unwrap()
+            .contains("expected x,y,w,h"));
+        assert!(parse_rect("1,2,3,4,5").is_err());
+        assert!(parse_rect("1,2,a,4")
+            .err()
+            .unwrap()
+            .contains("invalid rect"));
+        assert!(parse_rect("1,2,-3,4").is_err());
+        assert!(parse_rect("").is_err());
+    }
+
+    #[test]
+    fn rects_are_clipped_to_the_screen() {
+        let mut mask = Mask::empty();
+        mask.add_rect(Rect {
+            x: 590,
+            y: 440,
+            w: 100,
+            h: 100,
+        });
+        assert!(mask.contains(599, 447));
+        assert!(mask.contains(590, 440));
+        assert!(!mask.contains(589, 447));
+    }
+
+    #[test]
+    fn white_mask_pixels_are_inside() {
+        let mut img = bmp::Image::new(SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+        img.set_pixel(1, 0, bmp::Pixel::new(255, 255, 255));
+        img.set_pixel(2, 0, bmp::Pixel::new(128, 128, 128));
+        img.set_pixel(3, 0, bmp::Pixel::new(255, 255, 0));
+        let mask = Mask::from_image(&img);
+        assert!(!mask.contains(0, 0));
+        assert!(mask.contains(1, 0));
+        assert!(mask.contains(2, 0));
+        assert!(!mask.contains(3, 0));
+    }
+}
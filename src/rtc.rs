@@ -0,0 +1,71 @@
+use std::{process::Command, time::Duration};
+
+use rppal::{
+    gpio::Gpio,
+    i2c::{self, I2c},
+};
+
+const DS3231_ADDR: u16 = 0x68;
+const REG_TIME: u8 = 0x00;
+const REG_ALARM1: u8 = 0x07;
+const REG_CONTROL: u8 = 0x0E;
+const REG_STATUS: u8 = 0x0F;
+
+fn from_bcd(b: u8) -> u8 {
+    (b >> 4) * 10 + (b & 0x0F)
+}
+
+fn to_bcd(n: u8) -> u8 {
+    ((n / 10) << 4) | (n % 10)
+}
+
+// time of day kept by the rtc, as seconds since midnight
+fn read_time_of_day(i2c: &I2c) -> i2c::Result<u32> {
+    let mut buf = [0u8; 3];
+    i2c.write_read(&[REG_TIME], &mut buf)?;
+    let s = from_bcd(buf[0] & 0x7F) as u32;
+    let m = from_bcd(buf[1] & 0x7F) as u32;
+    // assumes the clock runs in 24h mode
+    let h = from_bcd(buf[2] & 0x3F) as u32;
+    Ok(h * 3600 + m * 60 + s)
+}
+
+// programs ds3231 alarm 1 to fire `after` from now (wrapping at midnight)
+// and routes it to the INT/SQW pin, which an external power controller
+// can use to switch the pi back on
+pub fn set_wake_alarm(after: Duration) -> i2c::Result<(u8, u8, u8)> {
+    let mut i2c = I2c::new()?;
+    i2c.set_slave_address(DS3231_ADDR)?;
+    let now = read_time_of_day(&i2c)?;
+    let at = (now + after.as_secs() as u32) % 86400;
+    let (h, m, s) = ((at / 3600) as u8, ((at / 60) % 60) as u8, (at % 60) as u8);
+    // match on hours, minutes and seconds (A1M4 set: any day)
+    i2c.block_write(REG_ALARM1, &[to_bcd(s), to_bcd(m), to_bcd(h), 0x80])?;
+    // INTCN + A1IE
+    let mut control = [0u8];
+    i2c.write_read(&[REG_CONTROL], &mut control)?;
+    i2c.block_write(REG_CONTROL, &[control[0] | 0b101])?;
+    // clear a pending A1F so the alarm can assert again
+    let mut status = [0u8];
+    i2c.write_read(&[REG_STATUS], &mut status)?;
+    i2c.block_write(REG_STATUS, &[status[0] & !0b1])?;
+    Ok((h, m, s))
+}
+
+// drives the power controller's "done" line high and leaves it there after
+// exit, then optionally halts the pi
+pub fn signal_power_off(pin: u8, shutdown: bool) -> Result<(), String> {
+    let mut pin = Gpio::new()
+        .and_then(|g| g.get(pin))
+        .map_err(|e| format!("could not claim power pin: {e}"))?
+        .into_output_low();
+    pin.set_reset_on_drop(false);
+    pin.set_high();
+    if shutdown {
+        Command::new("shutdown")
+            .args(["-h", "now"])
+            .status()
+            .map_err(|e| format!("could not shut down: {e}"))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,245 @@
+// a stack of text widgets laid out top to bottom, with a per scene policy
+// for what to give up when they don't all fit
+
+use std::{fmt, fs, str::FromStr};
+
+use crate::{
+    draw::Color,
+    font::{self, ADVANCE, GLYPH_HEIGHT},
+    layout, pages, Rgb, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+const MARGIN: u16 = 12;
+// glyph height plus 3 rows of spacing, before scaling
+const LINE_HEIGHT: u16 = GLYPH_HEIGHT + 3;
+// space between widgets
+const GAP: u16 = 6;
+
+pub struct Widget {
+    pub text: String,
+    pub scale: u16,
+    // lower goes first when widgets are dropped
+    pub priority: i32,
+    pub color: Color,
+}
+
+// one way to make an oversized scene fit, tried in the order given
+#[derive(Clone, Copy, PartialEq)]
+pub enum Step {
+    // scale the largest text down a step at a time, to no smaller than 1
+    Shrink,
+    // leave out the lowest priority widgets, keeping at least one
+    Drop,
+    // spread the widgets over several frames
+    Paginate,
+}
+
+impl FromStr for Step {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "shrink" => Ok(Step::Shrink),
+            "drop" => Ok(Step::Drop),
+            "paginate" => Ok(Step::Paginate),
+            _ => Err(format!(
+                "unknown policy step '{s}' (expected shrink, drop or paginate)"
+            )),
+        }
+    }
+}
+
+pub struct Scene {
+    pub widgets: Vec<Widget>,
+    pub policy: Vec<Step>,
+}
+
+// what was given up to make the scene fit. widgets are numbered from 1 in
+// file order.
+#[derive(Debug, PartialEq)]
+pub enum Degradation {
+    Shrunk { widget: usize, from: u16, to: u16 },
+    Dropped { widget: usize },
+    Paginated { pages: usize },
+    // the policy ran out and the bottom of the scene is cut off
+    Clipped,
+}
+
+impl fmt::Display for Degradation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Degradation::Shrunk { widget, from, to } => {
+                write!(f, "shrank widget {widget} from scale {from} to {to}")
+            }
+            Degradation::Dropped { widget } => write!(f, "dropped widget {widget}"),
+            Degradation::Paginated { pages } => write!(f, "split over {pages} pages"),
+            Degradation::Clipped => write!(f, "clipped"),
+        }
+    }
+}
+
+pub struct Rendered {
+    pub pages: Vec<bmp::Image>,
+    pub degradations: Vec<Degradation>,
+}
+
+// `policy step...` sets the policy, by default none, so oversized scenes
+// are clipped. `widget priority scale color text...` adds a widget below
+// the previous one. blank lines and lines starting with # are ignored.
+pub fn load(path: &str) -> Result<Scene, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("could not read {path}: {e}"))?;
+    let mut scene = Scene {
+        widgets: Vec::new(),
+        policy: Vec::new(),
+    };
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut parts = line.splitn(5, char::is_whitespace);
+        match parts.next() {
+            Some("policy") => {
+                scene.policy = parts
+                    .flat_map(str::split_whitespace)
+                    .map(str::parse)
+                    .collect::<Result<_, _>>()?;
+            }
+            Some("widget") => {
+                let (Some(priority), Some(scale), Some(color), Some(text)) =
+                    (parts.next(), parts.next(), parts.next(), parts.next())
+                else {
+                    return Err(format!(
+                        "invalid widget '{line}', expected: widget priority scale color text"
+                    ));
+                };
+                scene.widgets.push(Widget {
+                    text: text.trim().to_string(),
+                    scale: scale
+                        .parse::<u16>()
+                        .map_err(|e| format!("invalid scale in '{line}': {e}"))?
+                        .max(1),
+                    priority: priority
+                        .parse()
+                        .map_err(|e| format!("invalid priority in '{line}': {e}"))?,
+                    color: color.parse()?,
+                });
+            }
+            _ => return Err(format!("unknown scene line '{line}'")),
+        }
+    }
+    if scene.widgets.is_empty() {
+        return Err(format!("{path} has no widgets"));
+    }
+    Ok(scene)
+}
+
+// a widget wrapped to the screen width at a scale
+fn wrapped(w: &Widget, scale: u16) -> Vec<String> {
+    let cols = (SCREEN_WIDTH - MARGIN * 2 + scale) / (ADVANCE * scale);
+    pages::wrap(&w.text, cols.max(1) as usize)
+}
+
+fn height(w: &Widget, scale: u16) -> u16 {
+    wrapped(w, scale).len() as u16 * LINE_HEIGHT * scale
+}
+
+impl Scene {
+    // lays the scene out, degrading it by the policy until it fits
+    pub fn render(&self) -> Rendered {
+        let available = SCREEN_HEIGHT - MARGIN * 2;
+        let mut scales: Vec<u16> = self.widgets.iter().map(|w| w.scale).collect();
+        let mut shown: Vec<usize> = (0..self.widgets.len()).collect();
+        let total = |scales: &[u16], shown: &[usize]| -> u16 {
+            let gaps = GAP * shown.len().saturating_sub(1) as u16;
+            shown
+                .iter()
+                .map(|&i| height(&self.widgets[i], scales[i]))
+                .sum::<u16>()
+                + gaps
+        };
+        let mut degradations = Vec::new();
+        let mut paginate = false;
+        for step in &self.policy {
+            if total(&scales, &shown) <= available {
+                break;
+            }
+            match step {
+                Step::Shrink => {
+                    let from = scales.clone();
+                    while total(&scales, &shown) > available {
+                        // the largest text, the lowest priority of those first
+                        let Some(&i) = shown
+                            .iter()
+                            .filter(|&&i| scales[i] > 1)
+                            .max_by_key(|&&i| (scales[i], -self.widgets[i].priority, i))
+                        else {
+                            break;
+                        };
+                        scales[i] -= 1;
+                    }
+                    for &i in &shown {
+                        if scales[i] != from[i] {
+                            degradations.push(Degradation::Shrunk {
+                                widget: i + 1,
+                                from: from[i],
+                                to: scales[i],
+                            });
+                        }
+                    }
+                }
+                Step::Drop => {
+                    while total(&scales, &shown) > available && shown.len() > 1 {
+                        // the last of the lowest priority widgets
+                        let (pos, &i) = shown
+                            .iter()
+                            .enumerate()
+                            .min_by_key(|&(pos, &i)| (self.widgets[i].priority, -(pos as i64)))
+                            .expect("more than one widget is shown");
+                        shown.remove(pos);
+                        degradations.push(Degradation::Dropped { widget: i + 1 });
+                    }
+                }
+                Step::Paginate => paginate = true,
+            }
+        }
+        // one page per run of widgets that fits, or everything on one
+        let mut pages: Vec<Vec<usize>> = vec![Vec::new()];
+        let mut used = 0;
+        for &i in &shown {
+            let h = height(&self.widgets[i], scales[i]);
+            let page = pages.last_mut().expect("there is always a page");
+            let needed = used + if page.is_empty() { h } else { GAP + h };
+            if paginate && !page.is_empty() && needed > available {
+                pages.push(vec![i]);
+                used = h;
+            } else {
+                page.push(i);
+                used = needed;
+            }
+        }
+        if pages.len() > 1 {
+            degradations.push(Degradation::Paginated { pages: pages.len() });
+        }
+        if pages.iter().any(|p| total(&scales, p) > available) {
+            degradations.push(Degradation::Clipped);
+        }
+        Rendered {
+            pages: pages.iter().map(|p| self.draw(p, &scales)).collect(),
+            degradations,
+        }
+    }
+
+    fn draw(&self, shown: &[usize], scales: &[u16]) -> bmp::Image {
+        let mut img = layout::blank(Color::White);
+        let mut y = MARGIN;
+        for &i in shown {
+            let w = &self.widgets[i];
+            let scale = scales[i];
+            for line in wrapped(w, scale) {
+                font::draw_text(&mut img, &line, MARGIN, y, scale, Rgb::from(w.color).into());
+                y = y.saturating_add(LINE_HEIGHT * scale);
+            }
+            y = y.saturating_add(GAP);
+        }
+        img
+    }
+}
@@ -0,0 +1,70 @@
+use std::{fs, time::Duration};
+
+use crate::draw::Color;
+
+// one step of a sequence file
+pub enum Step {
+    Init,
+    Clean(Color),
+    Draw(String),
+    Sleep(Duration),
+    DeepSleep,
+    Deghost(u32),
+}
+
+// `10`, `90s`, `10m` or `2h`. bare numbers are seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let (num, unit) = match s.find(|c: char| !c.is_ascii_digit() && c != '.') {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let n: f64 = num.parse().map_err(|_| format!("invalid duration '{s}'"))?;
+    let secs = match unit {
+        "ms" => n / 1000.0,
+        "s" => n,
+        "m" => n * 60.0,
+        "h" => n * 3600.0,
+        _ => return Err(format!("invalid duration '{s}' (expected ms, s, m or h)")),
+    };
+    Ok(Duration::from_secs_f64(secs))
+}
+
+// steps are separated by newlines or `;`, and # starts a comment:
+// `init; clean white; draw a.png; sleep 10m; draw b.png; deepsleep`
+pub fn parse(text: &str) -> Result<Vec<Step>, String> {
+    let mut steps = Vec::new();
+    let lines = text.lines().map(|l| l.split('#').next().unwrap_or(""));
+    for step in lines.flat_map(|l| l.split(';')).map(str::trim) {
+        if step.is_empty() {
+            continue;
+        }
+        let (word, arg) = match step.split_once(char::is_whitespace) {
+            Some((w, a)) => (w, Some(a.trim())),
+            None => (step, None),
+        };
+        steps.push(match (word, arg) {
+            ("init", None) => Step::Init,
+            ("clean", None) => Step::Clean(Color::Clean),
+            ("clean", Some(color)) => Step::Clean(color.parse()?),
+            ("draw", Some(path)) => Step::Draw(path.to_string()),
+            ("sleep", Some(d)) => Step::Sleep(parse_duration(d)?),
+            ("deepsleep", None) => Step::DeepSleep,
+            ("deghost", None) => Step::Deghost(1),
+            ("deghost", Some(n)) => Step::Deghost(
+                n.parse()
+                    .map_err(|_| format!("invalid cycle count in '{step}'"))?,
+            ),
+            _ => return Err(format!("invalid step '{step}'")),
+        });
+    }
+    Ok(steps)
+}
+
+pub fn load(path: &str) -> Result<Vec<Step>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("could not read {path}: {e}"))?;
+    let steps = parse(&text).map_err(|e| format!("{path}: {e}"))?;
+    if steps.is_empty() {
+        return Err(format!("{path} has no steps"));
+    }
+    Ok(steps)
+}
@@ -0,0 +1,365 @@
+// the http side of `serve`: a daemon that owns the panel and takes jobs
+// from its clients. requests are parsed here and queued for the thread
+// driving the panel, which works through them one at a time so a refresh
+// never starts while another is still being sent. each request gets its
+// answer once its job is done.
+//
+//     POST   /image   a picture, raw or as a multipart/form-data upload.
+//                     ?client=&region=x,y,w,h draws it into a leased region,
+//                     in the panel's pixels.
+//                     a packed frame sent as application/x-epaper-frame
+//                     is checked against its checksum and covers the
+//                     whole panel
+//     POST   /clear   clears the panel. ?client= as for /image
+//     POST   /notify  a notification card, as for --notify. ?client= as
+//                     for /image
+//     POST   /lease   ?client=&name=&region=x,y,w,h&ttl=
+//     DELETE /lease   ?client=&name=
+//     GET    /status  what the panel is doing, answered straight away
+//     GET    /thumbnail  a png of what's on the panel
+
+use std::{
+    io::Cursor,
+    net::{TcpListener, TcpStream},
+    sync::{mpsc::Sender, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::{
+    decode,
+    draw::Drawable,
+    frame::{self, PackedFrame},
+    http,
+    layout::Rect,
+    notify::Notification,
+    preview, roi, script, Geometry,
+};
+
+// how long a lease lasts without a ttl
+const DEFAULT_TTL: Duration = Duration::from_secs(60);
+
+// a slow client only holds up the listener this long
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub enum Job {
+    // a picture for the whole panel, or for `region` of it. a client that
+    // doesn't give its name is anonymous.
+    Image {
+        img: bmp::Image,
+        client: String,
+        region: Option<Rect>,
+    },
+    // both cover the whole panel, so are refused while another client
+    // holds a lease
+    Clear {
+        client: String,
+    },
+    Notify {
+        notification: Notification,
+        client: String,
+    },
+    Lease {
+        client: String,
+        name: String,
+        rect: Rect,
+        ttl: Duration,
+    },
+    Release {
+        client: String,
+        name: String,
+    },
+}
+
+impl Job {
+    // for the log and the status
+    pub fn name(&self) -> &'static str {
+        match self {
+            Job::Image { .. } => "image",
+            Job::Clear { .. } => "clear",
+            Job::Notify { .. } => "notify",
+            Job::Lease { .. } => "lease",
+            Job::Release { .. } => "release",
+        }
+    }
+}
+
+// a job and the connection waiting on it
+pub struct Queued {
+    pub job: Job,
+    pub stream: TcpStream,
+}
+
+// tells the client how its job went. `Err` carries the http status to fail
+// with.
+pub fn answer(stream: &TcpStream, result: Result<Value, (u16, String)>) {
+    match result {
+        Ok(body) => http::respond(stream, 200, &body),
+        Err((status, e)) => http::respond(stream, status, &json!({ "error": e })),
+    }
+}
+
+// what GET /status reports, kept up to date by the panel thread
+#[derive(Default)]
+pub struct Status {
+    // a job is being worked on
+    pub busy: bool,
+    // jobs waiting behind it
+    pub queued: usize,
+    pub refreshes: u64,
+    // the last job finished, when and how long it took
+    pub last: Option<(&'static str, Instant, Duration)>,
+    pub last_error: Option<String>,
+    thumbnail: Option<Vec<u8>>,
+}
+
+impl Status {
+    // keeps a png of the frame for GET /thumbnail
+    pub fn set_thumbnail(&mut self, frame: &dyn Drawable) {
+        let mut png = Cursor::new(Vec::new());
+        match preview::thumbnail(frame, 4).write_to(&mut png, image::ImageFormat::Png) {
+            Ok(()) => self.thumbnail = Some(png.into_inner()),
+            Err(e) => warn!("could not encode thumbnail: {e}"),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "busy": self.busy,
+            "queued": self.queued,
+            "refreshes": self.refreshes,
+            "last": self.last.map(|(job, at, took)| json!({
+                "job": job,
+                "seconds_ago": at.elapsed().as_secs(),
+                "took_ms": took.as_millis() as u64,
+            })),
+            "last_error": self.last_error,
+        })
+    }
+}
+
+// ?region=x,y,w,h, which has to lie on the `area` sized panel
+fn rect(req: &http::Request, area: Geometry) -> Result<Option<Rect>, String> {
+    let Some(r) = req.query("region") else {
+        return Ok(None);
+    };
+    let rect = roi::parse_rect(&r)?;
+    let fits =
+        |at: u32, len: u32, max: u16| at.checked_add(len).is_some_and(|end| end <= max.into());
+    if rect.w == 0 || rect.h == 0 {
+        return Err(format!("region {r} is empty"));
+    }
+    if !fits(rect.x, rect.w, area.width) || !fits(rect.y, rect.h, area.height) {
+        return Err(format!(
+            "region {r} runs off the {}x{} panel",
+            area.width, area.height
+        ));
+    }
+    Ok(Some(rect))
+}
+
+// ?client=, for the requests that can also be made anonymously
+fn client(req: &http::Request) -> String {
+    req.query("client").unwrap_or_else(|| "anonymous".into())
+}
+
+fn required(req: &http::Request, name: &str) -> Result<String, String> {
+    req.query(name)
+        .filter(|v| !v.is_empty())
+        .ok_or_else(|| format!("missing ?{name}="))
+}
+
+// the picture in an upload, whichever way it was sent
+fn picture(req: &http::Request) -> Result<bmp::Image, String> {
+    let bytes = match req.header("Content-Type") {
+        Some(t) if t.starts_with("multipart/form-data") => http::multipart_file(&req.body, t)?,
+        // truncated or corrupted frames are refused before the panel sees
+        // them. its colors are all the panel's, so dithering keeps them.
+        Some(t) if t.starts_with(frame::CONTENT_TYPE) => {
+            if req.query("region").is_some() {
+                return Err("a packed frame covers the whole panel, not a region".into());
+            }
+            return PackedFrame::read_from(&mut &req.body[..]).map(|f| preview::render(&f));
+        }
+        _ => &req.body[..],
+    };
+    if bytes.is_empty() {
+        return Err("no picture in the request".into());
+    }
+    let img =
+        image::load_from_memory(bytes).map_err(|e| format!("could not decode picture: {e}"))?;
+    Ok(decode::from_rgb(&img.to_rgb8()))
+}
+
+// the job a request asks for, on an `area` sized panel
+fn job(req: &http::Request, area: Geometry) -> Result<Job, (u16, String)> {
+    let bad = |e: String| (400, e);
+    Ok(match (req.method.as_str(), req.route()) {
+        ("POST", "/image") => Job::Image {
+            img: picture(req).map_err(bad)?,
+            client: client(req),
+            region: rect(req, area).map_err(bad)?,
+        },
+        ("POST", "/clear") => Job::Clear {
+            client: client(req),
+        },
+        ("POST", "/notify") => Job::Notify {
+            notification: serde_json::from_slice(&req.body)
+                .map_err(|e| format!("invalid json: {e}"))
+                .and_then(|v| Notification::from_json(&v))
+                .map_err(bad)?,
+            client: client(req),
+        },
+        ("POST", "/lease") => Job::Lease {
+            client: required(req, "client").map_err(bad)?,
+            name: required(req, "name").map_err(bad)?,
+            rect: rect(req, area)
+                .and_then(|r| r.ok_or_else(|| "missing ?region=".into()))
+                .map_err(bad)?,
+            ttl: req
+                .query("ttl")
+                .map_or(Ok(DEFAULT_TTL), |t| script::parse_duration(&t))
+                .map_err(bad)?,
+        },
+        ("DELETE", "/lease") => Job::Release {
+            client: required(req, "client").map_err(bad)?,
+            name: required(req, "name").map_err(bad)?,
+        },
+        (_, "/image" | "/clear" | "/notify") => return Err((405, "use POST".into())),
+        (_, "/lease") => return Err((405, "use POST or DELETE".into())),
+        _ => return Err((404, "not found".into())),
+    })
+}
+
+// accepts requests on `addr` for an `area` sized panel in the background,
+// queueing jobs on `to` and answering status and thumbnail requests from
+// `status`
+pub fn listen(
+    addr: &str,
+    area: Geometry,
+    to: Sender<Queued>,
+    status: Arc<Mutex<Status>>,
+) -> Result<(), String> {
+    let listener =
+        TcpListener::bind(addr).map_err(|e| format!("could not listen on {addr}: {e}"))?;
+    thread::spawn(move || {
+        for stream in listener.incoming().filter_map(Result::ok) {
+            let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+            let req = match http::read_request(&stream) {
+                Ok(req) => req,
+                Err(e) => {
+                    http::respond(&stream, 400, &json!({ "error": e }));
+                    continue;
+                }
+            };
+            match (req.method.as_str(), req.route()) {
+                ("GET", "/status") => {
+                    http::respond(&stream, 200, &status.lock().unwrap().to_json());
+                    continue;
+                }
+                ("GET", "/thumbnail") => {
+                    match &status.lock().unwrap().thumbnail {
+                        Some(png) => http::respond_with(&stream, 200, "image/png", png),
+                        None => {
+                            http::respond(&stream, 404, &json!({ "error": "nothing drawn yet" }))
+                        }
+                    }
+                    continue;
+                }
+                (_, "/status" | "/thumbnail") => {
+                    http::respond(&stream, 405, &json!({ "error": "use GET" }));
+                    continue;
+                }
+                _ => {}
+            }
+            match job(&req, area) {
+                Ok(job) => {
+                    status.lock().unwrap().queued += 1;
+                    if to.send(Queued { job, stream }).is_err() {
+                        return;
+                    }
+                }
+                Err((code, e)) => http::respond(&stream, code, &json!({ "error": e })),
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::panel::{Bwr420, Panel};
+
+    fn req(method: &str, path: &str) -> http::Request {
+        http::Request {
+            method: method.into(),
+            path: path.into(),
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn regions_have_to_lie_on_the_panel() {
+        let region = |r: &str, area| rect(&req("POST", &format!("/image?region={r}")), area);
+        let r = region("590,440,10,8", Geometry::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!((r.x, r.y, r.w, r.h), (590, 440, 10, 8));
+        assert!(region("590,440,11,8", Geometry::default()).is_err());
+        assert!(region("0,0,0,8", Geometry::default())
+            .err()
+            .unwrap()
+            .contains("empty"));
+        assert!(region("4294967295,0,2,2", Geometry::default()).is_err());
+        // a smaller panel only takes regions in its own pixels
+        assert!(region("0,0,400,300", Bwr420.geometry()).is_ok());
+        assert!(region("0,0,401,300", Bwr420.geometry())
+            .err()
+            .unwrap()
+            .contains("off the 400x300 panel"));
+        assert!(region("0,290,10,20", Bwr420.geometry()).is_err());
+        assert!(rect(&req("POST", "/image"), Geometry::default())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn requests_map_to_jobs() {
+        let area = Geometry::default();
+        let status = |r: http::Request| job(&r, area).err().map(|(code, _)| code);
+        assert_eq!(status(req("GET", "/image")), Some(405));
+        assert_eq!(status(req("PUT", "/lease")), Some(405));
+        assert_eq!(status(req("POST", "/nowhere")), Some(404));
+        // a lease is only taken with a name, a client and a region
+        assert_eq!(status(req("POST", "/lease?client=a&name=b")), Some(400));
+        assert_eq!(
+            status(req("POST", "/lease?name=b&region=0,0,8,8")),
+            Some(400)
+        );
+        assert_eq!(status(req("POST", "/image")), Some(400));
+        match job(
+            &req("POST", "/lease?client=a&name=b&region=0,0,8,8&ttl=5m"),
+            area,
+        ) {
+            Ok(Job::Lease { ttl, rect, .. }) => {
+                assert_eq!(ttl, Duration::from_secs(300));
+                assert_eq!((rect.w, rect.h), (8, 8));
+            }
+            _ => panic!("not a lease"),
+        }
+        match job(&req("POST", "/clear"), area) {
+            Ok(Job::Clear { client }) => assert_eq!(client, "anonymous"),
+            _ => panic!("not a clear"),
+        }
+        let mut frame = req("POST", "/image?region=0,0,8,8");
+        frame
+            .headers
+            .push(("Content-Type".into(), frame::CONTENT_TYPE.into()));
+        assert_eq!(status(frame), Some(400));
+    }
+}
@@ -0,0 +1,450 @@
+use std::{
+    cell::Cell,
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    process::Command,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use tracing::warn;
+
+use crate::{
+    cmd,
+    draw::Drawable,
+    error,
+    frame::{PackedFrame, PACKED_LEN},
+    SpiDevice, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+// how long the panel reports busy after each command, at 25C
+pub struct BusyModel {
+    pub reset: Duration,
+    pub power_on: Duration,
+    pub refresh: Duration,
+    pub power_off: Duration,
+    // ambient temperature in C. ACeP refreshes slow down in the cold.
+    pub temperature: f32,
+}
+
+impl Default for BusyModel {
+    fn default() -> Self {
+        Self {
+            reset: Duration::from_millis(20),
+            power_on: Duration::from_millis(100),
+            refresh: Duration::from_secs(30),
+            power_off: Duration::from_millis(100),
+            temperature: 25.0,
+        }
+    }
+}
+
+impl BusyModel {
+    // roughly 4% slower per degree below 25C, and no faster above it
+    fn scale(&self) -> f32 {
+        1.0 + (25.0 - self.temperature).max(0.0) * 0.04
+    }
+
+    fn busy_for(&self, cmd: u8) -> Option<Duration> {
+        let d = match cmd {
+            0x04 => self.power_on,
+            0x12 => self.refresh,
+            0x02 => self.power_off,
+            _ => return None,
+        };
+        Some(d.mul_f32(self.scale()))
+    }
+}
+
+// where busy waits spend their time. the virtual clock just advances, so a
+// 30s refresh takes no wall time.
+pub enum Clock {
+    Real,
+    Virtual(Cell<Duration>),
+}
+
+impl Clock {
+    fn now(&self, start: Instant) -> Duration {
+        match self {
+            Clock::Real => start.elapsed(),
+            Clock::Virtual(t) => t.get(),
+        }
+    }
+
+    fn wait_until(&self, start: Instant, t: Duration) {
+        match self {
+            Clock::Real => sleep(t.saturating_sub(start.elapsed())),
+            Clock::Virtual(now) => now.set(now.get().max(t)),
+        }
+    }
+}
+
+// a stand-in for the panel that decodes the frame stream and models busy
+// timing, for running the full command flow without hardware
+pub struct SimPanel {
+    pub model: BusyModel,
+    pub clock: Clock,
+    start: Instant,
+    busy_until: Cell<Duration>,
+    cmd: u8,
+    ram: Vec<u8>,
+    // between PowerOn and PowerOff
+    powered: bool,
+    // between PartialIn and PartialOut, and the window the data goes to
+    partial: bool,
+    window: Option<(u16, u16, u16, u16)>,
+    // the frame latched by the last refresh
+    pub shown: Option<PackedFrame>,
+    pub refreshes: Vec<Duration>,
+    refresh_started: Option<Duration>,
+    pub recorder: Option<Recorder>,
+}
+
+impl SimPanel {
+    pub fn new(model: BusyModel, clock: Clock) -> Self {
+        Self {
+            model,
+            clock,
+            start: Instant::now(),
+            busy_until: Cell::new(Duration::ZERO),
+            cmd: 0,
+            ram: Vec::new(),
+            powered: false,
+            partial: false,
+            window: None,
+            shown: None,
+            refreshes: Vec::new(),
+            refresh_started: None,
+            recorder: None,
+        }
+    }
+
+    // simulated time since the panel was created
+    pub fn elapsed(&self) -> Duration {
+        self.clock.now(self.start)
+    }
+
+    // a partial window's data laid over the frame shown before it
+    fn compose(&self, (x, y, w, h): (u16, u16, u16, u16)) -> PackedFrame {
+        let mut data = match &self.shown {
+            Some(f) if f.data.len() == PACKED_LEN => f.data.clone(),
+            _ => vec![0x11; PACKED_LEN],
+        };
+        let row = SCREEN_WIDTH as usize / 2;
+        let bytes = w as usize / 2;
+        for (i, line) in self.ram.chunks(bytes.max(1)).take(h as usize).enumerate() {
+            let at = (y as usize + i) * row + x as usize / 2;
+            if let Some(dst) = data.get_mut(at..at + line.len()) {
+                dst.copy_from_slice(line);
+            }
+        }
+        PackedFrame { data }
+    }
+
+    fn wait_idle(&self) {
+        self.clock.wait_until(self.start, self.busy_until.get());
+    }
+
+    // the temperature reads as the model's and the status as the panel's
+    // power and busy line, anything else as zeros
+    fn answer(&self, buf: &mut [u8]) {
+        let answer = match self.cmd {
+            0x40 => cmd::temperature_bytes(self.model.temperature).to_vec(),
+            0x71 => {
+                let idle = self.elapsed() >= self.busy_until.get();
+                let power = if self.powered { 0b100 } else { 0b010 };
+                // the i2c bit is high while no sensor is being talked to
+                vec![0b1_0000 | power | idle as u8]
+            }
+            _ => Vec::new(),
+        };
+        buf.fill(0);
+        let n = buf.len().min(answer.len());
+        buf[..n].copy_from_slice(&answer[..n]);
+    }
+}
+
+impl SpiDevice for SimPanel {
+    fn send_cmd(&mut self, cmd: u8) -> error::Result<()> {
+        let now = self.elapsed();
+        if let Some(started) = self.refresh_started.take() {
+            self.refreshes.push(now - started);
+        }
+        self.cmd = cmd;
+        match cmd {
+            0x10 | 0x90 => self.ram.clear(),
+            0x04 => self.powered = true,
+            0x02 => self.powered = false,
+            0x91 => self.partial = true,
+            0x92 => self.partial = false,
+            0x12 => {
+                let frame = match (self.partial, self.window) {
+                    (true, Some(window)) => self.compose(window),
+                    _ => PackedFrame {
+                        data: self.ram.clone(),
+                    },
+                };
+                if let Some(rec) = &mut self.recorder {
+                    if frame.data.len() == PACKED_LEN {
+                        rec.push(now, &frame);
+                    }
+                }
+                self.shown = Some(frame);
+                self.refresh_started = Some(now);
+            }
+            _ => {}
+        }
+        if let Some(d) = self.model.busy_for(cmd) {
+            self.busy_until.set(now + d);
+        }
+        Ok(())
+    }
+
+    fn send_data(&mut self, data: &[u8]) -> error::Result<()> {
+        if self.cmd == 0x10 || self.cmd == 0x90 {
+            self.ram.extend_from_slice(data);
+        }
+        if let (0x90, &[hs1, hs0, he1, he0, vs1, vs0, ve1, ve0, _]) = (self.cmd, &self.ram[..]) {
+            let (x0, x1) = (
+                u16::from_be_bytes([hs1, hs0]),
+                u16::from_be_bytes([he1, he0]),
+            );
+            let (y0, y1) = (
+                u16::from_be_bytes([vs1, vs0]),
+                u16::from_be_bytes([ve1, ve0]),
+            );
+            self.window = Some((x0, y0, x1 + 1 - x0, y1 + 1 - y0));
+        }
+        Ok(())
+    }
+
+    fn read_data(&mut self, buf: &mut [u8]) -> error::Result<()> {
+        self.answer(buf);
+        Ok(())
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> error::Result<()> {
+        self.send_data(write)?;
+        self.answer(read);
+        Ok(())
+    }
+
+    fn wait_busy_high(&self) -> error::Result<()> {
+        self.wait_idle();
+        Ok(())
+    }
+
+    fn wait_busy_low(&self) -> error::Result<()> {
+        self.wait_idle();
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        let now = self.elapsed();
+        self.busy_until.set(now + self.model.reset);
+        self.cmd = 0;
+        self.powered = false;
+    }
+}
+
+// the last refreshed frame, if a whole one was sent
+pub fn shown_frame(panel: &SimPanel) -> Option<&dyn Drawable> {
+    panel
+        .shown
+        .as_ref()
+        .filter(|f| f.data.len() == PACKED_LEN)
+        .map(|f| f as &dyn Drawable)
+}
+
+// how long the last frame of a recording stays up
+const FINAL_HOLD: Duration = Duration::from_secs(2);
+
+// records every refresh with its simulated time. the video is rewritten
+// after each one, so interrupting a looping mode still leaves a recording.
+pub struct Recorder {
+    path: PathBuf,
+    frames: Vec<(Duration, Vec<u8>)>,
+}
+
+impl Recorder {
+    // .png writes an apng, anything else is handed to ffmpeg
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            frames: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, at: Duration, frame: &PackedFrame) {
+        let img = crate::preview::render(frame);
+        let mut rgb = Vec::with_capacity(PACKED_LEN * 6);
+        for y in 0..img.get_height() {
+            for x in 0..img.get_width() {
+                let px = img.get_pixel(x, y);
+                rgb.extend([px.r, px.g, px.b]);
+            }
+        }
+        self.frames.push((at, rgb));
+        if let Err(e) = self.write() {
+            warn!("could not write {}: {e}", self.path.display());
+        }
+    }
+
+    // how long each frame stays on screen
+    fn durations(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.frames.iter().enumerate().map(|(i, (at, _))| {
+            self.frames
+                .get(i + 1)
+                .map_or(FINAL_HOLD, |(next, _)| *next - *at)
+        })
+    }
+
+    fn write(&self) -> Result<(), String> {
+        match self.path.extension().and_then(|e| e.to_str()) {
+            Some("png" | "apng") => self.write_apng(),
+            _ => self.write_ffmpeg(),
+        }
+    }
+
+    fn write_apng(&self) -> Result<(), String> {
+        let err = |e: png::EncodingError| e.to_string();
+        let file = File::create(&self.path).map_err(|e| e.to_string())?;
+        let mut enc = png::Encoder::new(
+            BufWriter::new(file),
+            SCREEN_WIDTH as u32,
+            SCREEN_HEIGHT as u32,
+        );
+        enc.set_color(png::ColorType::Rgb);
+        enc.set_depth(png::BitDepth::Eight);
+        enc.set_animated(self.frames.len() as u32, 0).map_err(err)?;
+        let mut writer = enc.write_header().map_err(err)?;
+        for ((_, rgb), d) in self.frames.iter().zip(self.durations()) {
+            // centiseconds, clamped to what the delay field holds
+            let cs = (d.as_millis() / 10).clamp(1, u16::MAX as u128) as u16;
+            writer.set_frame_delay(cs, 100).map_err(err)?;
+            writer.write_image_data(rgb).map_err(err)?;
+        }
+        writer.finish().map_err(err)
+    }
+
+    // writes the frames as pngs next to a concat list and lets ffmpeg encode
+    // them with their real durations
+    fn write_ffmpeg(&self) -> Result<(), String> {
+        let dir = self.path.with_extension("frames");
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let mut list = String::new();
+        for (i, ((_, rgb), d)) in self.frames.iter().zip(self.durations()).enumerate() {
+            let png = dir.join(format!("{i:05}.png"));
+            if !png.exists() {
+                image::save_buffer(
+                    &png,
+                    rgb,
+                    SCREEN_WIDTH as u32,
+                    SCREEN_HEIGHT as u32,
+                    image::ColorType::Rgb8,
+                )
+                .map_err(|e| e.to_string())?;
+            }
+            list += &format!("file '{}'\nduration {}\n", png.display(), d.as_secs_f64());
+        }
+        // the concat demuxer ignores the last duration unless the file repeats
+        if let Some(last) = self.frames.len().checked_sub(1) {
+            list += &format!("file '{}'\n", dir.join(format!("{last:05}.png")).display());
+        }
+        let list_path = dir.join("frames.txt");
+        fs::write(&list_path, list).map_err(|e| e.to_string())?;
+        let status = Command::new("ffmpeg")
+            .args([
+                "-loglevel",
+                "error",
+                "-y",
+                "-f",
+                "concat",
+                "-safe",
+                "0",
+                "-i",
+            ])
+            .arg(&list_path)
+            .args(["-vf", "format=yuv420p", "-fps_mode", "vfr"])
+            .arg(&self.path)
+            .status()
+            .map_err(|e| format!("could not run ffmpeg: {e}"))?;
+        if !status.success() {
+            return Err(format!("ffmpeg exited with {status}"));
+        }
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn virtual_panel(model: BusyModel) -> SimPanel {
+        SimPanel::new(model, Clock::Virtual(Default::default()))
+    }
+
+    #[test]
+    fn the_cold_slows_refreshes_down() {
+        let warm = BusyModel::default();
+        assert_eq!(warm.busy_for(0x12), Some(Duration::from_secs(30)));
+        assert_eq!(warm.busy_for(0x10), None);
+        let cold = BusyModel {
+            temperature: 0.0,
+            ..BusyModel::default()
+        };
+        assert_eq!(cold.busy_for(0x12), Some(Duration::from_secs(60)));
+        let hot = BusyModel {
+            temperature: 40.0,
+            ..BusyModel::default()
+        };
+        assert_eq!(hot.busy_for(0x04).unwrap().as_millis(), 100);
+    }
+
+    #[test]
+    fn busy_waits_advance_the_virtual_clock() {
+        let wall = Instant::now();
+        let mut panel = virtual_panel(BusyModel::default());
+        let mut status = [0];
+        panel.send_cmd(0x12).unwrap();
+        panel.read_data(&mut status).unwrap();
+        assert_eq!(status[0], 0);
+        panel.wait_busy_high().unwrap();
+        assert_eq!(panel.elapsed(), Duration::from_secs(30));
+        // the next command closes off the refresh
+        panel.send_cmd(0x71).unwrap();
+        panel.read_data(&mut status).unwrap();
+        assert_eq!(status[0] & 1, 1, "idle once the refresh is done");
+        assert_eq!(panel.refreshes, [Duration::from_secs(30)]);
+        assert!(wall.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn partial_windows_land_on_the_shown_frame() {
+        let mut panel = virtual_panel(BusyModel::default());
+        panel.send_cmd(0x10).unwrap();
+        panel.send_data(&vec![0x11; PACKED_LEN]).unwrap();
+        panel.send_cmd(0x12).unwrap();
+        assert!(shown_frame(&panel).is_some());
+        // an 8x2 window at (4, 1)
+        panel.send_cmd(0x91).unwrap();
+        panel.send_cmd(0x90).unwrap();
+        panel.send_data(&[0, 4, 0, 11, 0, 1, 0, 2, 1]).unwrap();
+        panel.send_cmd(0x10).unwrap();
+        panel.send_data(&[0x22; 8]).unwrap();
+        panel.send_cmd(0x12).unwrap();
+        panel.send_cmd(0x92).unwrap();
+        let data = &panel.shown.as_ref().unwrap().data;
+        let row = SCREEN_WIDTH as usize / 2;
+        assert_eq!(&data[row + 2..row + 6], &[0x22; 4]);
+        assert_eq!(&data[2 * row + 2..2 * row + 6], &[0x22; 4]);
+        assert_eq!(data[row + 1], 0x11);
+        assert_eq!(data[row + 6], 0x11);
+        assert!(data[..row].iter().all(|&b| b == 0x11));
+    }
+}
@@ -0,0 +1,378 @@
+// where the daemon gets its pictures from. a new kind of content is one more
+// ImageSource rather than another loop in main.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{mpsc::Sender, Arc, Mutex},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use tracing::{info, warn};
+
+use crate::{decode, mqtt, payload, scene, store::Store, web};
+
+pub trait ImageSource {
+    // what is being shown, for log lines
+    fn name(&self) -> String;
+    // the next picture to show, or None if there is nothing new
+    fn next_frame(&mut self) -> Result<Option<bmp::Image>, String>;
+    // how long to wait before asking again, None for the daemon's default
+    fn interval(&self) -> Option<Duration> {
+        None
+    }
+    // hands over a channel to send on when there is something new before
+    // the interval is up
+    fn notify(&mut self, _invalidate: Sender<()>) {}
+}
+
+// cycles through the pictures in a directory. it is listed again each
+// time, so pictures added later join in.
+pub struct Directory {
+    store: Store,
+    next: usize,
+    shown: Option<PathBuf>,
+}
+
+impl Directory {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            store: Store::new(dir),
+            next: 0,
+            shown: None,
+        }
+    }
+}
+
+impl ImageSource for Directory {
+    fn name(&self) -> String {
+        let path = self.shown.as_deref().unwrap_or(self.store.dir());
+        path.display().to_string()
+    }
+
+    fn next_frame(&mut self) -> Result<Option<bmp::Image>, String> {
+        let images = self.store.list()?;
+        if images.is_empty() {
+            return Err(format!("{} has no pictures", self.store.dir().display()));
+        }
+        let (_, path) = &images[self.next % images.len()];
+        self.next = (self.next % images.len()) + 1;
+        self.shown = Some(path.clone());
+        decode::open(path).map(Some).map_err(|e| e.to_string())
+    }
+}
+
+// the frames of an animated gif, one per interval, starting over after the
+// last. a gif of a single frame is shown once.
+pub struct Animation {
+    path: PathBuf,
+    frames: Option<image::Frames<'static>>,
+    // of the current pass, None before the first
+    shown: Option<usize>,
+    // frames in a pass, known once the first one is done
+    total: Option<usize>,
+}
+
+impl Animation {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            frames: None,
+            shown: None,
+            total: None,
+        }
+    }
+
+    fn decode_next(&mut self) -> Result<Option<image::Frame>, String> {
+        let frames = match &mut self.frames {
+            Some(frames) => frames,
+            None => self
+                .frames
+                .insert(decode::frames(&self.path).map_err(|e| e.to_string())?),
+        };
+        frames
+            .next()
+            .transpose()
+            .map_err(|e| format!("could not decode {}: {e}", self.path.display()))
+    }
+}
+
+impl ImageSource for Animation {
+    fn name(&self) -> String {
+        match (self.shown, self.total) {
+            (Some(i), Some(total)) => format!("{} frame {}/{total}", self.path.display(), i + 1),
+            (Some(i), None) => format!("{} frame {}", self.path.display(), i + 1),
+            _ => self.path.display().to_string(),
+        }
+    }
+
+    fn next_frame(&mut self) -> Result<Option<bmp::Image>, String> {
+        if self.total == Some(1) {
+            return Ok(None);
+        }
+        let frame = match self.decode_next()? {
+            Some(frame) => frame,
+            None => {
+                // the end of a pass, so from the top again
+                let total = self.shown.map_or(0, |i| i + 1);
+                if total == 0 {
+                    return Err(format!("{} has no frames", self.path.display()));
+                }
+                self.total = Some(total);
+                if total == 1 {
+                    return Ok(None);
+                }
+                self.frames = None;
+                self.shown = None;
+                self.decode_next()?
+                    .ok_or_else(|| format!("{} has no frames", self.path.display()))?
+            }
+        };
+        self.shown = Some(self.shown.map_or(0, |i| i + 1));
+        Ok(Some(decode::from_frame(frame)))
+    }
+}
+
+// a gif's frames, or a directory's pictures
+pub fn slideshow(path: &Path) -> Box<dyn ImageSource> {
+    if path.is_dir() {
+        Box::new(Directory::new(path))
+    } else {
+        Box::new(Animation::new(path))
+    }
+}
+
+// a web page screenshotted with a headless browser on every interval
+pub struct Url {
+    pub browser: String,
+    pub url: String,
+}
+
+impl ImageSource for Url {
+    fn name(&self) -> String {
+        self.url.clone()
+    }
+
+    fn next_frame(&mut self) -> Result<Option<bmp::Image>, String> {
+        web::render_url(&self.browser, &self.url).map(Some)
+    }
+}
+
+// pictures and draw commands published to an mqtt topic, as read by
+// payload::picture. a lost broker is reconnected to, waiting longer after
+// each failed attempt.
+pub struct Mqtt {
+    pub broker: String,
+    pub topic: String,
+    latest: Arc<Mutex<Option<bmp::Image>>>,
+}
+
+// the wait before the first reconnect, doubled up to the most after each
+// failed one
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+impl Mqtt {
+    pub fn new(broker: &str, topic: &str) -> Self {
+        Self {
+            broker: broker.to_string(),
+            topic: topic.to_string(),
+            latest: Arc::default(),
+        }
+    }
+}
+
+impl ImageSource for Mqtt {
+    fn name(&self) -> String {
+        format!("{} on {}", self.topic, self.broker)
+    }
+
+    fn next_frame(&mut self) -> Result<Option<bmp::Image>, String> {
+        Ok(self.latest.lock().unwrap().take())
+    }
+
+    fn notify(&mut self, invalidate: Sender<()>) {
+        let (broker, topic, latest) =
+            (self.broker.clone(), self.topic.clone(), self.latest.clone());
+        let id = format!("rpi-epaper-{}", std::process::id());
+        thread::spawn(move || {
+            let mut backoff = MIN_BACKOFF;
+            loop {
+                match mqtt::Client::connect(&broker, &id)
+                    .and_then(|mut c| c.subscribe(&topic).map(|_| c))
+                {
+                    Ok(mut client) => {
+                        info!("Subscribed to {topic} on {broker}");
+                        backoff = MIN_BACKOFF;
+                        let lost = loop {
+                            let msg = match client.next_message() {
+                                Ok(msg) => msg,
+                                Err(e) => break e,
+                            };
+                            // a bad payload leaves the last good picture up
+                            match payload::picture(&msg.payload) {
+                                Ok(img) => {
+                                    *latest.lock().unwrap() = Some(img);
+                                    if invalidate.send(()).is_err() {
+                                        return;
+                                    }
+                                }
+                                Err(e) => warn!("message on {}: {e}", msg.topic),
+                            }
+                        };
+                        warn!("lost {broker}: {lost}");
+                    }
+                    Err(e) => warn!("could not subscribe to {topic} on {broker}: {e}"),
+                }
+                warn!("reconnecting in {backoff:?}");
+                thread::sleep(backoff);
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        });
+    }
+}
+
+// a scene file, reloaded when it changes. the pages of a paginated scene
+// take turns.
+pub struct Scene {
+    pub path: String,
+    pages: Vec<bmp::Image>,
+    next: usize,
+    loaded: Option<SystemTime>,
+}
+
+impl Scene {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            pages: Vec::new(),
+            next: 0,
+            loaded: None,
+        }
+    }
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl ImageSource for Scene {
+    fn name(&self) -> String {
+        self.path.clone()
+    }
+
+    fn next_frame(&mut self) -> Result<Option<bmp::Image>, String> {
+        let changed = modified(&self.path);
+        if self.pages.is_empty() || changed != self.loaded {
+            self.pages = scene::load(&self.path)?.render().pages;
+            self.loaded = changed;
+            self.next = 0;
+        } else if self.pages.len() == 1 {
+            return Ok(None);
+        }
+        let page = self.pages[self.next].clone();
+        self.next = (self.next + 1) % self.pages.len();
+        Ok(Some(page))
+    }
+
+    fn notify(&mut self, invalidate: Sender<()>) {
+        let path = self.path.clone();
+        thread::spawn(move || {
+            let mut last = modified(&path);
+            loop {
+                thread::sleep(Duration::from_secs(1));
+                let now = modified(&path);
+                if now != last {
+                    last = now;
+                    if invalidate.send(()).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{codecs::gif::GifEncoder, Frame, Rgba, RgbaImage};
+
+    use super::*;
+
+    // an empty directory for `test` to fill
+    fn dir(test: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("rpi-epaper-source-{test}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn picture(path: &Path, px: bmp::Pixel) {
+        let mut img = bmp::Image::new(2, 2);
+        for (x, y) in img.coordinates() {
+            img.set_pixel(x, y, px);
+        }
+        img.save(path).unwrap();
+    }
+
+    fn gif(path: &Path, colors: &[[u8; 4]]) {
+        let frames = colors
+            .iter()
+            .map(|&c| Frame::new(RgbaImage::from_pixel(2, 2, Rgba(c))));
+        GifEncoder::new(fs::File::create(path).unwrap())
+            .encode_frames(frames)
+            .unwrap();
+    }
+
+    fn first_pixel(source: &mut dyn ImageSource) -> Option<bmp::Pixel> {
+        source.next_frame().unwrap().map(|img| img.get_pixel(0, 0))
+    }
+
+    #[test]
+    fn a_directory_cycles_by_name_and_sees_new_pictures() {
+        let dir = dir("cycle");
+        picture(&dir.join("b.bmp"), bmp::consts::BLUE);
+        picture(&dir.join("a.bmp"), bmp::consts::RED);
+        let mut source = Directory::new(&dir);
+        assert_eq!(first_pixel(&mut source), Some(bmp::consts::RED));
+        assert!(source.name().ends_with("a.bmp"));
+        assert_eq!(first_pixel(&mut source), Some(bmp::consts::BLUE));
+        assert_eq!(first_pixel(&mut source), Some(bmp::consts::RED));
+        picture(&dir.join("c.bmp"), bmp::consts::LIME);
+        assert_eq!(first_pixel(&mut source), Some(bmp::consts::BLUE));
+        assert_eq!(first_pixel(&mut source), Some(bmp::consts::LIME));
+    }
+
+    #[test]
+    fn an_empty_directory_fails() {
+        let dir = dir("empty");
+        fs::write(dir.join("notes.txt"), b"").unwrap();
+        let e = Directory::new(&dir).next_frame().err().unwrap();
+        assert!(e.contains("has no pictures"), "{e}");
+    }
+
+    #[test]
+    fn an_animation_starts_over_after_its_last_frame() {
+        let path = dir("animation").join("a.gif");
+        gif(&path, &[[255, 0, 0, 255], [0, 0, 255, 255]]);
+        let mut source = Animation::new(&path);
+        assert_eq!(first_pixel(&mut source), Some(bmp::consts::RED));
+        assert!(source.name().ends_with("frame 1"));
+        assert_eq!(first_pixel(&mut source), Some(bmp::consts::BLUE));
+        assert_eq!(first_pixel(&mut source), Some(bmp::consts::RED));
+        // the length is known once a pass is done
+        assert!(source.name().ends_with("frame 1/2"));
+    }
+
+    #[test]
+    fn a_still_gif_is_shown_once() {
+        let path = dir("still").join("a.gif");
+        gif(&path, &[[255, 0, 0, 255]]);
+        let mut source = Animation::new(&path);
+        assert_eq!(first_pixel(&mut source), Some(bmp::consts::RED));
+        assert_eq!(first_pixel(&mut source), None);
+        assert_eq!(first_pixel(&mut source), None);
+    }
+}
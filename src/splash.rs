@@ -0,0 +1,63 @@
+use std::{fs, net::UdpSocket};
+
+use crate::{draw::Color, font, layout, localtime::Clock, Rgb, SCREEN_WIDTH};
+
+fn hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .or_else(|_| fs::read_to_string("/etc/hostname"))
+        .map(|s| s.trim().to_string())
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+// the address of the interface the default route goes out of. connecting
+// a udp socket only picks a route, nothing is sent.
+fn local_ip() -> Option<String> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("8.8.8.8:80").ok()?;
+    Some(socket.local_addr().ok()?.ip().to_string())
+}
+
+fn centered(img: &mut bmp::Image, text: &str, y: u16, scale: u16, color: Color) {
+    let (w, _) = font::text_size(text, scale);
+    let x = SCREEN_WIDTH.saturating_sub(w) / 2;
+    font::draw_text(img, text, x, y, scale, Rgb::from(color).into());
+}
+
+// shown on start so a headless panel says where to find it
+pub fn splash(clock: &Clock) -> bmp::Image {
+    let mut img = layout::blank(Color::White);
+    centered(&mut img, "rpi-epaper", 90, 7, Color::Black);
+    centered(
+        &mut img,
+        concat!("v", env!("CARGO_PKG_VERSION")),
+        150,
+        2,
+        Color::Orange,
+    );
+    centered(&mut img, &hostname(), 220, 4, Color::Blue);
+    let ip = local_ip().unwrap_or_else(|| "no network".to_string());
+    centered(&mut img, &ip, 270, 3, Color::Black);
+    centered(
+        &mut img,
+        &format!("started {}", clock.now()),
+        380,
+        2,
+        Color::Black,
+    );
+    img
+}
+
+// left up after a clean shutdown, so a stale panel doesn't look live
+pub fn offline(clock: &Clock) -> bmp::Image {
+    let mut img = layout::blank(Color::White);
+    centered(&mut img, "display offline", 170, 5, Color::Red);
+    centered(&mut img, &hostname(), 240, 3, Color::Black);
+    centered(
+        &mut img,
+        &format!("since {}", clock.now()),
+        290,
+        2,
+        Color::Black,
+    );
+    img
+}
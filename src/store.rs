@@ -0,0 +1,189 @@
+// a directory of pictures looked up by name, so bindings and remote
+// requests can say `@sunset` instead of a full path
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+// the files that can be drawn
+const EXTENSIONS: &[&str] = &["bmp", "png", "jpg", "jpeg", "gif", "webp"];
+
+// ~/.config/rpi-epaper/images, next to the palette
+pub fn default_dir() -> PathBuf {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+        .unwrap_or_else(|| PathBuf::from("."));
+    base.join("rpi-epaper").join("images")
+}
+
+pub struct Store {
+    dir: PathBuf,
+}
+
+impl Store {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // every picture in the store by name, sorted
+    pub fn list(&self) -> Result<Vec<(String, PathBuf)>, String> {
+        let entries = fs::read_dir(&self.dir)
+            .map_err(|e| format!("could not read {}: {e}", self.dir.display()))?;
+        let mut images: Vec<(String, PathBuf)> = entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.is_file())
+            .filter(|p| {
+                p.extension()
+                    .and_then(|e| e.to_str())
+                    .is_some_and(|e| EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+            })
+            .filter_map(|p| Some((p.file_stem()?.to_str()?.to_string(), p)))
+            .collect();
+        images.sort_by_key(|(n, _)| n.to_lowercase());
+        Ok(images)
+    }
+
+    // finds a picture by name. an exact match (ignoring case) wins, then a
+    // unique prefix, then a unique substring, then the only name within two
+    // typos.
+    pub fn find(&self, name: &str) -> Result<PathBuf, String> {
+        let images = self.list()?;
+        // the full file name picks between pictures sharing a name
+        let file_name = |p: &PathBuf| {
+            p.file_name()
+                .and_then(|f| f.to_str())
+                .map(str::to_lowercase)
+        };
+        let want = name.to_lowercase();
+        if let Some((_, path)) = images
+            .iter()
+            .find(|(_, p)| file_name(p).as_ref() == Some(&want))
+        {
+            return Ok(path.clone());
+        }
+        let lower: Vec<String> = images.iter().map(|(n, _)| n.to_lowercase()).collect();
+        let passes: [&dyn Fn(&str) -> bool; 4] = [
+            &|n| n == want,
+            &|n| n.starts_with(&want),
+            &|n| n.contains(&want),
+            &|n| edit_distance(n, &want) <= 2,
+        ];
+        for pass in passes {
+            let hits: Vec<usize> = (0..images.len()).filter(|&i| pass(&lower[i])).collect();
+            match hits[..] {
+                [] => continue,
+                [i] => return Ok(images[i].1.clone()),
+                _ => {
+                    let names: Vec<String> = hits
+                        .iter()
+                        .filter_map(|&i| file_name(&images[i].1))
+                        .collect();
+                    return Err(format!("'{name}' could be any of {}", names.join(", ")));
+                }
+            }
+        }
+        Err(format!("no image named '{name}' in {}", self.dir.display()))
+    }
+
+    // `@name` is looked up in the store, anything else is a path
+    pub fn resolve(&self, arg: &str) -> Result<PathBuf, String> {
+        match arg.strip_prefix('@') {
+            Some(name) => self.find(name),
+            None => Ok(PathBuf::from(arg)),
+        }
+    }
+}
+
+// levenshtein distance over chars
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cur = row[j + 1];
+            row[j + 1] = (prev + (ca != *cb) as usize).min(row[j] + 1).min(cur + 1);
+            prev = cur;
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a fresh directory holding empty files of these names
+    fn store(test: &str, files: &[&str]) -> Store {
+        let dir = env::temp_dir().join(format!("rpi-epaper-store-{test}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for f in files {
+            fs::write(dir.join(f), b"").unwrap();
+        }
+        Store::new(dir)
+    }
+
+    fn found(store: &Store, name: &str) -> String {
+        let path = store.find(name).unwrap();
+        path.file_name().unwrap().to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn edit_distance_counts_single_char_edits() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("sunset", "sunset"), 0);
+        assert_eq!(edit_distance("sunset", "sunst"), 1);
+        assert_eq!(edit_distance("sunset", "sunsrt"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("héllo", "hello"), 1);
+    }
+
+    #[test]
+    fn lists_only_pictures() {
+        let s = store("list", &["b.PNG", "a.bmp", "notes.txt", "c"]);
+        let names: Vec<String> = s.list().unwrap().into_iter().map(|(n, _)| n).collect();
+        assert_eq!(names, ["a", "b"]);
+        fs::remove_dir_all(s.dir()).unwrap();
+    }
+
+    #[test]
+    fn finds_by_name_prefix_substring_then_typo() {
+        let s = store(
+            "find",
+            &[
+                "sunset.png",
+                "sunrise.jpg",
+                "mountain.bmp",
+                "cat.gif",
+                "cat.png",
+            ],
+        );
+        assert_eq!(found(&s, "Mountain"), "mountain.bmp");
+        assert_eq!(found(&s, "sunr"), "sunrise.jpg");
+        assert_eq!(found(&s, "ntai"), "mountain.bmp");
+        assert_eq!(found(&s, "sunsett"), "sunset.png");
+        // the full file name picks between two of the same name
+        assert_eq!(found(&s, "cat.gif"), "cat.gif");
+        assert!(s.find("sun").unwrap_err().contains("could be any of"));
+        assert!(s.find("cat").unwrap_err().contains("could be any of"));
+        assert!(s.find("ocean").unwrap_err().contains("no image named"));
+        fs::remove_dir_all(s.dir()).unwrap();
+    }
+
+    #[test]
+    fn resolves_only_at_names_through_the_store() {
+        let s = store("resolve", &["sunset.png"]);
+        assert_eq!(s.resolve("@sunset").unwrap(), s.dir().join("sunset.png"));
+        assert_eq!(s.resolve("sunset").unwrap(), PathBuf::from("sunset"));
+        fs::remove_dir_all(s.dir()).unwrap();
+    }
+}
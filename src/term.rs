@@ -0,0 +1,159 @@
+use std::process::Command;
+
+use crate::{
+    draw::{Color, Drawable},
+    font::{self, ADVANCE, GLYPH_HEIGHT},
+    SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+const MARGIN: u16 = 6;
+
+#[derive(Clone, Copy)]
+struct Cell {
+    c: char,
+    color: Color,
+}
+
+// a grid of characters rendered with the bitmap font, like a terminal
+pub struct Terminal {
+    rows: Vec<Vec<Cell>>,
+    scale: u16,
+}
+
+// maps an ansi sgr foreground code onto the panel palette.
+// white and the default color both map to black since the background is white.
+fn ansi_color(code: u32) -> Option<Color> {
+    match code {
+        30 | 37 | 39 | 90 | 97 => Some(Color::Black),
+        31 | 35 | 91 | 95 => Some(Color::Red),
+        32 | 92 => Some(Color::Green),
+        33 => Some(Color::Orange),
+        93 => Some(Color::Yellow),
+        34 | 36 | 94 | 96 => Some(Color::Blue),
+        _ => None,
+    }
+}
+
+impl Terminal {
+    // parses text containing ansi color escapes. other escape sequences
+    // are discarded.
+    pub fn parse(text: &str, scale: u16) -> Self {
+        let mut rows = vec![Vec::new()];
+        let mut color = Color::Black;
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\x1b' => {
+                    if chars.next_if_eq(&'[').is_none() {
+                        continue;
+                    }
+                    let mut params = String::new();
+                    let mut end = None;
+                    for c in chars.by_ref() {
+                        if c.is_ascii_alphabetic() || c == '~' {
+                            end = Some(c);
+                            break;
+                        }
+                        params.push(c);
+                    }
+                    if end != Some('m') {
+                        continue;
+                    }
+                    for code in params.split(';') {
+                        let code = code.parse().unwrap_or(0);
+                        if code == 0 {
+                            color = Color::Black;
+                        } else if let Some(c) = ansi_color(code) {
+                            color = c;
+                        }
+                    }
+                }
+                '\n' => rows.push(Vec::new()),
+                '\t' => {
+                    let row = rows.last_mut().unwrap();
+                    row.push(Cell { c: ' ', color });
+                    while row.len() % 8 != 0 {
+                        row.push(Cell { c: ' ', color });
+                    }
+                }
+                c if c.is_control() => {}
+                c => rows.last_mut().unwrap().push(Cell { c, color }),
+            }
+        }
+        // drop the empty row left by a trailing newline
+        if rows.len() > 1 && rows.last().is_some_and(|r| r.is_empty()) {
+            rows.pop();
+        }
+        // keep the tail that fits on screen, like a terminal scrolls
+        let visible = ((SCREEN_HEIGHT - MARGIN * 2) / Self::line_height(scale)) as usize;
+        if rows.len() > visible {
+            rows.drain(..rows.len() - visible);
+        }
+        Self { rows, scale }
+    }
+
+    // the y of the bottom glyph row of every line that fits at a scale
+    pub fn baselines(scale: u16) -> Vec<u32> {
+        let line_h = Self::line_height(scale) as u32;
+        let visible = (SCREEN_HEIGHT - MARGIN * 2) as u32 / line_h;
+        (0..visible)
+            .map(|row| MARGIN as u32 + row * line_h + (GLYPH_HEIGHT * scale) as u32 - 1)
+            .collect()
+    }
+
+    fn line_height(scale: u16) -> u16 {
+        (GLYPH_HEIGHT + 2) * scale
+    }
+}
+
+impl Drawable for Terminal {
+    fn get_pixel(&self, x: u16, y: u16) -> Color {
+        if x < MARGIN || y < MARGIN || x >= SCREEN_WIDTH - MARGIN {
+            return Color::White;
+        }
+        let (x, y) = (x - MARGIN, y - MARGIN);
+        let cell_w = ADVANCE * self.scale;
+        let line_h = Self::line_height(self.scale);
+        let cell = self
+            .rows
+            .get((y / line_h) as usize)
+            .and_then(|r| r.get((x / cell_w) as usize));
+        match cell {
+            Some(cell)
+                if font::glyph_pixel(
+                    cell.c,
+                    (x % cell_w) / self.scale,
+                    (y % line_h) / self.scale,
+                ) =>
+            {
+                cell.color
+            }
+            _ => Color::White,
+        }
+    }
+}
+
+// runs a shell command and captures its stdout
+pub fn run(command: &str) -> Result<String, String> {
+    let out = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .output()
+        .map_err(|e| format!("could not run '{command}': {e}"))?;
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+// captures a tmux pane including its color escapes
+pub fn capture_tmux(target: &str) -> Result<String, String> {
+    let out = Command::new("tmux")
+        .args(["capture-pane", "-p", "-e", "-t", target])
+        .output()
+        .map_err(|e| format!("could not run tmux: {e}"))?;
+    if !out.status.success() {
+        return Err(format!(
+            "tmux capture-pane failed: {}",
+            String::from_utf8_lossy(&out.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
@@ -0,0 +1,214 @@
+// truetype text, for when the built-in bitmap font is too small or too
+// blocky. glyphs are rasterized once per size and cached on the font, so
+// widgets redrawn every tick only lay out again.
+
+use std::{fs, path::Path, rc::Rc, str::FromStr};
+
+use fontdue::{FontSettings, Metrics};
+
+use crate::{
+    draw::{Color, Drawable},
+    glyphs::GlyphCache,
+    layout::Rect,
+    SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+// coverage from which a pixel counts as ink. there's no blending with a
+// 7 color palette, so edges are thresholded.
+const INK: u8 = 128;
+
+struct Glyph {
+    metrics: Metrics,
+    coverage: Vec<u8>,
+}
+
+pub struct Font {
+    font: fontdue::Font,
+    // keyed by (size in px as bits, char)
+    glyphs: GlyphCache<(u32, char), Glyph>,
+}
+
+impl Font {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let bytes =
+            fs::read(path).map_err(|e| format!("could not read {}: {e}", path.display()))?;
+        Self::from_bytes(&bytes).map_err(|e| format!("could not load {}: {e}", path.display()))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let font = fontdue::Font::from_bytes(bytes, FontSettings::default())?;
+        Ok(Self {
+            font,
+            glyphs: GlyphCache::default(),
+        })
+    }
+
+    fn glyph(&self, c: char, size: f32) -> Rc<Glyph> {
+        self.glyphs.get((size.to_bits(), c), || {
+            let (metrics, coverage) = self.font.rasterize(c, size);
+            Glyph { metrics, coverage }
+        })
+    }
+
+    fn kern(&self, prev: Option<char>, c: char, size: f32) -> f32 {
+        prev.and_then(|p| self.font.horizontal_kern(p, c, size))
+            .unwrap_or(0.0)
+    }
+
+    // the width of a single line of text
+    pub fn measure(&self, text: &str, size: f32) -> f32 {
+        let mut prev = None;
+        text.chars()
+            .map(|c| {
+                let w = self.kern(prev, c, size) + self.glyph(c, size).metrics.advance_width;
+                prev = Some(c);
+                w
+            })
+            .sum()
+    }
+
+    // (ascent, distance between baselines)
+    fn line_metrics(&self, size: f32) -> (f32, f32) {
+        self.font
+            .horizontal_line_metrics(size)
+            .map_or((size, size * 1.2), |m| (m.ascent, m.new_line_size))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+impl FromStr for Align {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "left" => Ok(Align::Left),
+            "center" | "centre" => Ok(Align::Center),
+            "right" => Ok(Align::Right),
+            _ => Err(format!(
+                "unknown alignment '{s}' (expected left, center or right)"
+            )),
+        }
+    }
+}
+
+pub struct Style {
+    // height of a line in px
+    pub size: f32,
+    pub color: Color,
+    pub align: Align,
+    // wrap at this many px. without it lines only break at newlines.
+    pub width: Option<u32>,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            size: 32.0,
+            color: Color::Black,
+            align: Align::Left,
+            width: None,
+        }
+    }
+}
+
+// splits at newlines, then between words so no line is wider than `width`.
+// a single word wider than that gets a line of its own.
+fn wrap(font: &Font, text: &str, size: f32, width: Option<u32>) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let candidate = if line.is_empty() {
+                word.to_string()
+            } else {
+                format!("{line} {word}")
+            };
+            let too_wide = width.is_some_and(|w| font.measure(&candidate, size) > w as f32);
+            if too_wide && !line.is_empty() {
+                lines.push(std::mem::replace(&mut line, word.to_string()));
+            } else {
+                line = candidate;
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+// text laid out over another drawable, with its top-left at (x, y)
+pub struct Text<'a, D: Drawable + ?Sized> {
+    ink: Vec<bool>,
+    bounds: Rect,
+    color: Color,
+    rest: &'a D,
+}
+
+impl<'a, D: Drawable + ?Sized> Text<'a, D> {
+    pub fn new(font: &Font, text: &str, (x, y): (u32, u32), style: &Style, rest: &'a D) -> Self {
+        let (w, h) = (SCREEN_WIDTH as i32, SCREEN_HEIGHT as i32);
+        let mut ink = vec![false; (w * h) as usize];
+        let lines = wrap(font, text, style.size, style.width);
+        let widths: Vec<f32> = lines.iter().map(|l| font.measure(l, style.size)).collect();
+        let block = style
+            .width
+            .map_or_else(|| widths.iter().copied().fold(0.0, f32::max), |w| w as f32);
+        let (ascent, line_height) = font.line_metrics(style.size);
+        for (i, (line, lw)) in lines.iter().zip(&widths).enumerate() {
+            let baseline = y as f32 + ascent + i as f32 * line_height;
+            let mut pen = x as f32
+                + match style.align {
+                    Align::Left => 0.0,
+                    Align::Center => (block - lw) / 2.0,
+                    Align::Right => block - lw,
+                };
+            let mut prev = None;
+            for c in line.chars() {
+                pen += font.kern(prev, c, style.size);
+                prev = Some(c);
+                let g = font.glyph(c, style.size);
+                let m = &g.metrics;
+                let left = pen.round() as i32 + m.xmin;
+                let top = baseline.round() as i32 - m.height as i32 - m.ymin;
+                for (j, cov) in g.coverage.iter().enumerate() {
+                    let px = left + (j % m.width.max(1)) as i32;
+                    let py = top + (j / m.width.max(1)) as i32;
+                    if *cov >= INK && (0..w).contains(&px) && (0..h).contains(&py) {
+                        ink[(px + py * w) as usize] = true;
+                    }
+                }
+                pen += m.advance_width;
+            }
+        }
+        Self {
+            ink,
+            bounds: Rect {
+                x,
+                y,
+                w: block.ceil() as u32,
+                h: (lines.len() as f32 * line_height).ceil() as u32,
+            },
+            color: style.color,
+            rest,
+        }
+    }
+
+    // the box the laid out lines take up, for layout and --debug-layout
+    pub fn bounds(&self) -> Rect {
+        self.bounds
+    }
+}
+
+impl<D: Drawable + ?Sized> Drawable for Text<'_, D> {
+    fn get_pixel(&self, x: u16, y: u16) -> Color {
+        if self.ink[x as usize + y as usize * SCREEN_WIDTH as usize] {
+            self.color
+        } else {
+            self.rest.get_pixel(x, y)
+        }
+    }
+}
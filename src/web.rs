@@ -0,0 +1,74 @@
+use std::{
+    env,
+    fs::{self, File},
+    process::Command,
+};
+
+use crate::{draw::Color, layout, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// decodes a png into an rgb image, dropping any alpha channel
+pub fn decode_png(path: &str) -> Result<bmp::Image, String> {
+    let err = |e: png::DecodingError| format!("could not decode {path}: {e}");
+    let file = File::open(path).map_err(|e| format!("could not open {path}: {e}"))?;
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(err)?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).map_err(err)?;
+    let channels = match info.color_type {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::Rgb => 3,
+        png::ColorType::Rgba => 4,
+        // normalize_to_color8 expands palettes
+        png::ColorType::Indexed => unreachable!(),
+    };
+    let mut img = bmp::Image::new(info.width, info.height);
+    for (x, y) in img.coordinates() {
+        let i = (y as usize * info.line_size) + x as usize * channels;
+        let px = if channels < 3 {
+            bmp::Pixel::new(buf[i], buf[i], buf[i])
+        } else {
+            bmp::Pixel::new(buf[i], buf[i + 1], buf[i + 2])
+        };
+        img.set_pixel(x, y, px);
+    }
+    Ok(img)
+}
+
+// screenshots a page with a headless chromium-compatible browser at the
+// panel's resolution
+pub fn render_url(browser: &str, url: &str) -> Result<bmp::Image, String> {
+    let shot = screenshot(browser, url, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32)?;
+    if shot.get_width() == SCREEN_WIDTH as u32 && shot.get_height() == SCREEN_HEIGHT as u32 {
+        return Ok(shot);
+    }
+    let mut img = layout::blank(Color::White);
+    layout::fit_into(&mut img, &shot, layout::Region::Full.rect());
+    Ok(img)
+}
+
+// screenshots a page at an arbitrary window size
+pub fn screenshot(browser: &str, url: &str, w: u32, h: u32) -> Result<bmp::Image, String> {
+    let out = env::temp_dir().join(format!("rpi-epaper-web-{}.png", std::process::id()));
+    let status = Command::new(browser)
+        .args([
+            "--headless",
+            "--disable-gpu",
+            "--hide-scrollbars",
+            &format!("--window-size={w},{h}"),
+            &format!("--screenshot={}", out.display()),
+            url,
+        ])
+        .output()
+        .map_err(|e| format!("could not run {browser}: {e}"))?;
+    if !status.status.success() {
+        return Err(format!(
+            "{browser} failed to render {url}: {}",
+            String::from_utf8_lossy(&status.stderr).trim()
+        ));
+    }
+    let shot = decode_png(&out.to_string_lossy());
+    let _ = fs::remove_file(&out);
+    shot
+}